@@ -3,10 +3,11 @@
 /// Loopback mode captures whatever the system is playing on the default render
 /// endpoint — i.e. game audio — without requiring a virtual audio device.
 ///
-/// On non-Windows platforms the public API compiles but `run` returns an error.
-use anyhow::Result;
+/// On non-Windows platforms [`WasapiSource`] compiles but its `run` returns an error.
 use tokio::sync::{mpsc, watch};
 
+use crate::capture::CaptureFuture;
+
 /// A chunk of raw interleaved PCM audio from the system output device.
 #[derive(Debug)]
 pub struct RawAudio {
@@ -151,20 +152,45 @@ mod imp {
 
 // ── Public API ────────────────────────────────────────────────────────────────
 
-/// Captures system audio output (loopback) using WASAPI, sending [`RawAudio`]
-/// chunks to `audio_tx` until `stop_rx` is set to `true`.
-pub async fn run(
-    audio_tx: mpsc::Sender<RawAudio>,
-    stop_rx: watch::Receiver<bool>,
-) -> Result<()> {
-    #[cfg(windows)]
-    {
-        imp::run(audio_tx, stop_rx).await
+/// A producer of raw PCM audio for the encoder.
+///
+/// Implementations send [`RawAudio`] chunks (interleaved f32 at the encoder's
+/// sample rate and channel count) to `audio_tx` until `stop_rx` is set to
+/// `true` or the receiver is dropped.
+pub trait AudioSource: Send {
+    /// Short name used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// Consumes the source and returns the capture loop as a future.
+    fn run(
+        self: Box<Self>,
+        audio_tx: mpsc::Sender<RawAudio>,
+        stop_rx: watch::Receiver<bool>,
+    ) -> CaptureFuture;
+}
+
+/// Captures system audio output (loopback) using WASAPI.
+pub struct WasapiSource;
+
+impl AudioSource for WasapiSource {
+    fn name(&self) -> &'static str {
+        "wasapi"
     }
-    #[cfg(not(windows))]
-    {
-        let _ = (audio_tx, stop_rx);
-        anyhow::bail!("Audio capture (WASAPI) is only supported on Windows")
+
+    fn run(
+        self: Box<Self>,
+        audio_tx: mpsc::Sender<RawAudio>,
+        stop_rx: watch::Receiver<bool>,
+    ) -> CaptureFuture {
+        #[cfg(windows)]
+        {
+            Box::pin(imp::run(audio_tx, stop_rx))
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = (audio_tx, stop_rx);
+            Box::pin(async { anyhow::bail!("Audio capture (WASAPI) is only supported on Windows") })
+        }
     }
 }
 
@@ -179,4 +205,12 @@ mod tests {
         assert_eq!(audio.samples_f32, samples);
     }
 
+    #[cfg(not(windows))]
+    #[tokio::test]
+    async fn wasapi_source_errors_off_windows() {
+        let (tx, _rx) = mpsc::channel(1);
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        assert!(Box::new(WasapiSource).run(tx, stop_rx).await.is_err());
+    }
+
 }
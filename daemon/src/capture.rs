@@ -4,15 +4,61 @@
 /// at the GPU driver level.  It is safe from anti-cheat systems because it uses the
 /// same mechanism as Xbox Game Bar.
 ///
-/// On non-Windows platforms the public API compiles but `run` returns an error.
+/// The capture backend is abstracted behind [`VideoSource`] so the pipeline can
+/// also be driven by synthetic sources (see [`crate::synthetic`]).
+///
+/// On non-Windows platforms [`WgcSource`] compiles but its `run` returns an error.
 use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
 use tokio::sync::{mpsc, watch};
 
-/// A single captured video frame as tightly-packed BGRA8 pixels.
+/// A single captured video frame as BGRA8 pixels.
 #[derive(Debug)]
 pub struct RawFrame {
-    /// Row-major BGRA pixels: width × height × 4 bytes.
+    /// Row-major BGRA pixels: `height` rows of `stride` bytes each, of which
+    /// the first `width × 4` are pixel data and the rest is padding.
     pub bgra_data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from the start of one row to the start of the next.
+    pub stride: usize,
+}
+
+impl RawFrame {
+    /// Wraps tightly-packed BGRA pixels (`stride == width × 4`).
+    pub fn packed(bgra_data: Vec<u8>, width: u32, height: u32) -> Self {
+        Self { bgra_data, width, height, stride: width as usize * 4 }
+    }
+
+    /// Returns the `width × 4` pixel bytes of row `y`.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let start = y as usize * self.stride;
+        &self.bgra_data[start..start + self.width as usize * 4]
+    }
+
+    /// Checks that `bgra_data` is large enough for the declared geometry, so
+    /// [`row`](Self::row) cannot go out of bounds.
+    pub fn validate(&self) -> Result<()> {
+        let row_bytes = self.width as usize * 4;
+        if self.stride < row_bytes {
+            anyhow::bail!("Frame stride {} is smaller than a {}-pixel row", self.stride, self.width);
+        }
+        let needed = match self.height as usize {
+            0 => 0,
+            h => (h - 1) * self.stride + row_bytes,
+        };
+        if self.bgra_data.len() < needed {
+            anyhow::bail!(
+                "Frame has {} bytes, need {needed} for {}x{} with stride {}",
+                self.bgra_data.len(),
+                self.width,
+                self.height,
+                self.stride
+            );
+        }
+        Ok(())
+    }
 }
 
 // ── Windows implementation ────────────────────────────────────────────────────
@@ -85,19 +131,45 @@ mod imp {
         Ok(inspectable.cast()?)
     }
 
-    /// Copies a WGC frame's GPU surface into a CPU-side BGRA byte vector,
-    /// handling row-pitch padding.
+    /// Allocates a CPU-readable staging texture matching the capture size.
+    ///
+    /// The texture is created once per capture size and reused for every frame —
+    /// creating it per-frame causes a GPU memory allocation and GPU/CPU sync
+    /// stall on every captured frame, leading to frame drops and choppy output.
+    fn create_staging(device: &ID3D11Device, width: u32, height: u32) -> Result<ID3D11Texture2D> {
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+        };
+        let mut staging: Option<ID3D11Texture2D> = None;
+        unsafe {
+            device
+                .CreateTexture2D(&desc, None, Some(&mut staging))
+                .context("CreateTexture2D (staging) failed")?;
+        }
+        Ok(staging.unwrap())
+    }
+
+    /// Copies a WGC frame's GPU surface into a CPU-side [`RawFrame`].
     ///
-    /// `staging` must be a pre-allocated `D3D11_USAGE_STAGING` texture with the
-    /// same dimensions and format as the capture — creating it once and reusing it
-    /// avoids a per-frame GPU memory allocation and the associated GPU/CPU sync stall.
+    /// The mapped rows are copied as-is, so the frame's `stride` is the driver's
+    /// row pitch; the encoder skips the padding.  `staging` must have the same
+    /// dimensions and format as the capture.
     unsafe fn readback_frame(
         context: &ID3D11DeviceContext,
         staging: &ID3D11Texture2D,
         frame: &Direct3D11CaptureFrame,
         width: u32,
         height: u32,
-    ) -> Result<Vec<u8>> {
+    ) -> Result<RawFrame> {
         let surface = frame.Surface()?;
         let dxgi_access: IDirect3DDxgiInterfaceAccess = surface.cast()?;
         let texture: ID3D11Texture2D = dxgi_access.GetInterface()?;
@@ -111,17 +183,11 @@ mod imp {
 
         let row_pitch = mapped.RowPitch as usize;
         let row_bytes = width as usize * 4;
-        let mut bgra = Vec::with_capacity(height as usize * row_bytes);
-        for row in 0..height as usize {
-            let src = std::slice::from_raw_parts(
-                (mapped.pData as *const u8).add(row * row_pitch),
-                row_bytes,
-            );
-            bgra.extend_from_slice(src);
-        }
+        let len = (height as usize).saturating_sub(1) * row_pitch + row_bytes;
+        let bgra_data = std::slice::from_raw_parts(mapped.pData as *const u8, len).to_vec();
 
         context.Unmap(staging, 0);
-        Ok(bgra)
+        Ok(RawFrame { bgra_data, width, height, stride: row_pitch })
     }
 
     pub async fn run(
@@ -140,33 +206,10 @@ mod imp {
             interop.CreateForMonitor(monitor)?
         };
 
-        let size = capture_item.Size()?;
-        let width = size.Width as u32;
-        let height = size.Height as u32;
-
-        // Allocate the staging texture once and reuse it for every frame.
-        // Creating it per-frame (inside readback_frame) causes a GPU memory
-        // allocation and GPU/CPU sync stall on every captured frame, leading to
-        // frame drops and choppy output.
-        let staging = unsafe {
-            let desc = D3D11_TEXTURE2D_DESC {
-                Width: width,
-                Height: height,
-                MipLevels: 1,
-                ArraySize: 1,
-                Format: DXGI_FORMAT_B8G8R8A8_UNORM,
-                SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
-                Usage: D3D11_USAGE_STAGING,
-                BindFlags: 0,
-                CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
-                MiscFlags: 0,
-            };
-            let mut staging: Option<ID3D11Texture2D> = None;
-            d3d_device
-                .CreateTexture2D(&desc, None, Some(&mut staging))
-                .context("CreateTexture2D (staging) failed")?;
-            staging.unwrap()
-        };
+        let mut size = capture_item.Size()?;
+        let mut width = size.Width as u32;
+        let mut height = size.Height as u32;
+        let mut staging = create_staging(&d3d_device, width, height)?;
 
         // CreateFreeThreaded: no dispatcher queue / message pump needed.
         let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
@@ -207,10 +250,29 @@ mod imp {
 
             match cb_rx.recv_timeout(Duration::from_millis(50)) {
                 Ok(frame) => {
+                    // The monitor resolution changed: resize the pool and the
+                    // staging texture, and drop this frame (its surface still
+                    // has the old size).  The encoder reopens on the next frame.
+                    let content_size = frame.ContentSize()?;
+                    if content_size != size {
+                        drop(frame);
+                        size = content_size;
+                        width = size.Width as u32;
+                        height = size.Height as u32;
+                        frame_pool.Recreate(
+                            &direct3d_device.0,
+                            DirectXPixelFormat::B8G8R8A8UIntNormalized,
+                            2,
+                            size,
+                        )?;
+                        staging = create_staging(&d3d_device, width, height)?;
+                        eprintln!("[capture] Capture size changed to {}×{}", width, height);
+                        continue;
+                    }
+
                     match unsafe { readback_frame(&d3d_context, &staging, &frame, width, height) }
                     {
-                        Ok(bgra_data) => {
-                            let raw = RawFrame { bgra_data };
+                        Ok(raw) => {
                             if frame_tx.send(raw).await.is_err() {
                                 break; // Encoder task dropped.
                             }
@@ -233,20 +295,49 @@ mod imp {
 
 // ── Public API ────────────────────────────────────────────────────────────────
 
-/// Captures the primary monitor using WGC, sending [`RawFrame`]s to `frame_tx`
-/// until `stop_rx` is set to `true`.
-pub async fn run(
-    frame_tx: mpsc::Sender<RawFrame>,
-    stop_rx: watch::Receiver<bool>,
-) -> Result<()> {
-    #[cfg(windows)]
-    {
-        imp::run(frame_tx, stop_rx).await
+/// Future returned by [`VideoSource::run`] and [`AudioSource::run`].
+///
+/// [`AudioSource::run`]: crate::audio_capture::AudioSource::run
+pub type CaptureFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// A producer of raw video frames for the encoder.
+///
+/// Implementations send [`RawFrame`]s to `frame_tx` until `stop_rx` is set to
+/// `true` or the receiver is dropped.
+pub trait VideoSource: Send {
+    /// Short name used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// Consumes the source and returns the capture loop as a future.
+    fn run(
+        self: Box<Self>,
+        frame_tx: mpsc::Sender<RawFrame>,
+        stop_rx: watch::Receiver<bool>,
+    ) -> CaptureFuture;
+}
+
+/// Captures the primary monitor using Windows Graphics Capture (WGC).
+pub struct WgcSource;
+
+impl VideoSource for WgcSource {
+    fn name(&self) -> &'static str {
+        "wgc"
     }
-    #[cfg(not(windows))]
-    {
-        let _ = (frame_tx, stop_rx);
-        anyhow::bail!("Screen capture (WGC) is only supported on Windows")
+
+    fn run(
+        self: Box<Self>,
+        frame_tx: mpsc::Sender<RawFrame>,
+        stop_rx: watch::Receiver<bool>,
+    ) -> CaptureFuture {
+        #[cfg(windows)]
+        {
+            Box::pin(imp::run(frame_tx, stop_rx))
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = (frame_tx, stop_rx);
+            Box::pin(async { anyhow::bail!("Screen capture (WGC) is only supported on Windows") })
+        }
     }
 }
 
@@ -257,8 +348,34 @@ mod tests {
     #[test]
     fn raw_frame_stores_data() {
         let data = vec![0u8; 4];
-        let frame = RawFrame { bgra_data: data.clone() };
+        let frame = RawFrame::packed(data.clone(), 1, 1);
         assert_eq!(frame.bgra_data, data);
+        assert_eq!(frame.stride, 4);
+    }
+
+    #[test]
+    fn raw_frame_row_skips_stride_padding() {
+        // 2×2 frame with 4 bytes of padding per row.
+        let data: Vec<u8> = (0..24).collect();
+        let frame = RawFrame { bgra_data: data, width: 2, height: 2, stride: 12 };
+        assert!(frame.validate().is_ok());
+        assert_eq!(frame.row(0), &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(frame.row(1), &[12, 13, 14, 15, 16, 17, 18, 19]);
+    }
+
+    #[test]
+    fn raw_frame_validate_rejects_short_buffers() {
+        let frame = RawFrame { bgra_data: vec![0; 15], width: 2, height: 2, stride: 8 };
+        assert!(frame.validate().is_err());
+        let frame = RawFrame { bgra_data: vec![0; 64], width: 4, height: 2, stride: 8 };
+        assert!(frame.validate().is_err());
     }
 
+    #[cfg(not(windows))]
+    #[tokio::test]
+    async fn wgc_source_errors_off_windows() {
+        let (tx, _rx) = mpsc::channel(1);
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        assert!(Box::new(WgcSource).run(tx, stop_rx).await.is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use notify::{Config as NotifyConfig, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
@@ -7,14 +7,59 @@ use tokio::sync::mpsc;
 use crate::event::DaemonEvent;
 
 pub const MIN_BUFFER_LENGTH_SECS: u32 = 5;
-pub const MAX_BUFFER_LENGTH_SECS: u32 = 120;
+pub const MAX_BUFFER_LENGTH_SECS: u32 = 1800;
+/// Longest stretch of video the ring buffer keeps in RAM; longer buffers
+/// spill their older segments to disk.
+pub const MAX_MEMORY_BUFFER_SECS: u32 = 120;
 pub const DEFAULT_BUFFER_LENGTH_SECS: u32 = 15;
 pub const DEFAULT_HOTKEY: &str = "F8";
 /// Resolved at runtime by expanding %USERPROFILE%.
 pub const DEFAULT_CLIP_OUTPUT_DIR: &str = r"%USERPROFILE%\Videos\Peaking";
+pub const DEFAULT_CLIP_FILENAME_TEMPLATE: &str = "{date}_{time}";
+pub const DEFAULT_CLIP_DIR_TEMPLATE: &str = "{game}";
+/// Placeholders accepted in `clip_filename_template` and `clip_dir_template`.
+pub const CLIP_TEMPLATE_PLACEHOLDERS: &[&str] =
+    &["game", "date", "time", "ms", "duration", "counter", "tag"];
+
+pub const MIN_FPS: u32 = 1;
+pub const MAX_FPS: u32 = 240;
+pub const DEFAULT_FPS: u32 = 60;
+pub const MIN_VIDEO_BITRATE_KBPS: u32 = 100;
+pub const MAX_VIDEO_BITRATE_KBPS: u32 = 200_000;
+pub const DEFAULT_VIDEO_BITRATE_KBPS: u32 = 8_000;
+/// H.264 quantiser range used by constant-QP rate control.
+pub const MAX_QP: u32 = 51;
+pub const DEFAULT_QP: u32 = 23;
+/// Longest GOP allowed, in seconds of video (the limit in frames scales with fps).
+pub const MAX_GOP_SECS: u32 = 10;
+pub const MIN_AUDIO_BITRATE_KBPS: u32 = 32;
+pub const MAX_AUDIO_BITRATE_KBPS: u32 = 512;
+pub const DEFAULT_AUDIO_BITRATE_KBPS: u32 = 192;
+/// Smallest accepted `max_buffer_bytes` (1 MiB).
+pub const MIN_MAX_BUFFER_BYTES: u64 = 1 << 20;
+/// Longest accepted `post_roll_secs`.
+pub const MAX_POST_ROLL_SECS: u32 = 60;
+/// Smallest accepted `spill_file_bytes` (16 MiB).
+pub const MIN_SPILL_FILE_BYTES: u64 = 16 << 20;
+/// Smallest accepted `retention.max_total_bytes` (16 MiB).
+pub const MIN_RETENTION_TOTAL_BYTES: u64 = 16 << 20;
+/// Bitrate assumed when sizing the spill file for constant-QP encoding,
+/// which has no bitrate target.
+const CQP_SPILL_ESTIMATE_KBPS: u64 = 20_000;
+pub const MIN_OUTPUT_DIMENSION: u32 = 16;
+pub const MAX_OUTPUT_DIMENSION: u32 = 8192;
+pub const MIN_SCALE: f64 = 0.1;
+pub const MAX_SCALE: f64 = 1.0;
+/// Width of the PNG thumbnail saved next to each clip; 0 disables thumbnails.
+pub const DEFAULT_THUMBNAIL_WIDTH: u32 = 320;
+pub const MIN_THUMBNAIL_WIDTH: u32 = 64;
+pub const MAX_THUMBNAIL_WIDTH: u32 = 1920;
+/// Free space on the clip drive below which status.toml carries a warning
+/// (5 GiB); 0 disables the warning.
+pub const DEFAULT_DISK_SPACE_WARNING_BYTES: u64 = 5 << 30;
 
 /// Root configuration structure. Deserialized from %APPDATA%\Peaking\config.toml.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub global: GlobalConfig,
@@ -22,21 +67,25 @@ pub struct Config {
     pub applications: Vec<ApplicationConfig>,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            global: GlobalConfig::default(),
-            applications: Vec::new(),
-        }
-    }
-}
-
 /// Global defaults applied when no per-application override exists.
 #[derive(Debug, Deserialize)]
 pub struct GlobalConfig {
-    /// Length of the rolling video buffer in seconds. Clamped to [5, 120].
+    /// Length of the rolling video buffer in seconds. Clamped to [5, 1800].
+    /// Anything beyond the newest 120 seconds is kept in a spill file on disk.
     #[serde(default = "default_buffer_length")]
     pub buffer_length_secs: u32,
+    /// Optional cap on the compressed bytes the ring buffer holds in memory.
+    /// When reached, the oldest in-memory footage moves to the spill file, or
+    /// is dropped if the buffer does not spill. Minimum: 1 MiB. Default: no limit.
+    pub max_buffer_bytes: Option<u64>,
+    /// Size of the preallocated spill file used by buffers longer than 120
+    /// seconds. Minimum: 16 MiB. Default: estimated from the buffer length and
+    /// bitrates.
+    pub spill_file_bytes: Option<u64>,
+    /// Seconds to keep recording after the clip hotkey is pressed before the
+    /// clip is saved. Range: 0–60. Default: 0 (save immediately).
+    #[serde(default)]
+    pub post_roll_secs: u32,
     /// Virtual-key name of the clip hotkey (e.g. "F8").
     #[serde(default = "default_hotkey")]
     pub hotkey: String,
@@ -44,18 +93,445 @@ pub struct GlobalConfig {
     /// %USERPROFILE% is expanded at runtime.
     #[serde(default = "default_clip_output_dir")]
     pub clip_output_dir: String,
+    /// Name of each clip file, without the extension.  Placeholders: {game},
+    /// {date} (YYYY-MM-DD), {time} (HH-MM-SS), {ms}, {duration} (seconds),
+    /// {counter} (one more than the clips already in the directory) and {tag}.
+    /// A name that is already taken gets a `_2`, `_3`, … suffix.
+    #[serde(default = "default_clip_filename_template")]
+    pub clip_filename_template: String,
+    /// Directory under `clip_output_dir` that clips are saved in; `/` nests
+    /// directories.  Takes the same placeholders except {counter}.
+    #[serde(default = "default_clip_dir_template")]
+    pub clip_dir_template: String,
+    /// File format clips are saved in.
+    #[serde(default)]
+    pub container: ContainerFormat,
+    /// Width in pixels of the PNG thumbnail saved next to each clip; the
+    /// height follows the video's aspect ratio.  Range: 64–1920, or 0 to
+    /// disable thumbnails. Default: 320.
+    #[serde(default = "default_thumbnail_width")]
+    pub thumbnail_width: u32,
+    /// status.toml warns when the drive holding `clip_output_dir` has less
+    /// than this many bytes free.  0 disables the warning. Default: 5 GiB.
+    #[serde(default = "default_disk_space_warning_bytes")]
+    pub disk_space_warning_bytes: u64,
+    /// Which H.264 encoder backend to use. `auto` probes them in order.
+    #[serde(default)]
+    pub encoder: EncoderKind,
+    /// Video encoder settings (`[global.video]`).
+    #[serde(default)]
+    pub video: VideoConfig,
+    /// Audio encoder settings (`[global.audio]`).
+    #[serde(default)]
+    pub audio: AudioConfig,
+    /// Limits on all clips under `clip_output_dir` (`[global.retention]`).
+    #[serde(default)]
+    pub retention: RetentionConfig,
+}
+
+/// Encoder backend requested by the `[global] encoder` key.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncoderKind {
+    /// Probe the hardware encoders first, then libx264.
+    #[default]
+    Auto,
+    /// NVIDIA NVENC (`h264_nvenc`).
+    Nvenc,
+    /// libx264 on the CPU.
+    X264,
+    /// Intel Quick Sync (`h264_qsv`).
+    Qsv,
+    /// AMD AMF (`h264_amf`).
+    Amf,
+    /// Built-in FFmpeg-free encoder; intended for headless testing only.
+    Software,
+}
+
+impl EncoderKind {
+    /// The spelling used in config.toml and status.toml.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EncoderKind::Auto => "auto",
+            EncoderKind::Nvenc => "nvenc",
+            EncoderKind::X264 => "x264",
+            EncoderKind::Qsv => "qsv",
+            EncoderKind::Amf => "amf",
+            EncoderKind::Software => "software",
+        }
+    }
 }
 
 impl Default for GlobalConfig {
     fn default() -> Self {
         Self {
             buffer_length_secs: DEFAULT_BUFFER_LENGTH_SECS,
+            max_buffer_bytes: None,
+            spill_file_bytes: None,
+            post_roll_secs: 0,
             hotkey: DEFAULT_HOTKEY.to_string(),
             clip_output_dir: DEFAULT_CLIP_OUTPUT_DIR.to_string(),
+            clip_filename_template: DEFAULT_CLIP_FILENAME_TEMPLATE.to_string(),
+            clip_dir_template: DEFAULT_CLIP_DIR_TEMPLATE.to_string(),
+            container: ContainerFormat::Mp4,
+            thumbnail_width: DEFAULT_THUMBNAIL_WIDTH,
+            disk_space_warning_bytes: DEFAULT_DISK_SPACE_WARNING_BYTES,
+            encoder: EncoderKind::Auto,
+            video: VideoConfig::default(),
+            audio: AudioConfig::default(),
+            retention: RetentionConfig::default(),
+        }
+    }
+}
+
+/// Clip file format selected by the `[global] container` key.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerFormat {
+    /// Faststart MP4; the index is written before the media data.
+    #[default]
+    Mp4,
+    /// Fragmented MP4 with one fragment per buffered segment; stays playable
+    /// if the write is interrupted and can be watched while being written.
+    Fmp4,
+    /// Matroska; stays playable up to the last complete cluster if the write
+    /// is interrupted.
+    Mkv,
+}
+
+impl ContainerFormat {
+    /// File extension for clips in this format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ContainerFormat::Mp4 | ContainerFormat::Fmp4 => "mp4",
+            ContainerFormat::Mkv => "mkv",
+        }
+    }
+}
+
+/// Encoder rate-control mode.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateControl {
+    /// Constant bitrate: `bitrate_kbps` is held steady.
+    Cbr,
+    /// Variable bitrate averaging `bitrate_kbps`, with headroom for complex scenes.
+    #[default]
+    Vbr,
+    /// Constant quantiser `qp`; `bitrate_kbps` is ignored.
+    Cqp,
+}
+
+impl RateControl {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RateControl::Cbr => "cbr",
+            RateControl::Vbr => "vbr",
+            RateControl::Cqp => "cqp",
+        }
+    }
+}
+
+/// Speed/quality trade-off, mapped onto each backend's own preset names.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncoderPreset {
+    Speed,
+    #[default]
+    Balanced,
+    Quality,
+}
+
+impl EncoderPreset {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EncoderPreset::Speed => "speed",
+            EncoderPreset::Balanced => "balanced",
+            EncoderPreset::Quality => "quality",
+        }
+    }
+}
+
+/// How the captured picture is fitted into an `output_width` × `output_height`
+/// box whose aspect ratio differs from the capture's.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleFit {
+    /// Scale to fit inside the box and pad the remainder with black bars.
+    #[default]
+    Letterbox,
+    /// Scale to fill the box and crop the overflow equally from both sides.
+    Crop,
+}
+
+/// `[global.video]`: video encoder settings.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct VideoConfig {
+    /// Encoded frame rate. Range: 1–240.
+    #[serde(default = "default_fps")]
+    pub fps: u32,
+    /// Target bitrate in kbit/s for CBR/VBR. Range: 100–200000.
+    #[serde(default = "default_video_bitrate_kbps")]
+    pub bitrate_kbps: u32,
+    #[serde(default)]
+    pub rate_control: RateControl,
+    /// Quantiser for CQP rate control. Range: 0–51.
+    #[serde(default = "default_qp")]
+    pub qp: u32,
+    #[serde(default)]
+    pub preset: EncoderPreset,
+    /// Frames between keyframes. Each keyframe starts a new buffer segment.
+    /// Defaults to one second's worth of frames (`fps`).
+    pub gop_length: Option<u32>,
+    /// Encoded frame width. With only one of `output_width` / `output_height`
+    /// set, the other follows the capture's aspect ratio. Range: 16–8192.
+    pub output_width: Option<u32>,
+    pub output_height: Option<u32>,
+    /// Scale factor applied to the capture size instead of fixed dimensions.
+    /// Range: 0.1–1.0.
+    pub scale: Option<f64>,
+    /// How to handle an aspect-ratio mismatch with `output_width` × `output_height`.
+    #[serde(default)]
+    pub fit: ScaleFit,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            fps: DEFAULT_FPS,
+            bitrate_kbps: DEFAULT_VIDEO_BITRATE_KBPS,
+            rate_control: RateControl::default(),
+            qp: DEFAULT_QP,
+            preset: EncoderPreset::default(),
+            gop_length: None,
+            output_width: None,
+            output_height: None,
+            scale: None,
+            fit: ScaleFit::default(),
+        }
+    }
+}
+
+impl VideoConfig {
+    /// GOP length in frames, defaulting to one keyframe per second.
+    pub fn effective_gop_length(&self) -> u32 {
+        self.gop_length.unwrap_or(self.fps)
+    }
+
+    /// Checks every field against its documented range.  `table` names the
+    /// TOML table in error messages (e.g. `global.video`).
+    pub fn validate(&self, table: &str) -> Result<()> {
+        check_range(table, "fps", self.fps, MIN_FPS, MAX_FPS)?;
+        check_range(
+            table,
+            "bitrate_kbps",
+            self.bitrate_kbps,
+            MIN_VIDEO_BITRATE_KBPS,
+            MAX_VIDEO_BITRATE_KBPS,
+        )?;
+        check_range(table, "qp", self.qp, 0, MAX_QP)?;
+        if let Some(gop) = self.gop_length {
+            check_range(table, "gop_length", gop, 1, self.fps * MAX_GOP_SECS)?;
+        }
+        for (key, value) in [("output_width", self.output_width), ("output_height", self.output_height)] {
+            if let Some(value) = value {
+                check_range(table, key, value, MIN_OUTPUT_DIMENSION, MAX_OUTPUT_DIMENSION)?;
+            }
+        }
+        if let Some(scale) = self.scale {
+            if !(MIN_SCALE..=MAX_SCALE).contains(&scale) {
+                bail!("{table}.scale = {scale} is out of range (expected {MIN_SCALE}–{MAX_SCALE})");
+            }
+            if self.output_width.is_some() || self.output_height.is_some() {
+                bail!("{table}: set either scale or output_width/output_height, not both");
+            }
         }
+        Ok(())
+    }
+}
+
+/// `[global.audio]`: audio encoder settings.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AudioConfig {
+    /// AAC bitrate in kbit/s. Range: 32–512.
+    #[serde(default = "default_audio_bitrate_kbps")]
+    pub bitrate_kbps: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self { bitrate_kbps: DEFAULT_AUDIO_BITRATE_KBPS }
+    }
+}
+
+impl AudioConfig {
+    /// Checks every field against its documented range.
+    pub fn validate(&self, table: &str) -> Result<()> {
+        check_range(
+            table,
+            "bitrate_kbps",
+            self.bitrate_kbps,
+            MIN_AUDIO_BITRATE_KBPS,
+            MAX_AUDIO_BITRATE_KBPS,
+        )
     }
 }
 
+/// Per-application `[applications.video]` overrides; unset fields fall back
+/// to `[global.video]`.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct VideoOverrides {
+    pub fps: Option<u32>,
+    pub bitrate_kbps: Option<u32>,
+    pub rate_control: Option<RateControl>,
+    pub qp: Option<u32>,
+    pub preset: Option<EncoderPreset>,
+    pub gop_length: Option<u32>,
+    pub output_width: Option<u32>,
+    pub output_height: Option<u32>,
+    pub scale: Option<f64>,
+    pub fit: Option<ScaleFit>,
+}
+
+/// Per-application `[applications.audio]` overrides.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct AudioOverrides {
+    pub bitrate_kbps: Option<u32>,
+}
+
+/// `[global.retention]` and `[applications.retention]`: limits on saved
+/// clips, enforced by deleting the oldest ones.  Unset limits are not
+/// enforced.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionConfig {
+    /// Total size of the clips in bytes. Minimum: 16 MiB.
+    pub max_total_bytes: Option<u64>,
+    /// Number of clips.  Each part of a split clip counts as one.
+    pub max_count: Option<u32>,
+    /// Age in days, measured from when the clip was saved.
+    pub max_age_days: Option<u32>,
+}
+
+impl RetentionConfig {
+    /// True if no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Checks every set limit against its documented minimum.
+    pub fn validate(&self, table: &str) -> Result<()> {
+        if let Some(bytes) = self.max_total_bytes {
+            if bytes < MIN_RETENTION_TOTAL_BYTES {
+                bail!(
+                    "{table}.max_total_bytes = {bytes} is too small \
+                     (minimum {MIN_RETENTION_TOTAL_BYTES})"
+                );
+            }
+        }
+        for (key, value) in [("max_count", self.max_count), ("max_age_days", self.max_age_days)] {
+            if let Some(value) = value {
+                check_range(table, key, value, 1, u32::MAX)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_max_buffer_bytes(table: &str, value: Option<u64>) -> Result<()> {
+    match value {
+        Some(bytes) if bytes < MIN_MAX_BUFFER_BYTES => bail!(
+            "{table}.max_buffer_bytes = {bytes} is too small (minimum {MIN_MAX_BUFFER_BYTES})"
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn check_spill_file_bytes(value: Option<u64>) -> Result<()> {
+    match value {
+        Some(bytes) if bytes < MIN_SPILL_FILE_BYTES => bail!(
+            "global.spill_file_bytes = {bytes} is too small (minimum {MIN_SPILL_FILE_BYTES})"
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// A piece of a clip name template: literal text or a `{placeholder}` name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplatePart<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Splits a clip name template into literal text and placeholders, rejecting
+/// unclosed braces and placeholders not in [`CLIP_TEMPLATE_PLACEHOLDERS`].
+pub fn parse_clip_template(template: &str) -> Result<Vec<TemplatePart<'_>>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(TemplatePart::Text(&rest[..open]));
+        }
+        let Some(len) = rest[open..].find('}') else {
+            bail!("unclosed '{{' at \"{}\"", &rest[open..]);
+        };
+        let name = &rest[open + 1..open + len];
+        if !CLIP_TEMPLATE_PLACEHOLDERS.contains(&name) {
+            let known: Vec<String> =
+                CLIP_TEMPLATE_PLACEHOLDERS.iter().map(|p| format!("{{{p}}}")).collect();
+            bail!("unknown placeholder {{{name}}} (expected one of {})", known.join(", "));
+        }
+        parts.push(TemplatePart::Placeholder(name));
+        rest = &rest[open + len + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest));
+    }
+    Ok(parts)
+}
+
+fn check_clip_templates(global: &GlobalConfig) -> Result<()> {
+    let invalid = |key: &str, template: &str, reason: String| {
+        anyhow::anyhow!("global.{key} = {template:?} is invalid: {reason}")
+    };
+
+    let filename = &global.clip_filename_template;
+    let parts = parse_clip_template(filename)
+        .map_err(|e| invalid("clip_filename_template", filename, e.to_string()))?;
+    if parts.is_empty() {
+        return Err(invalid("clip_filename_template", filename, "it is empty".into()));
+    }
+    if filename.contains(['/', '\\']) {
+        let reason = "use clip_dir_template for subdirectories".into();
+        return Err(invalid("clip_filename_template", filename, reason));
+    }
+
+    let dir = &global.clip_dir_template;
+    let parts = parse_clip_template(dir)
+        .map_err(|e| invalid("clip_dir_template", dir, e.to_string()))?;
+    if parts.contains(&TemplatePart::Placeholder("counter")) {
+        let reason = "{counter} is only available in clip_filename_template".into();
+        return Err(invalid("clip_dir_template", dir, reason));
+    }
+    if dir.split(['/', '\\']).any(|component| component.trim() == "..") {
+        return Err(invalid("clip_dir_template", dir, "'..' is not allowed".into()));
+    }
+    Ok(())
+}
+
+fn check_thumbnail_width(value: u32) -> Result<()> {
+    if value == 0 {
+        return Ok(());
+    }
+    check_range("global", "thumbnail_width", value, MIN_THUMBNAIL_WIDTH, MAX_THUMBNAIL_WIDTH)
+}
+
+fn check_range(table: &str, key: &str, value: u32, min: u32, max: u32) -> Result<()> {
+    if !(min..=max).contains(&value) {
+        bail!("{table}.{key} = {value} is out of range (expected {min}–{max})");
+    }
+    Ok(())
+}
+
 /// Configuration entry for a single monitored game application.
 #[derive(Debug, Deserialize, Clone)]
 pub struct ApplicationConfig {
@@ -65,8 +541,22 @@ pub struct ApplicationConfig {
     pub executable_name: String,
     /// Overrides the global buffer length for this application (seconds).
     pub buffer_length_secs: Option<u32>,
+    /// Overrides the global ring-buffer byte budget for this application.
+    pub max_buffer_bytes: Option<u64>,
+    /// Overrides the global post-roll length for this application (seconds).
+    pub post_roll_secs: Option<u32>,
     /// Overrides the global hotkey for this application.
     pub hotkey: Option<String>,
+    /// Overrides individual `[global.video]` settings for this application.
+    #[serde(default)]
+    pub video: VideoOverrides,
+    /// Overrides individual `[global.audio]` settings for this application.
+    #[serde(default)]
+    pub audio: AudioOverrides,
+    /// Limits on this application's clips, enforced in addition to
+    /// `[global.retention]`.
+    #[serde(default)]
+    pub retention: RetentionConfig,
 }
 
 impl ApplicationConfig {
@@ -76,10 +566,104 @@ impl ApplicationConfig {
         raw.clamp(MIN_BUFFER_LENGTH_SECS, MAX_BUFFER_LENGTH_SECS)
     }
 
+    /// Returns the effective byte budget, falling back to the global config.
+    pub fn effective_max_buffer_bytes(&self, global: &GlobalConfig) -> Option<u64> {
+        self.max_buffer_bytes.or(global.max_buffer_bytes)
+    }
+
+    /// Returns the size of the disk spill file, or `None` if the effective
+    /// buffer fits in memory.  Unless `spill_file_bytes` is set, the size is
+    /// estimated from the effective bitrates with 25% headroom (VBR streams
+    /// are assumed to average 1.5× their target).
+    pub fn effective_spill_file_bytes(&self, global: &GlobalConfig) -> Option<u64> {
+        let secs = self.effective_buffer_length(global).saturating_sub(MAX_MEMORY_BUFFER_SECS);
+        if secs == 0 {
+            return None;
+        }
+        if let Some(bytes) = global.spill_file_bytes {
+            return Some(bytes);
+        }
+        let video = self.effective_video(global);
+        let video_kbps = match video.rate_control {
+            RateControl::Cbr => u64::from(video.bitrate_kbps),
+            RateControl::Vbr => u64::from(video.bitrate_kbps) * 3 / 2,
+            RateControl::Cqp => CQP_SPILL_ESTIMATE_KBPS,
+        };
+        let kbps = video_kbps + u64::from(self.effective_audio(global).bitrate_kbps);
+        let bytes = u64::from(secs) * kbps * 1000 / 8;
+        Some((bytes + bytes / 4).max(MIN_SPILL_FILE_BYTES))
+    }
+
+    /// Returns the effective post-roll length, falling back to the global config.
+    pub fn effective_post_roll(&self, global: &GlobalConfig) -> u32 {
+        self.post_roll_secs.unwrap_or(global.post_roll_secs)
+    }
+
     /// Returns the effective hotkey, falling back to the global config.
     pub fn effective_hotkey<'a>(&'a self, global: &'a GlobalConfig) -> &'a str {
         self.hotkey.as_deref().unwrap_or(&global.hotkey)
     }
+
+    /// Returns the video settings with this application's overrides applied.
+    /// A GOP length inherited from the global config is only kept if it was set
+    /// explicitly; otherwise it follows the effective fps.  Output size is
+    /// overridden as a whole: an application that sets `scale` drops the
+    /// global `output_width`/`output_height` and vice versa.
+    pub fn effective_video(&self, global: &GlobalConfig) -> VideoConfig {
+        let g = &global.video;
+        let o = &self.video;
+        let (output_width, output_height, scale) =
+            if o.scale.is_some() || o.output_width.is_some() || o.output_height.is_some() {
+                (o.output_width, o.output_height, o.scale)
+            } else {
+                (g.output_width, g.output_height, g.scale)
+            };
+        VideoConfig {
+            fps: o.fps.unwrap_or(g.fps),
+            bitrate_kbps: o.bitrate_kbps.unwrap_or(g.bitrate_kbps),
+            rate_control: o.rate_control.unwrap_or(g.rate_control),
+            qp: o.qp.unwrap_or(g.qp),
+            preset: o.preset.unwrap_or(g.preset),
+            gop_length: o.gop_length.or(g.gop_length),
+            output_width,
+            output_height,
+            scale,
+            fit: o.fit.unwrap_or(g.fit),
+        }
+    }
+
+    /// Returns the audio settings with this application's overrides applied.
+    pub fn effective_audio(&self, global: &GlobalConfig) -> AudioConfig {
+        AudioConfig {
+            bitrate_kbps: self.audio.bitrate_kbps.unwrap_or(global.audio.bitrate_kbps),
+        }
+    }
+}
+
+impl Config {
+    /// Validates the global settings and every application's effective
+    /// settings, naming the offending table and key in the error.
+    pub fn validate(&self) -> Result<()> {
+        check_max_buffer_bytes("global", self.global.max_buffer_bytes)?;
+        check_spill_file_bytes(self.global.spill_file_bytes)?;
+        check_clip_templates(&self.global)?;
+        check_range("global", "post_roll_secs", self.global.post_roll_secs, 0, MAX_POST_ROLL_SECS)?;
+        check_thumbnail_width(self.global.thumbnail_width)?;
+        self.global.video.validate("global.video")?;
+        self.global.audio.validate("global.audio")?;
+        self.global.retention.validate("global.retention")?;
+        for app in &self.applications {
+            let table = format!("applications[\"{}\"]", app.display_name);
+            check_max_buffer_bytes(&table, app.max_buffer_bytes)?;
+            if let Some(secs) = app.post_roll_secs {
+                check_range(&table, "post_roll_secs", secs, 0, MAX_POST_ROLL_SECS)?;
+            }
+            app.effective_video(&self.global).validate(&format!("{table}.video"))?;
+            app.effective_audio(&self.global).validate(&format!("{table}.audio"))?;
+            app.retention.validate(&format!("{table}.retention"))?;
+        }
+        Ok(())
+    }
 }
 
 /// Loads the config file at `path`, returning `Config::default()` if the file does not exist.
@@ -90,8 +674,12 @@ pub fn load_or_default(path: &Path) -> Result<Config> {
     }
     let content = std::fs::read_to_string(path)
         .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-    toml::from_str(&content)
-        .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    let config: Config = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    config
+        .validate()
+        .with_context(|| format!("Invalid config file: {}", path.display()))?;
+    Ok(config)
 }
 
 /// Spawns a file watcher on the parent directory of `path`.  Whenever the config
@@ -143,7 +731,7 @@ pub async fn watch_config(path: PathBuf, tx: mpsc::Sender<DaemonEvent>) {
                         break;
                     }
                 }
-                Err(e) => eprintln!("[config] Failed to reload config: {e}"),
+                Err(e) => eprintln!("[config] Failed to reload config: {e:#}"),
             }
         }
     }
@@ -161,6 +749,38 @@ fn default_clip_output_dir() -> String {
     DEFAULT_CLIP_OUTPUT_DIR.to_string()
 }
 
+fn default_clip_filename_template() -> String {
+    DEFAULT_CLIP_FILENAME_TEMPLATE.to_string()
+}
+
+fn default_clip_dir_template() -> String {
+    DEFAULT_CLIP_DIR_TEMPLATE.to_string()
+}
+
+fn default_fps() -> u32 {
+    DEFAULT_FPS
+}
+
+fn default_video_bitrate_kbps() -> u32 {
+    DEFAULT_VIDEO_BITRATE_KBPS
+}
+
+fn default_qp() -> u32 {
+    DEFAULT_QP
+}
+
+fn default_audio_bitrate_kbps() -> u32 {
+    DEFAULT_AUDIO_BITRATE_KBPS
+}
+
+fn default_thumbnail_width() -> u32 {
+    DEFAULT_THUMBNAIL_WIDTH
+}
+
+fn default_disk_space_warning_bytes() -> u64 {
+    DEFAULT_DISK_SPACE_WARNING_BYTES
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,8 +788,20 @@ mod tests {
     fn make_global(buffer_secs: u32) -> GlobalConfig {
         GlobalConfig {
             buffer_length_secs: buffer_secs,
+            max_buffer_bytes: None,
+            spill_file_bytes: None,
+            post_roll_secs: 0,
             hotkey: "F8".to_string(),
             clip_output_dir: DEFAULT_CLIP_OUTPUT_DIR.to_string(),
+            clip_filename_template: DEFAULT_CLIP_FILENAME_TEMPLATE.to_string(),
+            clip_dir_template: DEFAULT_CLIP_DIR_TEMPLATE.to_string(),
+            container: ContainerFormat::Mp4,
+            thumbnail_width: DEFAULT_THUMBNAIL_WIDTH,
+            disk_space_warning_bytes: DEFAULT_DISK_SPACE_WARNING_BYTES,
+            encoder: EncoderKind::Auto,
+            video: VideoConfig::default(),
+            audio: AudioConfig::default(),
+            retention: RetentionConfig::default(),
         }
     }
 
@@ -178,10 +810,22 @@ mod tests {
             display_name: "Test Game".to_string(),
             executable_name: "game.exe".to_string(),
             buffer_length_secs: buffer_override,
+            max_buffer_bytes: None,
+            post_roll_secs: None,
             hotkey: hotkey_override.map(|s| s.to_string()),
+            video: VideoOverrides::default(),
+            audio: AudioOverrides::default(),
+            retention: RetentionConfig::default(),
         }
     }
 
+    fn load_str(content: &str) -> Result<Config> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        load_or_default(&path)
+    }
+
     // ── defaults ──────────────────────────────────────────────────────────────
 
     #[test]
@@ -190,6 +834,7 @@ mod tests {
         assert_eq!(g.buffer_length_secs, DEFAULT_BUFFER_LENGTH_SECS);
         assert_eq!(g.hotkey, DEFAULT_HOTKEY);
         assert_eq!(g.clip_output_dir, DEFAULT_CLIP_OUTPUT_DIR);
+        assert_eq!(g.encoder, EncoderKind::Auto);
     }
 
     #[test]
@@ -224,7 +869,7 @@ mod tests {
     #[test]
     fn effective_buffer_length_clamps_above_max() {
         let global = make_global(DEFAULT_BUFFER_LENGTH_SECS);
-        let app = make_app(Some(999), None);
+        let app = make_app(Some(9999), None);
         assert_eq!(app.effective_buffer_length(&global), MAX_BUFFER_LENGTH_SECS);
     }
 
@@ -323,6 +968,24 @@ executable_name = "RocketLeague.exe"
         assert!(load_or_default(&path).is_err());
     }
 
+    #[test]
+    fn load_or_default_parses_encoder_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[global]\nencoder = \"x264\"\n").unwrap();
+        let config = load_or_default(&path).unwrap();
+        assert_eq!(config.global.encoder, EncoderKind::X264);
+        assert_eq!(config.global.encoder.as_str(), "x264");
+    }
+
+    #[test]
+    fn load_or_default_rejects_unknown_encoder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[global]\nencoder = \"vaapi\"\n").unwrap();
+        assert!(load_or_default(&path).is_err());
+    }
+
     #[test]
     fn load_or_default_app_with_overrides() {
         let dir = tempfile::tempdir().unwrap();
@@ -344,4 +1007,384 @@ hotkey = "F7"
         assert_eq!(app.buffer_length_secs, Some(30));
         assert_eq!(app.hotkey.as_deref(), Some("F7"));
     }
+
+    // ── max_buffer_bytes ──────────────────────────────────────────────────────
+
+    #[test]
+    fn effective_max_buffer_bytes_prefers_app_override() {
+        let mut global = make_global(DEFAULT_BUFFER_LENGTH_SECS);
+        global.max_buffer_bytes = Some(512 << 20);
+        let mut app = make_app(None, None);
+        assert_eq!(app.effective_max_buffer_bytes(&global), Some(512 << 20));
+        app.max_buffer_bytes = Some(256 << 20);
+        assert_eq!(app.effective_max_buffer_bytes(&global), Some(256 << 20));
+        assert_eq!(make_app(None, None).effective_max_buffer_bytes(&make_global(15)), None);
+    }
+
+    #[test]
+    fn load_or_default_rejects_tiny_max_buffer_bytes() {
+        let err = load_str("[global]\nmax_buffer_bytes = 1000\n").unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains("global.max_buffer_bytes = 1000"), "{msg}");
+        let config = load_str("[global]\nmax_buffer_bytes = 268435456\n").unwrap();
+        assert_eq!(config.global.max_buffer_bytes, Some(256 << 20));
+    }
+
+    // ── spill_file_bytes ──────────────────────────────────────────────────────
+
+    #[test]
+    fn short_buffers_do_not_spill() {
+        let global = make_global(MAX_MEMORY_BUFFER_SECS);
+        assert_eq!(make_app(None, None).effective_spill_file_bytes(&global), None);
+    }
+
+    #[test]
+    fn spill_file_size_is_estimated_from_bitrates() {
+        // 600 s buffer: 480 s on disk at 8000 × 1.5 + 192 kbps, plus 25%.
+        let global = make_global(600);
+        let expected = 480 * 12_192 * 1000 / 8 * 5 / 4;
+        assert_eq!(make_app(None, None).effective_spill_file_bytes(&global), Some(expected));
+
+        let mut cbr = make_global(600);
+        cbr.video.rate_control = RateControl::Cbr;
+        let expected = 480 * 8_192 * 1000 / 8 * 5 / 4;
+        assert_eq!(make_app(None, None).effective_spill_file_bytes(&cbr), Some(expected));
+    }
+
+    #[test]
+    fn explicit_spill_file_bytes_wins() {
+        let mut global = make_global(600);
+        global.spill_file_bytes = Some(1 << 30);
+        assert_eq!(make_app(None, None).effective_spill_file_bytes(&global), Some(1 << 30));
+        // An application that shortens its buffer to fit in memory does not spill.
+        assert_eq!(make_app(Some(60), None).effective_spill_file_bytes(&global), None);
+    }
+
+    // ── post_roll_secs ────────────────────────────────────────────────────────
+
+    #[test]
+    fn effective_post_roll_prefers_app_override() {
+        let mut global = make_global(15);
+        assert_eq!(make_app(None, None).effective_post_roll(&global), 0);
+        global.post_roll_secs = 5;
+        let mut app = make_app(None, None);
+        assert_eq!(app.effective_post_roll(&global), 5);
+        app.post_roll_secs = Some(0);
+        assert_eq!(app.effective_post_roll(&global), 0);
+    }
+
+    #[test]
+    fn load_or_default_rejects_long_post_roll() {
+        let err = load_str("[global]\npost_roll_secs = 61\n").unwrap_err();
+        assert!(format!("{err:#}").contains("global.post_roll_secs = 61"));
+        let err = load_str(
+            r#"
+[[applications]]
+display_name = "Fortnite"
+executable_name = "Fortnite.exe"
+post_roll_secs = 90
+"#,
+        )
+        .unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains(r#"applications["Fortnite"].post_roll_secs = 90"#), "{msg}");
+    }
+
+    // ── thumbnail_width ───────────────────────────────────────────────────────
+
+    #[test]
+    fn thumbnail_width_defaults_and_can_be_disabled() {
+        assert_eq!(load_str("").unwrap().global.thumbnail_width, DEFAULT_THUMBNAIL_WIDTH);
+        assert_eq!(load_str("[global]\nthumbnail_width = 0\n").unwrap().global.thumbnail_width, 0);
+        let err = load_str("[global]\nthumbnail_width = 32\n").unwrap_err();
+        assert!(format!("{err:#}").contains("global.thumbnail_width = 32"));
+    }
+
+    #[test]
+    fn disk_space_warning_defaults_to_5_gib() {
+        let config = load_str("").unwrap();
+        assert_eq!(config.global.disk_space_warning_bytes, 5 << 30);
+        let config = load_str("[global]\ndisk_space_warning_bytes = 0\n").unwrap();
+        assert_eq!(config.global.disk_space_warning_bytes, 0);
+    }
+
+    #[test]
+    fn load_or_default_rejects_tiny_spill_file_bytes() {
+        let err = load_str("[global]\nspill_file_bytes = 1000\n").unwrap_err();
+        assert!(format!("{err:#}").contains("global.spill_file_bytes = 1000"));
+    }
+
+    // ── video / audio settings ────────────────────────────────────────────────
+
+    #[test]
+    fn video_and_audio_defaults() {
+        let g = GlobalConfig::default();
+        assert_eq!(g.video.fps, DEFAULT_FPS);
+        assert_eq!(g.video.bitrate_kbps, DEFAULT_VIDEO_BITRATE_KBPS);
+        assert_eq!(g.video.rate_control, RateControl::Vbr);
+        assert_eq!(g.video.preset, EncoderPreset::Balanced);
+        assert_eq!(g.video.effective_gop_length(), DEFAULT_FPS);
+        assert_eq!(g.audio.bitrate_kbps, DEFAULT_AUDIO_BITRATE_KBPS);
+    }
+
+    #[test]
+    fn load_or_default_parses_video_and_audio_tables() {
+        let config = load_str(
+            r#"
+[global.video]
+fps = 30
+bitrate_kbps = 12000
+rate_control = "cqp"
+qp = 20
+preset = "quality"
+gop_length = 60
+
+[global.audio]
+bitrate_kbps = 256
+"#,
+        )
+        .unwrap();
+        let v = &config.global.video;
+        assert_eq!(v.fps, 30);
+        assert_eq!(v.bitrate_kbps, 12_000);
+        assert_eq!(v.rate_control, RateControl::Cqp);
+        assert_eq!(v.qp, 20);
+        assert_eq!(v.preset, EncoderPreset::Quality);
+        assert_eq!(v.effective_gop_length(), 60);
+        assert_eq!(config.global.audio.bitrate_kbps, 256);
+    }
+
+    #[test]
+    fn effective_video_uses_app_overrides() {
+        let global = make_global(DEFAULT_BUFFER_LENGTH_SECS);
+        let mut app = make_app(None, None);
+        app.video.fps = Some(144);
+        app.video.rate_control = Some(RateControl::Cbr);
+        app.audio.bitrate_kbps = Some(128);
+
+        let video = app.effective_video(&global);
+        assert_eq!(video.fps, 144);
+        assert_eq!(video.rate_control, RateControl::Cbr);
+        assert_eq!(video.bitrate_kbps, DEFAULT_VIDEO_BITRATE_KBPS);
+        assert_eq!(video.effective_gop_length(), 144, "GOP follows the overridden fps");
+        assert_eq!(app.effective_audio(&global).bitrate_kbps, 128);
+    }
+
+    #[test]
+    fn effective_video_falls_back_to_global() {
+        let mut global = make_global(DEFAULT_BUFFER_LENGTH_SECS);
+        global.video.preset = EncoderPreset::Speed;
+        global.video.gop_length = Some(120);
+        let app = make_app(None, None);
+        let video = app.effective_video(&global);
+        assert_eq!(video.preset, EncoderPreset::Speed);
+        assert_eq!(video.effective_gop_length(), 120);
+    }
+
+    #[test]
+    fn load_or_default_rejects_out_of_range_global_fps() {
+        let err = load_str("[global.video]\nfps = 500\n").unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains("global.video.fps = 500"), "{msg}");
+        assert!(msg.contains("1–240"), "{msg}");
+    }
+
+    #[test]
+    fn load_or_default_rejects_out_of_range_app_override() {
+        let err = load_str(
+            r#"
+[[applications]]
+display_name = "Fortnite"
+executable_name = "Fortnite.exe"
+
+[applications.audio]
+bitrate_kbps = 4
+"#,
+        )
+        .unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains(r#"applications["Fortnite"].audio.bitrate_kbps = 4"#), "{msg}");
+    }
+
+    #[test]
+    fn validate_limits_gop_length_by_fps() {
+        let video = VideoConfig { fps: 30, gop_length: Some(301), ..VideoConfig::default() };
+        let msg = video.validate("global.video").unwrap_err().to_string();
+        assert!(msg.contains("gop_length = 301"), "{msg}");
+        let video = VideoConfig { fps: 30, gop_length: Some(300), ..VideoConfig::default() };
+        assert!(video.validate("global.video").is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_qp_above_51() {
+        let video = VideoConfig { qp: 52, ..VideoConfig::default() };
+        assert!(video.validate("global.video").is_err());
+    }
+
+    #[test]
+    fn load_or_default_rejects_unknown_rate_control() {
+        assert!(load_str("[global.video]\nrate_control = \"abr\"\n").is_err());
+    }
+
+    // ── output scaling ────────────────────────────────────────────────────────
+
+    #[test]
+    fn load_or_default_parses_output_size() {
+        let config = load_str(
+            "[global.video]\noutput_width = 1920\noutput_height = 1080\nfit = \"crop\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.global.video.output_width, Some(1920));
+        assert_eq!(config.global.video.output_height, Some(1080));
+        assert_eq!(config.global.video.fit, ScaleFit::Crop);
+        assert_eq!(GlobalConfig::default().video.fit, ScaleFit::Letterbox);
+    }
+
+    #[test]
+    fn validate_rejects_scale_out_of_range() {
+        let video = VideoConfig { scale: Some(1.5), ..VideoConfig::default() };
+        let msg = video.validate("global.video").unwrap_err().to_string();
+        assert!(msg.contains("global.video.scale = 1.5"), "{msg}");
+    }
+
+    #[test]
+    fn validate_rejects_scale_with_output_size() {
+        let video = VideoConfig { scale: Some(0.5), output_width: Some(1280), ..VideoConfig::default() };
+        let msg = video.validate("global.video").unwrap_err().to_string();
+        assert!(msg.contains("not both"), "{msg}");
+    }
+
+    #[test]
+    fn validate_rejects_tiny_output_width() {
+        let video = VideoConfig { output_width: Some(8), ..VideoConfig::default() };
+        assert!(video.validate("global.video").is_err());
+    }
+
+    #[test]
+    fn app_scale_override_replaces_global_output_size() {
+        let mut global = make_global(DEFAULT_BUFFER_LENGTH_SECS);
+        global.video.output_width = Some(1280);
+        global.video.output_height = Some(720);
+        let mut app = make_app(None, None);
+        app.video.scale = Some(0.5);
+
+        let video = app.effective_video(&global);
+        assert_eq!((video.output_width, video.output_height, video.scale), (None, None, Some(0.5)));
+        assert!(video.validate("applications.video").is_ok());
+
+        let inherited = make_app(None, None).effective_video(&global);
+        assert_eq!((inherited.output_width, inherited.output_height), (Some(1280), Some(720)));
+    }
+
+    // ── container ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn load_or_default_parses_container() {
+        let config = load_str("[global]\ncontainer = \"mkv\"\n").unwrap();
+        assert_eq!(config.global.container, ContainerFormat::Mkv);
+        assert_eq!(config.global.container.extension(), "mkv");
+        assert_eq!(GlobalConfig::default().container, ContainerFormat::Mp4);
+    }
+
+    #[test]
+    fn load_or_default_rejects_unknown_container() {
+        assert!(load_str("[global]\ncontainer = \"avi\"\n").is_err());
+    }
+
+    #[test]
+    fn fragmented_mp4_uses_mp4_extension() {
+        let config = load_str("[global]\ncontainer = \"fmp4\"\n").unwrap();
+        assert_eq!(config.global.container, ContainerFormat::Fmp4);
+        assert_eq!(config.global.container.extension(), "mp4");
+    }
+
+    // ── clip name templates ───────────────────────────────────────────────────
+
+    #[test]
+    fn clip_templates_default_to_timestamp_in_game_dir() {
+        let config = load_str("[global]\nhotkey = \"F8\"\n").unwrap();
+        assert_eq!(config.global.clip_filename_template, "{date}_{time}");
+        assert_eq!(config.global.clip_dir_template, "{game}");
+    }
+
+    #[test]
+    fn parse_clip_template_splits_text_and_placeholders() {
+        let parts = parse_clip_template("{game} - {date}!").unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                TemplatePart::Placeholder("game"),
+                TemplatePart::Text(" - "),
+                TemplatePart::Placeholder("date"),
+                TemplatePart::Text("!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_or_default_rejects_unknown_placeholder() {
+        let config = load_str("[global]\nclip_filename_template = \"{date}_{player}\"\n");
+        let msg = format!("{:#}", config.unwrap_err());
+        assert!(msg.contains("global.clip_filename_template"), "{msg}");
+        assert!(msg.contains("{player}"), "{msg}");
+    }
+
+    #[test]
+    fn load_or_default_rejects_unclosed_brace() {
+        assert!(load_str("[global]\nclip_filename_template = \"{date\"\n").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_separators_in_filename_template() {
+        let mut config = Config::default();
+        config.global.clip_filename_template = "{game}/{date}".into();
+        let msg = config.validate().unwrap_err().to_string();
+        assert!(msg.contains("clip_dir_template"), "{msg}");
+        config.global.clip_filename_template = String::new();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_checks_dir_template() {
+        let mut config = Config::default();
+        config.global.clip_dir_template = "{game}/{date}".into();
+        assert!(config.validate().is_ok());
+        config.global.clip_dir_template = "{game}/{counter}".into();
+        assert!(config.validate().is_err());
+        config.global.clip_dir_template = "{game}/../{date}".into();
+        assert!(config.validate().is_err());
+    }
+
+    // ── retention ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn load_or_default_parses_retention_tables() {
+        let config = load_str(
+            r#"
+[global.retention]
+max_total_bytes = 53687091200
+max_age_days = 30
+
+[[applications]]
+display_name = "Game"
+executable_name = "game.exe"
+
+[applications.retention]
+max_count = 20
+"#,
+        )
+        .unwrap();
+        let global = config.global.retention;
+        assert_eq!(global.max_total_bytes, Some(50 << 30));
+        assert_eq!((global.max_count, global.max_age_days), (None, Some(30)));
+        assert_eq!(config.applications[0].retention.max_count, Some(20));
+        assert!(GlobalConfig::default().retention.is_unlimited());
+    }
+
+    #[test]
+    fn load_or_default_rejects_zero_retention_limits() {
+        let msg = format!("{:#}", load_str("[global.retention]\nmax_count = 0\n").unwrap_err());
+        assert!(msg.contains("global.retention.max_count = 0"), "{msg}");
+        assert!(load_str("[global.retention]\nmax_total_bytes = 1024\n").is_err());
+    }
 }
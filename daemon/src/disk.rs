@@ -0,0 +1,240 @@
+/// Free space on the clip drive.
+///
+/// Before a clip is written, [`ensure_space`] compares its estimated size
+/// ([`estimate_clip_bytes`]: the buffered packet bytes plus room for the
+/// container index, sidecar and thumbnail) with the space left on the target
+/// volume, so a full drive fails the flush up front with a "Disk full" error
+/// instead of part-way through the write.
+///
+/// [`DiskMonitor`] checks the drive holding `clip_output_dir` every minute
+/// and after every flush, and reports it as [`DaemonEvent::DiskSpaceChecked`];
+/// status.toml carries a warning while less than `disk_space_warning_bytes`
+/// is free.
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify, RwLock};
+
+use crate::config::Config;
+use crate::event::DaemonEvent;
+use crate::flush;
+use crate::ring_buffer::EpochSegments;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Container index bytes allowed per packet (MP4 sample tables or Matroska
+/// block headers, with margin).
+const INDEX_BYTES_PER_PACKET: u64 = 32;
+/// Allowance for file headers, the sidecar and the thumbnail.
+const CLIP_OVERHEAD_BYTES: u64 = 1 << 20;
+
+// ── Queries ────────────────────────────────────────────────────────────────────
+
+/// Returns the bytes available to the daemon on the volume holding `path`.
+/// `path` need not exist yet; its nearest existing ancestor is queried.
+pub fn available_space(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .with_context(|| format!("{} is not on a mounted drive", path.display()))?;
+    query_available(existing)
+        .with_context(|| format!("Failed to query free space for {}", path.display()))
+}
+
+#[cfg(windows)]
+fn query_available(path: &Path) -> Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut available = 0u64;
+    unsafe {
+        GetDiskFreeSpaceExW(PCWSTR(wide.as_ptr()), Some(&mut available as *mut u64), None, None)?
+    };
+    Ok(available)
+}
+
+/// Picks the mounted disk with the longest mount point containing `path`.
+#[cfg(not(windows))]
+fn query_available(path: &Path) -> Result<u64> {
+    let path = path.canonicalize()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+        .context("No mounted disk contains it")
+}
+
+// ── Pre-flush check ────────────────────────────────────────────────────────────
+
+/// Estimates the bytes needed to save `parts`, one file per part.
+pub fn estimate_clip_bytes(parts: &[EpochSegments]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| &part.segments)
+        .map(|segment| {
+            let packets = (segment.video_packets.len() + segment.audio_packets.len()) as u64;
+            segment.size_bytes() + packets * INDEX_BYTES_PER_PACKET
+        })
+        .sum::<u64>()
+        + parts.len() as u64 * CLIP_OVERHEAD_BYTES
+}
+
+/// Fails with a "Disk full" error if `needed` bytes do not fit in `available`.
+fn check_space(dir: &Path, needed: u64, available: u64) -> Result<()> {
+    if available < needed {
+        bail!(
+            "Disk full: saving this clip needs {} MiB but only {} MiB is free for {}",
+            needed.div_ceil(1 << 20),
+            available >> 20,
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Fails with a "Disk full" error if the volume holding `dir` has less than
+/// `needed` bytes free.  If free space cannot be determined the flush goes
+/// ahead.
+pub fn ensure_space(dir: &Path, needed: u64) -> Result<()> {
+    match available_space(dir) {
+        Ok(available) => check_space(dir, needed, available),
+        Err(e) => {
+            eprintln!("[disk] {e:#}; saving without a space check");
+            Ok(())
+        }
+    }
+}
+
+// ── Low-space warning ──────────────────────────────────────────────────────────
+
+/// Free space on the clip drive against the configured warning threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    pub free_bytes: u64,
+    /// `disk_space_warning_bytes`; 0 disables the warning.
+    pub warning_bytes: u64,
+}
+
+impl DiskSpace {
+    pub fn is_low(&self) -> bool {
+        self.free_bytes < self.warning_bytes
+    }
+
+    /// The status.toml warning while space is low.
+    pub fn warning(&self) -> Option<String> {
+        self.is_low().then(|| {
+            format!(
+                "Low disk space: {} MiB free on the clip drive (warning below {} MiB)",
+                self.free_bytes >> 20,
+                self.warning_bytes >> 20
+            )
+        })
+    }
+}
+
+/// Handle to the background disk-space task.
+pub struct DiskMonitor {
+    wake: Arc<Notify>,
+}
+
+impl DiskMonitor {
+    /// Spawns the task, which checks straight away and then every minute.
+    pub fn start(config: Arc<RwLock<Config>>, tx: mpsc::Sender<DaemonEvent>) -> Self {
+        let wake = Arc::new(Notify::new());
+        tokio::spawn(run(config, tx, Arc::clone(&wake)));
+        Self { wake }
+    }
+
+    /// Asks for a check now, e.g. after a clip was saved.
+    pub fn request(&self) {
+        self.wake.notify_one();
+    }
+}
+
+async fn run(config: Arc<RwLock<Config>>, tx: mpsc::Sender<DaemonEvent>, wake: Arc<Notify>) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = wake.notified() => {}
+        }
+        let (root, warning_bytes) = {
+            let global = &config.read().await.global;
+            (flush::clip_root(&global.clip_output_dir), global.disk_space_warning_bytes)
+        };
+        let free = tokio::task::spawn_blocking(move || available_space(&root)).await;
+        let space = match free {
+            Ok(Ok(free_bytes)) => DiskSpace { free_bytes, warning_bytes },
+            Ok(Err(e)) => {
+                eprintln!("[disk] {e:#}");
+                continue;
+            }
+            Err(e) => {
+                eprintln!("[disk] Check panicked: {e}");
+                continue;
+            }
+        };
+        if tx.send(DaemonEvent::DiskSpaceChecked(space)).await.is_err() {
+            break;
+        }
+    }
+}
+
+// ── Tests ──────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{make_part, packet};
+
+    /// One segment of `packets` video packets of `packet_bytes` bytes each.
+    fn part(packet_bytes: usize, packets: usize) -> EpochSegments {
+        let mut part = make_part(1920);
+        let segment = Arc::make_mut(&mut part.segments[0]);
+        segment.video_packets = vec![packet(vec![0; packet_bytes], 0, 1, true); packets];
+        segment.audio_packets.clear();
+        part
+    }
+
+    #[test]
+    fn estimate_covers_payload_index_and_overhead() {
+        let parts = [part(1000, 10), part(500, 4)];
+        let payload = 1000 * 10 + 500 * 4;
+        let expected = payload + 14 * INDEX_BYTES_PER_PACKET + 2 * CLIP_OVERHEAD_BYTES;
+        assert_eq!(estimate_clip_bytes(&parts), expected);
+    }
+
+    #[test]
+    fn check_space_reports_disk_full() {
+        let dir = Path::new("clips");
+        assert!(check_space(dir, 10 << 20, 10 << 20).is_ok());
+        let err = check_space(dir, 10 << 20, 3 << 20).unwrap_err().to_string();
+        assert!(err.starts_with("Disk full"), "{err}");
+        assert!(err.contains("needs 10 MiB but only 3 MiB is free"), "{err}");
+    }
+
+    #[test]
+    fn warning_only_below_threshold() {
+        let space = DiskSpace { free_bytes: 1 << 30, warning_bytes: 5 << 30 };
+        assert_eq!(
+            space.warning().as_deref(),
+            Some("Low disk space: 1024 MiB free on the clip drive (warning below 5120 MiB)")
+        );
+        assert_eq!(DiskSpace { free_bytes: 6 << 30, ..space }.warning(), None);
+        assert_eq!(DiskSpace { warning_bytes: 0, ..space }.warning(), None);
+    }
+
+    #[test]
+    fn available_space_walks_up_to_an_existing_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("not").join("yet");
+        let existing = available_space(dir.path()).unwrap();
+        let missing = available_space(&missing).unwrap();
+        assert!(existing.abs_diff(missing) < 1 << 30);
+    }
+}
@@ -1,5 +1,12 @@
 /// Encoding pipeline: converts raw BGRA video frames and PCM audio samples into
-/// 1-second [`EncodedSegment`]s using NVENC H.264 (with libx264 fallback) and AAC.
+/// one-GOP [`EncodedSegment`]s.
+///
+/// The actual H.264 + AAC encoding is delegated to an [`EncoderBackend`].  One
+/// backend exists per encoder (NVENC, QSV, AMF and libx264 through FFmpeg, plus
+/// the FFmpeg-free [`SoftwareBackend`]); [`probe`] picks one according to the
+/// `[global] encoder` config key and reports why the others were rejected.
+/// [`SegmentEncoder`] sits on top and cuts the packet stream into segments at
+/// each IDR frame.
 ///
 /// # Windows build requirements
 /// Set the `FFMPEG_DIR` environment variable to a static FFmpeg 7.x build with NVENC support
@@ -8,51 +15,210 @@ use anyhow::Result;
 
 use crate::capture::RawFrame;
 use crate::audio_capture::RawAudio;
-use crate::ring_buffer::{AudioCodecParams, EncodedSegment, VideoCodecParams};
-
-const AV_PKT_FLAG_KEY: i32 = 0x0001;
+use crate::config::{AudioConfig, EncoderKind, EncoderPreset, RateControl, VideoConfig};
+use crate::ring_buffer::{AudioCodecParams, EncodedPacket, EncodedSegment, VideoCodecParams};
+use crate::scale::{OutputScaling, ScalePlan};
+use crate::software_encoder::SoftwareBackend;
+use crate::status::EncoderStatus;
 
-/// Parameters used to configure the encoder on start-up.
+/// Parameters used to open an encoder backend.
 #[derive(Debug, Clone)]
 pub struct EncoderConfig {
+    /// Captured frame size the backend is opened for.  [`SegmentEncoder::reconfigure`]
+    /// reopens the backend when captured frames arrive at a different size.
     pub width: u32,
     pub height: u32,
-    /// Target frames-per-second. Also controls GOP size (1 IDR per second).
+    /// Requested encoded size; see [`EncoderConfig::scale_plan`].
+    pub output: OutputScaling,
+    /// Target frames-per-second.
     pub fps: u32,
+    /// Frames between IDRs.  Each IDR starts a new ring-buffer segment.
+    pub gop_length: u32,
     pub sample_rate: u32,
     pub channels: u16,
-    /// Video encode bitrate in bits/s (e.g. 8_000_000 for 8 Mbps).
+    /// Video encode bitrate in bits/s (e.g. 8_000_000 for 8 Mbps).  Ignored for CQP.
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub video_bitrate: i64,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub rate_control: RateControl,
+    /// Quantiser used when `rate_control` is CQP.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub qp: u32,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub preset: EncoderPreset,
     /// Audio encode bitrate in bits/s (e.g. 192_000 for 192 kbps).
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub audio_bitrate: i64,
 }
 
+impl EncoderConfig {
+    /// Builds an encoder config from validated `[global.video]` / `[global.audio]`
+    /// settings (with any per-application overrides already applied).  Frame
+    /// size and audio format keep their defaults until capture reports them.
+    pub fn from_settings(video: &VideoConfig, audio: &AudioConfig) -> Self {
+        Self {
+            fps: video.fps,
+            gop_length: video.effective_gop_length(),
+            video_bitrate: i64::from(video.bitrate_kbps) * 1000,
+            rate_control: video.rate_control,
+            qp: video.qp,
+            preset: video.preset,
+            audio_bitrate: i64::from(audio.bitrate_kbps) * 1000,
+            output: OutputScaling::from_video_config(video),
+            ..Self::default()
+        }
+    }
+
+    /// How captured frames map onto encoded frames.  The encoded size (and
+    /// so `VideoCodecParams::width`/`height`) is `scale_plan().output`.
+    pub fn scale_plan(&self) -> ScalePlan {
+        self.output.plan((self.width, self.height))
+    }
+}
+
 impl Default for EncoderConfig {
     fn default() -> Self {
         Self {
             width: 1920,
             height: 1080,
+            output: OutputScaling::default(),
             fps: 60,
+            gop_length: 60,
             sample_rate: 48_000,
             channels: 2,
             video_bitrate: 8_000_000,
+            rate_control: RateControl::Vbr,
+            qp: 23,
+            preset: EncoderPreset::Balanced,
             audio_bitrate: 192_000,
         }
     }
 }
 
+/// Private FFmpeg options for `kind`, translating the backend-neutral preset
+/// and rate-control settings into each encoder's own vocabulary.  Bitrate
+/// targets are set on the codec context instead (see `FfmpegBackend::open`).
+#[cfg_attr(not(windows), allow(dead_code))]
+fn ffmpeg_options(kind: EncoderKind, config: &EncoderConfig) -> Vec<(&'static str, String)> {
+    let preset = config.preset;
+    let rc = config.rate_control;
+    let qp = config.qp.to_string();
+    let pick = |speed: &str, balanced: &str, quality: &str| {
+        match preset {
+            EncoderPreset::Speed => speed,
+            EncoderPreset::Balanced => balanced,
+            EncoderPreset::Quality => quality,
+        }
+        .to_string()
+    };
+
+    let mut opts = Vec::new();
+    match kind {
+        EncoderKind::Nvenc => {
+            opts.push(("preset", pick("p2", "p4", "p6")));
+            opts.push(("tune", "ull".into()));
+            let mode = match rc {
+                RateControl::Cbr => "cbr",
+                RateControl::Vbr => "vbr",
+                RateControl::Cqp => "constqp",
+            };
+            opts.push(("rc", mode.into()));
+            if rc == RateControl::Cqp {
+                opts.push(("qp", qp));
+            }
+        }
+        EncoderKind::X264 => {
+            opts.push(("preset", pick("ultrafast", "veryfast", "medium")));
+            opts.push(("tune", "zerolatency".into()));
+            match rc {
+                RateControl::Cbr => opts.push(("nal-hrd", "cbr".into())),
+                RateControl::Vbr => {}
+                RateControl::Cqp => opts.push(("qp", qp)),
+            }
+        }
+        EncoderKind::Qsv => {
+            // QSV selects CQP through AV_CODEC_FLAG_QSCALE on the context.
+            opts.push(("preset", pick("veryfast", "medium", "slower")));
+            opts.push(("look_ahead", "0".into()));
+        }
+        EncoderKind::Amf => {
+            opts.push(("usage", "lowlatency".into()));
+            opts.push(("quality", pick("speed", "balanced", "quality")));
+            let mode = match rc {
+                RateControl::Cbr => "cbr",
+                RateControl::Vbr => "vbr_peak",
+                RateControl::Cqp => "cqp",
+            };
+            opts.push(("rc", mode.into()));
+            if rc == RateControl::Cqp {
+                opts.push(("qp_i", qp.clone()));
+                opts.push(("qp_p", qp));
+            }
+        }
+        EncoderKind::Auto | EncoderKind::Software => {}
+    }
+    opts
+}
+
+// ── Backend trait ─────────────────────────────────────────────────────────────
+
+/// A concrete H.264 + AAC encoder.
+///
+/// Backends only turn raw input into packets; segmenting is handled by
+/// [`SegmentEncoder`].  Video packets must use `video_params().time_base` and
+/// audio packets `audio_params().time_base`.
+pub trait EncoderBackend: Send {
+    /// Which backend this is; `kind().as_str()` is written to status.toml.
+    fn kind(&self) -> EncoderKind;
+
+    fn video_params(&self) -> &VideoCodecParams;
+
+    fn audio_params(&self) -> &AudioCodecParams;
+
+    /// Encodes one BGRA frame, appending any packets that became available.
+    /// Fails if the frame size differs from the size the backend was opened for.
+    fn encode_video(&mut self, frame: &RawFrame, out: &mut Vec<EncodedPacket>) -> Result<()>;
+
+    /// Feeds interleaved f32 PCM, appending any packets that became available.
+    fn encode_audio(&mut self, audio: &RawAudio, out: &mut Vec<EncodedPacket>) -> Result<()>;
+
+    /// Signals end of stream and appends every remaining packet.
+    fn flush(&mut self, video_out: &mut Vec<EncodedPacket>, audio_out: &mut Vec<EncodedPacket>) -> Result<()>;
+}
+
 // ── Windows implementation ────────────────────────────────────────────────────
 
 #[cfg(windows)]
 mod imp {
     use anyhow::{bail, Result};
     use ffmpeg_sys_next as ffsys;
+    use std::ffi::CString;
     use std::ptr;
+    use std::sync::Arc;
 
-    use super::{AV_PKT_FLAG_KEY, EncoderConfig};
+    use super::{ffmpeg_options, EncoderBackend, EncoderConfig};
+    use crate::config::{EncoderKind, RateControl};
     use crate::audio_capture::RawAudio;
     use crate::capture::RawFrame;
-    use crate::ring_buffer::{AudioCodecParams, EncodedPacket, EncodedSegment, VideoCodecParams};
+    use crate::ring_buffer::{AudioCodecParams, EncodedPacket, VideoCodecParams};
+    use crate::scale::ScalePlan;
+
+    const AV_PKT_FLAG_KEY: i32 = 0x0001;
+
+    /// FFmpeg's fixed-point scale for quantiser values (`FF_QP2LAMBDA`).
+    const FF_QP2LAMBDA: i32 = 118;
+
+    /// FFmpeg encoder name (NUL-terminated).  Private options come from
+    /// [`ffmpeg_options`].
+    pub struct FfmpegSpec {
+        kind: EncoderKind,
+        codec: &'static [u8],
+    }
+
+    pub const NVENC: FfmpegSpec = FfmpegSpec { kind: EncoderKind::Nvenc, codec: b"h264_nvenc\0" };
+    pub const X264: FfmpegSpec = FfmpegSpec { kind: EncoderKind::X264, codec: b"libx264\0" };
+    pub const QSV: FfmpegSpec = FfmpegSpec { kind: EncoderKind::Qsv, codec: b"h264_qsv\0" };
+    pub const AMF: FfmpegSpec = FfmpegSpec { kind: EncoderKind::Amf, codec: b"h264_amf\0" };
 
     // ── RAII wrappers ─────────────────────────────────────────────────────────
 
@@ -90,6 +256,17 @@ mod imp {
 
     // ── Helpers ───────────────────────────────────────────────────────────────
 
+    /// Formats an FFmpeg error code via `av_strerror`.
+    fn av_error_string(code: i32) -> String {
+        let mut buf = [0 as std::ffi::c_char; 128];
+        unsafe {
+            if ffsys::av_strerror(code, buf.as_mut_ptr(), buf.len()) < 0 {
+                return format!("error code {code}");
+            }
+            std::ffi::CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+        }
+    }
+
     /// Reads `extradata` out of a codec context after `avcodec_open2`.
     unsafe fn read_extradata(ctx: *mut ffsys::AVCodecContext) -> Vec<u8> {
         if (*ctx).extradata.is_null() || (*ctx).extradata_size == 0 {
@@ -119,10 +296,12 @@ mod imp {
             if ret < 0 {
                 break; // AVERROR(EAGAIN) or AVERROR_EOF — normal
             }
-            let data = if (*pkt.0).data.is_null() || (*pkt.0).size == 0 {
-                vec![]
+            // The one copy of the payload: out of FFmpeg's buffer into a
+            // shared one that the ring buffer and flushes reference.
+            let data: Arc<[u8]> = if (*pkt.0).data.is_null() || (*pkt.0).size == 0 {
+                Arc::from([])
             } else {
-                std::slice::from_raw_parts((*pkt.0).data, (*pkt.0).size as usize).to_vec()
+                Arc::from(std::slice::from_raw_parts((*pkt.0).data, (*pkt.0).size as usize))
             };
             out.push(EncodedPacket {
                 data,
@@ -135,43 +314,41 @@ mod imp {
         }
     }
 
-    // ── SegmentEncoderInner ───────────────────────────────────────────────────
+    // ── FfmpegBackend ─────────────────────────────────────────────────────────
 
-    pub struct SegmentEncoderInner {
+    /// An FFmpeg H.264 encoder (selected by [`FfmpegSpec`]) plus the native AAC encoder.
+    pub struct FfmpegBackend {
+        kind: EncoderKind,
         config: EncoderConfig,
+        /// Maps captured frames onto the encoded size.
+        plan: ScalePlan,
 
         video_ctx: CodecCtxGuard,
         sws_ctx: SwsCtxGuard,
         video_frame_count: u64,
-        current_video_packets: Vec<EncodedPacket>,
-        pub video_params: VideoCodecParams,
+        video_params: VideoCodecParams,
 
         audio_ctx: CodecCtxGuard,
         /// Accumulates interleaved f32 PCM samples until we have a full encoder frame.
         audio_sample_buf: Vec<f32>,
         audio_frame_size: usize,
         audio_pts: i64,
-        current_audio_packets: Vec<EncodedPacket>,
-        pub audio_params: AudioCodecParams,
+        audio_params: AudioCodecParams,
     }
 
-    impl SegmentEncoderInner {
-        pub fn new(config: &EncoderConfig) -> Result<Self> {
-            unsafe { Self::new_unsafe(config) }
+    impl FfmpegBackend {
+        /// Opens `spec` and the AAC encoder.  Any failure (encoder not compiled
+        /// in, no compatible GPU, driver refused the settings) is returned as an
+        /// error whose message becomes the rejection reason in status.toml.
+        pub fn open(spec: &FfmpegSpec, config: &EncoderConfig) -> Result<Self> {
+            unsafe { Self::open_unsafe(spec, config) }
         }
 
-        unsafe fn new_unsafe(config: &EncoderConfig) -> Result<Self> {
+        unsafe fn open_unsafe(spec: &FfmpegSpec, config: &EncoderConfig) -> Result<Self> {
             // ── Video encoder ─────────────────────────────────────────────────
-            let video_codec = {
-                let nvenc = ffsys::avcodec_find_encoder_by_name(b"h264_nvenc\0".as_ptr() as _);
-                if !nvenc.is_null() {
-                    nvenc
-                } else {
-                    ffsys::avcodec_find_encoder_by_name(b"libx264\0".as_ptr() as _)
-                }
-            };
+            let video_codec = ffsys::avcodec_find_encoder_by_name(spec.codec.as_ptr() as _);
             if video_codec.is_null() {
-                bail!("No H.264 encoder found (tried h264_nvenc and libx264)");
+                bail!("FFmpeg was built without the {} encoder", spec.kind.as_str());
             }
 
             let video_ctx = CodecCtxGuard(ffsys::avcodec_alloc_context3(video_codec));
@@ -179,38 +356,64 @@ mod imp {
                 bail!("avcodec_alloc_context3 failed for video encoder");
             }
 
-            (*video_ctx.0).width       = config.width as i32;
-            (*video_ctx.0).height      = config.height as i32;
+            let plan = config.scale_plan();
+            let (out_w, out_h) = plan.output;
+            (*video_ctx.0).width       = out_w as i32;
+            (*video_ctx.0).height      = out_h as i32;
             (*video_ctx.0).pix_fmt     = ffsys::AVPixelFormat::AV_PIX_FMT_NV12;
             (*video_ctx.0).time_base   = ffsys::AVRational { num: 1, den: config.fps as i32 };
             (*video_ctx.0).framerate   = ffsys::AVRational { num: config.fps as i32, den: 1 };
-            (*video_ctx.0).bit_rate    = config.video_bitrate;
-            (*video_ctx.0).gop_size    = config.fps as i32; // one IDR per second
+            (*video_ctx.0).gop_size    = config.gop_length as i32;
             (*video_ctx.0).max_b_frames = 0;
             // AV_CODEC_FLAG_GLOBAL_HEADER: put SPS+PPS in extradata (required for MP4).
             (*video_ctx.0).flags      |= ffsys::AV_CODEC_FLAG_GLOBAL_HEADER as i32;
 
+            let bitrate = config.video_bitrate;
+            match config.rate_control {
+                RateControl::Cbr => {
+                    (*video_ctx.0).bit_rate       = bitrate;
+                    (*video_ctx.0).rc_min_rate    = bitrate;
+                    (*video_ctx.0).rc_max_rate    = bitrate;
+                    (*video_ctx.0).rc_buffer_size = bitrate as i32;
+                }
+                RateControl::Vbr => {
+                    // Allow 1.5× peaks with a two-second VBV buffer.
+                    (*video_ctx.0).bit_rate       = bitrate;
+                    (*video_ctx.0).rc_max_rate    = bitrate * 3 / 2;
+                    (*video_ctx.0).rc_buffer_size = (bitrate * 2) as i32;
+                }
+                RateControl::Cqp => {
+                    (*video_ctx.0).bit_rate = 0;
+                    if spec.kind == EncoderKind::Qsv {
+                        (*video_ctx.0).flags |= ffsys::AV_CODEC_FLAG_QSCALE as i32;
+                        (*video_ctx.0).global_quality = config.qp as i32 * FF_QP2LAMBDA;
+                    }
+                }
+            }
+
             let mut opts: *mut ffsys::AVDictionary = ptr::null_mut();
-            ffsys::av_dict_set(&mut opts, b"preset\0".as_ptr() as _, b"p4\0".as_ptr() as _, 0);
-            ffsys::av_dict_set(&mut opts, b"tune\0".as_ptr() as _,   b"ull\0".as_ptr() as _, 0);
-            ffsys::av_dict_set(&mut opts, b"rc\0".as_ptr() as _,     b"vbr\0".as_ptr() as _, 0);
+            for (key, value) in ffmpeg_options(spec.kind, config) {
+                let key = CString::new(key).expect("option keys contain no NUL");
+                let value = CString::new(value).expect("option values contain no NUL");
+                ffsys::av_dict_set(&mut opts, key.as_ptr(), value.as_ptr(), 0);
+            }
             let ret = ffsys::avcodec_open2(video_ctx.0, video_codec, &mut opts);
             ffsys::av_dict_free(&mut opts);
             if ret < 0 {
-                bail!("Failed to open H.264 encoder (code {ret})");
+                bail!("Failed to open {} encoder: {}", spec.kind.as_str(), av_error_string(ret));
             }
 
             let video_params = VideoCodecParams {
                 extradata: read_extradata(video_ctx.0),
-                width: config.width,
-                height: config.height,
+                width: out_w,
+                height: out_h,
                 time_base: (1, config.fps as i32),
             };
 
-            // ── Scaler: BGRA → NV12 ───────────────────────────────────────────
+            // ── Scaler: BGRA → NV12, cropped region → letterboxed region ──────
             let sws_ctx = SwsCtxGuard(ffsys::sws_getContext(
-                config.width as i32,  config.height as i32, ffsys::AVPixelFormat::AV_PIX_FMT_BGRA,
-                config.width as i32,  config.height as i32, ffsys::AVPixelFormat::AV_PIX_FMT_NV12,
+                plan.src.width as i32, plan.src.height as i32, ffsys::AVPixelFormat::AV_PIX_FMT_BGRA,
+                plan.dst.width as i32, plan.dst.height as i32, ffsys::AVPixelFormat::AV_PIX_FMT_NV12,
                 ffsys::SwsFlags::SWS_BILINEAR as i32,
                 ptr::null_mut(), ptr::null_mut(), ptr::null(),
             ));
@@ -241,7 +444,7 @@ mod imp {
             let mut opts: *mut ffsys::AVDictionary = ptr::null_mut();
             let ret = ffsys::avcodec_open2(audio_ctx.0, audio_codec, &mut opts);
             if ret < 0 {
-                bail!("Failed to open AAC encoder (code {ret})");
+                bail!("Failed to open AAC encoder: {}", av_error_string(ret));
             }
 
             let audio_frame_size = (*audio_ctx.0).frame_size as usize;
@@ -253,65 +456,88 @@ mod imp {
             };
 
             Ok(Self {
+                kind: spec.kind,
                 config: config.clone(),
+                plan,
                 video_ctx,
                 sws_ctx,
                 video_frame_count: 0,
-                current_video_packets: vec![],
                 video_params,
                 audio_ctx,
                 audio_sample_buf: Vec::new(),
                 audio_frame_size,
                 audio_pts: 0,
-                current_audio_packets: vec![],
                 audio_params,
             })
         }
 
-        /// Encodes one BGRA video frame. Returns `Some(segment)` when a complete
-        /// 1-second segment boundary is crossed (a new IDR frame is emitted).
-        pub fn push_video_frame(&mut self, frame: &RawFrame) -> Result<Option<EncodedSegment>> {
-            unsafe { self.push_video_frame_unsafe(frame) }
-        }
-
-        unsafe fn push_video_frame_unsafe(
+        unsafe fn encode_video_unsafe(
             &mut self,
             frame: &RawFrame,
-        ) -> Result<Option<EncodedSegment>> {
-            // Allocate and fill the BGRA source frame.
+            out: &mut Vec<EncodedPacket>,
+        ) -> Result<()> {
+            if (frame.width, frame.height) != (self.config.width, self.config.height) {
+                bail!(
+                    "Frame is {}x{} but the encoder was opened for {}x{}",
+                    frame.width, frame.height, self.config.width, self.config.height
+                );
+            }
+            frame.validate()?;
+
+            let (src, dst_rect) = (self.plan.src, self.plan.dst);
+            let (out_w, out_h) = self.plan.output;
+
+            // Allocate the BGRA source frame and copy in the (cropped) region.
             let bgra = FrameGuard(ffsys::av_frame_alloc());
             if bgra.0.is_null() { bail!("av_frame_alloc failed (bgra)"); }
             (*bgra.0).format = ffsys::AVPixelFormat::AV_PIX_FMT_BGRA as i32;
-            (*bgra.0).width  = self.config.width as i32;
-            (*bgra.0).height = self.config.height as i32;
+            (*bgra.0).width  = src.width as i32;
+            (*bgra.0).height = src.height as i32;
             let ret = ffsys::av_frame_get_buffer(bgra.0, 0);
             if ret < 0 { bail!("av_frame_get_buffer(bgra) failed: {ret}"); }
 
             let stride    = (*bgra.0).linesize[0] as usize;
-            let row_bytes = self.config.width as usize * 4;
-            let height    = self.config.height as usize;
+            let row_bytes = src.width as usize * 4;
+            let height    = src.height as usize;
+            let x_offset  = src.x as usize * 4;
             let dst = std::slice::from_raw_parts_mut((*bgra.0).data[0], stride * height);
             for row in 0..height {
-                let src = &frame.bgra_data[row * row_bytes..(row + 1) * row_bytes];
-                dst[row * stride..row * stride + row_bytes].copy_from_slice(src);
+                let src_row = frame.row(src.y + row as u32);
+                dst[row * stride..row * stride + row_bytes]
+                    .copy_from_slice(&src_row[x_offset..x_offset + row_bytes]);
             }
 
-            // Allocate the NV12 destination frame.
+            // Allocate the NV12 destination frame at the output size.
             let nv12 = FrameGuard(ffsys::av_frame_alloc());
             if nv12.0.is_null() { bail!("av_frame_alloc failed (nv12)"); }
             (*nv12.0).format = ffsys::AVPixelFormat::AV_PIX_FMT_NV12 as i32;
-            (*nv12.0).width  = self.config.width as i32;
-            (*nv12.0).height = self.config.height as i32;
+            (*nv12.0).width  = out_w as i32;
+            (*nv12.0).height = out_h as i32;
             let ret = ffsys::av_frame_get_buffer(nv12.0, 0);
             if ret < 0 { bail!("av_frame_get_buffer(nv12) failed: {ret}"); }
 
-            // Scale BGRA → NV12.
+            let luma_stride   = (*nv12.0).linesize[0] as usize;
+            let chroma_stride = (*nv12.0).linesize[1] as usize;
+            if self.plan.has_borders() {
+                // Black in limited-range YUV; sws_scale then overwrites dst_rect.
+                ptr::write_bytes((*nv12.0).data[0], 16, luma_stride * out_h as usize);
+                ptr::write_bytes((*nv12.0).data[1], 128, chroma_stride * out_h as usize / 2);
+            }
+
+            // Scale BGRA → NV12 into dst_rect.  Its origin is even, so the
+            // interleaved UV plane offset is x bytes on row y / 2.
+            let dst_planes: [*mut u8; 4] = [
+                (*nv12.0).data[0].add(dst_rect.y as usize * luma_stride + dst_rect.x as usize),
+                (*nv12.0).data[1].add(dst_rect.y as usize / 2 * chroma_stride + dst_rect.x as usize),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            ];
             ffsys::sws_scale(
                 self.sws_ctx.0,
                 (*bgra.0).data.as_ptr() as *const *const u8,
                 (*bgra.0).linesize.as_ptr(),
-                0, self.config.height as i32,
-                (*nv12.0).data.as_mut_ptr(),
+                0, src.height as i32,
+                dst_planes.as_ptr(),
                 (*nv12.0).linesize.as_ptr(),
             );
 
@@ -319,35 +545,17 @@ mod imp {
             self.video_frame_count += 1;
 
             let ret = ffsys::avcodec_send_frame(self.video_ctx.0, nv12.0);
-            if ret < 0 { bail!("avcodec_send_frame(video) failed: {ret}"); }
-
-            // Drain encoded packets; detect IDR boundaries.
-            let prev_len = self.current_video_packets.len();
-            drain_packets(self.video_ctx.0, &mut self.current_video_packets);
-
-            let mut new_segment = None;
-            // If a new IDR arrived and there was already data, split a segment.
-            // The IDR is the first packet appended after prev_len.
-            if let Some(first_new) = self.current_video_packets.get(prev_len) {
-                if first_new.is_key && prev_len > 0 {
-                    let new_video = self.current_video_packets.split_off(prev_len);
-                    new_segment = Some(EncodedSegment {
-                        video_packets: std::mem::replace(&mut self.current_video_packets, new_video),
-                        audio_packets: std::mem::take(&mut self.current_audio_packets),
-                    });
-                }
-            }
+            if ret < 0 { bail!("avcodec_send_frame(video) failed: {}", av_error_string(ret)); }
 
-            Ok(new_segment)
-        }
-
-        /// Feeds raw interleaved PCM audio into the AAC encoder.
-        /// Buffers internally until a full encoder frame is available.
-        pub fn push_audio(&mut self, audio: &RawAudio) -> Result<()> {
-            unsafe { self.push_audio_unsafe(audio) }
+            drain_packets(self.video_ctx.0, out);
+            Ok(())
         }
 
-        unsafe fn push_audio_unsafe(&mut self, audio: &RawAudio) -> Result<()> {
+        unsafe fn encode_audio_unsafe(
+            &mut self,
+            audio: &RawAudio,
+            out: &mut Vec<EncodedPacket>,
+        ) -> Result<()> {
             self.audio_sample_buf.extend_from_slice(&audio.samples_f32);
 
             let channels             = self.config.channels as usize;
@@ -387,109 +595,313 @@ mod imp {
                     .copy_from_slice(rb);
 
                 let ret = ffsys::avcodec_send_frame(self.audio_ctx.0, af.0);
-                if ret < 0 { bail!("avcodec_send_frame(audio) failed: {ret}"); }
+                if ret < 0 { bail!("avcodec_send_frame(audio) failed: {}", av_error_string(ret)); }
 
-                drain_packets(self.audio_ctx.0, &mut self.current_audio_packets);
+                drain_packets(self.audio_ctx.0, out);
             }
 
             Ok(())
         }
+    }
+
+    impl EncoderBackend for FfmpegBackend {
+        fn kind(&self) -> EncoderKind {
+            self.kind
+        }
+
+        fn video_params(&self) -> &VideoCodecParams {
+            &self.video_params
+        }
+
+        fn audio_params(&self) -> &AudioCodecParams {
+            &self.audio_params
+        }
+
+        fn encode_video(&mut self, frame: &RawFrame, out: &mut Vec<EncodedPacket>) -> Result<()> {
+            unsafe { self.encode_video_unsafe(frame, out) }
+        }
 
-        /// Flushes remaining buffered packets as a final partial segment.
-        pub fn flush(&mut self) -> Result<Option<EncodedSegment>> {
-            unsafe { self.flush_unsafe() }
+        fn encode_audio(&mut self, audio: &RawAudio, out: &mut Vec<EncodedPacket>) -> Result<()> {
+            unsafe { self.encode_audio_unsafe(audio, out) }
         }
 
-        unsafe fn flush_unsafe(&mut self) -> Result<Option<EncodedSegment>> {
+        fn flush(&mut self, video_out: &mut Vec<EncodedPacket>, audio_out: &mut Vec<EncodedPacket>) -> Result<()> {
             // Signal EOF to both encoders.
-            ffsys::avcodec_send_frame(self.video_ctx.0, ptr::null());
-            drain_packets(self.video_ctx.0, &mut self.current_video_packets);
+            unsafe {
+                ffsys::avcodec_send_frame(self.video_ctx.0, ptr::null());
+                drain_packets(self.video_ctx.0, video_out);
 
-            ffsys::avcodec_send_frame(self.audio_ctx.0, ptr::null());
-            drain_packets(self.audio_ctx.0, &mut self.current_audio_packets);
+                ffsys::avcodec_send_frame(self.audio_ctx.0, ptr::null());
+                drain_packets(self.audio_ctx.0, audio_out);
+            }
+            Ok(())
+        }
+    }
+
+    // ── Keyframe decoding ─────────────────────────────────────────────────────
 
-            if self.current_video_packets.is_empty() && self.current_audio_packets.is_empty() {
-                return Ok(None);
+    /// Decodes one H.264 keyframe into a packed BGRA frame with FFmpeg's
+    /// software decoder.
+    pub fn decode_keyframe(params: &VideoCodecParams, data: &[u8]) -> Result<RawFrame> {
+        unsafe { decode_keyframe_unsafe(params, data) }
+    }
+
+    unsafe fn decode_keyframe_unsafe(params: &VideoCodecParams, data: &[u8]) -> Result<RawFrame> {
+        let codec = ffsys::avcodec_find_decoder(ffsys::AVCodecID::AV_CODEC_ID_H264);
+        if codec.is_null() {
+            bail!("FFmpeg was built without the H.264 decoder");
+        }
+        let ctx = CodecCtxGuard(ffsys::avcodec_alloc_context3(codec));
+        if ctx.0.is_null() {
+            bail!("avcodec_alloc_context3 failed for H.264 decoder");
+        }
+        if !params.extradata.is_empty() {
+            // Freed by avcodec_free_context, so it must come from av_malloc.
+            let size = params.extradata.len();
+            let padded = size + ffsys::AV_INPUT_BUFFER_PADDING_SIZE as usize;
+            let buf = ffsys::av_mallocz(padded) as *mut u8;
+            if buf.is_null() {
+                bail!("av_mallocz failed for decoder extradata");
             }
+            ptr::copy_nonoverlapping(params.extradata.as_ptr(), buf, size);
+            (*ctx.0).extradata      = buf;
+            (*ctx.0).extradata_size = size as i32;
+        }
+        let ret = ffsys::avcodec_open2(ctx.0, codec, ptr::null_mut());
+        if ret < 0 {
+            bail!("Failed to open H.264 decoder: {}", av_error_string(ret));
+        }
 
-            Ok(Some(EncodedSegment {
-                video_packets: std::mem::take(&mut self.current_video_packets),
-                audio_packets: std::mem::take(&mut self.current_audio_packets),
-            }))
+        let pkt = PacketGuard(ffsys::av_packet_alloc());
+        if pkt.0.is_null() {
+            bail!("av_packet_alloc failed");
+        }
+        let ret = ffsys::av_new_packet(pkt.0, data.len() as i32);
+        if ret < 0 { bail!("av_new_packet failed: {}", av_error_string(ret)); }
+        ptr::copy_nonoverlapping(data.as_ptr(), (*pkt.0).data, data.len());
+        (*pkt.0).flags |= AV_PKT_FLAG_KEY;
+        let ret = ffsys::avcodec_send_packet(ctx.0, pkt.0);
+        if ret < 0 {
+            bail!("Failed to decode keyframe: {}", av_error_string(ret));
+        }
+        // Signal end of stream so a decoder with frame delay outputs the picture.
+        ffsys::avcodec_send_packet(ctx.0, ptr::null());
+
+        let frame = FrameGuard(ffsys::av_frame_alloc());
+        if frame.0.is_null() {
+            bail!("av_frame_alloc failed");
+        }
+        let ret = ffsys::avcodec_receive_frame(ctx.0, frame.0);
+        if ret < 0 {
+            bail!("Keyframe decoded to no picture: {}", av_error_string(ret));
         }
+
+        // ── Scaler: decoded YUV → BGRA at the same size ───────────────────────
+        let (width, height) = ((*frame.0).width, (*frame.0).height);
+        let sws_ctx = SwsCtxGuard(ffsys::sws_getContext(
+            width, height, (*ctx.0).pix_fmt,
+            width, height, ffsys::AVPixelFormat::AV_PIX_FMT_BGRA,
+            ffsys::SwsFlags::SWS_BILINEAR as i32,
+            ptr::null_mut(), ptr::null_mut(), ptr::null(),
+        ));
+        if sws_ctx.0.is_null() {
+            bail!("sws_getContext failed (decoded picture→BGRA)");
+        }
+        let stride = width as usize * 4;
+        let mut bgra = vec![0u8; stride * height as usize];
+        let dst_planes: [*mut u8; 4] =
+            [bgra.as_mut_ptr(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut()];
+        let dst_strides: [i32; 4] = [stride as i32, 0, 0, 0];
+        ffsys::sws_scale(
+            sws_ctx.0,
+            (*frame.0).data.as_ptr() as *const *const u8,
+            (*frame.0).linesize.as_ptr(),
+            0, height,
+            dst_planes.as_ptr(),
+            dst_strides.as_ptr(),
+        );
+        Ok(RawFrame::packed(bgra, width as u32, height as u32))
     }
 }
 
-// ── Public SegmentEncoder (platform-dispatched) ───────────────────────────────
+// ── Backend selection ─────────────────────────────────────────────────────────
+
+/// Order in which `encoder = "auto"` tries backends: hardware first, then x264.
+/// The software backend is only an automatic fallback where FFmpeg is unavailable.
+const AUTO_ORDER: &[EncoderKind] = if cfg!(windows) {
+    &[EncoderKind::Nvenc, EncoderKind::Qsv, EncoderKind::Amf, EncoderKind::X264]
+} else {
+    &[EncoderKind::Nvenc, EncoderKind::Qsv, EncoderKind::Amf, EncoderKind::X264, EncoderKind::Software]
+};
+
+/// Opens a single concrete backend.  `kind` must not be [`EncoderKind::Auto`].
+pub fn open_backend(kind: EncoderKind, config: &EncoderConfig) -> Result<Box<dyn EncoderBackend>> {
+    match kind {
+        EncoderKind::Auto => anyhow::bail!("'auto' is not a concrete encoder backend"),
+        EncoderKind::Software => Ok(Box::new(SoftwareBackend::open(config)?)),
+        #[cfg(windows)]
+        EncoderKind::Nvenc => Ok(Box::new(imp::FfmpegBackend::open(&imp::NVENC, config)?)),
+        #[cfg(windows)]
+        EncoderKind::X264 => Ok(Box::new(imp::FfmpegBackend::open(&imp::X264, config)?)),
+        #[cfg(windows)]
+        EncoderKind::Qsv => Ok(Box::new(imp::FfmpegBackend::open(&imp::QSV, config)?)),
+        #[cfg(windows)]
+        EncoderKind::Amf => Ok(Box::new(imp::FfmpegBackend::open(&imp::AMF, config)?)),
+        #[cfg(not(windows))]
+        EncoderKind::Nvenc | EncoderKind::X264 | EncoderKind::Qsv | EncoderKind::Amf => {
+            anyhow::bail!("FFmpeg encoders are only available on Windows builds")
+        }
+    }
+}
 
-/// Encodes raw video and audio into 1-second [`EncodedSegment`]s.
+/// Decodes the keyframe `data` of a stream described by `params` into a BGRA
+/// frame, e.g. for a clip thumbnail.  Windows builds use FFmpeg's H.264
+/// decoder; elsewhere only keyframes from the [`SoftwareBackend`] can be
+/// decoded.
+pub fn decode_keyframe(params: &VideoCodecParams, data: &[u8]) -> Result<RawFrame> {
+    #[cfg(windows)]
+    {
+        imp::decode_keyframe(params, data)
+    }
+    #[cfg(not(windows))]
+    {
+        crate::software_encoder::decode_pcm_picture(params, data)
+    }
+}
+
+/// Picks an encoder backend for `requested`.
 ///
-/// On Windows, backed by NVENC H.264 + AAC via `ffmpeg-sys-next`.
-/// On other platforms, all methods compile but return `Ok(None)` / `Ok(())`.
-#[cfg(windows)]
-pub struct SegmentEncoder {
-    inner: imp::SegmentEncoderInner,
-    pub video_params: VideoCodecParams,
-    pub audio_params: AudioCodecParams,
+/// `auto` tries each backend in [`AUTO_ORDER`] and keeps the first that opens;
+/// an explicit choice is tried on its own, with no silent fallback.  The
+/// returned [`EncoderStatus`] records the selection and every rejection reason.
+pub fn probe(
+    requested: EncoderKind,
+    config: &EncoderConfig,
+) -> (Option<Box<dyn EncoderBackend>>, EncoderStatus) {
+    let explicit = [requested];
+    let candidates: &[EncoderKind] =
+        if requested == EncoderKind::Auto { AUTO_ORDER } else { &explicit };
+
+    let mut status = EncoderStatus { requested: requested.as_str().to_string(), ..Default::default() };
+    for &kind in candidates {
+        match open_backend(kind, config) {
+            Ok(backend) => {
+                status.backend = Some(backend.kind().as_str().to_string());
+                return (Some(backend), status);
+            }
+            Err(e) => {
+                status.rejected.insert(kind.as_str().to_string(), format!("{e:#}"));
+            }
+        }
+    }
+    (None, status)
 }
 
-#[cfg(not(windows))]
+// ── SegmentEncoder ────────────────────────────────────────────────────────────
+
+/// Encodes raw video and audio into one-GOP [`EncodedSegment`]s using any
+/// [`EncoderBackend`].  A segment is closed whenever the backend emits a new
+/// IDR frame, so every segment starts on a keyframe.
 pub struct SegmentEncoder {
+    backend: Box<dyn EncoderBackend>,
+    /// Configuration `backend` was opened with; reused on reconfigure.
+    config: EncoderConfig,
+    current_video_packets: Vec<EncodedPacket>,
+    current_audio_packets: Vec<EncodedPacket>,
     pub video_params: VideoCodecParams,
     pub audio_params: AudioCodecParams,
 }
 
 impl SegmentEncoder {
-    pub fn new(config: &EncoderConfig) -> Result<Self> {
-        #[cfg(windows)]
-        {
-            let inner = imp::SegmentEncoderInner::new(config)?;
-            let video_params = inner.video_params.clone();
-            let audio_params = inner.audio_params.clone();
-            Ok(Self { inner, video_params, audio_params })
-        }
-        #[cfg(not(windows))]
-        {
-            // Stub: return default params so the ring buffer can be initialised.
-            Ok(Self {
-                video_params: VideoCodecParams {
-                    extradata: vec![],
-                    width: config.width,
-                    height: config.height,
-                    time_base: (1, config.fps as i32),
-                },
-                audio_params: AudioCodecParams {
-                    extradata: vec![],
-                    sample_rate: config.sample_rate,
-                    channels: config.channels as u32,
-                    time_base: (1, config.sample_rate as i32),
-                },
-            })
+    /// Wraps `backend`, which must have been opened with `config`.
+    pub fn new(backend: Box<dyn EncoderBackend>, config: EncoderConfig) -> Self {
+        let video_params = backend.video_params().clone();
+        let audio_params = backend.audio_params().clone();
+        Self {
+            backend,
+            config,
+            current_video_packets: vec![],
+            current_audio_packets: vec![],
+            video_params,
+            audio_params,
         }
     }
 
-    /// Returns `Some(segment)` when a new IDR frame signals a 1-second boundary.
+    /// Name of the backend doing the encoding.
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.kind().as_str()
+    }
+
+    /// Frame size the backend is currently opened for.
+    pub fn frame_size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    /// Reopens the same backend for `width`×`height` frames.
+    ///
+    /// The new backend is opened before the old one is closed, so on error the
+    /// encoder keeps working at its previous size.  On success the old backend
+    /// is flushed and its final partial segment returned; it belongs to the
+    /// old codec parameters, and `video_params` / `audio_params` now describe
+    /// the new epoch.
+    pub fn reconfigure(&mut self, width: u32, height: u32) -> Result<Option<EncodedSegment>> {
+        let config = EncoderConfig { width, height, ..self.config.clone() };
+        let backend = open_backend(self.backend.kind(), &config)?;
+
+        let tail = self.flush().unwrap_or_else(|e| {
+            eprintln!("[encoder] Flush before reconfigure failed: {e}");
+            None
+        });
+        self.current_video_packets.clear();
+        self.current_audio_packets.clear();
+
+        self.video_params = backend.video_params().clone();
+        self.audio_params = backend.audio_params().clone();
+        self.backend = backend;
+        self.config = config;
+        Ok(tail)
+    }
+
+    /// Returns `Some(segment)` when a new IDR frame starts a new GOP.
     pub fn push_video_frame(&mut self, frame: &RawFrame) -> Result<Option<EncodedSegment>> {
-        #[cfg(windows)]
-        { self.inner.push_video_frame(frame) }
-        #[cfg(not(windows))]
-        { let _ = frame; Ok(None) }
+        let prev_len = self.current_video_packets.len();
+        self.backend.encode_video(frame, &mut self.current_video_packets)?;
+
+        // If a new IDR arrived and there was already data, split a segment at it.
+        let split_at = self.current_video_packets[prev_len..]
+            .iter()
+            .position(|p| p.is_key)
+            .map(|i| prev_len + i)
+            .filter(|&i| i > 0);
+
+        Ok(split_at.map(|i| {
+            let new_video = self.current_video_packets.split_off(i);
+            EncodedSegment {
+                video_packets: std::mem::replace(&mut self.current_video_packets, new_video),
+                audio_packets: std::mem::take(&mut self.current_audio_packets),
+                pushed_at: None,
+            }
+        }))
     }
 
-    /// Feeds raw interleaved PCM into the AAC encoder.
+    /// Feeds raw interleaved PCM into the audio encoder.
     pub fn push_audio(&mut self, audio: &RawAudio) -> Result<()> {
-        #[cfg(windows)]
-        { self.inner.push_audio(audio) }
-        #[cfg(not(windows))]
-        { let _ = audio; Ok(()) }
+        self.backend.encode_audio(audio, &mut self.current_audio_packets)
     }
 
     /// Flush remaining packets as a final partial segment.
     pub fn flush(&mut self) -> Result<Option<EncodedSegment>> {
-        #[cfg(windows)]
-        { self.inner.flush() }
-        #[cfg(not(windows))]
-        { Ok(None) }
+        self.backend.flush(&mut self.current_video_packets, &mut self.current_audio_packets)?;
+
+        if self.current_video_packets.is_empty() && self.current_audio_packets.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(EncodedSegment {
+            video_packets: std::mem::take(&mut self.current_video_packets),
+            audio_packets: std::mem::take(&mut self.current_audio_packets),
+            pushed_at: None,
+        }))
     }
 }
 
@@ -505,12 +917,202 @@ mod tests {
         assert_eq!(cfg.width, 1920);
         assert_eq!(cfg.height, 1080);
         assert_eq!(cfg.fps, 60);
+        assert_eq!(cfg.gop_length, 60);
         assert_eq!(cfg.sample_rate, 48_000);
         assert_eq!(cfg.channels, 2);
         assert_eq!(cfg.video_bitrate, 8_000_000);
         assert_eq!(cfg.audio_bitrate, 192_000);
     }
 
+    #[test]
+    fn encoder_config_from_settings_converts_units() {
+        let video = VideoConfig {
+            fps: 30,
+            bitrate_kbps: 6_000,
+            rate_control: RateControl::Cbr,
+            qp: 18,
+            preset: EncoderPreset::Quality,
+            scale: Some(0.5),
+            ..VideoConfig::default()
+        };
+        let audio = AudioConfig { bitrate_kbps: 128 };
+        let cfg = EncoderConfig::from_settings(&video, &audio);
+        assert_eq!(cfg.fps, 30);
+        assert_eq!(cfg.gop_length, 30, "GOP defaults to one second");
+        assert_eq!(cfg.video_bitrate, 6_000_000);
+        assert_eq!(cfg.rate_control, RateControl::Cbr);
+        assert_eq!(cfg.qp, 18);
+        assert_eq!(cfg.preset, EncoderPreset::Quality);
+        assert_eq!(cfg.audio_bitrate, 128_000);
+        assert_eq!((cfg.width, cfg.height), (1920, 1080));
+        assert_eq!(cfg.scale_plan().output, (960, 540));
+    }
+
+    // ── ffmpeg_options ────────────────────────────────────────────────────────
+
+    fn option<'a>(opts: &'a [(&'static str, String)], key: &str) -> Option<&'a str> {
+        opts.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn ffmpeg_options_map_presets_per_backend() {
+        let cfg = EncoderConfig { preset: EncoderPreset::Quality, ..EncoderConfig::default() };
+        assert_eq!(option(&ffmpeg_options(EncoderKind::Nvenc, &cfg), "preset"), Some("p6"));
+        assert_eq!(option(&ffmpeg_options(EncoderKind::X264, &cfg), "preset"), Some("medium"));
+        assert_eq!(option(&ffmpeg_options(EncoderKind::Qsv, &cfg), "preset"), Some("slower"));
+        assert_eq!(option(&ffmpeg_options(EncoderKind::Amf, &cfg), "quality"), Some("quality"));
+    }
+
+    #[test]
+    fn ffmpeg_options_map_constant_qp() {
+        let cfg = EncoderConfig { rate_control: RateControl::Cqp, qp: 20, ..EncoderConfig::default() };
+        let nvenc = ffmpeg_options(EncoderKind::Nvenc, &cfg);
+        assert_eq!(option(&nvenc, "rc"), Some("constqp"));
+        assert_eq!(option(&nvenc, "qp"), Some("20"));
+        assert_eq!(option(&ffmpeg_options(EncoderKind::X264, &cfg), "qp"), Some("20"));
+        let amf = ffmpeg_options(EncoderKind::Amf, &cfg);
+        assert_eq!(option(&amf, "rc"), Some("cqp"));
+        assert_eq!(option(&amf, "qp_p"), Some("20"));
+    }
+
+    #[test]
+    fn ffmpeg_options_vbr_sets_no_qp() {
+        let opts = ffmpeg_options(EncoderKind::Nvenc, &EncoderConfig::default());
+        assert_eq!(option(&opts, "rc"), Some("vbr"));
+        assert_eq!(option(&opts, "qp"), None);
+    }
+
+    /// 320×240 @ 30 fps config — small enough for fast test execution while
+    /// still being a valid resolution for both NVENC and libx264.
+    fn small_cfg() -> EncoderConfig {
+        EncoderConfig { width: 320, height: 240, fps: 30, gop_length: 30, ..EncoderConfig::default() }
+    }
+
+    fn blank_frame(cfg: &EncoderConfig) -> RawFrame {
+        RawFrame::packed(vec![0u8; (cfg.width * cfg.height * 4) as usize], cfg.width, cfg.height)
+    }
+
+    // ── probe ─────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn probe_explicit_software_selects_it() {
+        let (backend, status) = probe(EncoderKind::Software, &small_cfg());
+        assert_eq!(backend.unwrap().kind(), EncoderKind::Software);
+        assert_eq!(status.requested, "software");
+        assert_eq!(status.backend.as_deref(), Some("software"));
+        assert!(status.rejected.is_empty());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn probe_auto_falls_back_to_software_and_records_rejections() {
+        let (backend, status) = probe(EncoderKind::Auto, &small_cfg());
+        assert_eq!(backend.unwrap().kind(), EncoderKind::Software);
+        assert_eq!(status.requested, "auto");
+        let rejected: Vec<&str> = status.rejected.keys().map(String::as_str).collect();
+        assert_eq!(rejected, vec!["amf", "nvenc", "qsv", "x264"]);
+        assert!(status.rejected["nvenc"].contains("only available on Windows"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn probe_explicit_choice_does_not_fall_back() {
+        let (backend, status) = probe(EncoderKind::Nvenc, &small_cfg());
+        assert!(backend.is_none());
+        assert!(status.backend.is_none());
+        assert_eq!(status.rejected.len(), 1);
+        assert!(status.rejected.contains_key("nvenc"));
+    }
+
+    #[test]
+    fn probe_reports_backend_open_errors() {
+        let cfg = EncoderConfig { channels: 6, ..small_cfg() };
+        let (backend, status) = probe(EncoderKind::Software, &cfg);
+        assert!(backend.is_none());
+        assert!(status.rejected["software"].contains("6 channels"));
+    }
+
+    // ── SegmentEncoder ────────────────────────────────────────────────────────
+
+    #[test]
+    fn segment_encoder_splits_on_each_idr() {
+        let cfg = EncoderConfig { fps: 5, gop_length: 5, ..small_cfg() };
+        let backend = open_backend(EncoderKind::Software, &cfg).unwrap();
+        let mut enc = SegmentEncoder::new(backend, cfg.clone());
+        assert_eq!(enc.backend_name(), "software");
+
+        let mut segments = vec![];
+        for _ in 0..12 {
+            enc.push_audio(&RawAudio { samples_f32: vec![0.0; 9600 * 2 / 5] }).unwrap();
+            if let Some(seg) = enc.push_video_frame(&blank_frame(&cfg)).unwrap() {
+                segments.push(seg);
+            }
+        }
+        segments.extend(enc.flush().unwrap());
+
+        let sizes: Vec<usize> = segments.iter().map(|s| s.video_packets.len()).collect();
+        assert_eq!(sizes, vec![5, 5, 2]);
+        assert!(segments.iter().all(|s| s.video_packets[0].is_key));
+        assert!(segments.iter().all(|s| !s.audio_packets.is_empty()));
+    }
+
+    #[test]
+    fn segment_encoder_flush_on_empty_encoder_returns_none() {
+        let backend = open_backend(EncoderKind::Software, &small_cfg()).unwrap();
+        assert!(SegmentEncoder::new(backend, small_cfg()).flush().unwrap().is_none());
+    }
+
+    #[test]
+    fn segment_encoder_reconfigure_switches_frame_size() {
+        let cfg = EncoderConfig { fps: 5, gop_length: 5, ..small_cfg() };
+        let backend = open_backend(EncoderKind::Software, &cfg).unwrap();
+        let mut enc = SegmentEncoder::new(backend, cfg.clone());
+        for _ in 0..3 {
+            enc.push_video_frame(&blank_frame(&cfg)).unwrap();
+        }
+
+        let tail = enc.reconfigure(64, 48).unwrap().expect("old epoch tail");
+        assert_eq!(tail.video_packets.len(), 3);
+        assert_eq!(enc.frame_size(), (64, 48));
+        assert_eq!((enc.video_params.width, enc.video_params.height), (64, 48));
+
+        let big = EncoderConfig { width: 64, height: 48, ..cfg };
+        assert!(enc.push_video_frame(&blank_frame(&big)).unwrap().is_none());
+        let seg = enc.flush().unwrap().unwrap();
+        assert!(seg.video_packets[0].is_key, "new epoch starts on an IDR");
+        assert_eq!(seg.video_packets[0].pts, 0);
+    }
+
+    #[test]
+    fn segment_encoder_keeps_output_size_across_reconfigure() {
+        let output = OutputScaling { width: Some(32), height: Some(24), ..OutputScaling::default() };
+        let cfg = EncoderConfig { output, ..small_cfg() };
+        let backend = open_backend(EncoderKind::Software, &cfg).unwrap();
+        let mut enc = SegmentEncoder::new(backend, cfg.clone());
+        assert_eq!((enc.video_params.width, enc.video_params.height), (32, 24));
+
+        enc.reconfigure(64, 64).unwrap();
+        assert_eq!(enc.frame_size(), (64, 64));
+        assert_eq!((enc.video_params.width, enc.video_params.height), (32, 24));
+    }
+
+    #[test]
+    fn segment_encoder_rejects_mismatched_frame() {
+        let backend = open_backend(EncoderKind::Software, &small_cfg()).unwrap();
+        let mut enc = SegmentEncoder::new(backend, small_cfg());
+        let other = EncoderConfig { width: 64, height: 48, ..small_cfg() };
+        assert!(enc.push_video_frame(&blank_frame(&other)).is_err());
+    }
+
+    #[test]
+    fn segment_encoder_failed_reconfigure_keeps_old_backend() {
+        let backend = open_backend(EncoderKind::Software, &small_cfg()).unwrap();
+        let mut enc = SegmentEncoder::new(backend, small_cfg());
+        assert!(enc.reconfigure(0, 0).is_err());
+        assert_eq!(enc.frame_size(), (320, 240));
+        assert!(enc.push_video_frame(&blank_frame(&small_cfg())).is_ok());
+    }
+
     // ── Windows: real encoder tests ───────────────────────────────────────────
     //
     // These require FFmpeg with an H.264 encoder (h264_nvenc or libx264) and an
@@ -518,17 +1120,19 @@ mod tests {
     // and returns) if the encoder fails to initialise so that CI machines without
     // GPU support do not produce hard failures.
 
-    /// 320×240 @ 30 fps config — small enough for fast test execution while
-    /// still being a valid resolution for both NVENC and libx264.
+    /// Opens a [`SegmentEncoder`] on whatever FFmpeg backend `auto` selects.
     #[cfg(windows)]
-    fn small_cfg() -> EncoderConfig {
-        EncoderConfig { width: 320, height: 240, fps: 30, ..EncoderConfig::default() }
+    fn open_encoder(cfg: &EncoderConfig) -> Result<SegmentEncoder> {
+        match probe(EncoderKind::Auto, cfg) {
+            (Some(backend), _) => Ok(SegmentEncoder::new(backend, cfg.clone())),
+            (None, status) => anyhow::bail!("no encoder available: {:?}", status.rejected),
+        }
     }
 
     #[cfg(windows)]
     #[test]
     fn encoder_new_succeeds() {
-        match SegmentEncoder::new(&small_cfg()) {
+        match open_encoder(&small_cfg()) {
             Ok(_) => {}
             Err(e) => eprintln!("Skipping — no encoder available: {e}"),
         }
@@ -538,7 +1142,7 @@ mod tests {
     #[test]
     fn encoder_video_params_match_config() {
         let cfg = small_cfg();
-        let enc = match SegmentEncoder::new(&cfg) {
+        let enc = match open_encoder(&cfg) {
             Ok(e) => e,
             Err(e) => { eprintln!("Skipping: {e}"); return; }
         };
@@ -551,7 +1155,7 @@ mod tests {
     #[test]
     fn encoder_audio_params_match_config() {
         let cfg = small_cfg();
-        let enc = match SegmentEncoder::new(&cfg) {
+        let enc = match open_encoder(&cfg) {
             Ok(e) => e,
             Err(e) => { eprintln!("Skipping: {e}"); return; }
         };
@@ -563,16 +1167,12 @@ mod tests {
     #[cfg(windows)]
     #[test]
     fn encoder_push_first_video_frame_returns_none() {
-        use crate::capture::RawFrame;
         let cfg = small_cfg();
-        let mut enc = match SegmentEncoder::new(&cfg) {
+        let mut enc = match open_encoder(&cfg) {
             Ok(e) => e,
             Err(e) => { eprintln!("Skipping: {e}"); return; }
         };
-        let frame = RawFrame {
-            bgra_data: vec![0u8; (cfg.width * cfg.height * 4) as usize],
-        };
-        let result = enc.push_video_frame(&frame).expect("push_video_frame failed");
+        let result = enc.push_video_frame(&blank_frame(&cfg)).expect("push_video_frame failed");
         // The very first frame can never close a segment — there are no prior
         // packets to split off into a preceding segment at the IDR boundary.
         assert!(result.is_none());
@@ -581,9 +1181,8 @@ mod tests {
     #[cfg(windows)]
     #[test]
     fn encoder_push_audio_returns_ok() {
-        use crate::audio_capture::RawAudio;
         let cfg = small_cfg();
-        let mut enc = match SegmentEncoder::new(&cfg) {
+        let mut enc = match open_encoder(&cfg) {
             Ok(e) => e,
             Err(e) => { eprintln!("Skipping: {e}"); return; }
         };
@@ -597,7 +1196,7 @@ mod tests {
     #[test]
     fn encoder_flush_on_empty_encoder_returns_none() {
         let cfg = small_cfg();
-        let mut enc = match SegmentEncoder::new(&cfg) {
+        let mut enc = match open_encoder(&cfg) {
             Ok(e) => e,
             Err(e) => { eprintln!("Skipping: {e}"); return; }
         };
@@ -605,5 +1204,4 @@ mod tests {
         let result = enc.flush().expect("flush failed");
         assert!(result.is_none());
     }
-
 }
@@ -1,4 +1,13 @@
+use serde::Serialize;
+use tokio::sync::oneshot;
+
 use crate::config::{ApplicationConfig, Config};
+use crate::disk::DiskSpace;
+use crate::flush::SavedClip;
+use crate::flush_worker::{FlushStage, JobId};
+use crate::ipc::Subscription;
+use crate::ring_buffer::BufferStats;
+use crate::status::{DaemonStatus, EncoderStatus};
 
 pub enum DaemonEvent {
     /// A watched game process appeared in the process list.
@@ -7,9 +16,68 @@ pub enum DaemonEvent {
     ProcessStopped,
     /// The config file changed on disk and was successfully re-parsed.
     ConfigReloaded(Config),
-    /// The clip hotkey was pressed; flush the ring buffer to disk.
-    /// Implemented in Phase 8 (hotkey) and consumed in Phase 9 (flush).
-    FlushRequested,
-    /// Ctrl+C received; the daemon should flush state and exit.
+    /// The clip hotkey was pressed or `save_clip` arrived over IPC; flush
+    /// the ring buffer to disk.
+    FlushRequested(ClipRequest),
+    /// `get_status` arrived over IPC; reply with the current status.
+    StatusRequested(oneshot::Sender<DaemonStatus>),
+    /// `subscribe` arrived over IPC; reply with the current status and a
+    /// feed of the changes after it.
+    Subscribe(oneshot::Sender<Subscription>),
+    /// Stop recording until [`DaemonEvent::Resume`], even while a watched
+    /// game is running.
+    Pause,
+    /// Undo [`DaemonEvent::Pause`], restarting the recording of a running game.
+    Resume,
+    /// The pipeline finished probing encoder backends for a new recording.
+    /// `backend` is `None` if every candidate was rejected.
+    EncoderSelected(EncoderStatus),
+    /// The encoder pushed a segment; carries the ring buffer's new fill level.
+    BufferUpdated(BufferStats),
+    /// A background flush job started or wrote one of its files.
+    FlushProgress { job: JobId, stage: FlushStage },
+    /// A background flush job ended with the saved clip paths or the error.
+    FlushFinished { job: JobId, result: anyhow::Result<Vec<SavedClip>> },
+    /// Free space on the clip drive was measured.
+    DiskSpaceChecked(DiskSpace),
+    /// Time to refresh `heartbeat_at` in the status.
+    Heartbeat,
+    /// Ctrl+C received or `shutdown` arrived over IPC; the daemon should
+    /// flush state and exit.
     Shutdown,
 }
+
+/// A request to save a clip, from the hotkey or the control socket.
+#[derive(Default)]
+pub struct ClipRequest {
+    /// Saves only the newest this many seconds of footage; `None` saves the
+    /// whole buffer.
+    pub seconds: Option<u32>,
+    /// Tag for the clip's file name, metadata and sidecar.
+    pub tag: Option<String>,
+    /// Receives the outcome; `None` for hotkey presses.
+    pub reply: Option<oneshot::Sender<Result<ClipAccepted, String>>>,
+}
+
+impl ClipRequest {
+    /// Reports the outcome to the requester, if it is still waiting.
+    pub fn respond(&mut self, outcome: Result<ClipAccepted, String>) {
+        if let Some(reply) = self.reply.take() {
+            let _ = reply.send(outcome);
+        }
+    }
+}
+
+/// What the daemon did with an accepted [`ClipRequest`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ClipAccepted {
+    /// The clip was handed to the flush worker as `job`.
+    Saving { job: JobId },
+    /// The clip is handed to the flush worker as `job` once `post_roll_secs`
+    /// more footage is recorded.
+    PostRoll { job: JobId, post_roll_secs: u32 },
+    /// Clip `job` was already waiting for post-roll; the wait was restarted
+    /// and this request's `seconds` and `tag` were ignored.
+    Extended { job: JobId },
+}
@@ -1,18 +1,52 @@
-/// Clip flushing: mux all segments currently in the ring buffer into an MP4 file.
+/// Clip flushing: mux all segments currently in the ring buffer into an MP4,
+/// fragmented MP4 or Matroska file.
 ///
-/// The output path is derived from the configured clip directory, the active
-/// application's display name, and the current local timestamp:
-///   `<clip_output_dir>\<display_name>\YYYY-MM-DD_HH-MM-SS.mp4`
+/// The output path is `<clip_output_dir>\<clip_dir_template>\<clip_filename_template>.<mp4|mkv>`,
+/// by default `<clip_output_dir>\<display_name>\YYYY-MM-DD_HH-MM-SS.mp4`.
+/// A name that is taken, on disk or by a flush still in progress, gets a
+/// `_2`, `_3`, … suffix.
 ///
-/// On Windows the mux is performed by calling into the FFmpeg C API directly
-/// (via `ffmpeg_next::sys`) so that we can set up pre-encoded H.264 and AAC
-/// streams with their stored `extradata` blobs. `movflags=faststart` is set
-/// before writing the header so the `moov` atom ends up at the front of the
-/// file — no separate `qt-faststart` pass is needed.
-use anyhow::Result;
-use std::path::PathBuf;
+/// A track has a single set of codec parameters, so if the buffer spans more
+/// than one codec epoch (the capture resolution changed mid-buffer) the clip
+/// is split into one file per epoch: `…_part1.mp4`, `…_part2.mp4`, ….
+///
+/// Each file is written to a hidden `.<name>.partial` sibling, synced to disk
+/// and only then renamed into place, so a clip path never holds a half-written
+/// file.  [`sweep_partial_files`] cleans up after a flush cut short by a crash:
+/// fragmented MP4 and Matroska clips that still play are kept, the rest is
+/// deleted.  Next to each clip go a JSON sidecar describing it (see
+/// [`crate::sidecar`]) and, unless disabled, a PNG thumbnail (see
+/// [`crate::thumbnail`]).
+///
+/// With `post_roll_secs` set, the buffer is snapshotted when the hotkey is
+/// pressed and a [`PendingClip`] keeps collecting newly encoded segments until
+/// the post-roll has been recorded; only then is the combined range muxed.
+///
+/// Muxing is done by the native writers in [`crate::mp4`] and [`crate::mkv`],
+/// which turn the pre-encoded H.264 and AAC packets plus their stored
+/// `extradata` blobs into a faststart MP4 (`moov` ahead of `mdat`), a
+/// fragmented MP4 or a Matroska file without going through FFmpeg, so clips
+/// can be saved on every platform.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::ring_buffer::{AudioCodecParams, EncodedSegment, VideoCodecParams};
+use crate::config::{
+    self, ApplicationConfig, AudioConfig, ContainerFormat, GlobalConfig, RateControl,
+    TemplatePart, VideoConfig, DEFAULT_CLIP_FILENAME_TEMPLATE,
+};
+use crate::flush_worker::JobId;
+use crate::metadata::ClipMetadata;
+use crate::ring_buffer::{
+    AudioCodecParams, BufferSnapshot, BufferStats, EncodedSegment, EpochSegments, RingBuffer,
+    VideoCodecParams,
+};
+use crate::sidecar::{self, AudioInfo, ClipSidecar, VideoInfo, SIDECAR_VERSION};
+use crate::status::BufferStatus;
+use crate::{disk, mkv, mp4, thumbnail};
 
 // ── Path helpers ───────────────────────────────────────────────────────────────
 
@@ -37,307 +71,694 @@ fn sanitize_dirname(name: &str) -> String {
         .collect()
 }
 
-/// Returns the current local time formatted as `YYYY-MM-DD_HH-MM-SS`.
-fn local_timestamp() -> String {
-    chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string()
+/// Turns a rendered template into a usable path component: illegal
+/// characters become `_`, and leading or trailing spaces, dots, underscores
+/// and dashes (left over from empty placeholders) are trimmed.  A leading dot
+/// would hide the file, a trailing one is dropped by Windows.
+fn clean_name(name: &str) -> String {
+    sanitize_dirname(name)
+        .chars()
+        .map(|c| if c.is_control() { '_' } else { c })
+        .collect::<String>()
+        .trim_matches([' ', '.', '_', '-'])
+        .to_string()
 }
 
-/// Builds the output path `<clip_output_dir>/<display_name>/<timestamp>.mp4`
-/// and creates the full directory tree if it does not already exist.
-pub fn build_output_path(clip_output_dir: &str, display_name: &str) -> Result<PathBuf> {
-    let base = PathBuf::from(expand_env(clip_output_dir));
-    let game_dir = base.join(sanitize_dirname(display_name));
-    std::fs::create_dir_all(&game_dir)?;
-    Ok(game_dir.join(format!("{}.mp4", local_timestamp())))
+/// Values substituted into the clip name templates.
+struct ClipLabel<'a> {
+    game: &'a str,
+    tag: &'a str,
+    duration: Duration,
+    time: DateTime<Local>,
 }
 
-// ── Public flush entry point ───────────────────────────────────────────────────
+/// Expands the placeholders of `template` (see
+/// [`config::CLIP_TEMPLATE_PLACEHOLDERS`]).  Substituted values never contain
+/// path separators.  `{counter}` renders as three digits, or nothing when
+/// `counter` is `None`.
+fn render_template(template: &str, label: &ClipLabel, counter: Option<u32>) -> String {
+    // Templates are validated when the config is loaded.
+    let parts = config::parse_clip_template(template)
+        .unwrap_or_else(|_| vec![TemplatePart::Text(template)]);
+    let mut out = String::new();
+    for part in parts {
+        let value = match part {
+            TemplatePart::Text(text) => text.to_string(),
+            TemplatePart::Placeholder("game") => sanitize_dirname(label.game),
+            TemplatePart::Placeholder("date") => label.time.format("%Y-%m-%d").to_string(),
+            TemplatePart::Placeholder("time") => label.time.format("%H-%M-%S").to_string(),
+            TemplatePart::Placeholder("ms") => {
+                format!("{:03}", label.time.timestamp_subsec_millis())
+            }
+            TemplatePart::Placeholder("duration") => format!("{:.0}", label.duration.as_secs_f64()),
+            TemplatePart::Placeholder("counter") => {
+                counter.map(|n| format!("{n:03}")).unwrap_or_default()
+            }
+            TemplatePart::Placeholder("tag") => sanitize_dirname(label.tag),
+            TemplatePart::Placeholder(_) => String::new(),
+        };
+        out.push_str(&value);
+    }
+    out
+}
 
-/// Muxes `segments` into an MP4 file and returns the path of the saved clip.
-///
-/// The ffmpeg work runs on a blocking thread via [`tokio::task::spawn_blocking`]
-/// so the async event loop stays responsive while the file is being written.
-///
-/// On non-Windows builds this always returns an error (the encoder never
-/// produces segments on those platforms either, so this is never reached in
-/// practice).
-pub async fn flush_to_disk(
-    segments: Vec<EncodedSegment>,
-    video_params: VideoCodecParams,
-    audio_params: AudioCodecParams,
-    clip_output_dir: String,
-    display_name: String,
-) -> Result<PathBuf> {
-    if segments.is_empty() {
-        anyhow::bail!("Ring buffer is empty — nothing to save");
-    }
-
-    // On non-Windows the encoder never runs so this path is never reached,
-    // but it must compile cleanly for `cargo check`.
-    #[cfg(not(windows))]
-    {
-        let _ = (segments, video_params, audio_params, clip_output_dir, display_name);
-        anyhow::bail!("Clip flushing is only supported on Windows");
+/// Returns `clip_output_dir` with its environment variables expanded.
+pub fn clip_root(clip_output_dir: &str) -> PathBuf {
+    PathBuf::from(expand_env(clip_output_dir))
+}
+
+/// Returns the directory holding every clip of `display_name`: the leading
+/// components of the directory template that use no placeholder besides
+/// `{game}`, rendered.  `None` if those do not include `{game}` (as in
+/// `{date}/{game}`), since the game's clips are then spread over several
+/// directories.
+pub fn game_clip_dir(
+    clip_output_dir: &str,
+    dir_template: &str,
+    display_name: &str,
+) -> Option<PathBuf> {
+    let label =
+        ClipLabel { game: display_name, tag: "", duration: Duration::ZERO, time: Local::now() };
+    let mut dir = clip_root(clip_output_dir);
+    let mut per_game = false;
+    for component in dir_template.split(['/', '\\']) {
+        let parts = config::parse_clip_template(component).ok()?;
+        let placeholders = parts.iter().filter_map(|part| match part {
+            TemplatePart::Placeholder(name) => Some(*name),
+            TemplatePart::Text(_) => None,
+        });
+        if placeholders.clone().any(|name| name != "game") {
+            break;
+        }
+        per_game |= placeholders.count() > 0;
+        let name = clean_name(&render_template(component, &label, None));
+        if !name.is_empty() {
+            dir.push(name);
+        }
+    }
+    per_game.then_some(dir)
+}
+
+/// Returns the directory a clip is saved in: `clip_output_dir` with each
+/// `/`- or `\`-separated component of the directory template rendered and
+/// appended.  Components that render empty are skipped.
+fn clip_dir(output: &ClipOutput, label: &ClipLabel) -> PathBuf {
+    let mut dir = clip_root(&output.clip_output_dir);
+    for component in output.dir_template.split(['/', '\\']) {
+        let name = clean_name(&render_template(component, label, None));
+        if !name.is_empty() {
+            dir.push(name);
+        }
+    }
+    dir
+}
+
+/// Returns the file stem for naming attempt `attempt` (0-based).  Templates
+/// with `{counter}` count up from `first_counter`; others get a `_2`, `_3`, …
+/// suffix after the first attempt.  Falls back to the default template if the
+/// configured one renders empty.
+fn clip_stem(template: &str, label: &ClipLabel, first_counter: u32, attempt: u32) -> String {
+    let counter = first_counter.saturating_add(attempt);
+    let mut stem = clean_name(&render_template(template, label, Some(counter)));
+    if stem.is_empty() {
+        stem = clean_name(&render_template(DEFAULT_CLIP_FILENAME_TEMPLATE, label, None));
+    }
+    if attempt > 0 && !template.contains("{counter}") {
+        stem = format!("{stem}_{}", attempt + 1);
+    }
+    stem
+}
+
+/// True for the name of a saved clip (not a `.partial` file).
+pub fn is_clip_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    !name.starts_with('.') && (name.ends_with(".mp4") || name.ends_with(".mkv"))
+}
+
+/// Counts the clips already saved in `dir`, for `{counter}`.
+fn count_clips(dir: &Path) -> u32 {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    let count = entries
+        .flatten()
+        .filter(|entry| is_clip_file(&entry.file_name().to_string_lossy()))
+        .count();
+    u32::try_from(count).unwrap_or(u32::MAX)
+}
+
+/// Returns the path for part `index` (1-based) of a split clip:
+/// `<dir>/<stem>_part<index>.<ext>`.
+fn part_path(base: &Path, index: usize) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = base.extension().unwrap_or_default().to_string_lossy();
+    base.with_file_name(format!("{stem}_part{index}.{ext}"))
+}
+
+/// Returns the path of a file kept next to `clip`, such as its sidecar:
+/// `<clip file name><suffix>` in the same directory.
+pub fn sibling_path(clip: &Path, suffix: &str) -> PathBuf {
+    let name = clip.file_name().unwrap_or_default().to_string_lossy();
+    clip.with_file_name(format!("{name}{suffix}"))
+}
+
+// ── Output settings ────────────────────────────────────────────────────────────
+
+/// Where, under which name and in which format a clip is saved, captured from
+/// the config when the clip is queued.
+#[derive(Debug, Clone)]
+pub struct ClipOutput {
+    pub clip_output_dir: String,
+    pub dir_template: String,
+    pub filename_template: String,
+    pub container: ContainerFormat,
+    /// Width of the thumbnail saved next to each clip; 0 saves none.
+    pub thumbnail_width: u32,
+}
+
+impl ClipOutput {
+    pub fn from_config(global: &GlobalConfig) -> Self {
+        Self {
+            clip_output_dir: global.clip_output_dir.clone(),
+            dir_template: global.clip_dir_template.clone(),
+            filename_template: global.clip_filename_template.clone(),
+            container: global.container,
+            thumbnail_width: global.thumbnail_width,
+        }
+    }
+}
+
+/// What a clip was requested for: the game being recorded and an optional
+/// user tag, plus how it was recorded.  Used to name the clip and fill in
+/// its embedded metadata and sidecar.
+#[derive(Debug, Clone, Default)]
+pub struct ClipInfo {
+    pub display_name: String,
+    pub executable: String,
+    /// Recording session the clip was cut from.
+    pub session_id: String,
+    pub tag: Option<String>,
+    /// Configured buffer length when the clip was requested.
+    pub buffer_length_secs: u32,
+    /// Encoder settings in effect for the recording.
+    pub video: VideoConfig,
+    pub audio: AudioConfig,
+    /// Encoder backend that produced the footage, if known.
+    pub encoder_backend: Option<String>,
+    /// Ring-buffer fill level when the clip was requested.
+    pub buffer: Option<BufferStats>,
+    /// Keeps only the newest this much footage; `None` saves everything.
+    pub max_duration: Option<Duration>,
+    /// When the clip was requested.  Names the clip and dates its metadata,
+    /// however long the flush waits for post-roll or a free flush slot.
+    pub requested_at: DateTime<Local>,
+}
+
+impl ClipInfo {
+    /// A clip of `app` recorded with its effective settings.
+    pub fn for_app(app: &ApplicationConfig, global: &GlobalConfig) -> Self {
+        Self {
+            display_name: app.display_name.clone(),
+            executable: app.executable_name.clone(),
+            buffer_length_secs: app.effective_buffer_length(global),
+            video: app.effective_video(global),
+            audio: app.effective_audio(global),
+            ..Self::default()
+        }
+    }
+
+    /// The tags embedded in a clip saved at `created`.
+    fn metadata(&self, created: DateTime<Local>) -> ClipMetadata {
+        let backend = self.encoder_backend.as_deref().unwrap_or("unknown");
+        ClipMetadata {
+            title: self.display_name.clone(),
+            created: created.with_timezone(&Utc),
+            comment: format!("Buffer length: {} s, encoder: {backend}", self.buffer_length_secs),
+            tag: self.tag.clone(),
+        }
+    }
+
+    /// The sidecar of part `part` of `parts`, holding `segments` recorded
+    /// from `started` to `ended`.
+    fn sidecar(
+        &self,
+        segments: &EpochSegments,
+        (started, ended): (DateTime<Local>, DateTime<Local>),
+        (part, parts): (usize, usize),
+    ) -> ClipSidecar {
+        let (video, audio) = (&segments.epoch.video_params, &segments.epoch.audio_params);
+        let cqp = self.video.rate_control == RateControl::Cqp;
+        let has_audio = segments.segments.iter().any(|s| !s.audio_packets.is_empty());
+        ClipSidecar {
+            version: SIDECAR_VERSION,
+            game: self.display_name.clone(),
+            executable: self.executable.clone(),
+            session_id: self.session_id.clone(),
+            started_at: started.to_rfc3339(),
+            ended_at: ended.to_rfc3339(),
+            duration_secs: part_duration(segments).num_milliseconds() as f64 / 1000.0,
+            part,
+            parts,
+            video: VideoInfo {
+                codec: "h264".into(),
+                width: video.width,
+                height: video.height,
+                fps: self.video.fps,
+                rate_control: self.video.rate_control.as_str().into(),
+                bitrate_kbps: (!cqp).then_some(self.video.bitrate_kbps),
+                qp: cqp.then_some(self.video.qp),
+            },
+            audio: has_audio.then(|| AudioInfo {
+                codec: "aac".into(),
+                sample_rate: audio.sample_rate,
+                channels: audio.channels,
+                bitrate_kbps: self.audio.bitrate_kbps,
+            }),
+            encoder: self.encoder_backend.clone(),
+            tags: self.tag.iter().cloned().collect(),
+            buffer: self.buffer.map(BufferStatus::from),
+            thumbnail: None,
+        }
+    }
+}
+
+/// Playback length of `part`'s video.
+fn part_duration(part: &EpochSegments) -> chrono::Duration {
+    let time_base = part.epoch.video_params.time_base;
+    let total: Duration = part.segments.iter().map(|s| s.duration(time_base)).sum();
+    chrono::Duration::from_std(total).unwrap_or_default()
+}
+
+/// Wall-clock start and end of the footage in `part`, from the times its
+/// first and last segments reached the ring buffer.  Footage without those
+/// stamps is taken to end at `fallback`.
+fn footage_span(
+    part: &EpochSegments,
+    fallback: DateTime<Local>,
+) -> (DateTime<Local>, DateTime<Local>) {
+    let time_base = part.epoch.video_params.time_base;
+    let ended = part.segments.last().and_then(|s| s.pushed_at).unwrap_or(fallback);
+    let started = part.segments.first().and_then(|s| s.started_at(time_base));
+    (started.unwrap_or_else(|| ended - part_duration(part)), ended)
+}
+
+/// Drops the oldest segments of `parts` that are not needed to cover the
+/// newest `max` of footage.  Whole segments are kept, so the result can run
+/// a little over `max`.
+fn keep_newest(parts: Vec<EpochSegments>, max: Duration) -> Vec<EpochSegments> {
+    let mut remaining = max;
+    let mut kept = Vec::new();
+    for mut part in parts.into_iter().rev() {
+        if remaining.is_zero() {
+            break;
+        }
+        let time_base = part.epoch.video_params.time_base;
+        let mut first = part.segments.len();
+        while first > 0 && !remaining.is_zero() {
+            first -= 1;
+            remaining = remaining.saturating_sub(part.segments[first].duration(time_base));
+        }
+        part.segments.drain(..first);
+        kept.push(part);
+    }
+    kept.reverse();
+    kept
+}
+
+/// Writes one clip into `file` with the muxer for `container`.
+fn write_clip(
+    container: ContainerFormat,
+    file: &File,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    match container {
+        ContainerFormat::Mp4 => {
+            mp4::write_file(file, segments, video_params, audio_params, metadata)
+        }
+        ContainerFormat::Fmp4 => {
+            mp4::write_fragmented_file(file, segments, video_params, audio_params, metadata)
+        }
+        ContainerFormat::Mkv => {
+            mkv::write_file(file, segments, video_params, audio_params, metadata)
+        }
     }
+}
+
+// ── Atomic writes ──────────────────────────────────────────────────────────────
+
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Returns the temporary path a clip is written to before it is renamed to
+/// `path`: `<dir>/.<file name>.partial`.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let hidden = format!(".{}", path.file_name().unwrap_or_default().to_string_lossy());
+    sibling_path(&path.with_file_name(hidden), PARTIAL_SUFFIX)
+}
+
+/// Writes `contents` to `path` via its `.partial` file, like a clip, so a
+/// reader never sees a half-written file and one cut short by a crash is
+/// removed by [`sweep_partial_files`].  For the small files saved next to a
+/// clip, such as its sidecar and thumbnail.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let partial = partial_path(path);
+    std::fs::write(&partial, contents)
+        .and_then(|()| std::fs::rename(&partial, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&partial);
+        })
+}
+
+fn is_partial_file(name: &str) -> bool {
+    name.starts_with('.') && name.ends_with(PARTIAL_SUFFIX)
+}
 
+/// Creates a new temporary clip file, marked hidden on Windows.  Fails with
+/// `AlreadyExists` if another flush is writing to the same path.
+fn create_partial(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
     #[cfg(windows)]
     {
-        let output_path = build_output_path(&clip_output_dir, &display_name)?;
-        let path = output_path.clone();
-        tokio::task::spawn_blocking(move || {
-            imp::mux_to_mp4(&segments, &video_params, &audio_params, &path)
-        })
-        .await
-        .map_err(|e| anyhow::anyhow!("Flush task panicked: {e}"))??;
-        Ok(output_path)
+        use std::os::windows::fs::OpenOptionsExt;
+        use windows::Win32::Storage::FileSystem::FILE_ATTRIBUTE_HIDDEN;
+        options.attributes(FILE_ATTRIBUTE_HIDDEN.0);
     }
+    options.open(path)
 }
 
-// ── Windows mux implementation ─────────────────────────────────────────────────
-
+/// Clears the hidden attribute set by [`create_partial`] so the renamed clip
+/// shows up in Explorer.
 #[cfg(windows)]
-mod imp {
-    use anyhow::{bail, Result};
-    use ffmpeg_sys_next as ffsys;
-    use std::ffi::CString;
-    use std::path::PathBuf;
-
-    use crate::ring_buffer::{AudioCodecParams, EncodedPacket, EncodedSegment, VideoCodecParams};
-
-    /// RAII guard that always frees the `AVFormatContext` when dropped.
-    struct OctxGuard(*mut ffsys::AVFormatContext);
-
-    impl Drop for OctxGuard {
-        fn drop(&mut self) {
-            if !self.0.is_null() {
-                unsafe { ffsys::avformat_free_context(self.0) };
-                self.0 = std::ptr::null_mut();
+fn unhide(path: &Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{SetFileAttributesW, FILE_ATTRIBUTE_NORMAL};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    unsafe { SetFileAttributesW(PCWSTR(wide.as_ptr()), FILE_ATTRIBUTE_NORMAL)? };
+    Ok(())
+}
+
+/// A clip path claimed by creating its `.partial` file.  While the partial
+/// file exists no other flush picks the same name.
+struct PartialClip {
+    path: PathBuf,
+    partial: PathBuf,
+    file: File,
+}
+
+impl PartialClip {
+    /// Claims `path`, or returns `None` if a clip is already saved there or
+    /// another flush is writing it.
+    fn claim(path: PathBuf) -> Result<Option<Self>> {
+        let partial = partial_path(&path);
+        let file = match create_partial(&partial) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(None),
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Failed to create {}", partial.display())))
             }
+        };
+        let clip = Self { path, partial, file };
+        // Checked after creating the partial file so a clip renamed into
+        // place by a flush that just finished is not overwritten.
+        if clip.path.exists() {
+            clip.discard();
+            return Ok(None);
         }
+        Ok(Some(clip))
     }
 
-    /// Muxes `segments` into an MP4 file at `output_path`.
-    ///
-    /// Sets `movflags=faststart` on the MP4 muxer so the `moov` atom is written
-    /// to the front of the file (qt-faststart equivalent).
-    pub fn mux_to_mp4(
-        segments: &[EncodedSegment],
+    /// Writes the clip, syncs it to disk and renames it into place.  On
+    /// failure the partial file is deleted and the error names it.
+    fn write(
+        self,
+        container: ContainerFormat,
+        segments: &[Arc<EncodedSegment>],
         video_params: &VideoCodecParams,
         audio_params: &AudioCodecParams,
-        output_path: &PathBuf,
-    ) -> Result<()> {
-        let path_str = output_path.to_string_lossy();
-        let path_c = CString::new(path_str.as_ref())
-            .map_err(|e| anyhow::anyhow!("Invalid path characters: {e}"))?;
-
-        unsafe {
-            // ── Allocate output format context ────────────────────────────────
-            let mut raw_octx: *mut ffsys::AVFormatContext = std::ptr::null_mut();
-            let ret = ffsys::avformat_alloc_output_context2(
-                &mut raw_octx,
-                std::ptr::null_mut(),
-                std::ptr::null(),
-                path_c.as_ptr(),
-            );
-            if ret < 0 || raw_octx.is_null() {
-                bail!("avformat_alloc_output_context2 failed ({})", ret);
-            }
-            // Guard ensures avformat_free_context is always called.
-            let _guard = OctxGuard(raw_octx);
-            let octx = raw_octx;
-
-            // ── Video stream (H.264) ──────────────────────────────────────────
-            let vstream = ffsys::avformat_new_stream(octx, std::ptr::null());
-            if vstream.is_null() {
-                bail!("Failed to create video stream");
-            }
-            (*vstream).id = 0;
-            {
-                let vpar = (*vstream).codecpar;
-                (*vpar).codec_type = ffsys::AVMediaType::AVMEDIA_TYPE_VIDEO;
-                (*vpar).codec_id = ffsys::AVCodecID::AV_CODEC_ID_H264;
-                (*vpar).width = video_params.width as i32;
-                (*vpar).height = video_params.height as i32;
-                (*vpar).format = ffsys::AVPixelFormat::AV_PIX_FMT_YUV420P as i32;
-                if !video_params.extradata.is_empty() {
-                    copy_extradata(vpar, &video_params.extradata);
-                }
-            }
-            (*vstream).time_base = ffsys::AVRational {
-                num: video_params.time_base.0,
-                den: video_params.time_base.1,
-            };
-
-            // ── Audio stream (AAC) ────────────────────────────────────────────
-            let astream = ffsys::avformat_new_stream(octx, std::ptr::null());
-            if astream.is_null() {
-                bail!("Failed to create audio stream");
-            }
-            (*astream).id = 1;
-            {
-                let apar = (*astream).codecpar;
-                (*apar).codec_type = ffsys::AVMediaType::AVMEDIA_TYPE_AUDIO;
-                (*apar).codec_id = ffsys::AVCodecID::AV_CODEC_ID_AAC;
-                (*apar).sample_rate = audio_params.sample_rate as i32;
-                set_channel_layout(apar, audio_params.channels);
-                if !audio_params.extradata.is_empty() {
-                    copy_extradata(apar, &audio_params.extradata);
-                }
-            }
-            (*astream).time_base = ffsys::AVRational {
-                num: audio_params.time_base.0,
-                den: audio_params.time_base.1,
-            };
-
-            // ── movflags=faststart (moov atom at front — no separate pass needed) ─
-            {
-                let key = CString::new("movflags").unwrap();
-                let val = CString::new("faststart").unwrap();
-                ffsys::av_opt_set((*octx).priv_data, key.as_ptr(), val.as_ptr(), 0);
+        metadata: &ClipMetadata,
+    ) -> Result<PathBuf> {
+        let Self { path, partial, file } = self;
+        let result = (|| -> Result<()> {
+            write_clip(container, &file, segments, video_params, audio_params, metadata)?;
+            file.sync_all()?;
+            drop(file);
+            #[cfg(windows)]
+            unhide(&partial)?;
+            std::fs::rename(&partial, &path)?;
+            Ok(())
+        })();
+        match result {
+            Ok(()) => Ok(path),
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(e.context(format!("Discarded unfinished clip {}", partial.display())))
             }
+        }
+    }
 
-            // ── Open the output file ──────────────────────────────────────────
-            let ret = ffsys::avio_open(
-                &mut (*octx).pb,
-                path_c.as_ptr(),
-                ffsys::AVIO_FLAG_WRITE as i32,
-            );
-            if ret < 0 {
-                bail!("avio_open failed ({})", ret);
-            }
+    /// Releases the claim without saving anything.
+    fn discard(self) {
+        drop(self.file);
+        let _ = std::fs::remove_file(&self.partial);
+    }
+}
 
-            // ── Write MP4 header ──────────────────────────────────────────────
-            let ret = ffsys::avformat_write_header(octx, std::ptr::null_mut());
-            if ret < 0 {
-                ffsys::avio_closep(&mut (*octx).pb);
-                bail!("avformat_write_header failed ({})", ret);
-            }
+/// Bounds the `_2`, `_3`, … suffixes tried before giving up on a name.
+const MAX_NAME_ATTEMPTS: u32 = 1000;
 
-            // Capture the (possibly muxer-adjusted) output time bases.
-            let vtb_out = (*vstream).time_base;
-            let atb_out = (*astream).time_base;
-            let vtb_in = ffsys::AVRational {
-                num: video_params.time_base.0,
-                den: video_params.time_base.1,
-            };
-            let atb_in = ffsys::AVRational {
-                num: audio_params.time_base.0,
-                den: audio_params.time_base.1,
-            };
+/// Picks a free name for a clip of `part_count` files (one per codec epoch)
+/// and claims every file.  A name is only used if all of its parts are free,
+/// so the parts of a split clip always share a stem.
+fn reserve_clip(
+    output: &ClipOutput,
+    label: &ClipLabel,
+    part_count: usize,
+) -> Result<Vec<PartialClip>> {
+    let dir = clip_dir(output, label);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create clip directory {}", dir.display()))?;
+    let extension = output.container.extension();
+    let first_counter = count_clips(&dir).saturating_add(1);
 
-            // Compute PTS origin so the clip always starts at presentation time 0.
-            let video_pts_origin = segments
-                .iter()
-                .flat_map(|s| s.video_packets.iter())
-                .next()
-                .map(|p| p.pts)
-                .unwrap_or(0);
-            let audio_pts_origin = segments
-                .iter()
-                .flat_map(|s| s.audio_packets.iter())
-                .next()
-                .map(|p| p.pts)
-                .unwrap_or(0);
-
-            // ── Write all packets interleaved ─────────────────────────────────
-            for segment in segments {
-                for pkt in &segment.video_packets {
-                    write_interleaved(octx, pkt, 0, vtb_in, vtb_out, video_pts_origin);
+    'names: for attempt in 0..MAX_NAME_ATTEMPTS {
+        let stem = clip_stem(&output.filename_template, label, first_counter, attempt);
+        let base = dir.join(format!("{stem}.{extension}"));
+        let mut claimed = Vec::with_capacity(part_count);
+        for index in 1..=part_count {
+            let path = if part_count == 1 { base.clone() } else { part_path(&base, index) };
+            match PartialClip::claim(path) {
+                Ok(Some(clip)) => claimed.push(clip),
+                Ok(None) => {
+                    claimed.into_iter().for_each(PartialClip::discard);
+                    continue 'names;
                 }
-                for pkt in &segment.audio_packets {
-                    write_interleaved(octx, pkt, 1, atb_in, atb_out, audio_pts_origin);
+                Err(e) => {
+                    claimed.into_iter().for_each(PartialClip::discard);
+                    return Err(e);
                 }
             }
-
-            // ── Finalise ──────────────────────────────────────────────────────
-            ffsys::av_write_trailer(octx);
-            ffsys::avio_closep(&mut (*octx).pb);
-            // _guard drops here → avformat_free_context(octx).
         }
-
-        Ok(())
+        return Ok(claimed);
     }
+    anyhow::bail!("No free clip name left in {} after {MAX_NAME_ATTEMPTS} attempts", dir.display())
+}
+
+/// What [`sweep_partial_files`] did with the leftovers of unfinished flushes.
+#[derive(Debug, Default, PartialEq)]
+pub struct Sweep {
+    /// Fragmented MP4 and Matroska clips cut short that still play, renamed
+    /// from their partial file to the clip path, as `(partial, clip)`.
+    pub recovered: Vec<(PathBuf, PathBuf)>,
+    /// Partial files that were deleted.
+    pub removed: Vec<PathBuf>,
+}
 
-    /// Allocates and copies `data` into `par->extradata` with the required
-    /// `AV_INPUT_BUFFER_PADDING_SIZE` zero padding appended.
-    unsafe fn copy_extradata(par: *mut ffsys::AVCodecParameters, data: &[u8]) {
-        // AV_INPUT_BUFFER_PADDING_SIZE == 64 in FFmpeg 6.x.
-        let padding = 64usize;
-        let ptr = ffsys::av_mallocz(data.len() + padding) as *mut u8;
-        if !ptr.is_null() {
-            std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
-            (*par).extradata = ptr;
-            (*par).extradata_size = data.len() as i32;
+/// Cleans up the `.partial` files left anywhere under `clip_output_dir` by
+/// flushes that never finished.  A fragmented MP4 or Matroska clip with at
+/// least one complete fragment or Cluster is kept under its clip name (or the
+/// next free `_2`, `_3`, … name); everything else, including faststart MP4s,
+/// sidecars and thumbnails, is deleted.
+pub fn sweep_partial_files(clip_output_dir: &str) -> Sweep {
+    let mut sweep = Sweep::default();
+    let mut dirs = vec![clip_root(clip_output_dir)];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else { continue };
+            let path = entry.path();
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if is_partial_file(&entry.file_name().to_string_lossy()) {
+                if let Some(clip) = recover_partial(&path) {
+                    sweep.recovered.push((path, clip));
+                } else if std::fs::remove_file(&path).is_ok() {
+                    sweep.removed.push(path);
+                }
+            }
         }
     }
+    sweep
+}
 
-    /// Sets the channel layout on `par` for the given number of channels.
-    ///
-    /// FFmpeg uses `AVChannelLayout` in `AVCodecParameters`.
-    /// For AAC the `extradata` (AudioSpecificConfig) carries the full channel
-    /// description, so setting `nb_channels` is sufficient for the muxer.
-    unsafe fn set_channel_layout(par: *mut ffsys::AVCodecParameters, channels: u32) {
-        (*par).ch_layout.nb_channels = channels as i32;
-        if channels == 2 {
-            // AV_CHANNEL_ORDER_NATIVE = 1; AV_CH_LAYOUT_STEREO mask = 0x3.
-            (*par).ch_layout.order =
-                std::mem::transmute::<u32, ffsys::AVChannelOrder>(1u32);
-            (*par).ch_layout.u.mask = 0x3u64;
-        }
+/// Renames the partial file at `partial` to a free clip name if it is a clip
+/// that still plays, and returns the new path.
+fn recover_partial(partial: &Path) -> Option<PathBuf> {
+    let name = partial.file_name()?.to_string_lossy();
+    let clip = partial.with_file_name(name.strip_prefix('.')?.strip_suffix(PARTIAL_SUFFIX)?);
+    let extension = clip.extension()?.to_str()?;
+    let mut file = File::open(partial).ok()?;
+    let playable = if extension == ContainerFormat::Mkv.extension() {
+        mkv::has_complete_cluster(&mut file)
+    } else if extension == ContainerFormat::Fmp4.extension() {
+        mp4::has_complete_fragment(&mut file)
+    } else {
+        return None;
+    };
+    drop(file);
+    if !playable.unwrap_or(false) {
+        return None;
     }
+    let stem = clip.file_stem()?.to_string_lossy().into_owned();
+    let path = (1..=MAX_NAME_ATTEMPTS)
+        .map(|n| match n {
+            1 => clip.clone(),
+            n => clip.with_file_name(format!("{stem}_{n}.{extension}")),
+        })
+        .find(|path| !path.exists())?;
+    #[cfg(windows)]
+    unhide(partial).ok()?;
+    std::fs::rename(partial, &path).ok()?;
+    Some(path)
+}
+
+// ── Public flush entry point ───────────────────────────────────────────────────
 
-    /// Allocates a packet, copies the encoded data, rescales timestamps from the
-    /// encoder time base to the muxer stream time base, then writes it
-    /// interleaved into the output context.
-    ///
-    /// `pts_origin` is subtracted before rescaling so all clips start at t = 0.
-    ///
-    /// Errors are logged to stderr; individual packet failures are non-fatal so
-    /// that a corrupt packet doesn't abort the entire flush.
-    unsafe fn write_interleaved(
-        octx: *mut ffsys::AVFormatContext,
-        pkt: &EncodedPacket,
-        stream_index: i32,
-        in_tb: ffsys::AVRational,
-        out_tb: ffsys::AVRational,
-        pts_origin: i64,
-    ) {
-        let avpkt = ffsys::av_packet_alloc();
-        if avpkt.is_null() {
-            eprintln!("[flush] av_packet_alloc returned null — skipping packet");
-            return;
+/// One file of a saved clip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedClip {
+    pub path: PathBuf,
+    /// The thumbnail written next to it; `None` if disabled or it failed.
+    pub thumbnail: Option<PathBuf>,
+}
+
+/// Muxes the segments in `snapshot` into clip files, one per codec epoch, and
+/// returns the saved files oldest first.  `on_part_written` is
+/// called with `(written, total)` after each file is complete.
+///
+/// Reading spilled segments back from disk and the mux both run on a blocking
+/// thread via [`tokio::task::spawn_blocking`] so the async event loop stays
+/// responsive while the files are being written.
+pub async fn flush_to_disk(
+    snapshot: BufferSnapshot,
+    output: ClipOutput,
+    info: ClipInfo,
+    mut on_part_written: impl FnMut(usize, usize) + Send + 'static,
+) -> Result<Vec<SavedClip>> {
+    tokio::task::spawn_blocking(move || -> Result<Vec<SavedClip>> {
+        let time = info.requested_at;
+        let mut duration = snapshot.duration();
+        let mut parts = snapshot.load()?;
+        if let Some(max) = info.max_duration {
+            parts = keep_newest(parts, max);
+            let kept: chrono::Duration = parts.iter().map(part_duration).sum();
+            duration = kept.to_std().unwrap_or_default();
+        }
+        if parts.is_empty() {
+            anyhow::bail!("Ring buffer is empty — nothing to save");
         }
+        let label = ClipLabel {
+            game: &info.display_name,
+            tag: info.tag.as_deref().unwrap_or_default(),
+            duration,
+            time,
+        };
+        let metadata = info.metadata(label.time);
+        disk::ensure_space(&clip_dir(&output, &label), disk::estimate_clip_bytes(&parts))?;
 
-        let ret = ffsys::av_new_packet(avpkt, pkt.data.len() as i32);
-        if ret < 0 {
-            let mut p = avpkt;
-            ffsys::av_packet_free(&mut p);
-            eprintln!("[flush] av_new_packet failed ({ret}) — skipping packet");
-            return;
+        let mut clips = reserve_clip(&output, &label, parts.len())?.into_iter();
+        let mut saved = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let clip = clips.next().expect("one reserved file per part");
+            let (video, audio) = (&part.epoch.video_params, &part.epoch.audio_params);
+            let path = match clip.write(output.container, &part.segments, video, audio, &metadata) {
+                Ok(path) => path,
+                Err(e) => {
+                    clips.for_each(PartialClip::discard);
+                    return Err(e);
+                }
+            };
+            let span = footage_span(part, info.requested_at);
+            let mut sidecar = info.sidecar(part, span, (i + 1, parts.len()));
+            let mut thumbnail = None;
+            if output.thumbnail_width > 0 {
+                match thumbnail::write_thumbnail(&path, part, output.thumbnail_width) {
+                    Ok(thumb) => {
+                        let name = thumb.file_name().unwrap_or_default().to_string_lossy();
+                        sidecar.thumbnail = Some(name.into_owned());
+                        thumbnail = Some(thumb);
+                    }
+                    Err(e) => eprintln!("[flush] {e:#}"),
+                }
+            }
+            if let Err(e) = sidecar::write_sidecar(&path, &sidecar) {
+                eprintln!("[flush] {e:#}");
+            }
+            saved.push(SavedClip { path, thumbnail });
+            on_part_written(i + 1, parts.len());
         }
+        Ok(saved)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Flush task panicked: {e}"))?
+}
 
-        std::ptr::copy_nonoverlapping(pkt.data.as_ptr(), (*avpkt).data, pkt.data.len());
-        (*avpkt).pts = pkt.pts - pts_origin;
-        (*avpkt).dts = pkt.dts - pts_origin;
-        (*avpkt).duration = pkt.duration;
-        (*avpkt).flags = if pkt.is_key {
-            ffsys::AV_PKT_FLAG_KEY as i32
-        } else {
-            0
-        };
-        (*avpkt).stream_index = stream_index;
+// ── Post-roll ──────────────────────────────────────────────────────────────────
+
+/// A clip whose hotkey has been pressed but which is still recording
+/// post-roll.  Holds the buffer as it was at the press plus every segment
+/// pushed since, so nothing is lost to eviction while waiting.  The clip is
+/// still named after the first press (its [`ClipInfo::requested_at`]).
+pub struct PendingClip {
+    /// The flush job ID reserved when the hotkey was pressed.
+    job: JobId,
+    snapshot: BufferSnapshot,
+    info: ClipInfo,
+    post_roll: Duration,
+    /// Footage collected since the most recent hotkey press.
+    since_press: Duration,
+}
 
-        ffsys::av_packet_rescale_ts(avpkt, in_tb, out_tb);
+impl PendingClip {
+    pub fn new(job: JobId, snapshot: BufferSnapshot, info: ClipInfo, post_roll: Duration) -> Self {
+        Self { job, snapshot, info, post_roll, since_press: Duration::ZERO }
+    }
 
-        let ret = ffsys::av_interleaved_write_frame(octx, avpkt);
-        // av_interleaved_write_frame unrefs the packet data on both success and
-        // failure; we still need to free the packet struct itself.
-        let mut p = avpkt;
-        ffsys::av_packet_free(&mut p);
+    pub fn job(&self) -> JobId {
+        self.job
+    }
 
-        if ret < 0 {
-            eprintln!("[flush] av_interleaved_write_frame failed ({ret})");
-        }
+    /// Restarts the post-roll wait, so the clip runs until `post_roll` after
+    /// the latest hotkey press.
+    pub fn extend(&mut self) {
+        self.since_press = Duration::ZERO;
+    }
+
+    /// Adds the segments pushed into `rb` since the last call.  Returns true
+    /// once the post-roll has been recorded.
+    pub fn collect(&mut self, rb: &RingBuffer) -> bool {
+        let later = rb.snapshot_after(self.snapshot.last_seq());
+        self.since_press += later.duration();
+        self.snapshot.append(later);
+        self.since_press >= self.post_roll
+    }
+
+    pub fn into_parts(self) -> (JobId, BufferSnapshot, ClipInfo) {
+        (self.job, self.snapshot, self.info)
     }
 }
 
@@ -346,6 +767,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
     fn expand_env_replaces_userprofile() {
@@ -377,8 +799,9 @@ mod tests {
     }
 
     #[test]
-    fn local_timestamp_has_correct_format() {
-        let ts = local_timestamp();
+    fn default_template_renders_timestamp() {
+        let label = ClipLabel { time: Local::now(), ..label("Game") };
+        let ts = render_template(DEFAULT_CLIP_FILENAME_TEMPLATE, &label, None);
         // Should match YYYY-MM-DD_HH-MM-SS (19 chars).
         assert_eq!(ts.len(), 19, "Unexpected timestamp length: {ts}");
         assert_eq!(&ts[4..5], "-");
@@ -389,20 +812,18 @@ mod tests {
     }
 
     #[test]
-    fn build_output_path_creates_directory_and_has_mp4_extension() {
+    fn reserve_clip_creates_directory_and_has_mp4_extension() {
         let dir = tempfile::tempdir().unwrap();
-        let clip_dir = dir.path().to_string_lossy().into_owned();
-        let path = build_output_path(&clip_dir, "Rocket League").unwrap();
+        let path = reserve_one(dir.path(), ContainerFormat::Mp4, &label("Rocket League"));
         assert!(path.parent().unwrap().exists());
         assert_eq!(path.extension().unwrap(), "mp4");
         assert!(path.parent().unwrap().ends_with("Rocket League"));
     }
 
     #[test]
-    fn build_output_path_sanitizes_display_name() {
+    fn reserve_clip_sanitizes_display_name() {
         let dir = tempfile::tempdir().unwrap();
-        let clip_dir = dir.path().to_string_lossy().into_owned();
-        let path = build_output_path(&clip_dir, r#"Game: "Test""#).unwrap();
+        let path = reserve_one(dir.path(), ContainerFormat::Mp4, &label(r#"Game: "Test""#));
         let parent_name = path.parent().unwrap().file_name().unwrap().to_string_lossy();
         assert!(!parent_name.contains(':'));
         assert!(!parent_name.contains('"'));
@@ -447,13 +868,13 @@ mod tests {
         assert!(!result.contains('?'), "question mark should be replaced: {result}");
     }
 
-    // ── build_output_path: filename format ────────────────────────────────────
+    // ── reserve_clip: filename format ─────────────────────────────────────────
 
     #[test]
-    fn build_output_path_filename_has_timestamp_format() {
+    fn reserve_clip_filename_has_timestamp_format() {
         let dir = tempfile::tempdir().unwrap();
-        let clip_dir = dir.path().to_string_lossy().into_owned();
-        let path = build_output_path(&clip_dir, "TestGame").unwrap();
+        let label = ClipLabel { time: Local::now(), ..label("TestGame") };
+        let path = reserve_one(dir.path(), ContainerFormat::Mp4, &label);
         let stem = path.file_stem().unwrap().to_string_lossy();
         // Stem should be YYYY-MM-DD_HH-MM-SS (19 characters).
         assert_eq!(stem.len(), 19, "Unexpected stem: {stem}");
@@ -464,21 +885,575 @@ mod tests {
         assert_eq!(&stem[16..17], "-");
     }
 
-    // ── flush_to_disk: empty-segments guard ───────────────────────────────────
+    // ── part_path ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn part_path_appends_index_to_stem() {
+        let base = PathBuf::from("clips").join("Game").join("2026-01-01_12-00-00.mp4");
+        let part = part_path(&base, 2);
+        assert_eq!(part.file_name().unwrap(), "2026-01-01_12-00-00_part2.mp4");
+        assert_eq!(part.parent(), base.parent());
+    }
+
+    #[test]
+    fn part_path_keeps_container_extension() {
+        let base = PathBuf::from("2026-01-01_12-00-00.mkv");
+        assert_eq!(part_path(&base, 1), PathBuf::from("2026-01-01_12-00-00_part1.mkv"));
+    }
+
+    #[test]
+    fn reserve_clip_uses_container_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = reserve_one(dir.path(), ContainerFormat::Mkv, &label("Game"));
+        assert_eq!(path.extension().unwrap(), "mkv");
+    }
+
+    // ── Name templates ────────────────────────────────────────────────────────
+
+    /// A label for a 30 s clip taken at 2026-01-02 03:04:05.067.
+    fn label(game: &str) -> ClipLabel<'_> {
+        let time = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        ClipLabel {
+            game,
+            tag: "",
+            duration: Duration::from_millis(29_600),
+            time: time + chrono::TimeDelta::milliseconds(67),
+        }
+    }
+
+    /// Reserves a single-file clip with the default templates, releases it
+    /// and returns its path.
+    fn reserve_one(dir: &Path, container: ContainerFormat, label: &ClipLabel) -> PathBuf {
+        let mut clips = reserve_clip(&output(dir, container), label, 1).unwrap();
+        let clip = clips.remove(0);
+        let path = clip.path.clone();
+        clip.discard();
+        path
+    }
+
+    #[test]
+    fn render_template_expands_every_placeholder() {
+        let label = ClipLabel { tag: "ace", ..label("Rocket League") };
+        let template = "{game}_{date}_{time}.{ms}_{duration}s_{counter}_{tag}";
+        assert_eq!(
+            render_template(template, &label, Some(7)),
+            "Rocket League_2026-01-02_03-04-05.067_30s_007_ace"
+        );
+        assert_eq!(render_template("#{counter}", &label, None), "#");
+    }
+
+    #[test]
+    fn render_template_keeps_separators_out_of_values() {
+        let label = ClipLabel { tag: "a/b", ..label(r"C:\Game") };
+        assert_eq!(render_template("{game}-{tag}", &label, None), "C__Game-a_b");
+    }
+
+    #[test]
+    fn clip_stem_trims_empty_placeholders_and_falls_back() {
+        assert_eq!(clip_stem("{tag}_{date}", &label("G"), 1, 0), "2026-01-02");
+        assert_eq!(clip_stem("{tag}", &label("G"), 1, 0), "2026-01-02_03-04-05");
+        assert_eq!(clip_stem(". {game} .", &label("G"), 1, 0), "G");
+    }
+
+    #[test]
+    fn clip_stem_adds_suffix_or_counts_up() {
+        assert_eq!(clip_stem("{date}", &label("G"), 1, 0), "2026-01-02");
+        assert_eq!(clip_stem("{date}", &label("G"), 1, 2), "2026-01-02_3");
+        assert_eq!(clip_stem("clip{counter}", &label("G"), 4, 2), "clip006");
+    }
+
+    #[test]
+    fn clip_dir_renders_each_component() {
+        let mut out = output(Path::new("clips"), ContainerFormat::Mp4);
+        out.dir_template = r"{game}/{date}\{tag}".into();
+        let dir = clip_dir(&out, &label("A/B"));
+        assert_eq!(dir, Path::new("clips").join("A_B").join("2026-01-02"));
+        out.dir_template = String::new();
+        assert_eq!(clip_dir(&out, &label("G")), Path::new("clips"));
+    }
+
+    // ── Unique names ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn reserve_clip_adds_suffix_when_name_is_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = output(dir.path(), ContainerFormat::Mp4);
+        let game = dir.path().join("G");
+
+        // An in-progress flush holds the first name, a saved clip the second.
+        let held = reserve_clip(&out, &label("G"), 1).unwrap();
+        assert_eq!(held[0].path, game.join("2026-01-02_03-04-05.mp4"));
+        std::fs::write(game.join("2026-01-02_03-04-05_2.mp4"), b"clip").unwrap();
+
+        let next = reserve_clip(&out, &label("G"), 1).unwrap();
+        assert_eq!(next[0].path, game.join("2026-01-02_03-04-05_3.mp4"));
+        held.into_iter().chain(next).for_each(PartialClip::discard);
+        assert_eq!(files_in(&game), vec!["2026-01-02_03-04-05_2.mp4"]);
+    }
+
+    #[test]
+    fn reserve_clip_counts_existing_clips() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut out = output(dir.path(), ContainerFormat::Mp4);
+        out.filename_template = "clip_{counter}".into();
+        let game = dir.path().join("G");
+        std::fs::create_dir_all(&game).unwrap();
+        for name in ["a.mp4", "b.MKV", "notes.txt", ".c.mp4.partial"] {
+            std::fs::write(game.join(name), b"").unwrap();
+        }
+        std::fs::write(game.join("clip_003.mp4"), b"").unwrap();
+
+        let clips = reserve_clip(&out, &label("G"), 1).unwrap();
+        assert_eq!(clips[0].path, game.join("clip_004.mp4"));
+    }
+
+    #[test]
+    fn reserve_clip_keeps_parts_under_one_stem() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = output(dir.path(), ContainerFormat::Mp4);
+        let game = dir.path().join("G");
+        std::fs::create_dir_all(&game).unwrap();
+        std::fs::write(game.join("2026-01-02_03-04-05_part2.mp4"), b"clip").unwrap();
+
+        let clips = reserve_clip(&out, &label("G"), 2).unwrap();
+        let names: Vec<_> = clips.iter().map(|c| c.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["2026-01-02_03-04-05_2_part1.mp4", "2026-01-02_03-04-05_2_part2.mp4"]);
+        clips.into_iter().for_each(PartialClip::discard);
+        assert_eq!(files_in(&game), vec!["2026-01-02_03-04-05_part2.mp4"]);
+    }
+
+    // ── Atomic writes ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn partial_path_is_hidden_sibling() {
+        let path = PathBuf::from("clips").join("Game").join("2026-01-01_12-00-00.mp4");
+        let partial = partial_path(&path);
+        assert_eq!(partial.file_name().unwrap(), ".2026-01-01_12-00-00.mp4.partial");
+        assert_eq!(partial.parent(), path.parent());
+        assert!(is_partial_file(".2026-01-01_12-00-00.mp4.partial"));
+        assert!(!is_partial_file("2026-01-01_12-00-00.mp4"));
+    }
+
+    #[test]
+    fn sweep_removes_only_partial_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let game = dir.path().join("Game");
+        std::fs::create_dir_all(&game).unwrap();
+        std::fs::write(game.join("a.mp4"), b"clip").unwrap();
+        std::fs::write(game.join(".b.mp4.partial"), b"half").unwrap();
+
+        let sweep = sweep_partial_files(&dir.path().to_string_lossy());
+        assert_eq!(sweep.removed, vec![game.join(".b.mp4.partial")]);
+        assert!(sweep.recovered.is_empty());
+        assert!(game.join("a.mp4").exists());
+    }
+
+    #[test]
+    fn sweep_recovers_interrupted_fragmented_and_matroska_clips() {
+        let dir = tempfile::tempdir().unwrap();
+        let game = dir.path().join("Game");
+        std::fs::create_dir_all(&game).unwrap();
+        let part = make_part(640);
+        let (video, audio) = (&part.epoch.video_params, &part.epoch.audio_params);
+        let meta = clip_info("Game").metadata(Local::now());
+        let mut fragmented = vec![];
+        mp4::write_fragmented_mp4(&mut fragmented, &part.segments, video, audio, &meta).unwrap();
+        let mut matroska = vec![];
+        mkv::write_mkv(&mut matroska, &part.segments, video, audio, &meta).unwrap();
+        let mut faststart = vec![];
+        mp4::write_mp4(&mut faststart, &part.segments, video, audio, &meta).unwrap();
+        std::fs::write(game.join("a.mp4"), b"clip").unwrap();
+        std::fs::write(game.join(".a.mp4.partial"), &fragmented).unwrap();
+        std::fs::write(game.join(".b.mkv.partial"), &matroska).unwrap();
+        std::fs::write(game.join(".c.mp4.partial"), &faststart[..faststart.len() - 1]).unwrap();
+        std::fs::write(game.join(".b.mkv.json.partial"), b"{").unwrap();
+
+        let mut sweep = sweep_partial_files(&dir.path().to_string_lossy());
+        sweep.recovered.sort();
+        sweep.removed.sort();
+        assert_eq!(
+            sweep.recovered,
+            vec![
+                (game.join(".a.mp4.partial"), game.join("a_2.mp4")),
+                (game.join(".b.mkv.partial"), game.join("b.mkv")),
+            ]
+        );
+        assert_eq!(
+            sweep.removed,
+            vec![game.join(".b.mkv.json.partial"), game.join(".c.mp4.partial")]
+        );
+        assert_eq!(std::fs::read(game.join("a_2.mp4")).unwrap(), fragmented);
+        assert_eq!(std::fs::read(game.join("a.mp4")).unwrap(), b"clip");
+    }
+
+    #[test]
+    fn sweep_of_missing_directory_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        let sweep = sweep_partial_files(&dir.path().join("missing").to_string_lossy());
+        assert_eq!(sweep, Sweep::default());
+    }
+
+    // ── flush_to_disk ─────────────────────────────────────────────────────────
+
+    use crate::ring_buffer::CodecEpoch;
+    use crate::test_support::{clip_info, make_part};
+
+    fn paths(saved: Vec<SavedClip>) -> Vec<PathBuf> {
+        saved.into_iter().map(|clip| clip.path).collect()
+    }
+
+    fn files_in(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn output(dir: &Path, container: ContainerFormat) -> ClipOutput {
+        ClipOutput {
+            clip_output_dir: dir.to_string_lossy().into_owned(),
+            dir_template: config::DEFAULT_CLIP_DIR_TEMPLATE.into(),
+            filename_template: DEFAULT_CLIP_FILENAME_TEMPLATE.into(),
+            container,
+            thumbnail_width: 0,
+        }
+    }
+
+    // ── PendingClip ───────────────────────────────────────────────────────────
+
+    /// A ring buffer in `make_part`'s epoch, where each pushed frame lasts 1/30 s.
+    fn recording_buffer() -> RingBuffer {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_part(320).epoch);
+        rb
+    }
+
+    fn push_frames(rb: &mut RingBuffer, frames: usize) {
+        for _ in 0..frames {
+            let segment = make_part(320).segments.remove(0);
+            rb.push(Arc::unwrap_or_clone(segment));
+        }
+    }
+
+    #[test]
+    fn pending_clip_waits_for_post_roll() {
+        let mut rb = recording_buffer();
+        push_frames(&mut rb, 4);
+        let info = clip_info("Game");
+        let mut clip = PendingClip::new(1, rb.snapshot(), info, Duration::from_millis(90));
+        push_frames(&mut rb, 2);
+        assert!(!clip.collect(&rb));
+        push_frames(&mut rb, 1);
+        assert!(clip.collect(&rb));
+        assert_eq!(clip.into_parts().1.segment_count(), 7);
+    }
+
+    #[test]
+    fn pending_clip_extend_restarts_the_wait() {
+        let mut rb = recording_buffer();
+        push_frames(&mut rb, 1);
+        let info = clip_info("Game");
+        let mut clip = PendingClip::new(1, rb.snapshot(), info, Duration::from_millis(90));
+        push_frames(&mut rb, 2);
+        assert!(!clip.collect(&rb));
+        clip.extend();
+        push_frames(&mut rb, 2);
+        assert!(!clip.collect(&rb));
+        push_frames(&mut rb, 1);
+        assert!(clip.collect(&rb));
+        assert_eq!(clip.into_parts().1.segment_count(), 6);
+    }
+
+    // ── keep_newest ───────────────────────────────────────────────────────────
+
+    /// `part` with every segment stamped as pushed at `pushed_at`.
+    fn pushed(mut part: EpochSegments, pushed_at: DateTime<Local>) -> EpochSegments {
+        for segment in &mut part.segments {
+            Arc::make_mut(segment).pushed_at = Some(pushed_at);
+        }
+        part
+    }
+
+    /// `make_part(width)` holding `frames` one-frame segments of 1/30 s each.
+    fn part_of(width: u32, frames: usize) -> EpochSegments {
+        let mut part = make_part(width);
+        part.segments = vec![Arc::clone(&part.segments[0]); frames];
+        part
+    }
+
+    #[test]
+    fn keep_newest_trims_whole_segments_from_the_front() {
+        let kept = keep_newest(vec![part_of(320, 3), part_of(640, 3)], Duration::from_millis(50));
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].epoch.video_params.width, 640);
+        assert_eq!(kept[0].segments.len(), 2);
+    }
+
+    #[test]
+    fn keep_newest_spans_codec_epochs() {
+        let kept = keep_newest(vec![part_of(320, 3), part_of(640, 3)], Duration::from_millis(150));
+        let lengths: Vec<_> = kept.iter().map(|part| part.segments.len()).collect();
+        assert_eq!(lengths, [2, 3]);
+        let all = keep_newest(vec![part_of(320, 3), part_of(640, 3)], Duration::from_secs(60));
+        assert_eq!(all.iter().map(|part| part.segments.len()).sum::<usize>(), 6);
+    }
 
     #[tokio::test]
     async fn flush_to_disk_with_empty_segments_returns_error() {
-        use crate::ring_buffer::{AudioCodecParams, VideoCodecParams};
+        let mut empty = make_part(320);
+        empty.segments.clear();
         let result = flush_to_disk(
-            vec![],
-            VideoCodecParams { extradata: vec![], width: 1920, height: 1080, time_base: (1, 60) },
-            AudioCodecParams { extradata: vec![], sample_rate: 48_000, channels: 2, time_base: (1, 48_000) },
-            std::env::temp_dir().to_string_lossy().into_owned(),
-            "TestGame".to_string(),
+            vec![empty].into(),
+            output(&std::env::temp_dir(), ContainerFormat::Mp4),
+            clip_info("TestGame"),
+            |_, _| {},
         )
         .await;
         assert!(result.is_err());
         let msg = result.unwrap_err().to_string();
         assert!(msg.contains("empty"), "Expected 'empty' in error: {msg}");
     }
+
+    #[tokio::test]
+    async fn flush_to_disk_writes_mp4_from_hand_made_packets() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = flush_to_disk(
+            vec![make_part(320)].into(),
+            output(dir.path(), ContainerFormat::Mp4),
+            clip_info("TestGame"),
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+        let paths = paths(saved);
+        assert_eq!(paths.len(), 1);
+        assert!(!paths[0].to_string_lossy().contains("_part"));
+        let bytes = std::fs::read(&paths[0]).unwrap();
+        assert_eq!(&bytes[4..8], b"ftyp");
+        let name = paths[0].file_name().unwrap().to_string_lossy().into_owned();
+        let sidecar = format!("{name}.json");
+        assert_eq!(files_in(paths[0].parent().unwrap()), [name, sidecar], "no partial file left");
+    }
+
+    #[tokio::test]
+    async fn failed_write_discards_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut part = make_part(320);
+        // Audio only: the muxer rejects a clip without video mid-write.
+        part.segments = vec![Arc::new(EncodedSegment {
+            video_packets: vec![],
+            audio_packets: part.segments[0].audio_packets.clone(),
+            pushed_at: None,
+        })];
+        let err = flush_to_disk(
+            vec![part].into(),
+            output(dir.path(), ContainerFormat::Mp4),
+            clip_info("TestGame"),
+            |_, _| {},
+        )
+        .await
+        .unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains("Discarded unfinished clip"), "{msg}");
+        assert!(msg.contains(".partial"), "{msg}");
+        assert!(files_in(&dir.path().join("TestGame")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn flush_to_disk_splits_clip_per_epoch() {
+        let dir = tempfile::tempdir().unwrap();
+        let progress = Arc::new(std::sync::Mutex::new(vec![]));
+        let reported = Arc::clone(&progress);
+        let saved = flush_to_disk(
+            vec![make_part(320), make_part(640)].into(),
+            output(dir.path(), ContainerFormat::Mp4),
+            clip_info("TestGame"),
+            move |written, total| reported.lock().unwrap().push((written, total)),
+        )
+        .await
+        .unwrap();
+        let paths = paths(saved);
+        assert_eq!(*progress.lock().unwrap(), vec![(1, 2), (2, 2)]);
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].to_string_lossy().ends_with("_part1.mp4"));
+        assert!(paths[1].to_string_lossy().ends_with("_part2.mp4"));
+        assert!(paths.iter().all(|p| p.exists()));
+    }
+
+    #[tokio::test]
+    async fn flush_to_disk_writes_sidecar_per_part() {
+        let dir = tempfile::tempdir().unwrap();
+        let info = ClipInfo {
+            executable: "game.exe".into(),
+            session_id: "s1".into(),
+            tag: Some("ace".into()),
+            encoder_backend: Some("x264".into()),
+            ..clip_info("TestGame")
+        };
+        // Recorded well before the request, as with a queued or post-roll clip.
+        let first_pushed = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        let second_pushed = first_pushed + chrono::Duration::nanoseconds(33_333_333);
+        let parts =
+            vec![pushed(make_part(320), first_pushed), pushed(make_part(640), second_pushed)];
+        let saved = flush_to_disk(
+            parts.into(),
+            output(dir.path(), ContainerFormat::Mp4),
+            info,
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+        let paths = paths(saved);
+
+        let sidecars: Vec<ClipSidecar> = paths
+            .iter()
+            .map(|p| {
+                let json = std::fs::read_to_string(sidecar::sidecar_path(p)).unwrap();
+                serde_json::from_str(&json).unwrap()
+            })
+            .collect();
+        let first = &sidecars[0];
+        assert_eq!((first.game.as_str(), first.executable.as_str()), ("TestGame", "game.exe"));
+        assert_eq!(first.session_id, "s1");
+        assert_eq!(first.tags, ["ace"]);
+        assert_eq!(first.encoder.as_deref(), Some("x264"));
+        assert_eq!((first.part, first.parts), (1, 2));
+        assert_eq!((first.video.width, first.video.height), (320, 240));
+        assert_eq!(first.video.bitrate_kbps, Some(config::DEFAULT_VIDEO_BITRATE_KBPS));
+        assert_eq!(first.audio.as_ref().unwrap().sample_rate, 48_000);
+        // One 1/30 s frame per part, the second following the first.
+        assert_eq!(first.duration_secs, 0.033);
+        assert_eq!(first.ended_at, first_pushed.to_rfc3339());
+        assert_eq!(sidecars[1].video.width, 640);
+        assert_eq!(sidecars[1].started_at, first.ended_at);
+        assert_eq!(sidecars[1].ended_at, second_pushed.to_rfc3339());
+    }
+
+    #[tokio::test]
+    async fn flush_to_disk_writes_thumbnail_when_frame_decodes() {
+        use crate::encoder::{EncoderBackend, EncoderConfig};
+        use crate::software_encoder::SoftwareBackend;
+
+        let cfg = EncoderConfig { width: 64, height: 48, ..EncoderConfig::default() };
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut video_packets = vec![];
+        let frame = crate::capture::RawFrame::packed(vec![0x80; 64 * 48 * 4], 64, 48);
+        backend.encode_video(&frame, &mut video_packets).unwrap();
+        let decodable = EpochSegments {
+            epoch: CodecEpoch {
+                video_params: backend.video_params().clone(),
+                audio_params: backend.audio_params().clone(),
+            },
+            segments: vec![Arc::new(EncodedSegment {
+                video_packets,
+                audio_packets: vec![],
+                pushed_at: None,
+            })],
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = ClipOutput { thumbnail_width: 32, ..output(dir.path(), ContainerFormat::Mp4) };
+        let parts = vec![make_part(320), decodable].into();
+        let saved = flush_to_disk(parts, output, clip_info("TestGame"), |_, _| {}).await.unwrap();
+
+        // The first part's placeholder packet cannot be decoded; the clip is
+        // still saved, just without a thumbnail.
+        assert_eq!(saved[0].thumbnail, None);
+        assert!(!thumbnail::thumbnail_path(&saved[0].path).exists());
+        assert_eq!(saved[1].thumbnail, Some(thumbnail::thumbnail_path(&saved[1].path)));
+        assert!(thumbnail::thumbnail_path(&saved[1].path).exists());
+        let json = std::fs::read_to_string(sidecar::sidecar_path(&saved[1].path)).unwrap();
+        let sidecar: ClipSidecar = serde_json::from_str(&json).unwrap();
+        let name = saved[1].path.file_name().unwrap().to_string_lossy();
+        assert_eq!(sidecar.thumbnail, Some(format!("{name}.png")));
+    }
+
+    #[tokio::test]
+    async fn flush_to_disk_writes_mkv_when_selected() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = flush_to_disk(
+            vec![make_part(320)].into(),
+            output(dir.path(), ContainerFormat::Mkv),
+            clip_info("TestGame"),
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+        let paths = paths(saved);
+        assert_eq!(paths[0].extension().unwrap(), "mkv");
+        let bytes = std::fs::read(&paths[0]).unwrap();
+        assert_eq!(&bytes[..4], &[0x1A, 0x45, 0xDF, 0xA3]);
+    }
+
+    #[tokio::test]
+    async fn simultaneous_flushes_get_distinct_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let save = || {
+            flush_to_disk(
+                vec![make_part(320)].into(),
+                output(dir.path(), ContainerFormat::Mp4),
+                clip_info("TestGame"),
+                |_, _| {},
+            )
+        };
+        let (a, b) = tokio::join!(save(), save());
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_ne!(a, b);
+        let files = files_in(&dir.path().join("TestGame"));
+        assert_eq!(files.iter().filter(|name| is_clip_file(name)).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn flush_to_disk_names_clip_from_template_and_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut out = output(dir.path(), ContainerFormat::Mkv);
+        out.filename_template = "{tag}_{counter}".into();
+        let info = ClipInfo { tag: Some("ace".into()), ..clip_info("TestGame") };
+        let saved = flush_to_disk(vec![make_part(320)].into(), out, info, |_, _| {}).await.unwrap();
+        assert_eq!(paths(saved), vec![dir.path().join("TestGame").join("ace_001.mkv")]);
+    }
+
+    #[tokio::test]
+    async fn flush_to_disk_names_clip_after_request_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let requested_at = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        let info = ClipInfo { requested_at, ..clip_info("TestGame") };
+        let out = output(dir.path(), ContainerFormat::Mp4);
+        let saved = flush_to_disk(vec![make_part(320)].into(), out, info, |_, _| {}).await.unwrap();
+        let expected = dir.path().join("TestGame").join("2026-01-02_03-04-05.mp4");
+        assert_eq!(paths(saved), vec![expected]);
+    }
+
+    #[test]
+    fn clip_metadata_describes_recording() {
+        let info = ClipInfo {
+            tag: Some("ace".into()),
+            buffer_length_secs: 30,
+            encoder_backend: Some("nvenc".into()),
+            ..clip_info("Rocket League")
+        };
+        let created = Local::now();
+        let metadata = info.metadata(created);
+        assert_eq!(metadata.title, "Rocket League");
+        assert_eq!(metadata.created, created);
+        assert_eq!(metadata.comment, "Buffer length: 30 s, encoder: nvenc");
+        assert_eq!(metadata.tag.as_deref(), Some("ace"));
+
+        let unknown = clip_info("G").metadata(created);
+        assert_eq!(unknown.comment, "Buffer length: 0 s, encoder: unknown");
+    }
+
+    // ── game_clip_dir ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn game_clip_dir_stops_at_first_per_clip_component() {
+        let dir = |template| game_clip_dir("clips", template, "A:B");
+        assert_eq!(dir("{game}"), Some(Path::new("clips").join("A_B")));
+        let nested = Path::new("clips").join("Games").join("A_B clips");
+        assert_eq!(dir("Games/{game} clips/{date}"), Some(nested));
+        assert_eq!(dir("{date}/{game}"), None);
+        assert_eq!(dir(""), None);
+    }
 }
@@ -0,0 +1,202 @@
+/// Background clip saving.
+///
+/// The event loop hands each clip to the worker as a job and moves straight
+/// on, so hotkey presses, process changes and config reloads keep being
+/// handled while clips are muxed.  Up to `max_parallel` jobs run at once; the
+/// rest wait in submission order.  Every job reports
+/// [`DaemonEvent::FlushProgress`] as it starts and writes each file, then
+/// exactly one [`DaemonEvent::FlushFinished`] with the saved files or the error.
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::event::DaemonEvent;
+use crate::flush::{self, ClipInfo, ClipOutput, SavedClip};
+use crate::ring_buffer::BufferSnapshot;
+
+/// Clips muxed at the same time.
+pub const MAX_PARALLEL_FLUSHES: usize = 2;
+
+/// Identifies a flush job in its progress and result events.
+pub type JobId = u64;
+
+/// How far a job has got, reported via [`DaemonEvent::FlushProgress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushStage {
+    /// A worker slot is free and the job is reading and muxing its segments.
+    Started,
+    /// `written` of the clip's `total` files (one per codec epoch) are done.
+    PartWritten { written: usize, total: usize },
+}
+
+struct FlushJob {
+    id: JobId,
+    snapshot: BufferSnapshot,
+    output: ClipOutput,
+    info: ClipInfo,
+}
+
+/// Handle used by the event loop to queue clips.
+pub struct FlushWorker {
+    tx: mpsc::UnboundedSender<FlushJob>,
+    next_id: JobId,
+}
+
+impl FlushWorker {
+    /// Spawns the worker task.  Events are sent on `event_tx`.
+    pub fn start(max_parallel: usize, event_tx: mpsc::Sender<DaemonEvent>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(rx, max_parallel.max(1), event_tx));
+        Self { tx, next_id: 1 }
+    }
+
+    /// Allocates the ID of the next job.  A clip waiting for post-roll gets
+    /// its ID when the hotkey is pressed, so requesters can match the result.
+    pub fn reserve(&mut self) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Queues `snapshot` as job `id` (from [`FlushWorker::reserve`]) to be
+    /// saved as described by `output`.  Never blocks.
+    pub fn submit(
+        &mut self,
+        id: JobId,
+        snapshot: BufferSnapshot,
+        output: ClipOutput,
+        info: ClipInfo,
+    ) {
+        let job = FlushJob { id, snapshot, output, info };
+        if self.tx.send(job).is_err() {
+            eprintln!("[flush] Worker has stopped; clip {id} was not saved");
+        }
+    }
+}
+
+async fn run(
+    mut rx: mpsc::UnboundedReceiver<FlushJob>,
+    max_parallel: usize,
+    event_tx: mpsc::Sender<DaemonEvent>,
+) {
+    let slots = Arc::new(Semaphore::new(max_parallel));
+    while let Some(job) = rx.recv().await {
+        let permit = Arc::clone(&slots).acquire_owned().await.expect("semaphore is never closed");
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            let result = save(job.id, job.snapshot, job.output, job.info, &event_tx).await;
+            drop(permit);
+            let _ = event_tx.send(DaemonEvent::FlushFinished { job: job.id, result }).await;
+        });
+    }
+}
+
+async fn save(
+    id: JobId,
+    snapshot: BufferSnapshot,
+    output: ClipOutput,
+    info: ClipInfo,
+    event_tx: &mpsc::Sender<DaemonEvent>,
+) -> Result<Vec<SavedClip>> {
+    let _ = event_tx.send(DaemonEvent::FlushProgress { job: id, stage: FlushStage::Started }).await;
+    let progress_tx = event_tx.clone();
+    flush::flush_to_disk(snapshot, output, info, move |written, total| {
+        // Called on the blocking mux thread.  Progress is advisory, so it is
+        // dropped rather than stalling the mux if the event queue is full.
+        let stage = FlushStage::PartWritten { written, total };
+        let _ = progress_tx.try_send(DaemonEvent::FlushProgress { job: id, stage });
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GlobalConfig;
+    use crate::test_support::{clip_info, make_part};
+
+    fn output(dir: &std::path::Path) -> ClipOutput {
+        ClipOutput::from_config(&GlobalConfig {
+            clip_output_dir: dir.to_string_lossy().into_owned(),
+            ..GlobalConfig::default()
+        })
+    }
+
+    /// Collects events until `count` jobs have finished, returning
+    /// `(job, progress stages, succeeded)` in finishing order.
+    async fn wait_for_jobs(
+        rx: &mut mpsc::Receiver<DaemonEvent>,
+        count: usize,
+    ) -> Vec<(JobId, Vec<FlushStage>, bool)> {
+        let mut stages: std::collections::HashMap<JobId, Vec<FlushStage>> = Default::default();
+        let mut finished = vec![];
+        while finished.len() < count {
+            match rx.recv().await.expect("worker dropped the event channel") {
+                DaemonEvent::FlushProgress { job, stage } => {
+                    stages.entry(job).or_default().push(stage)
+                }
+                DaemonEvent::FlushFinished { job, result } => {
+                    finished.push((job, stages.remove(&job).unwrap_or_default(), result.is_ok()))
+                }
+                _ => {}
+            }
+        }
+        finished
+    }
+
+    #[tokio::test]
+    async fn jobs_report_progress_and_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = mpsc::channel(32);
+        let mut worker = FlushWorker::start(1, tx);
+        let id = worker.reserve();
+        worker.submit(id, vec![make_part(320)].into(), output(dir.path()), clip_info("G"));
+        let finished = wait_for_jobs(&mut rx, 1).await;
+        assert_eq!(
+            finished,
+            vec![(
+                id,
+                vec![FlushStage::Started, FlushStage::PartWritten { written: 1, total: 1 }],
+                true
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn back_to_back_jobs_are_all_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = mpsc::channel(32);
+        let mut worker = FlushWorker::start(MAX_PARALLEL_FLUSHES, tx);
+        let ids: Vec<JobId> = (0..4)
+            .map(|i| {
+                let id = worker.reserve();
+                let info = clip_info(&format!("Game{i}"));
+                worker.submit(id, vec![make_part(320)].into(), output(dir.path()), info);
+                id
+            })
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        let mut finished: Vec<JobId> = wait_for_jobs(&mut rx, 4)
+            .await
+            .into_iter()
+            .map(|(id, _, ok)| {
+                assert!(ok);
+                id
+            })
+            .collect();
+        finished.sort();
+        assert_eq!(finished, ids);
+    }
+
+    #[tokio::test]
+    async fn failed_job_reports_error() {
+        let (tx, mut rx) = mpsc::channel(32);
+        let mut worker = FlushWorker::start(1, tx);
+        let empty = BufferSnapshot::from(vec![]);
+        let id = worker.reserve();
+        worker.submit(id, empty, output(&std::env::temp_dir()), clip_info("G"));
+        let finished = wait_for_jobs(&mut rx, 1).await;
+        assert!(!finished[0].2);
+    }
+}
@@ -0,0 +1,169 @@
+/// Daemon liveness, as recorded in status.toml.
+///
+/// The status carries the daemon's `pid` and `started_at`, and [`run`]
+/// refreshes `heartbeat_at` every [`HEARTBEAT_INTERVAL_SECS`] seconds; a clean
+/// exit clears it.  A daemon that crashed leaves its last status behind, so
+/// `peaking-daemon status` ([`status_command`]) reports it as stale once the
+/// heartbeat is older than [`STALE_AFTER_SECS`] or the process is gone.
+use chrono::{DateTime, Local};
+use std::path::Path;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration};
+
+use crate::event::DaemonEvent;
+use crate::status::DaemonStatus;
+
+const HEARTBEAT_INTERVAL_SECS: u64 = 5;
+/// Three missed heartbeats.
+const STALE_AFTER_SECS: i64 = 15;
+
+/// Emits [`DaemonEvent::Heartbeat`] every [`HEARTBEAT_INTERVAL_SECS`] seconds.
+pub async fn run(tx: mpsc::Sender<DaemonEvent>) {
+    let mut ticker = interval(Duration::from_secs(HEARTBEAT_INTERVAL_SECS));
+    loop {
+        ticker.tick().await;
+        if tx.send(DaemonEvent::Heartbeat).await.is_err() {
+            break;
+        }
+    }
+}
+
+// ── Status check ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, PartialEq)]
+pub enum Liveness {
+    /// The daemon is alive and its heartbeat is `heartbeat_age_secs` old.
+    Running { pid: u32, heartbeat_age_secs: i64 },
+    /// There is no status file, or the daemon exited cleanly.
+    Stopped,
+    /// The status describes a daemon that is no longer running.
+    Stale(String),
+}
+
+/// Judges `status` as of `now`.  `process_alive` reports whether a PID still
+/// belongs to a running daemon.
+pub fn check(
+    status: Option<&DaemonStatus>,
+    now: DateTime<Local>,
+    process_alive: impl Fn(u32) -> bool,
+) -> Liveness {
+    let Some(heartbeat) = status.and_then(|s| s.heartbeat_at.as_deref()) else {
+        return Liveness::Stopped;
+    };
+    let pid = status.map_or(0, |s| s.pid);
+    let Ok(beat) = DateTime::parse_from_rfc3339(heartbeat) else {
+        return Liveness::Stale(format!("heartbeat_at \"{heartbeat}\" is not a timestamp"));
+    };
+    if !process_alive(pid) {
+        return Liveness::Stale(format!("process {pid} is gone"));
+    }
+    let age = (now.fixed_offset() - beat).num_seconds();
+    if age > STALE_AFTER_SECS {
+        return Liveness::Stale(format!("last heartbeat was {age} s ago"));
+    }
+    Liveness::Running { pid, heartbeat_age_secs: age }
+}
+
+/// Whether `pid` is a running process with this executable's name, so a
+/// recycled PID is not mistaken for the daemon.
+fn process_alive(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    let refresh = ProcessRefreshKind::new().with_exe(UpdateKind::Always);
+    sys.refresh_processes_specifics(ProcessesToUpdate::Some(&[pid]), true, refresh);
+    let Some(process) = sys.process(pid) else {
+        return false;
+    };
+    let own_exe = std::env::current_exe().ok();
+    let name = |exe: &Path| exe.file_name().map(|name| name.to_os_string());
+    match (process.exe(), own_exe.as_deref()) {
+        (Some(exe), Some(own)) => name(exe) == name(own),
+        // Unknown executable: trust the PID.
+        _ => true,
+    }
+}
+
+/// `peaking-daemon status`: prints whether the daemon described by the status
+/// file at `path` is running, stopped or stale.  Returns the exit code: 0
+/// running, 1 stale, 2 unreadable status file, 3 stopped.
+pub fn status_command(path: &Path) -> i32 {
+    let status = match std::fs::read_to_string(path) {
+        Ok(content) => match toml::from_str::<DaemonStatus>(&content) {
+            Ok(status) => Some(status),
+            Err(e) => {
+                eprintln!("Failed to parse {}: {e}", path.display());
+                return 2;
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Failed to read {}: {e}", path.display());
+            return 2;
+        }
+    };
+    match check(status.as_ref(), Local::now(), process_alive) {
+        Liveness::Running { pid, heartbeat_age_secs } => {
+            let state = status.map(|s| format!("{:?}", s.state).to_lowercase()).unwrap_or_default();
+            println!("running (pid {pid}, {state}, heartbeat {heartbeat_age_secs} s ago)");
+            0
+        }
+        Liveness::Stale(reason) => {
+            println!("stale: {reason}");
+            1
+        }
+        Liveness::Stopped => {
+            println!("stopped");
+            3
+        }
+    }
+}
+
+// ── Tests ──────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_with_heartbeat(beat: DateTime<Local>) -> DaemonStatus {
+        DaemonStatus { heartbeat_at: Some(beat.to_rfc3339()), ..DaemonStatus::new() }
+    }
+
+    #[test]
+    fn fresh_heartbeat_of_live_process_is_running() {
+        let now = Local::now();
+        let status = status_with_heartbeat(now - chrono::Duration::seconds(4));
+        assert_eq!(
+            check(Some(&status), now, |pid| pid == std::process::id()),
+            Liveness::Running { pid: std::process::id(), heartbeat_age_secs: 4 }
+        );
+    }
+
+    #[test]
+    fn old_heartbeat_or_missing_process_is_stale() {
+        let now = Local::now();
+        let old = status_with_heartbeat(now - chrono::Duration::seconds(60));
+        assert_eq!(
+            check(Some(&old), now, |_| true),
+            Liveness::Stale("last heartbeat was 60 s ago".into())
+        );
+        let fresh = status_with_heartbeat(now);
+        let pid = fresh.pid;
+        assert_eq!(
+            check(Some(&fresh), now, |_| false),
+            Liveness::Stale(format!("process {pid} is gone"))
+        );
+    }
+
+    #[test]
+    fn no_status_or_clean_exit_is_stopped() {
+        assert_eq!(check(None, Local::now(), |_| true), Liveness::Stopped);
+        let exited = DaemonStatus { heartbeat_at: None, ..DaemonStatus::new() };
+        assert_eq!(check(Some(&exited), Local::now(), |_| true), Liveness::Stopped);
+    }
+
+    #[test]
+    fn own_process_is_alive() {
+        assert!(process_alive(std::process::id()));
+    }
+}
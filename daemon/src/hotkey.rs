@@ -136,7 +136,7 @@ mod imp {
         KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL, WM_KEYDOWN, WM_QUIT,
     };
 
-    use crate::event::DaemonEvent;
+    use crate::event::{ClipRequest, DaemonEvent};
     use super::{HOOK_TX, HOOK_VK};
 
     /// Low-level keyboard hook procedure.
@@ -154,7 +154,7 @@ mod imp {
             if target != 0 && kb.vkCode == target {
                 if let Some(tx) = HOOK_TX.get() {
                     // try_send is non-blocking; a full channel silently drops this press.
-                    let _ = tx.try_send(DaemonEvent::FlushRequested);
+                    let _ = tx.try_send(DaemonEvent::FlushRequested(ClipRequest::default()));
                 }
             }
         }
@@ -0,0 +1,77 @@
+/// Single-instance guard.
+///
+/// At startup the daemon takes an exclusive advisory lock on `daemon.lock`
+/// in the app data directory and holds it until it exits, so a second daemon
+/// refuses to start instead of hooking the keyboard and writing status.toml
+/// alongside the first.  The file holds the owner's PID for the error
+/// message.  The OS drops the lock when its process dies, so a lock file
+/// left behind by a crash is simply taken over by the next daemon.
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+
+/// Held for the daemon's lifetime; dropping it releases the lock.
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Locks `path`, creating it if needed.  Fails with "already running" if
+/// another live process holds it.
+pub fn acquire(path: &Path) -> Result<InstanceLock> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            // Unreadable on Windows while locked, so the PID is best effort.
+            let owner = std::fs::read_to_string(path)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<u32>().ok())
+                .map(|pid| format!(" (pid {pid})"))
+                .unwrap_or_default();
+            bail!("Another peaking-daemon is already running{owner}; {} is locked", path.display());
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+        }
+    }
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    Ok(InstanceLock { _file: file })
+}
+
+// ── Tests ──────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_instance_is_refused_until_the_first_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.lock");
+        let first = acquire(&path).unwrap();
+        let err = acquire(&path).err().expect("lock is held").to_string();
+        assert!(err.starts_with("Another peaking-daemon is already running"), "{err}");
+        drop(first);
+        assert!(acquire(&path).is_ok());
+    }
+
+    #[test]
+    fn stale_lock_file_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.lock");
+        // Left behind by a daemon that crashed: present but not locked.
+        std::fs::write(&path, "4294967295 and some leftovers").unwrap();
+        let lock = acquire(&path).unwrap();
+        #[cfg(not(windows))]
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), std::process::id().to_string());
+        drop(lock);
+    }
+}
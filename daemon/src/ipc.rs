@@ -0,0 +1,625 @@
+/// Local control endpoint for the GUI, scripts and stream-deck tools.
+///
+/// The daemon listens on a Unix domain socket at `<app data dir>/daemon.sock`,
+/// or on Windows on the named pipe `\\.\pipe\peaking-daemon`, for
+/// line-delimited JSON-RPC 2.0: each line is one request object and each
+/// request with an `id` gets one response line.
+///
+///   → {"jsonrpc":"2.0","id":1,"method":"save_clip","params":{"seconds":30,"tag":"ace"}}
+///   ← {"jsonrpc":"2.0","id":1,"result":{"status":"saving","job":3}}
+///
+/// Methods: `save_clip {seconds?, tag?}`, `get_status`, `reload_config`,
+/// `pause`, `resume`, `shutdown` and `subscribe`.  Each is forwarded to the
+/// event loop as a [`DaemonEvent`], exactly like a hotkey press or a config
+/// file change.
+///
+/// `subscribe` returns the current status and its `sequence`; from then on
+/// the connection also receives `status`, `clip_saved` and `flush_failed`
+/// notifications, each numbered one higher than the last.  A gap in the
+/// numbers means the client fell behind and missed some; `get_status`
+/// catches up.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::config::{self, MAX_BUFFER_LENGTH_SECS};
+use crate::event::{ClipRequest, DaemonEvent};
+use crate::flush_worker::JobId;
+use crate::status::DaemonStatus;
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The daemon refused or failed the command, e.g. nothing is being recorded.
+const COMMAND_FAILED: i64 = -32000;
+/// Notifications a subscriber may fall behind by before it misses some.
+const NOTIFICATION_BACKLOG: usize = 64;
+
+// ── Subscriptions ──────────────────────────────────────────────────────────────
+
+/// A message pushed to subscribed clients, sent as a JSON-RPC notification
+/// with `method` and `params`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Notification {
+    /// The daemon status changed.
+    Status { sequence: u64, status: Box<DaemonStatus> },
+    /// A flush job saved its clip files, oldest first.
+    ClipSaved { sequence: u64, job: JobId, paths: Vec<PathBuf> },
+    /// A flush job failed.
+    FlushFailed { sequence: u64, job: JobId, error: String },
+}
+
+/// What a client gets back from `subscribe`: the status as of `sequence`,
+/// and every notification after it.
+pub struct Subscription {
+    pub sequence: u64,
+    pub status: DaemonStatus,
+    pub events: broadcast::Receiver<Notification>,
+}
+
+/// Numbers status changes and clip events and pushes them to subscribers.
+/// Owned by the event loop, which reports every change through it.
+pub struct Notifier {
+    tx: broadcast::Sender<Notification>,
+    sequence: u64,
+    last_status: Option<DaemonStatus>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(NOTIFICATION_BACKLOG);
+        Self { tx, sequence: 0, last_status: None }
+    }
+
+    /// Starts a subscription from the current `status`.
+    pub fn subscribe(&self, status: &DaemonStatus) -> Subscription {
+        Subscription {
+            sequence: self.sequence,
+            status: status.clone(),
+            events: self.tx.subscribe(),
+        }
+    }
+
+    /// Pushes `status` unless only its revision stamps differ from the last
+    /// one pushed.
+    pub fn status_changed(&mut self, status: &DaemonStatus) {
+        if self.last_status.as_ref().is_some_and(|last| last.same_as(status)) {
+            return;
+        }
+        self.last_status = Some(status.clone());
+        self.push(|sequence| Notification::Status { sequence, status: Box::new(status.clone()) });
+    }
+
+    pub fn clip_saved(&mut self, job: JobId, paths: &[PathBuf]) {
+        self.push(|sequence| Notification::ClipSaved { sequence, job, paths: paths.to_vec() });
+    }
+
+    pub fn flush_failed(&mut self, job: JobId, error: String) {
+        self.push(|sequence| Notification::FlushFailed { sequence, job, error });
+    }
+
+    fn push(&mut self, notification: impl FnOnce(u64) -> Notification) {
+        self.sequence += 1;
+        // Fails only when nobody is subscribed.
+        let _ = self.tx.send(notification(self.sequence));
+    }
+}
+
+// ── Protocol ───────────────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SaveClipParams {
+    seconds: Option<u32>,
+    tag: Option<String>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    fn daemon_stopped() -> Self {
+        Self::new(COMMAND_FAILED, "The daemon is shutting down")
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    }
+}
+
+/// One client connection.
+struct Connection {
+    /// Re-read by `reload_config`.
+    config_path: PathBuf,
+    tx: mpsc::Sender<DaemonEvent>,
+    /// Set once the client calls `subscribe`.
+    events: Option<broadcast::Receiver<Notification>>,
+}
+
+impl Connection {
+    fn new(config_path: PathBuf, tx: mpsc::Sender<DaemonEvent>) -> Self {
+        Self { config_path, tx, events: None }
+    }
+
+    /// Parses one request line, runs it and returns the response line, or
+    /// `None` for a notification.
+    async fn handle_line(&mut self, line: &str) -> Option<String> {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => {
+                let e = RpcError::new(PARSE_ERROR, e.to_string());
+                return Some(response(Value::Null, Err(e)).to_string());
+            }
+        };
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+        let request = match serde_json::from_value::<Request>(value) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            Ok(_) => {
+                let e = RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"");
+                return Some(response(id, Err(e)).to_string());
+            }
+            Err(e) => {
+                let e = RpcError::new(INVALID_REQUEST, e.to_string());
+                return Some(response(id, Err(e)).to_string());
+            }
+        };
+        let result = self.dispatch(&request.method, request.params).await;
+        if let Err(e) = &result {
+            eprintln!("[ipc] {} failed: {}", request.method, e.message);
+        }
+        request.id.map(|id| response(id, result).to_string())
+    }
+
+    /// Runs `method`, forwarding it to the event loop.
+    async fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "save_clip" => {
+                let params: SaveClipParams = match params {
+                    Value::Null => SaveClipParams::default(),
+                    params => serde_json::from_value(params)
+                        .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?,
+                };
+                if let Some(seconds) = params.seconds {
+                    if !(1..=MAX_BUFFER_LENGTH_SECS).contains(&seconds) {
+                        return Err(RpcError::new(
+                            INVALID_PARAMS,
+                            format!("seconds must be between 1 and {MAX_BUFFER_LENGTH_SECS}"),
+                        ));
+                    }
+                }
+                let tag = params.tag.filter(|tag| !tag.trim().is_empty());
+                let outcome = self
+                    .ask(|reply| {
+                        DaemonEvent::FlushRequested(ClipRequest {
+                            seconds: params.seconds,
+                            tag,
+                            reply: Some(reply),
+                        })
+                    })
+                    .await?;
+                match outcome {
+                    Ok(accepted) => Ok(serde_json::to_value(accepted).unwrap_or_default()),
+                    Err(reason) => Err(RpcError::new(COMMAND_FAILED, reason)),
+                }
+            }
+            "get_status" => {
+                let status = self.ask(DaemonEvent::StatusRequested).await?;
+                Ok(serde_json::to_value(status).unwrap_or_default())
+            }
+            "subscribe" => {
+                let subscription = self.ask(DaemonEvent::Subscribe).await?;
+                self.events = Some(subscription.events);
+                Ok(json!({ "sequence": subscription.sequence, "status": subscription.status }))
+            }
+            "reload_config" => {
+                let config = config::load_or_default(&self.config_path)
+                    .map_err(|e| RpcError::new(COMMAND_FAILED, format!("{e:#}")))?;
+                self.send(DaemonEvent::ConfigReloaded(config)).await.map(|_| Value::Null)
+            }
+            "pause" => self.send(DaemonEvent::Pause).await.map(|_| Value::Null),
+            "resume" => self.send(DaemonEvent::Resume).await.map(|_| Value::Null),
+            "shutdown" => self.send(DaemonEvent::Shutdown).await.map(|_| Value::Null),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{method}'"))),
+        }
+    }
+
+    async fn send(&self, event: DaemonEvent) -> Result<(), RpcError> {
+        self.tx.send(event).await.map_err(|_| RpcError::daemon_stopped())
+    }
+
+    /// Sends the event built by `event` and waits for the event loop's reply.
+    async fn ask<T>(
+        &self,
+        event: impl FnOnce(oneshot::Sender<T>) -> DaemonEvent,
+    ) -> Result<T, RpcError> {
+        let (reply, answer) = oneshot::channel();
+        self.send(event(reply)).await?;
+        answer.await.map_err(|_| RpcError::daemon_stopped())
+    }
+
+    /// Waits for the next notification for a subscribed client.  Never
+    /// resolves before `subscribe`.
+    async fn next_notification(events: &mut Option<broadcast::Receiver<Notification>>) -> Value {
+        let Some(events) = events else {
+            return std::future::pending().await;
+        };
+        loop {
+            match events.recv().await {
+                Ok(notification) => {
+                    let mut value = serde_json::to_value(notification).unwrap_or_default();
+                    value["jsonrpc"] = "2.0".into();
+                    return value;
+                }
+                // The client sees the gap in sequence numbers.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return std::future::pending().await,
+            }
+        }
+    }
+}
+
+/// Answers requests on one client connection, and pushes notifications once
+/// it subscribes, until it closes.
+async fn serve_connection(
+    stream: impl AsyncRead + AsyncWrite,
+    config_path: PathBuf,
+    tx: mpsc::Sender<DaemonEvent>,
+) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let mut connection = Connection::new(config_path, tx);
+    loop {
+        let mut reply = tokio::select! {
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("[ipc] Failed to read request: {e}");
+                        break;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match connection.handle_line(&line).await {
+                    Some(reply) => reply,
+                    None => continue,
+                }
+            }
+            notification = Connection::next_notification(&mut connection.events) => {
+                notification.to_string()
+            }
+        };
+        reply.push('\n');
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+// ── Listener ───────────────────────────────────────────────────────────────────
+
+/// Spawns the control endpoint.  `config_path` is re-read by `reload_config`.
+pub fn start(config_path: PathBuf, tx: mpsc::Sender<DaemonEvent>) {
+    tokio::spawn(async move {
+        if let Err(e) = listen(config_path, tx).await {
+            eprintln!("[ipc] Control endpoint unavailable: {e:#}");
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn listen(config_path: PathBuf, tx: mpsc::Sender<DaemonEvent>) -> Result<()> {
+    serve_socket(&crate::paths::socket_path(), config_path, tx).await
+}
+
+/// Listens on the Unix socket at `path`, replacing a socket file left behind
+/// by a daemon that did not exit cleanly.
+#[cfg(unix)]
+async fn serve_socket(
+    path: &Path,
+    config_path: PathBuf,
+    tx: mpsc::Sender<DaemonEvent>,
+) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::UnixListener;
+
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("Another daemon is listening on {}", path.display());
+    }
+    let _ = std::fs::remove_file(path);
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    // Only the user running the daemon may control it.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    println!("[ipc] Listening on {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await.context("Failed to accept connection")?;
+        tokio::spawn(serve_connection(stream, config_path.clone(), tx.clone()));
+    }
+}
+
+#[cfg(windows)]
+async fn listen(config_path: PathBuf, tx: mpsc::Sender<DaemonEvent>) -> Result<()> {
+    use crate::paths::PIPE_NAME;
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    // Fails if another daemon already owns the pipe.  Remote clients are
+    // rejected by default.
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(PIPE_NAME)
+        .with_context(|| format!("Failed to create {PIPE_NAME}"))?;
+    println!("[ipc] Listening on {PIPE_NAME}");
+    loop {
+        server.connect().await.context("Failed to accept connection")?;
+        // Create the next instance before handing this one off so a client
+        // never finds the pipe missing.
+        let client = std::mem::replace(&mut server, ServerOptions::new().create(PIPE_NAME)?);
+        tokio::spawn(serve_connection(client, config_path.clone(), tx.clone()));
+    }
+}
+
+// ── Tests ──────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::ClipAccepted;
+
+    /// Stands in for the event loop: answers clip and status requests and
+    /// returns the names of the events it saw.
+    fn fake_daemon() -> (mpsc::Sender<DaemonEvent>, tokio::task::JoinHandle<Vec<String>>) {
+        let (tx, mut rx) = mpsc::channel(8);
+        let handle = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            while let Some(event) = rx.recv().await {
+                let name = match event {
+                    DaemonEvent::FlushRequested(mut request) => {
+                        let outcome = match request.seconds {
+                            Some(5) => Err("No game is being recorded".to_string()),
+                            Some(10) => Ok(ClipAccepted::PostRoll { job: 8, post_roll_secs: 3 }),
+                            _ => Ok(ClipAccepted::Saving { job: 7 }),
+                        };
+                        let name = format!("save {:?} {:?}", request.seconds, request.tag);
+                        request.respond(outcome);
+                        name
+                    }
+                    DaemonEvent::StatusRequested(reply) => {
+                        let _ = reply.send(DaemonStatus::new());
+                        "status".into()
+                    }
+                    DaemonEvent::Subscribe(reply) => {
+                        // One clip saved straight after the client subscribes.
+                        let mut notifier = Notifier::new();
+                        notifier.status_changed(&DaemonStatus::new());
+                        let _ = reply.send(notifier.subscribe(&DaemonStatus::new()));
+                        notifier.clip_saved(3, &[PathBuf::from("clip.mp4")]);
+                        "subscribe".into()
+                    }
+                    DaemonEvent::ConfigReloaded(_) => "reload".into(),
+                    DaemonEvent::Pause => "pause".into(),
+                    DaemonEvent::Resume => "resume".into(),
+                    DaemonEvent::Shutdown => "shutdown".into(),
+                    _ => "other".into(),
+                };
+                seen.push(name);
+            }
+            seen
+        });
+        (tx, handle)
+    }
+
+    async fn call(line: &str, tx: &mpsc::Sender<DaemonEvent>) -> Value {
+        let mut connection = Connection::new("missing-config.toml".into(), tx.clone());
+        let reply = connection.handle_line(line).await.expect("a response");
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    #[tokio::test]
+    async fn save_clip_forwards_seconds_and_tag() {
+        let (tx, daemon) = fake_daemon();
+        let reply = call(
+            r#"{"jsonrpc":"2.0","id":1,"method":"save_clip","params":{"seconds":30,"tag":"ace"}}"#,
+            &tx,
+        )
+        .await;
+        let saving = json!({ "status": "saving", "job": 7 });
+        assert_eq!(reply, json!({ "jsonrpc": "2.0", "id": 1, "result": saving }));
+        let reply = call(r#"{"jsonrpc":"2.0","id":2,"method":"save_clip"}"#, &tx).await;
+        assert_eq!(reply["result"]["job"], 7);
+        let reply =
+            call(r#"{"jsonrpc":"2.0","id":3,"method":"save_clip","params":{"seconds":10}}"#, &tx)
+                .await;
+        let post_roll = json!({ "status": "post_roll", "job": 8, "post_roll_secs": 3 });
+        assert_eq!(reply["result"], post_roll, "post-roll clips report their job up front");
+        drop(tx);
+        assert_eq!(
+            daemon.await.unwrap(),
+            ["save Some(30) Some(\"ace\")", "save None None", "save Some(10) None"]
+        );
+    }
+
+    #[tokio::test]
+    async fn save_clip_reports_refusal_and_bad_params() {
+        let (tx, _daemon) = fake_daemon();
+        let refused =
+            call(r#"{"jsonrpc":"2.0","id":1,"method":"save_clip","params":{"seconds":5}}"#, &tx)
+                .await;
+        assert_eq!(refused["error"]["code"], COMMAND_FAILED);
+        assert_eq!(refused["error"]["message"], "No game is being recorded");
+        let zero =
+            call(r#"{"jsonrpc":"2.0","id":2,"method":"save_clip","params":{"seconds":0}}"#, &tx)
+                .await;
+        assert_eq!(zero["error"]["code"], INVALID_PARAMS);
+        let unknown =
+            call(r#"{"jsonrpc":"2.0","id":3,"method":"save_clip","params":{"secs":5}}"#, &tx)
+                .await;
+        assert_eq!(unknown["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn get_status_returns_the_daemon_status() {
+        let (tx, _daemon) = fake_daemon();
+        let reply = call(r#"{"jsonrpc":"2.0","id":"a","method":"get_status"}"#, &tx).await;
+        assert_eq!(reply["id"], "a");
+        assert_eq!(reply["result"]["state"], "idle");
+        assert_eq!(reply["result"]["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn commands_become_events() {
+        let (tx, daemon) = fake_daemon();
+        for method in ["reload_config", "pause", "resume", "shutdown"] {
+            let line = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"{method}"}}"#);
+            assert_eq!(call(&line, &tx).await["result"], Value::Null, "{method}");
+        }
+        drop(tx);
+        assert_eq!(daemon.await.unwrap(), ["reload", "pause", "resume", "shutdown"]);
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_get_json_rpc_errors() {
+        let (tx, _daemon) = fake_daemon();
+        assert_eq!(call("{not json", &tx).await["error"]["code"], PARSE_ERROR);
+        let no_version = call(r#"{"id":1,"method":"pause"}"#, &tx).await;
+        assert_eq!(no_version["error"]["code"], INVALID_REQUEST);
+        let unknown = call(r#"{"jsonrpc":"2.0","id":1,"method":"explode"}"#, &tx).await;
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn notifications_get_no_response() {
+        let (tx, daemon) = fake_daemon();
+        let line = r#"{"jsonrpc":"2.0","method":"pause"}"#;
+        let mut connection = Connection::new("missing-config.toml".into(), tx);
+        assert!(connection.handle_line(line).await.is_none());
+        drop(connection);
+        assert_eq!(daemon.await.unwrap(), ["pause"]);
+    }
+
+    // ── Subscriptions ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn notifier_numbers_changes_and_skips_repeats() {
+        let mut notifier = Notifier::new();
+        let mut status = DaemonStatus::new();
+        notifier.status_changed(&status);
+        let mut events = notifier.subscribe(&status).events;
+        notifier.status_changed(&status);
+        status.error = Some("Flush failed: disk full".into());
+        notifier.status_changed(&status);
+        notifier.flush_failed(4, "disk full".into());
+
+        let Ok(Notification::Status { sequence: 2, status: pushed }) = events.try_recv() else {
+            panic!("expected the changed status");
+        };
+        assert_eq!(*pushed, status);
+        let next = events.try_recv().unwrap();
+        assert!(matches!(next, Notification::FlushFailed { sequence: 3, job: 4, .. }), "{next:?}");
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn notifications_serialize_as_json_rpc_methods() {
+        let saved =
+            Notification::ClipSaved { sequence: 9, job: 2, paths: vec![PathBuf::from("a.mp4")] };
+        assert_eq!(
+            serde_json::to_value(saved).unwrap(),
+            json!({
+                "method": "clip_saved",
+                "params": { "sequence": 9, "job": 2, "paths": ["a.mp4"] },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribed_connection_receives_notifications() {
+        let (tx, _daemon) = fake_daemon();
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(serve_connection(server, PathBuf::from("missing-config.toml"), tx));
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(reader).lines();
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"subscribe\"}\n")
+            .await
+            .unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["result"]["sequence"], 1);
+        assert_eq!(reply["result"]["status"]["state"], "idle");
+        let line = lines.next_line().await.unwrap().unwrap();
+        let pushed: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            pushed,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "clip_saved",
+                "params": { "sequence": 2, "job": 3, "paths": ["clip.mp4"] },
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn socket_round_trip() {
+        use tokio::net::UnixStream;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.sock");
+        // A socket file left behind by a crashed daemon is replaced.
+        std::fs::write(&path, b"").unwrap();
+        let (tx, _daemon) = fake_daemon();
+        let server_path = path.clone();
+        tokio::spawn(async move {
+            serve_socket(&server_path, PathBuf::from("missing-config.toml"), tx).await
+        });
+
+        let stream = loop {
+            match UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let (reader, mut writer) = tokio::io::split(stream);
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"get_status\"}\n")
+            .await
+            .unwrap();
+        let line = BufReader::new(reader).lines().next_line().await.unwrap().unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["result"]["state"], "idle");
+    }
+}
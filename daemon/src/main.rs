@@ -1,24 +1,57 @@
 mod audio_capture;
 mod capture;
 mod config;
+mod disk;
 mod encoder;
 mod event;
 mod flush;
+mod flush_worker;
+mod heartbeat;
 mod hotkey;
+mod instance;
+mod ipc;
+mod metadata;
+mod mkv;
+mod mp4;
 mod paths;
 mod pipeline;
 mod process_monitor;
+mod retention;
 mod ring_buffer;
+mod scale;
+mod sidecar;
+mod software_encoder;
+mod spill;
 mod status;
+mod synthetic;
+#[cfg(test)]
+mod test_support;
+mod thumbnail;
 
+use std::collections::BTreeSet;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
 
-use crate::config::DEFAULT_BUFFER_LENGTH_SECS;
-use crate::ring_buffer::RingBuffer;
+use crate::config::{DEFAULT_BUFFER_LENGTH_SECS, MAX_MEMORY_BUFFER_SECS};
+use crate::flush_worker::{FlushWorker, JobId, MAX_PARALLEL_FLUSHES};
+use crate::ring_buffer::{BufferSnapshot, RingBuffer};
 
 #[tokio::main]
 async fn main() {
+    // ── Subcommands ───────────────────────────────────────────────────────────
+    match std::env::args().nth(1).as_deref() {
+        None => {}
+        Some("status") => {
+            std::process::exit(heartbeat::status_command(&paths::status_file_path()))
+        }
+        Some(other) => {
+            eprintln!("Unknown command '{other}'. Usage: peaking-daemon [status]");
+            std::process::exit(2);
+        }
+    }
+
     // ── App data directory ────────────────────────────────────────────────────
     let app_dir = paths::app_data_dir();
     if let Err(e) = std::fs::create_dir_all(&app_dir) {
@@ -26,31 +59,66 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // ── Single instance ───────────────────────────────────────────────────────
+    // Held until exit; the OS releases it if the daemon crashes.
+    let _instance = instance::acquire(&paths::lock_file_path()).unwrap_or_else(|e| {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    });
+
     // ── Configuration ─────────────────────────────────────────────────────────
     let config_path = paths::config_file_path();
     let initial_config = config::load_or_default(&config_path).unwrap_or_else(|e| {
-        eprintln!("[config] Error (using defaults): {e}");
+        eprintln!("[config] Error (using defaults): {e:#}");
         config::Config::default()
     });
     let initial_hotkey = initial_config.global.hotkey.clone();
+
+    // ── Unfinished clips ──────────────────────────────────────────────────────
+    // A flush cut short by a crash leaves its hidden `.partial` file behind.
+    let sweep = flush::sweep_partial_files(&initial_config.global.clip_output_dir);
+    for (partial, clip) in sweep.recovered {
+        println!("[flush] Recovered interrupted clip {} as {}", partial.display(), clip.display());
+    }
+    for path in sweep.removed {
+        println!("[flush] Removed unfinished clip {}", path.display());
+    }
     let shared_config = Arc::new(RwLock::new(initial_config));
 
     // ── Initial status ────────────────────────────────────────────────────────
     let status_path = paths::status_file_path();
     let mut current_status = status::DaemonStatus::new();
-    status::write_status(&status_path, &current_status);
+    current_status.sequence = status::last_sequence(&status_path);
+    current_status.heartbeat_at = current_status.started_at.clone();
+    // Pushes every status change and saved clip to IPC subscribers.
+    let mut notifier = ipc::Notifier::new();
+    publish_status(&status_path, &mut current_status, &mut notifier);
 
     // ── Ring buffer ───────────────────────────────────────────────────────────
     let ring_buffer: Arc<Mutex<RingBuffer>> =
         Arc::new(Mutex::new(RingBuffer::new(DEFAULT_BUFFER_LENGTH_SECS)));
+    let spill_path = paths::spill_file_path();
+    // Spill files of a daemon that crashed are never read again.
+    for path in spill::remove_stale(&spill_path) {
+        println!("[spill] Removed stale spill file {}", path.display());
+    }
 
     let (event_tx, mut event_rx) = mpsc::channel::<event::DaemonEvent>(32);
 
     // ── Background tasks ──────────────────────────────────────────────────────
+    // Control socket (named pipe on Windows) for the GUI and scripts.
+    ipc::start(config_path.clone(), event_tx.clone());
     tokio::spawn(config::watch_config(config_path, event_tx.clone()));
     tokio::spawn(process_monitor::run(Arc::clone(&shared_config), event_tx.clone()));
+    tokio::spawn(heartbeat::run(event_tx.clone()));
 
     let hotkey_handle = hotkey::start(&initial_hotkey, event_tx.clone());
+    let mut flush_worker = FlushWorker::start(MAX_PARALLEL_FLUSHES, event_tx.clone());
+    // Prunes old clips now and again after every saved clip.
+    let retention =
+        retention::RetentionWorker::start(Arc::clone(&shared_config), paths::retention_log_path());
+    // Checks free space on the clip drive now, every minute and after every flush.
+    let disk_monitor = disk::DiskMonitor::start(Arc::clone(&shared_config), event_tx.clone());
 
     // Graceful shutdown on Ctrl+C.
     {
@@ -68,31 +136,60 @@ async fn main() {
     let mut active_pipeline: Option<pipeline::Pipeline> = None;
     // Tracks the currently-recording app so we can apply its hotkey/buffer overrides.
     let mut active_app: Option<config::ApplicationConfig> = None;
+    // A clip waiting for its post-roll to be recorded.
+    let mut pending_clip: Option<flush::PendingClip> = None;
+    // Clips handed to the flush worker that have not finished yet.
+    let mut flush_jobs: BTreeSet<JobId> = BTreeSet::new();
+    // Set by `pause` over IPC; watched games are not recorded until `resume`.
+    let mut paused = false;
+    // Set once Shutdown arrives; the loop then only waits for flush jobs.
+    let mut shutting_down = false;
 
     while let Some(evt) = event_rx.recv().await {
+        if shutting_down
+            && !matches!(
+                evt,
+                event::DaemonEvent::FlushProgress { .. }
+                    | event::DaemonEvent::FlushFinished { .. }
+                    | event::DaemonEvent::Heartbeat
+            )
+        {
+            continue;
+        }
         match evt {
             event::DaemonEvent::ProcessStarted(app) => {
                 if let Some(p) = active_pipeline.take() {
                     p.stop().await;
                 }
+                if let Some(clip) = pending_clip.take() {
+                    let job =
+                        finish_pending_clip(clip, &ring_buffer, &shared_config, &mut flush_worker)
+                            .await;
+                    flush_jobs.insert(job);
+                }
 
-                println!("Recording started: {}", app.display_name);
-                current_status.state = status::DaemonState::Recording;
                 current_status.active_application = Some(app.display_name.clone());
                 current_status.error = None;
-                status::write_status(&status_path, &current_status);
+                if paused {
+                    // Recorded once `resume` arrives.
+                    println!("Recording paused; not recording {}", app.display_name);
+                    active_app = Some(app);
+                    publish_status(&status_path, &mut current_status, &mut notifier);
+                    continue;
+                }
+                println!("Recording started: {}", app.display_name);
+                current_status.state =
+                    status::DaemonState::from_activity(true, false, false, !flush_jobs.is_empty());
+                publish_status(&status_path, &mut current_status, &mut notifier);
 
                 let cfg = shared_config.read().await;
-                {
-                    let mut rb = ring_buffer.lock().unwrap();
-                    rb.clear();
-                    rb.resize(app.effective_buffer_length(&cfg.global));
-                }
-                hotkey_handle.update_key(app.effective_hotkey(&cfg.global));
-                active_pipeline = Some(pipeline::Pipeline::start(
+                active_pipeline = Some(start_recording(
                     &app,
                     &cfg,
-                    Arc::clone(&ring_buffer),
+                    &ring_buffer,
+                    &spill_path,
+                    &hotkey_handle,
+                    &event_tx,
                 ));
                 active_app = Some(app);
             }
@@ -102,15 +199,28 @@ async fn main() {
                     p.stop().await;
                 }
                 active_app = None;
+                // Save a clip still waiting for post-roll with whatever was recorded.
+                if let Some(clip) = pending_clip.take() {
+                    let job =
+                        finish_pending_clip(clip, &ring_buffer, &shared_config, &mut flush_worker)
+                            .await;
+                    flush_jobs.insert(job);
+                }
 
                 // Restore the global hotkey now that no per-app override is active.
                 let global_hotkey = shared_config.read().await.global.hotkey.clone();
                 hotkey_handle.update_key(&global_hotkey);
 
                 println!("Recording stopped");
-                current_status.state = status::DaemonState::Idle;
+                current_status.state = status::DaemonState::from_activity(
+                    false,
+                    paused,
+                    false,
+                    !flush_jobs.is_empty(),
+                );
                 current_status.active_application = None;
-                status::write_status(&status_path, &current_status);
+                current_status.buffer = None;
+                publish_status(&status_path, &mut current_status, &mut notifier);
             }
 
             event::DaemonEvent::ConfigReloaded(new_config) => {
@@ -122,85 +232,262 @@ async fn main() {
                 };
                 hotkey_handle.update_key(&effective_key);
                 {
-                    let new_capacity = match &active_app {
-                        Some(app) => app.effective_buffer_length(&new_config.global),
-                        None => new_config.global.buffer_length_secs,
+                    let (new_capacity, new_max_bytes, new_spill) = match &active_app {
+                        Some(app) => (
+                            app.effective_buffer_length(&new_config.global),
+                            app.effective_max_buffer_bytes(&new_config.global),
+                            app.effective_spill_file_bytes(&new_config.global),
+                        ),
+                        None => (
+                            new_config.global.buffer_length_secs,
+                            new_config.global.max_buffer_bytes,
+                            None,
+                        ),
                     };
                     let mut rb = ring_buffer.lock().unwrap();
                     rb.resize(new_capacity);
+                    rb.set_max_bytes(new_max_bytes);
+                    apply_spill(&mut rb, &spill_path, new_spill);
                 }
                 *shared_config.write().await = new_config;
+                disk_monitor.request();
             }
 
-            event::DaemonEvent::FlushRequested => {
+            event::DaemonEvent::FlushRequested(mut request) => {
+                if paused {
+                    request.respond(Err("Recording is paused".into()));
+                    continue;
+                }
                 if active_pipeline.is_none() {
-                    // No active recording — silently no-op (task 8.4).
+                    // No active recording — nothing to save (task 8.4).
+                    request.respond(Err("No game is being recorded".into()));
+                    continue;
+                }
+                if let Some(clip) = &mut pending_clip {
+                    println!("[flush] Clip requested again; extending post-roll");
+                    clip.extend();
+                    request.respond(Ok(event::ClipAccepted::Extended { job: clip.job() }));
                     continue;
                 }
 
-                let display_name = match &current_status.active_application {
-                    Some(name) => name.clone(),
-                    None => {
-                        eprintln!("[flush] FlushRequested but active_application is unset");
-                        continue;
-                    }
+                let (Some(app), Some(pipeline)) = (&active_app, &active_pipeline) else {
+                    eprintln!("[flush] FlushRequested but no application is active");
+                    request.respond(Err("No application is active".into()));
+                    continue;
                 };
+                let display_name = app.display_name.clone();
 
                 // Snapshot the ring buffer without draining it so recording
                 // continues to accumulate while the MP4 is being written.
-                let (segments, video_params, audio_params) = {
+                // Spilled segments are read back from disk after the lock is released.
+                let requested_at = chrono::Local::now();
+                let (snapshot, stats) = {
                     let rb = ring_buffer.lock().unwrap();
-                    let segs = rb.segments().iter().cloned().collect::<Vec<_>>();
-                    let vp = rb.video_params.clone();
-                    let ap = rb.audio_params.clone();
-                    (segs, vp, ap)
-                };
-
-                let (video_params, audio_params) = match (video_params, audio_params) {
-                    (Some(v), Some(a)) => (v, a),
-                    _ => {
+                    if rb.current_epoch().is_none() {
                         eprintln!("[flush] Codec parameters not yet available; skipping flush");
+                        request.respond(Err("Codec parameters not yet available".into()));
                         continue;
                     }
+                    (rb.snapshot(), rb.stats())
                 };
 
-                let clip_output_dir = {
+                let (output, post_roll, info) = {
                     let cfg = shared_config.read().await;
-                    cfg.global.clip_output_dir.clone()
+                    let info = flush::ClipInfo {
+                        session_id: pipeline.session_id().to_string(),
+                        tag: request.tag.take(),
+                        encoder_backend: current_status
+                            .encoder
+                            .as_ref()
+                            .and_then(|e| e.backend.clone()),
+                        buffer: Some(stats),
+                        max_duration: request.seconds.map(|s| Duration::from_secs(u64::from(s))),
+                        requested_at,
+                        ..flush::ClipInfo::for_app(app, &cfg.global)
+                    };
+                    (
+                        flush::ClipOutput::from_config(&cfg.global),
+                        app.effective_post_roll(&cfg.global),
+                        info,
+                    )
                 };
 
-                // Signal flushing state to the GUI.
+                let job = flush_worker.reserve();
+                if post_roll > 0 {
+                    println!("[flush] Recording {post_roll} s of post-roll for '{display_name}'…");
+                    pending_clip = Some(flush::PendingClip::new(
+                        job,
+                        snapshot,
+                        info,
+                        Duration::from_secs(u64::from(post_roll)),
+                    ));
+                    let accepted = event::ClipAccepted::PostRoll { job, post_roll_secs: post_roll };
+                    request.respond(Ok(accepted));
+                    current_status.state = status::DaemonState::Pending;
+                    publish_status(&status_path, &mut current_status, &mut notifier);
+                    continue;
+                }
+
+                submit_clip(&mut flush_worker, job, snapshot, info, output);
+                flush_jobs.insert(job);
+                request.respond(Ok(event::ClipAccepted::Saving { job }));
                 current_status.state = status::DaemonState::Flushing;
-                status::write_status(&status_path, &current_status);
-
-                println!("[flush] Saving clip for '{display_name}' ({} segments)…", segments.len());
-
-                match flush::flush_to_disk(
-                    segments,
-                    video_params,
-                    audio_params,
-                    clip_output_dir,
-                    display_name,
-                )
-                .await
-                {
-                    Ok(path) => {
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
+
+            event::DaemonEvent::StatusRequested(reply) => {
+                let _ = reply.send(current_status.clone());
+            }
+
+            event::DaemonEvent::Subscribe(reply) => {
+                let _ = reply.send(notifier.subscribe(&current_status));
+            }
+
+            event::DaemonEvent::Pause => {
+                if paused {
+                    continue;
+                }
+                paused = true;
+                println!("Recording paused");
+                if let Some(p) = active_pipeline.take() {
+                    p.stop().await;
+                }
+                // A clip waiting for post-roll keeps what was recorded so far.
+                if let Some(clip) = pending_clip.take() {
+                    let job =
+                        finish_pending_clip(clip, &ring_buffer, &shared_config, &mut flush_worker)
+                            .await;
+                    flush_jobs.insert(job);
+                }
+                current_status.state =
+                    status::DaemonState::from_activity(false, true, false, !flush_jobs.is_empty());
+                current_status.buffer = None;
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
+
+            event::DaemonEvent::Resume => {
+                if !paused {
+                    continue;
+                }
+                paused = false;
+                println!("Recording resumed");
+                if let Some(app) = &active_app {
+                    println!("Recording started: {}", app.display_name);
+                    let cfg = shared_config.read().await;
+                    active_pipeline = Some(start_recording(
+                        app,
+                        &cfg,
+                        &ring_buffer,
+                        &spill_path,
+                        &hotkey_handle,
+                        &event_tx,
+                    ));
+                }
+                current_status.state = status::DaemonState::from_activity(
+                    active_pipeline.is_some(),
+                    false,
+                    false,
+                    !flush_jobs.is_empty(),
+                );
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
+
+            event::DaemonEvent::EncoderSelected(encoder_status) => {
+                match &encoder_status.backend {
+                    Some(backend) => println!("[encoder] Using {backend} backend"),
+                    None => {
+                        current_status.error = Some(format!(
+                            "No usable encoder (requested \"{}\")",
+                            encoder_status.requested
+                        ));
+                    }
+                }
+                current_status.encoder = Some(encoder_status);
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
+
+            event::DaemonEvent::BufferUpdated(stats) => {
+                // Late reports from a pipeline that has just been stopped are stale.
+                if active_pipeline.is_none() {
+                    continue;
+                }
+                current_status.buffer = Some(stats.into());
+                let post_roll_done = match &mut pending_clip {
+                    Some(clip) => clip.collect(&ring_buffer.lock().unwrap()),
+                    None => false,
+                };
+                if let (true, Some(clip)) = (post_roll_done, pending_clip.take()) {
+                    let output = flush::ClipOutput::from_config(&shared_config.read().await.global);
+                    let (job, snapshot, info) = clip.into_parts();
+                    submit_clip(&mut flush_worker, job, snapshot, info, output);
+                    flush_jobs.insert(job);
+                    current_status.state = status::DaemonState::Flushing;
+                }
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
+
+            event::DaemonEvent::FlushProgress { job, stage } => match stage {
+                flush_worker::FlushStage::Started => println!("[flush] Clip {job}: muxing…"),
+                flush_worker::FlushStage::PartWritten { written, total } => {
+                    println!("[flush] Clip {job}: wrote file {written} of {total}")
+                }
+            },
+
+            event::DaemonEvent::FlushFinished { job, result } => {
+                flush_jobs.remove(&job);
+                match result {
+                    Ok(saved) => {
                         let timestamp = chrono::Local::now().to_rfc3339();
-                        println!("[flush] Clip saved: {}", path.display());
+                        for clip in &saved {
+                            println!("[flush] Clip saved: {}", clip.path.display());
+                        }
+                        let last = saved.last();
                         current_status.last_clip_path =
-                            Some(path.to_string_lossy().into_owned());
+                            last.map(|clip| clip.path.to_string_lossy().into_owned());
                         current_status.last_clip_timestamp = Some(timestamp);
+                        current_status.last_clip_thumbnail = last
+                            .and_then(|clip| clip.thumbnail.as_ref())
+                            .map(|p| p.to_string_lossy().into_owned());
                         current_status.error = None;
+                        let paths: Vec<_> = saved.into_iter().map(|clip| clip.path).collect();
+                        notifier.clip_saved(job, &paths);
+                        retention.request();
                     }
                     Err(e) => {
-                        eprintln!("[flush] Failed to save clip: {e}");
-                        current_status.error = Some(format!("Flush failed: {e}"));
+                        eprintln!("[flush] Failed to save clip {job}: {e:#}");
+                        current_status.error = Some(format!("Flush failed: {e:#}"));
+                        notifier.flush_failed(job, format!("{e:#}"));
                     }
                 }
+                disk_monitor.request();
+                if shutting_down {
+                    if flush_jobs.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+                current_status.state = status::DaemonState::from_activity(
+                    active_pipeline.is_some(),
+                    paused,
+                    pending_clip.is_some(),
+                    !flush_jobs.is_empty(),
+                );
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
+
+            event::DaemonEvent::DiskSpaceChecked(space) => {
+                let warning = space.warning();
+                if warning.is_some() && current_status.warning != warning {
+                    eprintln!("[disk] {}", warning.as_deref().unwrap_or_default());
+                }
+                current_status.warning = warning;
+                current_status.disk_free_bytes = Some(space.free_bytes);
+                publish_status(&status_path, &mut current_status, &mut notifier);
+            }
 
-                // Return to recording state regardless of flush outcome.
-                current_status.state = status::DaemonState::Recording;
-                status::write_status(&status_path, &current_status);
+            event::DaemonEvent::Heartbeat => {
+                current_status.heartbeat_at = Some(chrono::Local::now().to_rfc3339());
+                publish_status(&status_path, &mut current_status, &mut notifier);
             }
 
             event::DaemonEvent::Shutdown => {
@@ -208,14 +495,115 @@ async fn main() {
                 if let Some(p) = active_pipeline.take() {
                     p.stop().await;
                 }
-                current_status.state = status::DaemonState::Idle;
+                if let Some(clip) = pending_clip.take() {
+                    let job =
+                        finish_pending_clip(clip, &ring_buffer, &shared_config, &mut flush_worker)
+                            .await;
+                    flush_jobs.insert(job);
+                }
+                if flush_jobs.is_empty() {
+                    break;
+                }
+                // Keep handling events until every queued clip is on disk.
+                println!("[flush] Waiting for {} clip(s) to finish saving", flush_jobs.len());
+                shutting_down = true;
+                current_status.state = status::DaemonState::Flushing;
                 current_status.active_application = None;
-                current_status.error = None;
-                status::write_status(&status_path, &current_status);
-                break;
+                current_status.buffer = None;
+                publish_status(&status_path, &mut current_status, &mut notifier);
             }
         }
     }
 
+    // Drops the buffered footage and deletes the spill file, now that no
+    // flush job can still be reading from it.
+    ring_buffer.lock().unwrap().clear();
+    current_status.state = status::DaemonState::Idle;
+    current_status.active_application = None;
+    current_status.buffer = None;
+    // No heartbeat marks a clean exit for `peaking-daemon status`.
+    current_status.heartbeat_at = None;
+    publish_status(&status_path, &mut current_status, &mut notifier);
+
     hotkey_handle.stop();
 }
+
+/// Stamps `status` as a new revision, writes it to status.toml and pushes it
+/// to IPC subscribers.
+fn publish_status(path: &Path, status: &mut status::DaemonStatus, notifier: &mut ipc::Notifier) {
+    status.stamp();
+    status::write_status(path, status);
+    notifier.status_changed(status);
+}
+
+/// Enables the ring buffer's disk spill tier with a `bytes`-sized file, or
+/// disables it with `None`.  On failure the buffer keeps only what fits in
+/// memory.
+fn apply_spill(rb: &mut RingBuffer, path: &Path, bytes: Option<u64>) {
+    if let Err(e) = rb.set_spill(bytes.map(|bytes| (path, bytes))) {
+        eprintln!(
+            "[ring_buffer] Disk spill unavailable; buffering at most {MAX_MEMORY_BUFFER_SECS} s: {e:#}"
+        );
+    }
+}
+
+/// Resets the ring buffer for `app`, points the hotkey at its key and starts
+/// capturing it.
+fn start_recording(
+    app: &config::ApplicationConfig,
+    cfg: &config::Config,
+    ring_buffer: &Arc<Mutex<RingBuffer>>,
+    spill_path: &Path,
+    hotkey: &hotkey::HotkeyHandle,
+    event_tx: &mpsc::Sender<event::DaemonEvent>,
+) -> pipeline::Pipeline {
+    {
+        let mut rb = ring_buffer.lock().unwrap();
+        rb.clear();
+        rb.resize(app.effective_buffer_length(&cfg.global));
+        rb.set_max_bytes(app.effective_max_buffer_bytes(&cfg.global));
+        let spill = app.effective_spill_file_bytes(&cfg.global);
+        apply_spill(&mut rb, spill_path, spill);
+    }
+    hotkey.update_key(app.effective_hotkey(&cfg.global));
+    let (video_source, audio_source) =
+        pipeline::default_sources(&pipeline::encoder_config_for(app, cfg));
+    pipeline::Pipeline::start(
+        app,
+        cfg,
+        Arc::clone(ring_buffer),
+        video_source,
+        audio_source,
+        event_tx.clone(),
+    )
+}
+
+/// Hands `snapshot` to the flush worker as `job`.  The outcome arrives later
+/// as [`event::DaemonEvent::FlushFinished`].
+fn submit_clip(
+    worker: &mut FlushWorker,
+    job: JobId,
+    snapshot: BufferSnapshot,
+    info: flush::ClipInfo,
+    output: flush::ClipOutput,
+) {
+    let segment_count = snapshot.segment_count();
+    let game = info.display_name.clone();
+    worker.submit(job, snapshot, output, info);
+    println!("[flush] Saving clip {job} for '{game}' ({segment_count} segments)…");
+}
+
+/// Queues a clip whose post-roll was cut short (the game exited or the daemon
+/// is shutting down), including every segment recorded so far.
+async fn finish_pending_clip(
+    mut clip: flush::PendingClip,
+    ring_buffer: &Mutex<RingBuffer>,
+    config: &RwLock<config::Config>,
+    worker: &mut FlushWorker,
+) -> JobId {
+    clip.collect(&ring_buffer.lock().unwrap());
+    let output = flush::ClipOutput::from_config(&config.read().await.global);
+    let (job, snapshot, info) = clip.into_parts();
+    submit_clip(worker, job, snapshot, info, output);
+    job
+}
@@ -0,0 +1,23 @@
+/// Descriptive tags embedded in every saved clip, so players and the GUI can
+/// tell where a clip came from without parsing its file name.
+///
+/// The MP4 writer stores them as iTunes-style `moov/udta/meta/ilst` items
+/// (`©nam`, `©day`, `©too`, `©cmt`, `keyw`) plus the `mvhd` creation time;
+/// the Matroska writer uses the Segment Info (`Title`, `DateUTC`,
+/// `WritingApp`) and a `Tags` element (`COMMENT`, `ENCODER`, `KEYWORDS`).
+use chrono::{DateTime, Utc};
+
+/// Written as the encoder / writing application of every clip.
+pub const ENCODER: &str = concat!("peaking-daemon ", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClipMetadata {
+    /// Display name of the recorded application.
+    pub title: String,
+    /// When the clip was saved.
+    pub created: DateTime<Utc>,
+    /// Free-form details, e.g. `Buffer length: 30 s, encoder: nvenc`.
+    pub comment: String,
+    /// Tag given with the save request.
+    pub tag: Option<String>,
+}
@@ -0,0 +1,882 @@
+/// Native Matroska (MKV) writer for pre-encoded H.264 + AAC segments.
+///
+/// Carries the same packets, timestamps and codec configuration as the MP4
+/// writer in [`crate::mp4`] — `V_MPEG4/ISO/AVC` with an `avcC` CodecPrivate
+/// and `A_AAC` with an AudioSpecificConfig — but lays the file out so an
+/// interrupted write still leaves a playable clip:
+///
+///   EBML header, Segment (unknown size) { SeekHead, Info, Tracks, Tags,
+///   Cluster…, Cues }
+///
+/// Each ring-buffer segment becomes one Cluster whose blocks are interleaved
+/// by timestamp.  Everything a player needs to start decoding precedes the
+/// first Cluster, and the Segment's size is left open, so a file cut short
+/// plays up to its last complete Cluster; only the trailing Cues (used for
+/// seeking) are lost.
+///
+/// Timestamps are stored in milliseconds.  As in the MP4 writer, each track
+/// starts at zero: video from its first presentation timestamp, audio from
+/// its first packet.
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use crate::metadata::{self, ClipMetadata};
+use crate::mp4;
+use crate::ring_buffer::{AudioCodecParams, EncodedPacket, EncodedSegment, VideoCodecParams};
+
+const VIDEO_TRACK: u8 = 1;
+const AUDIO_TRACK: u8 = 2;
+/// Nanoseconds per timestamp tick (1 ms).
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+/// Reserved "unknown" size for the Segment element (all value bits set).
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+/// DateUTC counts nanoseconds from 2001-01-01T00:00:00 UTC.
+const DATE_UTC_EPOCH_SECS: i64 = 978_307_200;
+/// TargetTypeValue of tags that apply to the whole clip ("MOVIE").
+const TARGET_MOVIE: u64 = 50;
+
+// Element IDs, including their length-marker bits.
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const DURATION: u32 = 0x4489;
+const TITLE: u32 = 0x7BA9;
+const DATE_UTC: u32 = 0x4461;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const LANGUAGE: u32 = 0x22_B59C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const TAGS: u32 = 0x1254_C367;
+const TAG: u32 = 0x7373;
+const TARGETS: u32 = 0x63C0;
+const TARGET_TYPE_VALUE: u32 = 0x68CA;
+const SIMPLE_TAG: u32 = 0x67C8;
+const TAG_NAME: u32 = 0x45A3;
+const TAG_STRING: u32 = 0x4487;
+const CLUSTER: u32 = 0x1F43_B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+/// Bytes in a SimpleBlock header: track number, relative timestamp, flags.
+const BLOCK_HEADER_LEN: u64 = 4;
+
+// ── Public entry points ────────────────────────────────────────────────────────
+
+/// Writes `segments` as Matroska into the newly created `file`.
+pub fn write_file(
+    file: &File,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    let mut out = BufWriter::new(file);
+    write_mkv(&mut out, segments, video_params, audio_params, metadata)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes `segments` as a Matroska stream into `out`.
+///
+/// Fails if there are no video packets.  An empty audio track is omitted.
+pub fn write_mkv<W: Write>(
+    out: &mut W,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    let video_packets: Vec<&EncodedPacket> =
+        segments.iter().flat_map(|s| s.video_packets.iter()).collect();
+    let first_video = video_packets.first().context("No video packets to mux")?;
+    let first_audio = segments.iter().flat_map(|s| s.audio_packets.first()).next();
+
+    let video_clock = Clock::new(video_params.time_base, first_video.pts)?;
+    let audio_clock = match first_audio {
+        Some(pkt) => Some(Clock::new(audio_params.time_base, pkt.pts)?),
+        None => None,
+    };
+
+    let avcc = mp4::avc_decoder_config(&video_packets, video_params)?;
+    let asc = match audio_clock {
+        Some(_) => Some(mp4::audio_specific_config(audio_params)?),
+        None => None,
+    };
+
+    let clusters = plan_clusters(segments, &video_clock, audio_clock.as_ref());
+    let duration_ms = clusters
+        .iter()
+        .flat_map(|c| &c.blocks)
+        .map(|b| b.end)
+        .max()
+        .unwrap_or(0);
+
+    let info = build_info(duration_ms, metadata);
+    let tracks = build_tracks(video_params, &avcc, audio_params, asc.as_deref());
+    let tags = build_tags(metadata);
+
+    // Positions are relative to the start of the Segment's data.  The
+    // SeekHead stores them at a fixed width, so its length is known upfront.
+    let seek_head_len = build_seek_head(0, 0, 0, 0).len() as u64;
+    let info_pos = seek_head_len;
+    let tracks_pos = info_pos + info.len() as u64;
+    let tags_pos = tracks_pos + tracks.len() as u64;
+    let mut cluster_pos = Vec::with_capacity(clusters.len());
+    let mut pos = tags_pos + tags.len() as u64;
+    for cluster in &clusters {
+        cluster_pos.push(pos);
+        pos += element_len(CLUSTER, cluster.body_len());
+    }
+    let cues_pos = pos;
+
+    let mut head = build_ebml_header();
+    head.put_id(SEGMENT);
+    head.extend_from_slice(&UNKNOWN_SIZE);
+    head.extend_from_slice(&build_seek_head(info_pos, tracks_pos, tags_pos, cues_pos));
+    head.extend_from_slice(&info);
+    head.extend_from_slice(&tracks);
+    head.extend_from_slice(&tags);
+    out.write_all(&head)?;
+
+    for cluster in &clusters {
+        write_cluster(out, cluster)?;
+    }
+    out.write_all(&build_cues(&clusters, &cluster_pos))?;
+    Ok(())
+}
+
+// ── Block layout ───────────────────────────────────────────────────────────────
+
+/// Converts a track's timestamps to milliseconds from the track's origin.
+struct Clock {
+    num: i64,
+    den: i64,
+    origin: i64,
+}
+
+impl Clock {
+    fn new(time_base: (i32, i32), origin: i64) -> Result<Self> {
+        let (num, den) = time_base;
+        if num <= 0 || den <= 0 {
+            bail!("Invalid time base {num}/{den}");
+        }
+        Ok(Self { num: i64::from(num), den: i64::from(den), origin })
+    }
+
+    fn ms(&self, ts: i64) -> i64 {
+        ((ts - self.origin) * self.num * 1000).div_euclid(self.den)
+    }
+}
+
+/// One SimpleBlock: a single packet on one track.
+struct Block<'a> {
+    track: u8,
+    /// Presentation time in ms.
+    time: i64,
+    /// `time` plus the packet's duration, for the Info duration.
+    end: i64,
+    packet: &'a EncodedPacket,
+    /// Size of the frame data as written (length-prefixed / ADTS-stripped).
+    data_len: u64,
+}
+
+struct Cluster<'a> {
+    timestamp: u64,
+    blocks: Vec<Block<'a>>,
+}
+
+impl Cluster<'_> {
+    fn body_len(&self) -> u64 {
+        let blocks: u64 = self
+            .blocks
+            .iter()
+            .map(|b| element_len(SIMPLE_BLOCK, BLOCK_HEADER_LEN + b.data_len))
+            .sum();
+        element_len(TIMESTAMP, uint_len(self.timestamp)) + blocks
+    }
+
+    /// Time of the first video keyframe, which the Cues point at.
+    fn keyframe_time(&self) -> Option<u64> {
+        self.blocks
+            .iter()
+            .find(|b| b.track == VIDEO_TRACK && b.packet.is_key)
+            .map(|b| b.time.max(0) as u64)
+    }
+}
+
+/// Groups each segment's packets into one Cluster, ordered by time.  A
+/// segment spanning more than a block's 16-bit relative timestamp range is
+/// split across several Clusters.
+fn plan_clusters<'a>(
+    segments: &'a [Arc<EncodedSegment>],
+    video: &Clock,
+    audio: Option<&Clock>,
+) -> Vec<Cluster<'a>> {
+    let mut clusters: Vec<Cluster<'a>> = vec![];
+    for segment in segments {
+        let mut blocks: Vec<Block<'a>> = segment
+            .video_packets
+            .iter()
+            .map(|p| Block {
+                track: VIDEO_TRACK,
+                time: video.ms(p.pts),
+                end: video.ms(p.pts + p.duration),
+                packet: p,
+                data_len: mp4::video_sample_size(&p.data),
+            })
+            .collect();
+        if let Some(audio) = audio {
+            blocks.extend(segment.audio_packets.iter().map(|p| Block {
+                track: AUDIO_TRACK,
+                time: audio.ms(p.pts),
+                end: audio.ms(p.pts + p.duration),
+                packet: p,
+                data_len: mp4::strip_adts(&p.data).len() as u64,
+            }));
+        }
+        // Stable, so frames sharing a timestamp keep video-before-audio order.
+        blocks.sort_by_key(|b| b.time);
+
+        let mut current: Option<Cluster<'a>> = None;
+        for block in blocks {
+            let fits = current.as_ref().is_some_and(|c| {
+                i16::try_from(block.time - c.timestamp as i64).is_ok()
+            });
+            if !fits {
+                clusters.extend(current.take());
+                current = Some(Cluster { timestamp: block.time.max(0) as u64, blocks: vec![] });
+            }
+            if let Some(c) = current.as_mut() {
+                c.blocks.push(block);
+            }
+        }
+        clusters.extend(current);
+    }
+    clusters
+}
+
+fn write_cluster<W: Write>(out: &mut W, cluster: &Cluster) -> std::io::Result<()> {
+    let mut header = vec![];
+    header.put_id(CLUSTER);
+    header.put_size(cluster.body_len());
+    header.put_uint(TIMESTAMP, cluster.timestamp);
+    out.write_all(&header)?;
+
+    for block in &cluster.blocks {
+        let mut header = vec![];
+        header.put_id(SIMPLE_BLOCK);
+        header.put_size(BLOCK_HEADER_LEN + block.data_len);
+        header.push(0x80 | block.track); // track number as a 1-byte vint
+        let relative = (block.time - cluster.timestamp as i64) as i16;
+        header.extend_from_slice(&relative.to_be_bytes());
+        header.push(if block.packet.is_key { 0x80 } else { 0 });
+        out.write_all(&header)?;
+        if block.track == VIDEO_TRACK {
+            mp4::write_video_sample(out, &block.packet.data)?;
+        } else {
+            out.write_all(mp4::strip_adts(&block.packet.data))?;
+        }
+    }
+    Ok(())
+}
+
+// ── Header elements ────────────────────────────────────────────────────────────
+
+fn build_ebml_header() -> Vec<u8> {
+    let mut buf = vec![];
+    buf.put_master(EBML, |b| {
+        b.put_uint(EBML_VERSION, 1);
+        b.put_uint(EBML_READ_VERSION, 1);
+        b.put_uint(EBML_MAX_ID_LENGTH, 4);
+        b.put_uint(EBML_MAX_SIZE_LENGTH, 8);
+        b.put_str(DOC_TYPE, "matroska");
+        b.put_uint(DOC_TYPE_VERSION, 4);
+        b.put_uint(DOC_TYPE_READ_VERSION, 2);
+    });
+    buf
+}
+
+fn build_seek_head(info_pos: u64, tracks_pos: u64, tags_pos: u64, cues_pos: u64) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.put_master(SEEK_HEAD, |b| {
+        let entries = [(INFO, info_pos), (TRACKS, tracks_pos), (TAGS, tags_pos), (CUES, cues_pos)];
+        for (id, pos) in entries {
+            b.put_master(SEEK, |b| {
+                let mut id_bytes = vec![];
+                id_bytes.put_id(id);
+                b.put_binary(SEEK_ID, &id_bytes);
+                b.put_id(SEEK_POSITION);
+                b.put_size(8);
+                b.extend_from_slice(&pos.to_be_bytes());
+            });
+        }
+    });
+    buf
+}
+
+fn build_info(duration_ms: i64, metadata: &ClipMetadata) -> Vec<u8> {
+    let created = &metadata.created;
+    let date_ns = (created.timestamp() - DATE_UTC_EPOCH_SECS)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(i64::from(created.timestamp_subsec_nanos()));
+    let mut buf = vec![];
+    buf.put_master(INFO, |b| {
+        b.put_uint(TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS);
+        b.put_str(MUXING_APP, metadata::ENCODER);
+        b.put_str(WRITING_APP, metadata::ENCODER);
+        b.put_float(DURATION, duration_ms.max(0) as f64);
+        if !metadata.title.is_empty() {
+            b.put_str(TITLE, &metadata.title);
+        }
+        b.put_id(DATE_UTC);
+        b.put_size(8);
+        b.extend_from_slice(&date_ns.to_be_bytes());
+    });
+    buf
+}
+
+/// Clip-wide tags: the comment, the encoder and the user's tag as keywords.
+fn build_tags(metadata: &ClipMetadata) -> Vec<u8> {
+    let items = [
+        ("COMMENT", Some(metadata.comment.as_str())),
+        ("ENCODER", Some(metadata::ENCODER)),
+        ("KEYWORDS", metadata.tag.as_deref()),
+    ];
+    let mut buf = vec![];
+    buf.put_master(TAGS, |b| {
+        b.put_master(TAG, |b| {
+            b.put_master(TARGETS, |b| b.put_uint(TARGET_TYPE_VALUE, TARGET_MOVIE));
+            for (name, value) in items {
+                let Some(value) = value.filter(|v| !v.is_empty()) else { continue };
+                b.put_master(SIMPLE_TAG, |b| {
+                    b.put_str(TAG_NAME, name);
+                    b.put_str(TAG_STRING, value);
+                });
+            }
+        });
+    });
+    buf
+}
+
+fn build_tracks(
+    video_params: &VideoCodecParams,
+    avcc: &[u8],
+    audio_params: &AudioCodecParams,
+    asc: Option<&[u8]>,
+) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.put_master(TRACKS, |b| {
+        b.put_master(TRACK_ENTRY, |b| {
+            put_track_header(b, VIDEO_TRACK, 1, "V_MPEG4/ISO/AVC", avcc);
+            b.put_master(VIDEO, |b| {
+                b.put_uint(PIXEL_WIDTH, u64::from(video_params.width));
+                b.put_uint(PIXEL_HEIGHT, u64::from(video_params.height));
+            });
+        });
+        if let Some(asc) = asc {
+            b.put_master(TRACK_ENTRY, |b| {
+                put_track_header(b, AUDIO_TRACK, 2, "A_AAC", asc);
+                b.put_master(AUDIO, |b| {
+                    b.put_float(SAMPLING_FREQUENCY, f64::from(audio_params.sample_rate));
+                    b.put_uint(CHANNELS, u64::from(audio_params.channels));
+                });
+            });
+        }
+    });
+    buf
+}
+
+fn put_track_header(buf: &mut Vec<u8>, number: u8, track_type: u64, codec: &str, private: &[u8]) {
+    buf.put_uint(TRACK_NUMBER, u64::from(number));
+    buf.put_uint(TRACK_UID, u64::from(number));
+    buf.put_uint(TRACK_TYPE, track_type);
+    buf.put_uint(FLAG_LACING, 0);
+    buf.put_str(LANGUAGE, "und");
+    buf.put_str(CODEC_ID, codec);
+    buf.put_binary(CODEC_PRIVATE, private);
+}
+
+/// One CuePoint per Cluster that contains a video keyframe.
+fn build_cues(clusters: &[Cluster], positions: &[u64]) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.put_master(CUES, |b| {
+        for (cluster, &pos) in clusters.iter().zip(positions) {
+            let Some(time) = cluster.keyframe_time() else { continue };
+            b.put_master(CUE_POINT, |b| {
+                b.put_uint(CUE_TIME, time);
+                b.put_master(CUE_TRACK_POSITIONS, |b| {
+                    b.put_uint(CUE_TRACK, u64::from(VIDEO_TRACK));
+                    b.put_uint(CUE_CLUSTER_POSITION, pos);
+                });
+            });
+        }
+    });
+    buf
+}
+
+// ── EBML serialisation ─────────────────────────────────────────────────────────
+
+/// Bytes in an element ID (IDs carry their own length marker).
+fn id_len(id: u32) -> u64 {
+    match id {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    }
+}
+
+/// Bytes in the shortest size vint for `n`.  The all-ones value of each
+/// width is reserved for "unknown", so it is avoided.
+fn vint_len(n: u64) -> u64 {
+    (1..8).find(|&k| n < (1u64 << (7 * k)) - 1).unwrap_or(8)
+}
+
+/// Bytes in the shortest big-endian encoding of `v` (at least one).
+fn uint_len(v: u64) -> u64 {
+    (u64::from(64 - v.leading_zeros()).div_ceil(8)).max(1)
+}
+
+/// Total bytes of an element whose body is `body_len` bytes.
+fn element_len(id: u32, body_len: u64) -> u64 {
+    id_len(id) + vint_len(body_len) + body_len
+}
+
+/// Append helpers for building EBML elements in a `Vec<u8>`.
+trait PutEbml {
+    fn put_id(&mut self, id: u32);
+    fn put_size(&mut self, n: u64);
+    fn put_uint(&mut self, id: u32, v: u64);
+    fn put_float(&mut self, id: u32, v: f64);
+    fn put_str(&mut self, id: u32, s: &str);
+    fn put_binary(&mut self, id: u32, data: &[u8]);
+    fn put_master(&mut self, id: u32, body: impl FnOnce(&mut Vec<u8>));
+}
+
+impl PutEbml for Vec<u8> {
+    fn put_id(&mut self, id: u32) {
+        let len = id_len(id) as usize;
+        self.extend_from_slice(&id.to_be_bytes()[4 - len..]);
+    }
+    fn put_size(&mut self, n: u64) {
+        let len = vint_len(n) as usize;
+        let marked = n | (1u64 << (7 * len));
+        self.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+    }
+    fn put_uint(&mut self, id: u32, v: u64) {
+        let len = uint_len(v) as usize;
+        self.put_id(id);
+        self.put_size(len as u64);
+        self.extend_from_slice(&v.to_be_bytes()[8 - len..]);
+    }
+    fn put_float(&mut self, id: u32, v: f64) {
+        self.put_id(id);
+        self.put_size(8);
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+    fn put_str(&mut self, id: u32, s: &str) {
+        self.put_binary(id, s.as_bytes());
+    }
+    fn put_binary(&mut self, id: u32, data: &[u8]) {
+        self.put_id(id);
+        self.put_size(data.len() as u64);
+        self.extend_from_slice(data);
+    }
+    fn put_master(&mut self, id: u32, body: impl FnOnce(&mut Vec<u8>)) {
+        let mut inner = vec![];
+        body(&mut inner);
+        self.put_binary(id, &inner);
+    }
+}
+
+// ── Interrupted files ──────────────────────────────────────────────────────────
+
+/// Whether `input` holds at least one complete Cluster, so a file cut short
+/// still has footage to play.
+pub fn has_complete_cluster<R: Read + Seek>(input: &mut R) -> std::io::Result<bool> {
+    let len = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+    let mut input = BufReader::new(input);
+    let Some((EBML, Some(size), header_len)) = read_element_header(&mut input)? else {
+        return Ok(false);
+    };
+    let mut pos = header_len + size;
+    input.seek(SeekFrom::Start(pos))?;
+    let Some((SEGMENT, _, header_len)) = read_element_header(&mut input)? else {
+        return Ok(false);
+    };
+    pos += header_len;
+    while let Some((id, Some(size), header_len)) = read_element_header(&mut input)? {
+        let end = pos.saturating_add(header_len).saturating_add(size);
+        if end > len {
+            break;
+        }
+        if id == CLUSTER {
+            return Ok(true);
+        }
+        pos = end;
+        input.seek(SeekFrom::Start(pos))?;
+    }
+    Ok(false)
+}
+
+/// Reads an element's ID (with its length marker) and size, and returns them
+/// with the header's length.  The size is `None` if it is unknown.  Returns
+/// `None` at the end of the input or on a malformed header.
+fn read_element_header(input: &mut impl Read) -> std::io::Result<Option<(u32, Option<u64>, u64)>> {
+    let Some((id, id_len)) = read_element_vint(input, true)? else { return Ok(None) };
+    let Some((size, size_len)) = read_element_vint(input, false)? else { return Ok(None) };
+    if id_len > 4 {
+        return Ok(None);
+    }
+    let unknown = size == (1u64 << (7 * size_len)) - 1;
+    Ok(Some((id as u32, (!unknown).then_some(size), (id_len + size_len) as u64)))
+}
+
+/// Reads a vint, returning `(value, len)`.  `keep_marker` is used for IDs.
+fn read_element_vint(
+    input: &mut impl Read,
+    keep_marker: bool,
+) -> std::io::Result<Option<(u64, usize)>> {
+    let mut bytes = [0u8; 8];
+    if let Err(e) = input.read_exact(&mut bytes[..1]) {
+        return if e.kind() == std::io::ErrorKind::UnexpectedEof { Ok(None) } else { Err(e) };
+    }
+    let len = bytes[0].leading_zeros() as usize + 1;
+    if len > 8 {
+        return Ok(None);
+    }
+    if let Err(e) = input.read_exact(&mut bytes[1..len]) {
+        return if e.kind() == std::io::ErrorKind::UnexpectedEof { Ok(None) } else { Err(e) };
+    }
+    let first = if keep_marker { bytes[0] } else { bytes[0] & (0xFFu16 >> len) as u8 };
+    let value = bytes[1..len].iter().fold(u64::from(first), |v, b| (v << 8) | u64::from(*b));
+    Ok(Some((value, len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    /// [`make_segments`] starting at a non-zero timestamp.
+    fn offset_segments() -> Vec<Arc<EncodedSegment>> {
+        shared(&make_segments_from(900, 500))
+    }
+
+    fn mux(segments: &[Arc<EncodedSegment>]) -> Vec<u8> {
+        let mut out = vec![];
+        write_mkv(&mut out, segments, &video_params(), &audio_params(), &metadata()).unwrap();
+        out
+    }
+
+    // ── Minimal EBML reader ───────────────────────────────────────────────────
+
+    struct Element<'a> {
+        id: u32,
+        /// Offset of the element's first byte within the parsed slice.
+        offset: usize,
+        body: &'a [u8],
+    }
+
+    /// Reads a vint at `data[0]`, returning `(value, len)`.  `keep_marker`
+    /// is used for IDs.
+    fn read_vint(data: &[u8], keep_marker: bool) -> (u64, usize) {
+        let len = data[0].leading_zeros() as usize + 1;
+        let mut v = u64::from(if keep_marker { data[0] } else { data[0] & (0xFFu16 >> len) as u8 });
+        for b in &data[1..len] {
+            v = (v << 8) | u64::from(*b);
+        }
+        (v, len)
+    }
+
+    /// Parses the elements directly inside `data`.  An unknown size runs to
+    /// the end of `data`.
+    fn children(data: &[u8]) -> Vec<Element<'_>> {
+        let mut elements = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let (id, id_len) = read_vint(&data[pos..], true);
+            let size_at = pos + id_len;
+            let (size, size_len) = read_vint(&data[size_at..], false);
+            let start = size_at + size_len;
+            let unknown = size == (1u64 << (7 * size_len)) - 1;
+            let end = if unknown { data.len() } else { (start + size as usize).min(data.len()) };
+            elements.push(Element { id: id as u32, offset: pos, body: &data[start..end] });
+            pos = end;
+        }
+        elements
+    }
+
+    fn child(data: &[u8], id: u32) -> &[u8] {
+        children(data).into_iter().find(|e| e.id == id).map(|e| e.body).unwrap()
+    }
+
+    fn uint(body: &[u8]) -> u64 {
+        body.iter().fold(0, |v, b| (v << 8) | u64::from(*b))
+    }
+
+    fn segment_body(file: &[u8]) -> &[u8] {
+        child(file, SEGMENT)
+    }
+
+    // ── File layout ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn output_is_ebml_header_then_open_ended_segment() {
+        let file = mux(&offset_segments());
+        let top: Vec<u32> = children(&file).iter().map(|e| e.id).collect();
+        assert_eq!(top, [EBML, SEGMENT]);
+        assert_eq!(child(child(&file, EBML), DOC_TYPE), b"matroska");
+
+        let segment_at = children(&file)[1].offset;
+        assert_eq!(&file[segment_at + 4..segment_at + 12], &UNKNOWN_SIZE);
+    }
+
+    #[test]
+    fn segment_has_header_elements_then_clusters_then_cues() {
+        let file = mux(&offset_segments());
+        let ids: Vec<u32> = children(segment_body(&file)).iter().map(|e| e.id).collect();
+        assert_eq!(ids, [SEEK_HEAD, INFO, TRACKS, TAGS, CLUSTER, CLUSTER, CUES]);
+    }
+
+    #[test]
+    fn truncated_file_keeps_its_complete_clusters() {
+        let has_cluster =
+            |data: &[u8]| has_complete_cluster(&mut std::io::Cursor::new(data)).unwrap();
+        let file = mux(&offset_segments());
+        let segment = segment_body(&file);
+        let first_cluster = children(segment).iter().find(|e| e.id == CLUSTER).unwrap().offset;
+        let cluster_at = file.len() - segment.len() + first_cluster;
+        assert!(has_cluster(&file));
+        assert!(has_cluster(&file[..file.len() - 1]), "only the Cues cut short");
+        assert!(!has_cluster(&file[..cluster_at + 20]), "first Cluster cut short");
+        assert!(!has_cluster(&[]));
+    }
+
+    #[test]
+    fn seek_head_points_at_elements() {
+        let file = mux(&offset_segments());
+        let segment = segment_body(&file);
+        let seeks = children(child(segment, SEEK_HEAD));
+        for seek in seeks {
+            let (target, _) = read_vint(child(seek.body, SEEK_ID), true);
+            let pos = uint(child(seek.body, SEEK_POSITION)) as usize;
+            let (id, _) = read_vint(&segment[pos..], true);
+            assert_eq!(id, target);
+        }
+    }
+
+    #[test]
+    fn tracks_carry_codec_private_data() {
+        let file = mux(&offset_segments());
+        let entries = children(child(segment_body(&file), TRACKS));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(child(entries[0].body, CODEC_ID), b"V_MPEG4/ISO/AVC");
+        let avcc = child(entries[0].body, CODEC_PRIVATE);
+        assert_eq!(&avcc[..4], &[1, 0x64, 0x00, 0x1F]);
+        assert_eq!(uint(child(child(entries[0].body, VIDEO), PIXEL_WIDTH)), 320);
+        assert_eq!(child(entries[1].body, CODEC_ID), b"A_AAC");
+        assert_eq!(child(entries[1].body, CODEC_PRIVATE), &[0x11, 0x90]);
+    }
+
+    #[test]
+    fn audio_track_omitted_when_no_audio_packets() {
+        let segments: Vec<_> = offset_segments()
+            .into_iter()
+            .map(|s| Arc::new(EncodedSegment { audio_packets: vec![], ..(*s).clone() }))
+            .collect();
+        let file = mux(&segments);
+        assert_eq!(children(child(segment_body(&file), TRACKS)).len(), 1);
+    }
+
+    #[test]
+    fn no_video_packets_is_an_error() {
+        let segments = [Arc::new(EncodedSegment {
+            video_packets: vec![],
+            audio_packets: vec![],
+            pushed_at: None,
+        })];
+        let mut out = vec![];
+        let result = write_mkv(&mut out, &segments, &video_params(), &audio_params(), &metadata());
+        assert!(result.is_err());
+    }
+
+    // ── Clusters and blocks ───────────────────────────────────────────────────
+
+    fn clusters(file: &[u8]) -> Vec<&[u8]> {
+        children(segment_body(file)).into_iter().filter(|e| e.id == CLUSTER).map(|e| e.body).collect()
+    }
+
+    #[test]
+    fn clusters_start_at_zero_and_follow_segments() {
+        let file = mux(&offset_segments());
+        let timestamps: Vec<u64> = clusters(&file).iter().map(|c| uint(child(c, TIMESTAMP))).collect();
+        assert_eq!(timestamps, [0, 1000]);
+    }
+
+    #[test]
+    fn first_block_is_length_prefixed_keyframe() {
+        let file = mux(&offset_segments());
+        let block = child(clusters(&file)[0], SIMPLE_BLOCK);
+        assert_eq!(block[0], 0x80 | VIDEO_TRACK);
+        assert_eq!(&block[1..3], &0i16.to_be_bytes());
+        assert_eq!(block[3], 0x80, "keyframe flag");
+        assert_eq!(&block[4..], &[0, 0, 0, 3, 0x65, 0x88, 0x84]);
+    }
+
+    #[test]
+    fn blocks_are_interleaved_by_time() {
+        let file = mux(&offset_segments());
+        let blocks: Vec<(u8, i16)> = children(clusters(&file)[0])
+            .iter()
+            .filter(|e| e.id == SIMPLE_BLOCK)
+            .map(|e| (e.body[0] & 0x7F, i16::from_be_bytes([e.body[1], e.body[2]])))
+            .collect();
+        assert_eq!(blocks.len(), 77);
+        assert!(blocks.windows(2).all(|w| w[0].1 <= w[1].1));
+        // Second video frame at 33 ms; second audio frame at 21 ms.
+        assert!(blocks.contains(&(VIDEO_TRACK, 33)));
+        assert!(blocks.contains(&(AUDIO_TRACK, 21)));
+    }
+
+    #[test]
+    fn long_segment_is_split_across_clusters() {
+        let segment = EncodedSegment {
+            video_packets: (0..3i64).map(|f| packet(annex_b(&[&[0x65]]), f * 600, 1, true)).collect(),
+            audio_packets: vec![],
+            pushed_at: None,
+        };
+        let file = mux(&[Arc::new(segment)]);
+        let timestamps: Vec<u64> = clusters(&file).iter().map(|c| uint(child(c, TIMESTAMP))).collect();
+        // Frames at 0, 20 and 40 s; 40 s is beyond an i16 millisecond offset.
+        assert_eq!(timestamps, [0, 40_000]);
+    }
+
+    #[test]
+    fn info_duration_covers_longest_track() {
+        let file = mux(&offset_segments());
+        let duration = child(child(segment_body(&file), INFO), DURATION);
+        // Audio: 94 frames × 1024 samples at 48 kHz ≈ 2005 ms; video is 2000 ms.
+        assert_eq!(f64::from_be_bytes(duration.try_into().unwrap()), 2005.0);
+    }
+
+    #[test]
+    fn info_carries_title_and_date() {
+        let file = mux(&offset_segments());
+        let info = child(segment_body(&file), INFO);
+        assert_eq!(child(info, TITLE), b"Rocket League");
+        assert_eq!(child(info, WRITING_APP), metadata::ENCODER.as_bytes());
+        let date = i64::from_be_bytes(child(info, DATE_UTC).try_into().unwrap());
+        // 2024-03-01T12:00:00Z is 730_987_200 s after 2001-01-01.
+        assert_eq!(date, 730_987_200 * 1_000_000_000);
+    }
+
+    fn simple_tags(file: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let tag = child(child(segment_body(file), TAGS), TAG);
+        children(tag)
+            .into_iter()
+            .filter(|e| e.id == SIMPLE_TAG)
+            .map(|e| (child(e.body, TAG_NAME), child(e.body, TAG_STRING)))
+            .collect()
+    }
+
+    #[test]
+    fn tags_carry_comment_encoder_and_keywords() {
+        let file = mux(&offset_segments());
+        let tags = simple_tags(&file);
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0], (&b"COMMENT"[..], &b"Buffer length: 30 s, encoder: nvenc"[..]));
+        assert_eq!(tags[1], (&b"ENCODER"[..], metadata::ENCODER.as_bytes()));
+
+        let tagged = ClipMetadata { tag: Some("ace".into()), ..metadata() };
+        let mut out = vec![];
+        write_mkv(&mut out, &offset_segments(), &video_params(), &audio_params(), &tagged).unwrap();
+        assert_eq!(simple_tags(&out)[2], (&b"KEYWORDS"[..], &b"ace"[..]));
+    }
+
+    #[test]
+    fn cues_point_at_keyframe_clusters() {
+        let file = mux(&offset_segments());
+        let segment = segment_body(&file);
+        let points = children(child(segment, CUES));
+        assert_eq!(points.len(), 2);
+        for (point, expected_time) in points.iter().zip([0, 1000]) {
+            assert_eq!(uint(child(point.body, CUE_TIME)), expected_time);
+            let pos = uint(child(child(point.body, CUE_TRACK_POSITIONS), CUE_CLUSTER_POSITION));
+            let (id, _) = read_vint(&segment[pos as usize..], true);
+            assert_eq!(id, u64::from(CLUSTER));
+        }
+    }
+
+    #[test]
+    fn truncated_file_keeps_complete_clusters() {
+        let file = mux(&offset_segments());
+        let segment_at = children(&file)[1].offset;
+        let first_cluster = children(segment_body(&file))
+            .into_iter()
+            .find(|e| e.id == CLUSTER)
+            .unwrap();
+        // Cut the file partway through the second cluster.
+        let segment_data_at = segment_at + 12;
+        let cut = segment_data_at + first_cluster.offset + first_cluster.body.len() + 40;
+        let truncated = &file[..cut];
+        let ids: Vec<u32> = children(segment_body(truncated)).iter().map(|e| e.id).collect();
+        assert_eq!(&ids[..5], [SEEK_HEAD, INFO, TRACKS, TAGS, CLUSTER]);
+    }
+
+    // ── EBML encoding ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn sizes_use_shortest_vint() {
+        let mut buf = vec![];
+        buf.put_size(5);
+        buf.put_size(126);
+        buf.put_size(127);
+        assert_eq!(buf, [0x85, 0xFE, 0x40, 0x7F]);
+    }
+
+    #[test]
+    fn write_file_creates_mkv_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.mkv");
+        let file = File::create(&path).unwrap();
+        let segments = offset_segments();
+        write_file(&file, &segments, &video_params(), &audio_params(), &metadata()).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(&file[..4], &EBML.to_be_bytes());
+    }
+}
@@ -0,0 +1,1516 @@
+/// Native ISO BMFF (MP4) writer for pre-encoded H.264 + AAC segments.
+///
+/// Produces a "faststart" file — `ftyp`, then `moov`, then a single `mdat` —
+/// so players can start decoding before the whole file has been read.  The
+/// sample tables (`stts`/`ctts`/`stss`/`stsz`/`stsc`/`stco`) are derived
+/// directly from the packet timestamps stored in the ring buffer, so no FFmpeg
+/// code is involved and the writer runs on every platform.
+///
+/// Each ring-buffer segment becomes one video chunk followed by one audio
+/// chunk, which keeps the two tracks interleaved at one-second granularity.
+///
+/// [`write_fragmented_mp4`] instead writes a fragmented file: a `moov` with
+/// empty sample tables and an `mvex`, then one `moof` + `mdat` pair per
+/// ring-buffer segment.  Every fragment carries its own index, so a file cut
+/// short still plays up to its last complete fragment.
+///
+/// Input conventions:
+///   - Video packets may be Annex B (start-code delimited) or already
+///     length-prefixed; Annex B is rewritten to 4-byte NAL lengths on the fly.
+///   - Video extradata may be an `avcC` record or Annex B SPS/PPS.  If it is
+///     empty, SPS/PPS are taken from the first keyframe.
+///   - Audio packets may be raw AAC or ADTS; ADTS headers are stripped.
+///   - Audio extradata is an AudioSpecificConfig.  If it is empty, an AAC-LC
+///     config is synthesised from the sample rate and channel count.
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use crate::metadata::{self, ClipMetadata};
+use crate::ring_buffer::{AudioCodecParams, EncodedPacket, EncodedSegment, VideoCodecParams};
+
+/// Timescale used for `mvhd`/`tkhd`/`elst` durations (milliseconds).
+const MOVIE_TIMESCALE: u32 = 1000;
+const VIDEO_TRACK_ID: u32 = 1;
+const AUDIO_TRACK_ID: u32 = 2;
+/// Packed ISO 639-2 code for "und" (undetermined), as stored in `mdhd`.
+const LANGUAGE_UND: u16 = 0x55C4;
+/// Size of the NAL length prefix written in front of every video NAL unit.
+const NAL_LENGTH_SIZE: u64 = 4;
+/// Seconds from the MP4 epoch (1904-01-01) to the Unix epoch.
+const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+/// `data` box type for UTF-8 text (iTunes well-known type 1).
+const DATA_TYPE_UTF8: u32 = 1;
+
+/// AAC sampling frequencies indexed by `samplingFrequencyIndex` (ISO 14496-3).
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96_000, 88_200, 64_000, 48_000, 44_100, 32_000, 24_000, 22_050, 16_000, 12_000, 11_025,
+    8_000, 7_350,
+];
+
+// ── Public entry points ────────────────────────────────────────────────────────
+
+/// Writes `segments` as a faststart MP4 into the newly created `file`.
+pub fn write_file(
+    file: &File,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    write_buffered(file, |out| write_mp4(out, segments, video_params, audio_params, metadata))
+}
+
+/// Writes `segments` as a fragmented MP4 into the newly created `file`.  Each
+/// fragment reaches the file as soon as it is written.
+pub fn write_fragmented_file(
+    file: &File,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    write_buffered(file, |out| {
+        write_fragmented_mp4(out, segments, video_params, audio_params, metadata)
+    })
+}
+
+fn write_buffered(file: &File, write: impl FnOnce(&mut BufWriter<&File>) -> Result<()>) -> Result<()> {
+    let mut out = BufWriter::new(file);
+    write(&mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes `segments` as a faststart MP4 stream into `out`.
+///
+/// Fails if there are no video packets — a clip without video is never
+/// useful.  An empty audio track is simply omitted.
+pub fn write_mp4<W: Write>(
+    out: &mut W,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    let (video_chunks, audio_chunks) = layout_chunks(segments);
+    let video = TrackLayout::video(segments, video_params, video_chunks)?;
+    let audio = TrackLayout::audio(segments, audio_params, audio_chunks)?;
+
+    let ftyp = build_ftyp();
+    let payload_len = video.total_bytes() + audio.as_ref().map_or(0, |a| a.total_bytes());
+    let mdat_header_len: u64 = if payload_len + 8 > u64::from(u32::MAX) { 16 } else { 8 };
+
+    // The moov size depends only on whether 32- or 64-bit chunk offsets are
+    // used, not on the offset values, so it can be sized with a zero base.
+    let mut use_co64 = false;
+    let moov_at = |mdat_data_start, use_co64| {
+        build_moov(&video, audio.as_ref(), mdat_data_start, use_co64, None, metadata)
+    };
+    let mut moov_len = moov_at(0, use_co64).len() as u64;
+    if ftyp.len() as u64 + moov_len + mdat_header_len + payload_len > u64::from(u32::MAX) {
+        use_co64 = true;
+        moov_len = moov_at(0, use_co64).len() as u64;
+    }
+    let mdat_data_start = ftyp.len() as u64 + moov_len + mdat_header_len;
+    let moov = moov_at(mdat_data_start, use_co64);
+
+    out.write_all(&ftyp)?;
+    out.write_all(&moov)?;
+    if mdat_header_len == 16 {
+        out.write_all(&1u32.to_be_bytes())?;
+        out.write_all(b"mdat")?;
+        out.write_all(&(payload_len + 16).to_be_bytes())?;
+    } else {
+        out.write_all(&((payload_len + 8) as u32).to_be_bytes())?;
+        out.write_all(b"mdat")?;
+    }
+
+    for segment in segments {
+        for pkt in &segment.video_packets {
+            write_video_sample(out, &pkt.data)?;
+        }
+        for pkt in &segment.audio_packets {
+            out.write_all(strip_adts(&pkt.data))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes `segments` as a fragmented MP4 stream into `out`, flushing `out`
+/// after each fragment.
+///
+/// Composition offsets are shifted so each track's first sample is presented
+/// at its decode time, which replaces the edit list of the faststart layout.
+/// Fails if there are no video packets; an empty audio track is omitted.
+pub fn write_fragmented_mp4<W: Write>(
+    out: &mut W,
+    segments: &[Arc<EncodedSegment>],
+    video_params: &VideoCodecParams,
+    audio_params: &AudioCodecParams,
+    metadata: &ClipMetadata,
+) -> Result<()> {
+    let video = TrackLayout::video(segments, video_params, vec![])?;
+    let audio = TrackLayout::audio(segments, audio_params, vec![])?;
+    let duration = audio
+        .as_ref()
+        .map_or(0, |a| a.movie_duration())
+        .max(video.movie_duration());
+
+    let init_video = video.without_samples();
+    let init_audio = audio.as_ref().map(TrackLayout::without_samples);
+    out.write_all(&build_ftyp())?;
+    let moov = build_moov(&init_video, init_audio.as_ref(), 0, false, Some(duration), metadata);
+    out.write_all(&moov)?;
+    out.flush()?;
+
+    let mut video_cursor = FragmentCursor::new(&video);
+    let mut audio_cursor = audio.as_ref().map(FragmentCursor::new);
+    let mut sequence = 0;
+    for segment in segments {
+        let mut runs = vec![];
+        runs.extend(video_cursor.take(segment.video_packets.len()));
+        if let Some(cursor) = audio_cursor.as_mut() {
+            runs.extend(cursor.take(segment.audio_packets.len()));
+        }
+        if runs.is_empty() {
+            continue;
+        }
+        sequence += 1;
+
+        let payload_len: u64 = runs.iter().map(TrackRun::byte_len).sum();
+        let mdat_header_len: u64 = if payload_len + 8 > u64::from(u32::MAX) { 16 } else { 8 };
+        let moof_len = build_moof(sequence, &runs, 0)?.len() as u64;
+        out.write_all(&build_moof(sequence, &runs, moof_len + mdat_header_len)?)?;
+        if mdat_header_len == 16 {
+            out.write_all(&1u32.to_be_bytes())?;
+            out.write_all(b"mdat")?;
+            out.write_all(&(payload_len + 16).to_be_bytes())?;
+        } else {
+            out.write_all(&((payload_len + 8) as u32).to_be_bytes())?;
+            out.write_all(b"mdat")?;
+        }
+        for pkt in &segment.video_packets {
+            write_video_sample(out, &pkt.data)?;
+        }
+        if audio.is_some() {
+            for pkt in &segment.audio_packets {
+                out.write_all(strip_adts(&pkt.data))?;
+            }
+        }
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+// ── Sample tables ──────────────────────────────────────────────────────────────
+
+/// Per-sample timing and size information for one track.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    size: u32,
+    /// Decode duration in media timescale units.
+    duration: u32,
+    /// `pts - dts` in media timescale units.
+    cts_offset: i32,
+    is_key: bool,
+}
+
+/// One contiguous run of samples from a single track inside `mdat`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Chunk {
+    sample_count: u32,
+    byte_len: u64,
+    /// Offset from the first byte of the `mdat` payload.
+    offset: u64,
+}
+
+#[derive(Clone)]
+enum TrackKind {
+    Video { width: u32, height: u32, avcc: Vec<u8> },
+    Audio { sample_rate: u32, channels: u32, asc: Vec<u8> },
+}
+
+/// Everything needed to emit one `trak` box.
+struct TrackLayout {
+    track_id: u32,
+    /// Media timescale (ticks per second) used by `mdhd`, `stts` and `ctts`.
+    timescale: u32,
+    samples: Vec<Sample>,
+    chunks: Vec<Chunk>,
+    kind: TrackKind,
+}
+
+impl TrackLayout {
+    fn video(
+        segments: &[Arc<EncodedSegment>],
+        params: &VideoCodecParams,
+        chunks: Vec<Chunk>,
+    ) -> Result<Self> {
+        let packets: Vec<&EncodedPacket> =
+            segments.iter().flat_map(|s| s.video_packets.iter()).collect();
+        if packets.is_empty() {
+            bail!("No video packets to mux");
+        }
+
+        let avcc = avc_decoder_config(&packets, params)?;
+
+        Ok(Self {
+            track_id: VIDEO_TRACK_ID,
+            timescale: timescale_of(params.time_base)?,
+            samples: build_samples(&packets, params.time_base.0, video_sample_size),
+            chunks,
+            kind: TrackKind::Video { width: params.width, height: params.height, avcc },
+        })
+    }
+
+    fn audio(
+        segments: &[Arc<EncodedSegment>],
+        params: &AudioCodecParams,
+        chunks: Vec<Chunk>,
+    ) -> Result<Option<Self>> {
+        let packets: Vec<&EncodedPacket> =
+            segments.iter().flat_map(|s| s.audio_packets.iter()).collect();
+        if packets.is_empty() {
+            return Ok(None);
+        }
+
+        let asc = audio_specific_config(params)?;
+
+        Ok(Some(Self {
+            track_id: AUDIO_TRACK_ID,
+            timescale: timescale_of(params.time_base)?,
+            samples: build_samples(&packets, params.time_base.0, |d| strip_adts(d).len() as u64),
+            chunks,
+            kind: TrackKind::Audio {
+                sample_rate: params.sample_rate,
+                channels: params.channels,
+                asc,
+            },
+        }))
+    }
+
+    fn total_bytes(&self) -> u64 {
+        self.chunks.iter().map(|c| c.byte_len).sum()
+    }
+
+    /// The same track with empty sample tables, as described by the `moov`
+    /// of a fragmented file.
+    fn without_samples(&self) -> Self {
+        Self {
+            track_id: self.track_id,
+            timescale: self.timescale,
+            samples: vec![],
+            chunks: vec![],
+            kind: self.kind.clone(),
+        }
+    }
+
+    /// Track duration in media timescale units.
+    fn media_duration(&self) -> u64 {
+        self.samples.iter().map(|s| u64::from(s.duration)).sum()
+    }
+
+    /// Track duration in [`MOVIE_TIMESCALE`] units.
+    fn movie_duration(&self) -> u64 {
+        self.media_duration() * u64::from(MOVIE_TIMESCALE) / u64::from(self.timescale)
+    }
+}
+
+/// Returns the media timescale for an FFmpeg-style `(num, den)` time base.
+/// Timestamps are multiplied by `num` so that one tick is `1/den` seconds.
+fn timescale_of(time_base: (i32, i32)) -> Result<u32> {
+    let (num, den) = time_base;
+    if num <= 0 || den <= 0 {
+        bail!("Invalid time base {num}/{den}");
+    }
+    Ok(den as u32)
+}
+
+/// Converts packet timestamps into MP4 sample entries.
+///
+/// Durations come from consecutive DTS deltas; the final sample uses the
+/// packet's own duration (or the previous delta if the encoder left it unset).
+fn build_samples(
+    packets: &[&EncodedPacket],
+    tb_num: i32,
+    size_of: impl Fn(&[u8]) -> u64,
+) -> Vec<Sample> {
+    let scale = i64::from(tb_num);
+    let mut samples: Vec<Sample> = Vec::with_capacity(packets.len());
+    for (i, pkt) in packets.iter().enumerate() {
+        let delta = match packets.get(i + 1) {
+            Some(next) => next.dts - pkt.dts,
+            None if pkt.duration > 0 => pkt.duration,
+            None => samples.last().map_or(1, |s: &Sample| i64::from(s.duration) / scale),
+        };
+        samples.push(Sample {
+            size: size_of(&pkt.data) as u32,
+            duration: (delta * scale).clamp(0, i64::from(u32::MAX)) as u32,
+            cts_offset: ((pkt.pts - pkt.dts) * scale) as i32,
+            is_key: pkt.is_key,
+        });
+    }
+    samples
+}
+
+/// Lays out the `mdat` payload: for every segment, its video packets then its
+/// audio packets.  Returns the video and audio chunk lists.
+fn layout_chunks(segments: &[Arc<EncodedSegment>]) -> (Vec<Chunk>, Vec<Chunk>) {
+    let mut video = vec![];
+    let mut audio = vec![];
+    let mut offset = 0u64;
+    for segment in segments {
+        if !segment.video_packets.is_empty() {
+            let byte_len = segment.video_packets.iter().map(|p| video_sample_size(&p.data)).sum();
+            video.push(Chunk {
+                sample_count: segment.video_packets.len() as u32,
+                byte_len,
+                offset,
+            });
+            offset += byte_len;
+        }
+        if !segment.audio_packets.is_empty() {
+            let byte_len =
+                segment.audio_packets.iter().map(|p| strip_adts(&p.data).len() as u64).sum();
+            audio.push(Chunk {
+                sample_count: segment.audio_packets.len() as u32,
+                byte_len,
+                offset,
+            });
+            offset += byte_len;
+        }
+    }
+    (video, audio)
+}
+
+// ── H.264 helpers ──────────────────────────────────────────────────────────────
+
+/// Returns true if `data` begins with an Annex B start code.
+fn is_annex_b(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1])
+}
+
+/// Splits an Annex B byte stream into NAL units (without start codes).
+pub(crate) fn annex_b_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let end = starts.get(n + 1).map_or(data.len(), |&next| next - 3);
+            let mut nal = &data[start..end];
+            // Drop trailing zero bytes (the leading zero of a 4-byte start code).
+            while let [rest @ .., 0] = nal {
+                nal = rest;
+            }
+            nal
+        })
+        .filter(|nal| !nal.is_empty())
+        .collect()
+}
+
+/// Size of a video sample once it has been converted to length-prefixed form.
+pub(crate) fn video_sample_size(data: &[u8]) -> u64 {
+    if is_annex_b(data) {
+        annex_b_nal_units(data).iter().map(|n| NAL_LENGTH_SIZE + n.len() as u64).sum()
+    } else {
+        data.len() as u64
+    }
+}
+
+/// Writes a video sample, converting Annex B to 4-byte NAL length prefixes.
+pub(crate) fn write_video_sample<W: Write>(out: &mut W, data: &[u8]) -> std::io::Result<()> {
+    if !is_annex_b(data) {
+        return out.write_all(data);
+    }
+    for nal in annex_b_nal_units(data) {
+        out.write_all(&(nal.len() as u32).to_be_bytes())?;
+        out.write_all(nal)?;
+    }
+    Ok(())
+}
+
+/// Returns the `avcC` record for a track: from `params.extradata` if it holds
+/// SPS/PPS, otherwise from the first keyframe in `packets`.
+pub(crate) fn avc_decoder_config(
+    packets: &[&EncodedPacket],
+    params: &VideoCodecParams,
+) -> Result<Vec<u8>> {
+    match build_avcc(&params.extradata) {
+        Some(avcc) => Ok(avcc),
+        None => packets
+            .iter()
+            .find(|p| p.is_key)
+            .and_then(|p| build_avcc(&p.data))
+            .context("No H.264 SPS/PPS found in extradata or the first keyframe"),
+    }
+}
+
+/// Builds an `AVCDecoderConfigurationRecord` from `data`.
+///
+/// `data` may already be an `avcC` record (first byte 1), in which case it is
+/// returned unchanged, or an Annex B stream containing SPS and PPS NAL units.
+/// Returns `None` if no SPS/PPS pair can be found.
+fn build_avcc(data: &[u8]) -> Option<Vec<u8>> {
+    if data.first() == Some(&1) {
+        return Some(data.to_vec());
+    }
+    if !is_annex_b(data) {
+        return None;
+    }
+    let nals = annex_b_nal_units(data);
+    let sps: Vec<&[u8]> = nals.iter().copied().filter(|n| n[0] & 0x1F == 7).collect();
+    let pps: Vec<&[u8]> = nals.iter().copied().filter(|n| n[0] & 0x1F == 8).collect();
+    let first_sps = sps.first()?;
+    if pps.is_empty() || first_sps.len() < 4 {
+        return None;
+    }
+
+    let profile_idc = first_sps[1];
+    let mut avcc = vec![
+        1,            // configurationVersion
+        profile_idc,  // AVCProfileIndication
+        first_sps[2], // profile_compatibility
+        first_sps[3], // AVCLevelIndication
+        0xFC | (NAL_LENGTH_SIZE as u8 - 1),
+        0xE0 | sps.len() as u8,
+    ];
+    for s in &sps {
+        avcc.put_u16(s.len() as u16);
+        avcc.extend_from_slice(s);
+    }
+    avcc.push(pps.len() as u8);
+    for p in &pps {
+        avcc.put_u16(p.len() as u16);
+        avcc.extend_from_slice(p);
+    }
+    // High-profile extension: the encoder always emits 8-bit 4:2:0 (NV12 input).
+    if matches!(profile_idc, 100 | 110 | 122 | 144) {
+        avcc.extend_from_slice(&[0xFC | 1, 0xF8, 0xF8, 0]);
+    }
+    Some(avcc)
+}
+
+// ── AAC helpers ────────────────────────────────────────────────────────────────
+
+/// Returns the track's AudioSpecificConfig: `params.extradata`, or an AAC-LC
+/// config synthesised from the sample rate and channel count if it is empty.
+pub(crate) fn audio_specific_config(params: &AudioCodecParams) -> Result<Vec<u8>> {
+    if params.extradata.is_empty() {
+        build_audio_specific_config(params.sample_rate, params.channels)
+    } else {
+        Ok(params.extradata.clone())
+    }
+}
+
+/// Strips an ADTS header from `data` if one is present.
+pub(crate) fn strip_adts(data: &[u8]) -> &[u8] {
+    if data.len() >= 7 && data[0] == 0xFF && data[1] & 0xF6 == 0xF0 {
+        // protection_absent == 0 means a 2-byte CRC follows the 7-byte header.
+        let header_len = if data[1] & 0x01 == 0 { 9 } else { 7 };
+        return data.get(header_len..).unwrap_or(&[]);
+    }
+    data
+}
+
+/// Synthesises a 2-byte AAC-LC AudioSpecificConfig.
+pub fn build_audio_specific_config(sample_rate: u32, channels: u32) -> Result<Vec<u8>> {
+    let freq_index = AAC_SAMPLE_RATES
+        .iter()
+        .position(|&r| r == sample_rate)
+        .with_context(|| format!("Unsupported AAC sample rate {sample_rate}"))?
+        as u16;
+    if !(1..=7).contains(&channels) {
+        bail!("Unsupported AAC channel count {channels}");
+    }
+    const AAC_LC: u16 = 2;
+    let config = (AAC_LC << 11) | (freq_index << 7) | ((channels as u16) << 3);
+    Ok(config.to_be_bytes().to_vec())
+}
+
+// ── Box serialisation ──────────────────────────────────────────────────────────
+
+/// Big-endian append helpers for building boxes in a `Vec<u8>`.
+trait PutBe {
+    fn put_u16(&mut self, v: u16);
+    fn put_u32(&mut self, v: u32);
+    fn put_u64(&mut self, v: u64);
+}
+
+impl PutBe for Vec<u8> {
+    fn put_u16(&mut self, v: u16) {
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+    fn put_u32(&mut self, v: u32) {
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+    fn put_u64(&mut self, v: u64) {
+        self.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+/// Version of a box holding the time or duration `value`: 1, with 64-bit
+/// fields, if it does not fit in the 32 bits of version 0.
+fn version_for(value: u64) -> u8 {
+    u8::from(u32::try_from(value).is_err())
+}
+
+/// Appends a time or duration field of a box of `version` (see
+/// [`version_for`]).
+fn put_versioned(buf: &mut Vec<u8>, version: u8, value: u64) {
+    if version == 1 {
+        buf.put_u64(value);
+    } else {
+        buf.put_u32(value as u32);
+    }
+}
+
+/// Appends a box of type `fourcc` whose body is produced by `body`.
+fn put_box(buf: &mut Vec<u8>, fourcc: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = buf.len();
+    buf.put_u32(0);
+    buf.extend_from_slice(fourcc);
+    body(buf);
+    let size = (buf.len() - start) as u32;
+    buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Appends a "full box" (version + 24-bit flags header).
+fn put_full_box(
+    buf: &mut Vec<u8>,
+    fourcc: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    put_box(buf, fourcc, |b| {
+        b.put_u32((u32::from(version) << 24) | (flags & 0x00FF_FFFF));
+        body(b);
+    });
+}
+
+/// Appends an MPEG-4 descriptor with a 4-byte expandable length field.
+fn put_descriptor(buf: &mut Vec<u8>, tag: u8, body: impl FnOnce(&mut Vec<u8>)) {
+    buf.push(tag);
+    let len_pos = buf.len();
+    buf.extend_from_slice(&[0x80, 0x80, 0x80, 0]);
+    body(buf);
+    let len = buf.len() - len_pos - 4;
+    buf[len_pos] = 0x80 | ((len >> 21) & 0x7F) as u8;
+    buf[len_pos + 1] = 0x80 | ((len >> 14) & 0x7F) as u8;
+    buf[len_pos + 2] = 0x80 | ((len >> 7) & 0x7F) as u8;
+    buf[len_pos + 3] = (len & 0x7F) as u8;
+}
+
+/// Unity transformation matrix used by `mvhd` and `tkhd`.
+fn put_matrix(buf: &mut Vec<u8>) {
+    for v in [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        buf.put_u32(v);
+    }
+}
+
+fn build_ftyp() -> Vec<u8> {
+    let mut buf = vec![];
+    put_box(&mut buf, b"ftyp", |b| {
+        b.extend_from_slice(b"isom");
+        b.put_u32(0x200);
+        for brand in [b"isom", b"iso2", b"avc1", b"mp41"] {
+            b.extend_from_slice(brand);
+        }
+    });
+    buf
+}
+
+/// Builds the complete `moov` box.  `mdat_data_start` is the absolute file
+/// offset of the first byte of `mdat` payload.
+fn build_moov(
+    video: &TrackLayout,
+    audio: Option<&TrackLayout>,
+    mdat_data_start: u64,
+    use_co64: bool,
+    fragment_duration: Option<u64>,
+    metadata: &ClipMetadata,
+) -> Vec<u8> {
+    let duration = audio
+        .map_or(0, |a| a.movie_duration())
+        .max(video.movie_duration());
+    // Version 0 times run out in 2040; leave them unset after that.
+    let created = u32::try_from(metadata.created.timestamp() + MP4_EPOCH_OFFSET).unwrap_or(0);
+    let mut buf = vec![];
+    put_box(&mut buf, b"moov", |b| {
+        let version = version_for(duration);
+        put_full_box(b, b"mvhd", version, 0, |b| {
+            put_versioned(b, version, created.into()); // creation_time
+            put_versioned(b, version, created.into()); // modification_time
+            b.put_u32(MOVIE_TIMESCALE);
+            put_versioned(b, version, duration);
+            b.put_u32(0x0001_0000); // rate 1.0
+            b.put_u16(0x0100); // volume 1.0
+            b.extend_from_slice(&[0; 10]);
+            put_matrix(b);
+            b.extend_from_slice(&[0; 24]); // pre_defined
+            b.put_u32(AUDIO_TRACK_ID + 1); // next_track_ID
+        });
+        put_trak(b, video, mdat_data_start, use_co64, created);
+        if let Some(audio) = audio {
+            put_trak(b, audio, mdat_data_start, use_co64, created);
+        }
+        if let Some(fragment_duration) = fragment_duration {
+            put_box(b, b"mvex", |b| {
+                let version = version_for(fragment_duration);
+                put_full_box(b, b"mehd", version, 0, |b| {
+                    put_versioned(b, version, fragment_duration);
+                });
+                for track in std::iter::once(video).chain(audio) {
+                    put_full_box(b, b"trex", 0, 0, |b| {
+                        b.put_u32(track.track_id);
+                        b.put_u32(1); // default_sample_description_index
+                        b.put_u32(0); // default_sample_duration
+                        b.put_u32(0); // default_sample_size
+                        b.put_u32(0); // default_sample_flags
+                    });
+                }
+            });
+        }
+        put_udta(b, metadata);
+    });
+    buf
+}
+
+/// Writes `metadata` as an iTunes-style `udta/meta/ilst` item list.
+fn put_udta(buf: &mut Vec<u8>, metadata: &ClipMetadata) {
+    let created = metadata.created.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let items: [(&[u8; 4], Option<&str>); 5] = [
+        (b"\xA9nam", Some(&metadata.title)),
+        (b"\xA9day", Some(&created)),
+        (b"\xA9too", Some(metadata::ENCODER)),
+        (b"\xA9cmt", Some(&metadata.comment)),
+        (b"keyw", metadata.tag.as_deref()),
+    ];
+    put_box(buf, b"udta", |b| {
+        put_full_box(b, b"meta", 0, 0, |b| {
+            put_full_box(b, b"hdlr", 0, 0, |b| {
+                b.put_u32(0); // pre_defined
+                b.extend_from_slice(b"mdir");
+                b.extend_from_slice(b"appl");
+                b.extend_from_slice(&[0; 9]); // reserved, empty name
+            });
+            put_box(b, b"ilst", |b| {
+                for (fourcc, value) in items {
+                    let Some(value) = value.filter(|v| !v.is_empty()) else { continue };
+                    put_box(b, fourcc, |b| {
+                        put_box(b, b"data", |b| {
+                            b.put_u32(DATA_TYPE_UTF8);
+                            b.put_u32(0); // locale
+                            b.extend_from_slice(value.as_bytes());
+                        });
+                    });
+                }
+            });
+        });
+    });
+}
+
+fn put_trak(
+    buf: &mut Vec<u8>,
+    track: &TrackLayout,
+    mdat_data_start: u64,
+    use_co64: bool,
+    created: u32,
+) {
+    let is_video = matches!(track.kind, TrackKind::Video { .. });
+    put_box(buf, b"trak", |b| {
+        // flags: track_enabled | track_in_movie
+        let version = version_for(track.movie_duration());
+        put_full_box(b, b"tkhd", version, 0x3, |b| {
+            put_versioned(b, version, created.into()); // creation_time
+            put_versioned(b, version, created.into()); // modification_time
+            b.put_u32(track.track_id);
+            b.put_u32(0); // reserved
+            put_versioned(b, version, track.movie_duration());
+            b.extend_from_slice(&[0; 8]);
+            b.put_u16(0); // layer
+            b.put_u16(if is_video { 0 } else { 1 }); // alternate_group
+            b.put_u16(if is_video { 0 } else { 0x0100 }); // volume
+            b.put_u16(0);
+            put_matrix(b);
+            let (w, h) = match track.kind {
+                TrackKind::Video { width, height, .. } => (width, height),
+                TrackKind::Audio { .. } => (0, 0),
+            };
+            b.put_u32(w << 16);
+            b.put_u32(h << 16);
+        });
+
+        // B-frames give the first sample a non-zero composition offset; an
+        // edit list shifts presentation back so the clip starts at t = 0.
+        let first_cts = track.samples.first().map_or(0, |s| s.cts_offset);
+        if first_cts > 0 {
+            put_box(b, b"edts", |b| {
+                let version = version_for(track.movie_duration());
+                put_full_box(b, b"elst", version, 0, |b| {
+                    b.put_u32(1);
+                    put_versioned(b, version, track.movie_duration());
+                    put_versioned(b, version, first_cts as u64); // media_time
+                    b.put_u32(0x0001_0000); // media_rate 1.0
+                });
+            });
+        }
+
+        put_box(b, b"mdia", |b| {
+            let version = version_for(track.media_duration());
+            put_full_box(b, b"mdhd", version, 0, |b| {
+                put_versioned(b, version, created.into());
+                put_versioned(b, version, created.into());
+                b.put_u32(track.timescale);
+                put_versioned(b, version, track.media_duration());
+                b.put_u16(LANGUAGE_UND);
+                b.put_u16(0);
+            });
+            put_full_box(b, b"hdlr", 0, 0, |b| {
+                b.put_u32(0);
+                b.extend_from_slice(if is_video { b"vide" } else { b"soun" });
+                b.extend_from_slice(&[0; 12]);
+                b.extend_from_slice(if is_video { b"VideoHandler\0" } else { b"SoundHandler\0" });
+            });
+            put_box(b, b"minf", |b| {
+                if is_video {
+                    put_full_box(b, b"vmhd", 0, 1, |b| b.extend_from_slice(&[0; 8]));
+                } else {
+                    put_full_box(b, b"smhd", 0, 0, |b| b.put_u32(0));
+                }
+                put_box(b, b"dinf", |b| {
+                    put_full_box(b, b"dref", 0, 0, |b| {
+                        b.put_u32(1);
+                        // flags = 1: media data is in this file.
+                        put_full_box(b, b"url ", 0, 1, |_| {});
+                    });
+                });
+                put_stbl(b, track, mdat_data_start, use_co64);
+            });
+        });
+    });
+}
+
+fn put_stbl(buf: &mut Vec<u8>, track: &TrackLayout, mdat_data_start: u64, use_co64: bool) {
+    put_box(buf, b"stbl", |b| {
+        put_full_box(b, b"stsd", 0, 0, |b| {
+            b.put_u32(1);
+            put_sample_entry(b, track);
+        });
+
+        // stts: run-length encoded sample durations.
+        let mut stts: Vec<(u32, u32)> = vec![];
+        for s in &track.samples {
+            match stts.last_mut() {
+                Some((count, delta)) if *delta == s.duration => *count += 1,
+                _ => stts.push((1, s.duration)),
+            }
+        }
+        put_full_box(b, b"stts", 0, 0, |b| {
+            b.put_u32(stts.len() as u32);
+            for (count, delta) in &stts {
+                b.put_u32(*count);
+                b.put_u32(*delta);
+            }
+        });
+
+        // ctts: only needed when presentation order differs from decode order.
+        if track.samples.iter().any(|s| s.cts_offset != 0) {
+            let mut ctts: Vec<(u32, i32)> = vec![];
+            for s in &track.samples {
+                match ctts.last_mut() {
+                    Some((count, offset)) if *offset == s.cts_offset => *count += 1,
+                    _ => ctts.push((1, s.cts_offset)),
+                }
+            }
+            // Version 1 permits negative (signed) offsets.
+            let version = u8::from(ctts.iter().any(|(_, o)| *o < 0));
+            put_full_box(b, b"ctts", version, 0, |b| {
+                b.put_u32(ctts.len() as u32);
+                for (count, offset) in &ctts {
+                    b.put_u32(*count);
+                    b.put_u32(*offset as u32);
+                }
+            });
+        }
+
+        // stss: omitted when every sample is a sync sample (e.g. audio).
+        if track.samples.iter().any(|s| !s.is_key) {
+            let sync: Vec<u32> = track
+                .samples
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.is_key)
+                .map(|(i, _)| i as u32 + 1)
+                .collect();
+            put_full_box(b, b"stss", 0, 0, |b| {
+                b.put_u32(sync.len() as u32);
+                for n in &sync {
+                    b.put_u32(*n);
+                }
+            });
+        }
+
+        // stsc: run-length encoded samples-per-chunk.
+        let mut stsc: Vec<(u32, u32)> = vec![];
+        for (i, chunk) in track.chunks.iter().enumerate() {
+            if stsc.last().map(|(_, n)| *n) != Some(chunk.sample_count) {
+                stsc.push((i as u32 + 1, chunk.sample_count));
+            }
+        }
+        put_full_box(b, b"stsc", 0, 0, |b| {
+            b.put_u32(stsc.len() as u32);
+            for (first_chunk, samples_per_chunk) in &stsc {
+                b.put_u32(*first_chunk);
+                b.put_u32(*samples_per_chunk);
+                b.put_u32(1); // sample_description_index
+            }
+        });
+
+        put_full_box(b, b"stsz", 0, 0, |b| {
+            b.put_u32(0); // sample_size 0: sizes follow in the table
+            b.put_u32(track.samples.len() as u32);
+            for s in &track.samples {
+                b.put_u32(s.size);
+            }
+        });
+
+        if use_co64 {
+            put_full_box(b, b"co64", 0, 0, |b| {
+                b.put_u32(track.chunks.len() as u32);
+                for c in &track.chunks {
+                    b.put_u64(mdat_data_start + c.offset);
+                }
+            });
+        } else {
+            put_full_box(b, b"stco", 0, 0, |b| {
+                b.put_u32(track.chunks.len() as u32);
+                for c in &track.chunks {
+                    b.put_u32((mdat_data_start + c.offset) as u32);
+                }
+            });
+        }
+    });
+}
+
+fn put_sample_entry(buf: &mut Vec<u8>, track: &TrackLayout) {
+    match &track.kind {
+        TrackKind::Video { width, height, avcc } => put_box(buf, b"avc1", |b| {
+            b.extend_from_slice(&[0; 6]);
+            b.put_u16(1); // data_reference_index
+            b.extend_from_slice(&[0; 16]); // pre_defined + reserved
+            b.put_u16(*width as u16);
+            b.put_u16(*height as u16);
+            b.put_u32(0x0048_0000); // 72 dpi
+            b.put_u32(0x0048_0000);
+            b.put_u32(0);
+            b.put_u16(1); // frame_count
+            b.extend_from_slice(&[0; 32]); // compressorname
+            b.put_u16(0x0018); // depth
+            b.put_u16(0xFFFF); // pre_defined = -1
+            put_box(b, b"avcC", |b| b.extend_from_slice(avcc));
+        }),
+        TrackKind::Audio { sample_rate, channels, asc } => put_box(buf, b"mp4a", |b| {
+            b.extend_from_slice(&[0; 6]);
+            b.put_u16(1); // data_reference_index
+            b.extend_from_slice(&[0; 8]);
+            b.put_u16(*channels as u16);
+            b.put_u16(16); // samplesize
+            b.put_u32(0);
+            // 16.16 fixed point; rates above 65535 Hz do not fit and are zeroed.
+            b.put_u32(if *sample_rate <= 0xFFFF { sample_rate << 16 } else { 0 });
+            put_esds(b, track, asc);
+        }),
+    }
+}
+
+// ── Fragments ──────────────────────────────────────────────────────────────────
+
+/// `tfhd` flag: data offsets are relative to the start of the `moof`.
+const TFHD_DEFAULT_BASE_IS_MOOF: u32 = 0x02_0000;
+/// `trun` flags: data offset plus per-sample duration, size, flags and
+/// composition offset.
+const TRUN_FLAGS: u32 = 0x0001 | 0x0100 | 0x0200 | 0x0400 | 0x0800;
+/// Sample flags: depends on no other sample (a sync sample).
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+/// Sample flags: depends on earlier samples and is not a sync sample.
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+/// One track's samples within a fragment.
+struct TrackRun<'a> {
+    track_id: u32,
+    /// Sum of the durations of the track's earlier samples.
+    base_decode_time: u64,
+    samples: &'a [Sample],
+    cts_shift: i32,
+}
+
+impl TrackRun<'_> {
+    fn byte_len(&self) -> u64 {
+        self.samples.iter().map(|s| u64::from(s.size)).sum()
+    }
+}
+
+/// Walks a track's sample table one fragment at a time.
+struct FragmentCursor<'a> {
+    track: &'a TrackLayout,
+    next: usize,
+    decode_time: u64,
+    cts_shift: i32,
+}
+
+impl<'a> FragmentCursor<'a> {
+    fn new(track: &'a TrackLayout) -> Self {
+        let cts_shift = track.samples.first().map_or(0, |s| s.cts_offset);
+        Self { track, next: 0, decode_time: 0, cts_shift }
+    }
+
+    /// The next `count` samples, or `None` if `count` is zero.
+    fn take(&mut self, count: usize) -> Option<TrackRun<'a>> {
+        if count == 0 {
+            return None;
+        }
+        let samples = &self.track.samples[self.next..self.next + count];
+        let run = TrackRun {
+            track_id: self.track.track_id,
+            base_decode_time: self.decode_time,
+            samples,
+            cts_shift: self.cts_shift,
+        };
+        self.next += count;
+        self.decode_time += samples.iter().map(|s| u64::from(s.duration)).sum::<u64>();
+        Some(run)
+    }
+}
+
+/// Builds a `moof` for `runs`, whose sample data starts `data_start` bytes
+/// after the first byte of the `moof` and follows in `runs` order.  Fails if
+/// a run starts beyond the signed 32-bit `trun` data offset.
+fn build_moof(sequence: u32, runs: &[TrackRun], data_start: u64) -> Result<Vec<u8>> {
+    let mut data_offsets = Vec::with_capacity(runs.len());
+    let mut data_offset = data_start;
+    for run in runs {
+        let offset = i32::try_from(data_offset)
+            .with_context(|| format!("Fragment {sequence} is too large for a trun data offset"))?;
+        data_offsets.push(offset);
+        data_offset += run.byte_len();
+    }
+    let mut buf = vec![];
+    put_box(&mut buf, b"moof", |b| {
+        put_full_box(b, b"mfhd", 0, 0, |b| b.put_u32(sequence));
+        for (run, data_offset) in runs.iter().zip(data_offsets) {
+            put_box(b, b"traf", |b| {
+                put_full_box(b, b"tfhd", 0, TFHD_DEFAULT_BASE_IS_MOOF, |b| b.put_u32(run.track_id));
+                put_full_box(b, b"tfdt", 1, 0, |b| b.put_u64(run.base_decode_time));
+                let offsets: Vec<i32> =
+                    run.samples.iter().map(|s| s.cts_offset - run.cts_shift).collect();
+                // Version 1 permits negative (signed) offsets.
+                let version = u8::from(offsets.iter().any(|o| *o < 0));
+                put_full_box(b, b"trun", version, TRUN_FLAGS, |b| {
+                    b.put_u32(run.samples.len() as u32);
+                    b.put_u32(data_offset as u32);
+                    for (s, offset) in run.samples.iter().zip(&offsets) {
+                        b.put_u32(s.duration);
+                        b.put_u32(s.size);
+                        b.put_u32(if s.is_key { SAMPLE_FLAGS_SYNC } else { SAMPLE_FLAGS_NON_SYNC });
+                        b.put_u32(*offset as u32);
+                    }
+                });
+            });
+        }
+    });
+    Ok(buf)
+}
+
+/// Appends the `esds` box carrying the AAC AudioSpecificConfig.
+fn put_esds(buf: &mut Vec<u8>, track: &TrackLayout, asc: &[u8]) {
+    let max_sample = track.samples.iter().map(|s| s.size).max().unwrap_or(0);
+    let duration_secs = track.media_duration() as f64 / f64::from(track.timescale);
+    let avg_bitrate = if duration_secs > 0.0 {
+        (track.total_bytes() as f64 * 8.0 / duration_secs) as u32
+    } else {
+        0
+    };
+    put_full_box(buf, b"esds", 0, 0, |b| {
+        put_descriptor(b, 0x03, |b| {
+            b.put_u16(track.track_id as u16); // ES_ID
+            b.push(0); // flags
+            put_descriptor(b, 0x04, |b| {
+                b.push(0x40); // objectTypeIndication: MPEG-4 Audio
+                b.push(0x15); // streamType 5 (audio) << 2 | upStream 0 | reserved 1
+                b.extend_from_slice(&max_sample.to_be_bytes()[1..]); // bufferSizeDB (24-bit)
+                b.put_u32(avg_bitrate); // maxBitrate
+                b.put_u32(avg_bitrate); // avgBitrate
+                put_descriptor(b, 0x05, |b| b.extend_from_slice(asc));
+            });
+            put_descriptor(b, 0x06, |b| b.push(0x02)); // SLConfig: predefined MP4
+        });
+    });
+}
+
+// ── Interrupted files ──────────────────────────────────────────────────────────
+
+/// Whether `input` starts with at least one complete `moof` + `mdat` pair, so
+/// a fragmented file cut short still has footage to play.  A faststart file
+/// has no fragments and never qualifies.
+pub fn has_complete_fragment<R: Read + Seek>(input: &mut R) -> std::io::Result<bool> {
+    let len = input.seek(SeekFrom::End(0))?;
+    let mut pos = 0;
+    let mut in_fragment = false;
+    while pos + 8 <= len {
+        input.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        input.read_exact(&mut header)?;
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => len - pos,
+            1 if pos + 16 <= len => {
+                let mut large = [0u8; 8];
+                input.read_exact(&mut large)?;
+                u64::from_be_bytes(large)
+            }
+            1 => break,
+            size => u64::from(size),
+        };
+        let end = pos.saturating_add(size);
+        if size < 8 || end > len {
+            break;
+        }
+        match &header[4..] {
+            b"moof" => in_fragment = true,
+            b"mdat" if in_fragment => return Ok(true),
+            _ => {}
+        }
+        pos = end;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn mux(segments: &[EncodedSegment]) -> Vec<u8> {
+        let mut out = vec![];
+        write_mp4(&mut out, &shared(segments), &video_params(), &audio_params(), &metadata())
+            .unwrap();
+        out
+    }
+
+    /// Returns the body of the first box at `path` (e.g. `["moov", "trak"]`).
+    fn find_box<'a>(mut data: &'a [u8], path: &[&str]) -> Option<&'a [u8]> {
+        let (first, rest) = path.split_first()?;
+        while data.len() >= 8 {
+            let size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+            let fourcc = std::str::from_utf8(&data[4..8]).unwrap_or("");
+            if fourcc == *first {
+                let body = &data[8..size];
+                return if rest.is_empty() { Some(body) } else { find_box(body, rest) };
+            }
+            data = &data[size..];
+        }
+        None
+    }
+
+    /// Returns `(type, body)` for every box directly inside `data`.
+    fn child_boxes(mut data: &[u8]) -> Vec<(String, &[u8])> {
+        let mut boxes = vec![];
+        while data.len() >= 8 {
+            let size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+            boxes.push((String::from_utf8_lossy(&data[4..8]).into_owned(), &data[8..size]));
+            data = &data[size..];
+        }
+        boxes
+    }
+
+    fn top_level_types(data: &[u8]) -> Vec<String> {
+        child_boxes(data).into_iter().map(|(t, _)| t).collect()
+    }
+
+    fn be32(data: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    fn be64(data: &[u8], at: usize) -> u64 {
+        u64::from_be_bytes(data[at..at + 8].try_into().unwrap())
+    }
+
+    fn video_stbl(file: &[u8]) -> &[u8] {
+        find_box(file, &["moov", "trak", "mdia", "minf", "stbl"]).unwrap()
+    }
+
+    // ── File layout ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn output_is_faststart_ftyp_moov_mdat() {
+        let file = mux(&make_segments());
+        assert_eq!(top_level_types(&file), ["ftyp", "moov", "mdat"]);
+    }
+
+    #[test]
+    fn moov_has_video_and_audio_tracks() {
+        let file = mux(&make_segments());
+        let moov = find_box(&file, &["moov"]).unwrap();
+        let traks = top_level_types(moov).iter().filter(|t| *t == "trak").count();
+        assert_eq!(traks, 2);
+    }
+
+    #[test]
+    fn audio_track_omitted_when_no_audio_packets() {
+        let mut segments = make_segments();
+        for s in &mut segments {
+            s.audio_packets.clear();
+        }
+        let file = mux(&segments);
+        let moov = find_box(&file, &["moov"]).unwrap();
+        let traks = top_level_types(moov).iter().filter(|t| *t == "trak").count();
+        assert_eq!(traks, 1);
+    }
+
+    #[test]
+    fn no_video_packets_is_an_error() {
+        let empty =
+            EncodedSegment { video_packets: vec![], audio_packets: vec![], pushed_at: None };
+        let segments = shared(&[empty]);
+        let mut out = vec![];
+        let result = write_mp4(&mut out, &segments, &video_params(), &audio_params(), &metadata());
+        assert!(result.is_err());
+    }
+
+    // ── Sample tables ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn stts_collapses_constant_frame_duration() {
+        let file = mux(&make_segments());
+        let stts = find_box(video_stbl(&file), &["stts"]).unwrap();
+        assert_eq!(be32(stts, 4), 1, "one run expected");
+        assert_eq!(be32(stts, 8), 60, "sample count");
+        assert_eq!(be32(stts, 12), 1, "sample delta");
+    }
+
+    #[test]
+    fn stss_lists_one_keyframe_per_segment() {
+        let file = mux(&make_segments());
+        let stss = find_box(video_stbl(&file), &["stss"]).unwrap();
+        assert_eq!(be32(stss, 4), 2);
+        assert_eq!(be32(stss, 8), 1);
+        assert_eq!(be32(stss, 12), 31);
+    }
+
+    #[test]
+    fn ctts_omitted_without_reordering() {
+        let file = mux(&make_segments());
+        assert!(find_box(video_stbl(&file), &["ctts"]).is_none());
+    }
+
+    #[test]
+    fn ctts_written_for_reordered_frames() {
+        let mut segments = make_segments();
+        segments[0].video_packets[1].pts += 2;
+        let file = mux(&segments);
+        let ctts = find_box(video_stbl(&file), &["ctts"]).unwrap();
+        // Entries: (1, 0), (1, 2), (58, 0).
+        assert_eq!(be32(ctts, 4), 3);
+        assert_eq!(be32(ctts, 16), 1);
+        assert_eq!(be32(ctts, 20), 2);
+    }
+
+    #[test]
+    fn stsz_reflects_length_prefixed_sizes() {
+        let file = mux(&make_segments());
+        let stsz = find_box(video_stbl(&file), &["stsz"]).unwrap();
+        assert_eq!(be32(stsz, 8), 60);
+        // Keyframe NAL is 3 bytes + 4-byte length; P-frame NAL is 2 + 4.
+        assert_eq!(be32(stsz, 12), 7);
+        assert_eq!(be32(stsz, 16), 6);
+    }
+
+    #[test]
+    fn stco_points_at_sample_data() {
+        let segments = make_segments();
+        let file = mux(&segments);
+        let stco = find_box(video_stbl(&file), &["stco"]).unwrap();
+        assert_eq!(be32(stco, 4), 2, "one chunk per segment");
+        let first = be32(stco, 8) as usize;
+        // First sample: 4-byte length then the IDR NAL.
+        assert_eq!(&file[first..first + 7], &[0, 0, 0, 3, 0x65, 0x88, 0x84]);
+
+        let audio_trak = child_boxes(find_box(&file, &["moov"]).unwrap())
+            .into_iter()
+            .filter(|(t, _)| t == "trak")
+            .nth(1)
+            .unwrap()
+            .1;
+        let audio_stco = find_box(audio_trak, &["mdia", "minf", "stbl", "stco"]).unwrap();
+        let audio_first = be32(audio_stco, 8) as usize;
+        assert_eq!(&file[audio_first..audio_first + 10], &[0x21; 10]);
+    }
+
+    #[test]
+    fn mvhd_duration_is_clip_length_in_ms() {
+        let file = mux(&make_segments());
+        let mvhd = find_box(&file, &["moov", "mvhd"]).unwrap();
+        assert_eq!(be32(mvhd, 12), 1000, "timescale");
+        // Audio: 94 frames × 1024 samples at 48 kHz ≈ 2005 ms; video is 2000 ms.
+        assert_eq!(be32(mvhd, 16), 2005);
+    }
+
+    #[test]
+    fn durations_beyond_32_bits_use_version_1_boxes() {
+        // A three-billion-tick gap before the last frame and as its length.
+        let mut segments = make_segments();
+        let last = segments[1].video_packets.last_mut().unwrap();
+        last.pts += 3_000_000_000;
+        last.dts += 3_000_000_000;
+        last.duration = 3_000_000_000;
+        let file = mux(&segments);
+
+        // Version 1 bodies: creation and modification times, the timescale,
+        // then the duration, with 64-bit times.
+        let mdhd = find_box(&file, &["moov", "trak", "mdia", "mdhd"]).unwrap();
+        assert_eq!(mdhd[0], 1, "version");
+        assert_eq!(be32(mdhd, 20), 30, "timescale");
+        assert_eq!(be64(mdhd, 24), 58 + 3_000_000_001 + 3_000_000_000);
+        let mvhd = find_box(&file, &["moov", "mvhd"]).unwrap();
+        assert_eq!(mvhd[0], 1, "version");
+        assert_eq!(be32(mvhd, 20), 1000, "timescale");
+        assert_eq!(be64(mvhd, 24), 6_000_000_059 * 1000 / 30);
+    }
+
+    #[test]
+    fn trun_data_offset_beyond_31_bits_is_an_error() {
+        let run =
+            TrackRun { track_id: VIDEO_TRACK_ID, base_decode_time: 0, samples: &[], cts_shift: 0 };
+        assert!(build_moof(1, &[run], 1 << 31).is_err());
+    }
+
+    // ── Metadata ──────────────────────────────────────────────────────────────
+
+    /// Returns `(item type, text)` for every `ilst` item in `file`'s moov.
+    fn ilst_items(file: &[u8]) -> Vec<([u8; 4], String)> {
+        let meta = find_box(file, &["moov", "udta", "meta"]).unwrap();
+        let ilst = find_box(&meta[4..], &["ilst"]).unwrap();
+        let mut items = vec![];
+        let mut data = ilst;
+        while data.len() >= 8 {
+            let size = be32(data, 0) as usize;
+            let value = find_box(&data[8..size], &["data"]).unwrap();
+            assert_eq!(be32(value, 0), DATA_TYPE_UTF8);
+            let text = String::from_utf8(value[8..].to_vec()).unwrap();
+            items.push((data[4..8].try_into().unwrap(), text));
+            data = &data[size..];
+        }
+        items
+    }
+
+    #[test]
+    fn ilst_carries_clip_metadata() {
+        let items = ilst_items(&mux(&make_segments()));
+        let expected = [
+            (*b"\xA9nam", "Rocket League"),
+            (*b"\xA9day", "2024-03-01T12:00:00Z"),
+            (*b"\xA9too", metadata::ENCODER),
+            (*b"\xA9cmt", "Buffer length: 30 s, encoder: nvenc"),
+        ];
+        let expected: Vec<_> = expected.iter().map(|(t, v)| (*t, v.to_string())).collect();
+        assert_eq!(items, expected);
+    }
+
+    #[test]
+    fn ilst_includes_tag_as_keywords() {
+        let tagged = ClipMetadata { tag: Some("ace".into()), ..metadata() };
+        let mut out = vec![];
+        write_mp4(&mut out, &shared(&make_segments()), &video_params(), &audio_params(), &tagged)
+            .unwrap();
+        assert_eq!(ilst_items(&out).last().unwrap(), &(*b"keyw", "ace".to_string()));
+    }
+
+    #[test]
+    fn creation_time_counts_from_1904() {
+        let file = mux(&make_segments());
+        let mvhd = find_box(&file, &["moov", "mvhd"]).unwrap();
+        // 2024-03-01T12:00:00Z is 1_709_294_400 s after the Unix epoch.
+        assert_eq!(i64::from(be32(mvhd, 4)), 1_709_294_400 + MP4_EPOCH_OFFSET);
+        assert_eq!(be32(mvhd, 4), be32(mvhd, 8), "modification_time");
+    }
+
+    // ── Codec configuration ───────────────────────────────────────────────────
+
+    #[test]
+    fn avcc_built_from_annex_b_extradata() {
+        let avcc = build_avcc(&annex_b(&[SPS, PPS])).unwrap();
+        assert_eq!(&avcc[..6], &[1, 0x64, 0x00, 0x1F, 0xFF, 0xE1]);
+        assert_eq!(&avcc[6..8], &(SPS.len() as u16).to_be_bytes());
+        assert_eq!(&avcc[8..8 + SPS.len()], SPS);
+        assert_eq!(avcc[8 + SPS.len()], 1, "numOfPictureParameterSets");
+    }
+
+    #[test]
+    fn avcc_passthrough_when_already_avcc() {
+        let record = vec![1, 0x42, 0xC0, 0x1E, 0xFF, 0xE0, 0];
+        assert_eq!(build_avcc(&record), Some(record));
+    }
+
+    #[test]
+    fn avcc_falls_back_to_first_keyframe() {
+        let mut segments = make_segments();
+        segments[0].video_packets[0].data = annex_b(&[SPS, PPS, &[0x65, 0x88, 0x84]]).into();
+        let mut params = video_params();
+        params.extradata.clear();
+        let mut out = vec![];
+        write_mp4(&mut out, &shared(&segments), &params, &audio_params(), &metadata()).unwrap();
+        assert!(find_box(&out, &["moov"]).is_some());
+    }
+
+    #[test]
+    fn annex_b_split_handles_mixed_start_codes() {
+        let data = [0, 0, 0, 1, 0x67, 0xAA, 0, 0, 1, 0x68, 0xBB, 0, 0, 0, 1, 0x65];
+        let nals = annex_b_nal_units(&data);
+        assert_eq!(nals, vec![&[0x67, 0xAA][..], &[0x68, 0xBB][..], &[0x65][..]]);
+    }
+
+    #[test]
+    fn strip_adts_removes_header() {
+        let mut frame = vec![0xFF, 0xF1, 0x50, 0x80, 0x02, 0x1F, 0xFC];
+        frame.extend_from_slice(&[0xAB, 0xCD]);
+        assert_eq!(strip_adts(&frame), &[0xAB, 0xCD]);
+        assert_eq!(strip_adts(&[0x21, 0x10]), &[0x21, 0x10]);
+    }
+
+    #[test]
+    fn audio_specific_config_for_48k_stereo() {
+        assert_eq!(build_audio_specific_config(48_000, 2).unwrap(), vec![0x11, 0x90]);
+        assert!(build_audio_specific_config(12_345, 2).is_err());
+    }
+
+    // ── Fragmented output ─────────────────────────────────────────────────────
+
+    fn mux_fragmented(segments: &[EncodedSegment]) -> Vec<u8> {
+        let mut out = vec![];
+        let (video, audio) = (video_params(), audio_params());
+        write_fragmented_mp4(&mut out, &shared(segments), &video, &audio, &metadata()).unwrap();
+        out
+    }
+
+    fn moofs(file: &[u8]) -> Vec<&[u8]> {
+        child_boxes(file).into_iter().filter(|(t, _)| t == "moof").map(|(_, b)| b).collect()
+    }
+
+    fn trafs(moof: &[u8]) -> Vec<&[u8]> {
+        child_boxes(moof).into_iter().filter(|(t, _)| t == "traf").map(|(_, b)| b).collect()
+    }
+
+    #[test]
+    fn fragmented_output_has_one_fragment_per_segment() {
+        let file = mux_fragmented(&make_segments());
+        assert_eq!(top_level_types(&file), ["ftyp", "moov", "moof", "mdat", "moof", "mdat"]);
+    }
+
+    #[test]
+    fn truncated_fragmented_file_keeps_its_complete_fragments() {
+        let has_fragment = |data: &[u8]| {
+            has_complete_fragment(&mut std::io::Cursor::new(data)).unwrap()
+        };
+        let file = mux_fragmented(&make_segments());
+        assert!(has_fragment(&file));
+        let first_moof = child_boxes(&file)[..2].iter().map(|(_, b)| b.len() + 8).sum::<usize>();
+        assert!(!has_fragment(&file[..first_moof + 20]), "first fragment cut short");
+        assert!(!has_fragment(&mux(&make_segments())), "faststart file");
+        assert!(!has_fragment(&[]));
+    }
+
+    #[test]
+    fn fragmented_moov_has_empty_tables_and_mvex() {
+        let file = mux_fragmented(&make_segments());
+        let stsz = find_box(video_stbl(&file), &["stsz"]).unwrap();
+        assert_eq!(be32(stsz, 8), 0, "no samples in the moov");
+        let mvex = find_box(&file, &["moov", "mvex"]).unwrap();
+        assert_eq!(top_level_types(mvex), ["mehd", "trex", "trex"]);
+        assert_eq!(be32(find_box(mvex, &["mehd"]).unwrap(), 4), 2005);
+    }
+
+    #[test]
+    fn fragment_decode_times_continue_across_segments() {
+        let file = mux_fragmented(&make_segments());
+        let second = moofs(&file)[1];
+        assert_eq!(be32(find_box(second, &["mfhd"]).unwrap(), 4), 2, "sequence number");
+        let tfdt = |traf: &[u8]| {
+            let body = find_box(traf, &["tfdt"]).unwrap();
+            u64::from_be_bytes(body[4..12].try_into().unwrap())
+        };
+        let trafs = trafs(second);
+        assert_eq!(tfdt(trafs[0]), 30);
+        assert_eq!(tfdt(trafs[1]), 47 * 1024);
+    }
+
+    #[test]
+    fn trun_data_offset_points_at_sample_data() {
+        let file = mux_fragmented(&make_segments());
+        let moof_start: usize = child_boxes(&file)
+            .iter()
+            .take_while(|(t, _)| t != "moof")
+            .map(|(_, body)| body.len() + 8)
+            .sum();
+        let trafs = trafs(moofs(&file)[0]);
+        let video_trun = find_box(trafs[0], &["trun"]).unwrap();
+        assert_eq!(be32(video_trun, 4), 30, "sample count");
+        let video_at = moof_start + be32(video_trun, 8) as usize;
+        assert_eq!(&file[video_at..video_at + 7], &[0, 0, 0, 3, 0x65, 0x88, 0x84]);
+
+        let audio_trun = find_box(trafs[1], &["trun"]).unwrap();
+        let audio_at = moof_start + be32(audio_trun, 8) as usize;
+        assert_eq!(&file[audio_at..audio_at + 10], &[0x21; 10]);
+    }
+
+    #[test]
+    fn trun_shifts_composition_offsets_to_start_at_zero() {
+        let mut segments = make_segments();
+        segments[0].video_packets[0].pts += 1;
+        let file = mux_fragmented(&segments);
+        let trun = find_box(trafs(moofs(&file)[0])[0], &["trun"]).unwrap();
+        assert_eq!(trun[0], 1, "signed offsets need version 1");
+        // First sample: offset 1 − 1 = 0; second: 0 − 1 = −1.
+        assert_eq!(be32(trun, 12 + 12), 0);
+        assert_eq!(be32(trun, 28 + 12) as i32, -1);
+        assert_eq!(be32(trun, 12 + 8), SAMPLE_FLAGS_SYNC);
+        assert_eq!(be32(trun, 28 + 8), SAMPLE_FLAGS_NON_SYNC);
+    }
+
+    #[test]
+    fn fragmented_audio_track_omitted_when_no_audio_packets() {
+        let mut segments = make_segments();
+        for s in &mut segments {
+            s.audio_packets.clear();
+        }
+        let file = mux_fragmented(&segments);
+        assert_eq!(trafs(moofs(&file)[0]).len(), 1);
+        let mvex = find_box(&file, &["moov", "mvex"]).unwrap();
+        assert_eq!(top_level_types(mvex), ["mehd", "trex"]);
+    }
+
+    #[test]
+    fn write_file_creates_mp4_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.mp4");
+        let file = File::create(&path).unwrap();
+        let metadata = metadata();
+        write_file(&file, &shared(&make_segments()), &video_params(), &audio_params(), &metadata)
+            .unwrap();
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(top_level_types(&file), ["ftyp", "moov", "mdat"]);
+    }
+}
@@ -1,13 +1,25 @@
 /// Canonical file paths for Peaking data files on Windows.
 ///
-/// Both files live under %APPDATA%\Peaking\:
-///   - config.toml  Written by the GUI, read by the daemon.
-///   - status.toml  Written by the daemon, read by the GUI.
+/// All files live under %APPDATA%\Peaking\:
+///   - config.toml   Written by the GUI, read by the daemon.
+///   - status.toml   Written by the daemon, read by the GUI.
+///   - buffer.N.spill The daemon's disk spill tier for long ring buffers.
+///   - retention.log Clips deleted by the retention rules, one per line.
+///   - daemon.sock   The control socket; Windows uses [`PIPE_NAME`] instead.
+///   - daemon.lock   Held by the running daemon so a second one refuses to start.
 use std::path::PathBuf;
 
 const APP_DIR_NAME: &str = "Peaking";
 pub const CONFIG_FILE_NAME: &str = "config.toml";
 pub const STATUS_FILE_NAME: &str = "status.toml";
+pub const SPILL_FILE_NAME: &str = "buffer.spill";
+pub const RETENTION_LOG_FILE_NAME: &str = "retention.log";
+pub const LOCK_FILE_NAME: &str = "daemon.lock";
+#[cfg_attr(windows, allow(dead_code))]
+pub const SOCKET_FILE_NAME: &str = "daemon.sock";
+/// The daemon's control endpoint on Windows.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub const PIPE_NAME: &str = r"\\.\pipe\peaking-daemon";
 
 /// Returns the Peaking application data directory: %APPDATA%\Peaking\
 pub fn app_data_dir() -> PathBuf {
@@ -25,6 +37,28 @@ pub fn status_file_path() -> PathBuf {
     app_data_dir().join(STATUS_FILE_NAME)
 }
 
+/// Returns the location the ring-buffer spill files are numbered from:
+/// %APPDATA%\Peaking\buffer.spill
+pub fn spill_file_path() -> PathBuf {
+    app_data_dir().join(SPILL_FILE_NAME)
+}
+
+/// Returns the full path to the retention log: %APPDATA%\Peaking\retention.log
+pub fn retention_log_path() -> PathBuf {
+    app_data_dir().join(RETENTION_LOG_FILE_NAME)
+}
+
+/// Returns the full path to the single-instance lock file: %APPDATA%\Peaking\daemon.lock
+pub fn lock_file_path() -> PathBuf {
+    app_data_dir().join(LOCK_FILE_NAME)
+}
+
+/// Returns the full path to the control socket: %APPDATA%\Peaking\daemon.sock
+#[cfg_attr(windows, allow(dead_code))]
+pub fn socket_path() -> PathBuf {
+    app_data_dir().join(SOCKET_FILE_NAME)
+}
+
 #[cfg(test)]
 #[cfg(windows)]
 mod tests {
@@ -55,10 +89,28 @@ mod tests {
         assert_eq!(path.file_name().unwrap(), STATUS_FILE_NAME);
     }
 
+    #[test]
+    fn spill_file_path_has_correct_name() {
+        let path = spill_file_path();
+        assert_eq!(path.file_name().unwrap(), SPILL_FILE_NAME);
+    }
+
+    #[test]
+    fn retention_log_path_has_correct_name() {
+        let path = retention_log_path();
+        assert_eq!(path.file_name().unwrap(), RETENTION_LOG_FILE_NAME);
+    }
+
     #[test]
     fn config_and_status_share_same_parent_dir() {
         let config = config_file_path();
         let status = status_file_path();
         assert_eq!(config.parent(), status.parent());
     }
+
+    #[test]
+    fn lock_file_path_has_correct_name() {
+        let path = lock_file_path();
+        assert_eq!(path.file_name().unwrap(), LOCK_FILE_NAME);
+    }
 }
@@ -2,50 +2,83 @@
 ///
 /// A `Pipeline` is started when a watched game process is detected and stopped
 /// when that process exits.  It owns:
-///   - a screen-capture task (WGC, Phase 4)
-///   - an audio-capture task (WASAPI, Phase 5)
-///   - an encoder task (NVENC H.264 + AAC, Phase 6)
+///   - a screen-capture task (any [`VideoSource`]; WGC in production, Phase 4)
+///   - an audio-capture task (any [`AudioSource`]; WASAPI in production, Phase 5)
+///   - an encoder task (an [`EncoderBackend`] chosen by [`encoder::probe`], Phase 6)
 ///
 /// The ring buffer (Phase 7) is shared via `Arc<Mutex<RingBuffer>>` so that
 /// the hotkey handler (Phase 8) can drain it for flushing (Phase 9).
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
 use tokio::{sync::{mpsc, watch}, task::JoinHandle};
 use tokio::sync::mpsc::error::TryRecvError;
 
-use crate::audio_capture::{self, RawAudio};
-use crate::capture::{self, RawFrame};
-use crate::config::{ApplicationConfig, Config};
-use crate::encoder::{EncoderConfig, SegmentEncoder};
-use crate::ring_buffer::RingBuffer;
+use crate::audio_capture::{AudioSource, RawAudio, WasapiSource};
+use crate::capture::{RawFrame, VideoSource, WgcSource};
+use crate::config::{ApplicationConfig, Config, EncoderKind, RateControl};
+use crate::encoder::{self, EncoderConfig, SegmentEncoder};
+use crate::event::DaemonEvent;
+use crate::ring_buffer::{CodecEpoch, EncodedSegment, RingBuffer};
+use crate::synthetic::{SineToneSource, TestPatternSource};
+
+/// Returns the capture sources for this platform: WGC + WASAPI on Windows, and
+/// the synthetic test-pattern + sine-tone sources everywhere else so the
+/// pipeline can run headless.  The synthetic sources produce `cfg`'s format.
+pub fn default_sources(cfg: &EncoderConfig) -> (Box<dyn VideoSource>, Box<dyn AudioSource>) {
+    if cfg!(windows) {
+        (Box::new(WgcSource), Box::new(WasapiSource))
+    } else {
+        (
+            Box::new(TestPatternSource::new(cfg.width, cfg.height, cfg.fps)),
+            Box::new(SineToneSource::new(cfg.sample_rate, cfg.channels)),
+        )
+    }
+}
+
+/// Encoder settings for `app`: the `[global.video]` / `[global.audio]` tables
+/// with the application's overrides applied.
+pub fn encoder_config_for(app: &ApplicationConfig, config: &Config) -> EncoderConfig {
+    EncoderConfig::from_settings(
+        &app.effective_video(&config.global),
+        &app.effective_audio(&config.global),
+    )
+}
+
+/// Returns an ID for a new recording session: its start time, the daemon's
+/// PID and a per-process count, so IDs stay unique across restarts.
+fn new_session_id() -> String {
+    static SESSIONS: AtomicU32 = AtomicU32::new(0);
+    let n = SESSIONS.fetch_add(1, Ordering::Relaxed) + 1;
+    let started = chrono::Local::now().format("%Y%m%dT%H%M%S");
+    format!("{started}-{}-{n}", std::process::id())
+}
 
 /// A running capture + encode pipeline.
 pub struct Pipeline {
+    /// Identifies this recording session in clip sidecars.
+    session_id: String,
     /// Setting this to `true` signals all sub-tasks to stop.
     stop_tx: watch::Sender<bool>,
     handles: Vec<JoinHandle<()>>,
 }
 
 impl Pipeline {
-    /// Starts the pipeline for `app` using `config`.
-    /// The encoder feeds completed segments into `ring_buffer`.
+    /// Starts the pipeline for `app` using `config`, reading frames from
+    /// `video_source` and samples from `audio_source`.
+    /// The encoder feeds completed segments into `ring_buffer` and reports its
+    /// backend selection on `event_tx`.
     pub fn start(
         app: &ApplicationConfig,
         config: &Config,
         ring_buffer: Arc<Mutex<RingBuffer>>,
+        video_source: Box<dyn VideoSource>,
+        audio_source: Box<dyn AudioSource>,
+        event_tx: mpsc::Sender<DaemonEvent>,
     ) -> Self {
-        let encoder_config = EncoderConfig {
-            // Resolution will be filled in by the first captured frame; use
-            // defaults here — the encoder will be re-initialised if the
-            // resolution changes (this is a future enhancement).
-            width: 1920,
-            height: 1080,
-            fps: 60,
-            sample_rate: 48_000,
-            channels: 2,
-            video_bitrate: 8_000_000,
-            audio_bitrate: 192_000,
-        };
+        // The frame size is only a starting guess: the encoder reopens at the
+        // captured size as soon as a frame of a different size arrives.
+        let encoder_config = encoder_config_for(app, config);
 
         let (stop_tx, stop_rx) = watch::channel(false);
         let (frame_tx, frame_rx) = mpsc::channel::<RawFrame>(8);
@@ -56,9 +89,10 @@ impl Pipeline {
         // ── Screen capture task ───────────────────────────────────────────────
         {
             let stop_rx = stop_rx.clone();
+            let name = video_source.name();
             handles.push(tokio::spawn(async move {
-                if let Err(e) = capture::run(frame_tx, stop_rx).await {
-                    eprintln!("[capture] Stopped: {e}");
+                if let Err(e) = video_source.run(frame_tx, stop_rx).await {
+                    eprintln!("[capture] {name} stopped: {e}");
                 }
             }));
         }
@@ -66,9 +100,10 @@ impl Pipeline {
         // ── Audio capture task ────────────────────────────────────────────────
         {
             let stop_rx = stop_rx.clone();
+            let name = audio_source.name();
             handles.push(tokio::spawn(async move {
-                if let Err(e) = audio_capture::run(audio_tx, stop_rx).await {
-                    eprintln!("[audio] Stopped: {e}");
+                if let Err(e) = audio_source.run(audio_tx, stop_rx).await {
+                    eprintln!("[audio] {name} stopped: {e}");
                 }
             }));
         }
@@ -78,21 +113,28 @@ impl Pipeline {
             let ring_buffer = Arc::clone(&ring_buffer);
             let display_name = app.display_name.clone();
             let effective_buffer_secs = app.effective_buffer_length(&config.global);
+            let encoder_kind = config.global.encoder;
 
             handles.push(tokio::spawn(async move {
                 run_encoder(
                     frame_rx,
                     audio_rx,
                     ring_buffer,
+                    encoder_kind,
                     encoder_config,
                     effective_buffer_secs,
                     &display_name,
+                    event_tx,
                 )
                 .await;
             }));
         }
 
-        Pipeline { stop_tx, handles }
+        Pipeline { session_id: new_session_id(), stop_tx, handles }
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
     }
 
     /// Signals all sub-tasks to stop and waits for them to finish.
@@ -104,42 +146,81 @@ impl Pipeline {
     }
 }
 
-/// Encoder loop: receives raw frames and audio, encodes them, and pushes
-/// completed [`EncodedSegment`]s into the ring buffer.
+/// Encoder loop: probes for a backend, then receives raw frames and audio,
+/// encodes them, and pushes completed [`EncodedSegment`]s into the ring buffer.
+#[allow(clippy::too_many_arguments)]
 async fn run_encoder(
     mut frame_rx: mpsc::Receiver<RawFrame>,
     mut audio_rx: mpsc::Receiver<RawAudio>,
     ring_buffer: Arc<Mutex<RingBuffer>>,
+    encoder_kind: EncoderKind,
     config: EncoderConfig,
     buffer_secs: u32,
     display_name: &str,
+    event_tx: mpsc::Sender<DaemonEvent>,
 ) {
-    let mut encoder = match SegmentEncoder::new(&config) {
-        Ok(e) => e,
-        Err(err) => {
-            eprintln!("[encoder] Init failed for '{display_name}': {err}");
-            return;
-        }
+    let (backend, encoder_status) = encoder::probe(encoder_kind, &config);
+    for (name, reason) in &encoder_status.rejected {
+        eprintln!("[encoder] Rejected {name}: {reason}");
+    }
+    let _ = event_tx.send(DaemonEvent::EncoderSelected(encoder_status)).await;
+    let Some(backend) = backend else {
+        eprintln!("[encoder] Init failed for '{display_name}': no usable encoder backend");
+        return;
     };
+    let rate = match config.rate_control {
+        RateControl::Cqp => format!("cqp {}", config.qp),
+        rc => format!("{} {} kbps", rc.as_str(), config.video_bitrate / 1000),
+    };
+    let settings = format!(
+        "{} fps, GOP {}, {rate}, {} preset",
+        config.fps,
+        config.gop_length,
+        config.preset.as_str()
+    );
+    let mut encoder = SegmentEncoder::new(backend, config);
 
-    // Initialise ring buffer capacity and store codec parameters.
+    // Initialise ring buffer capacity and open the first codec epoch.
     {
         let mut rb = ring_buffer.lock().unwrap();
         rb.resize(buffer_secs);
-        rb.video_params = Some(encoder.video_params.clone());
-        rb.audio_params = Some(encoder.audio_params.clone());
+        rb.begin_epoch(current_epoch(&encoder));
     }
+    // A frame size the backend refused to open at; such frames are dropped
+    // rather than retrying the open on every frame.
+    let mut rejected_size: Option<(u32, u32)> = None;
 
-    eprintln!("[encoder] Started for '{display_name}' ({buffer_secs}s buffer)");
+    eprintln!(
+        "[encoder] Started for '{display_name}' ({buffer_secs}s buffer, {} backend, {settings})",
+        encoder.backend_name()
+    );
 
     loop {
         tokio::select! {
             frame = frame_rx.recv() => {
                 let Some(frame) = frame else { break };
-                match encoder.push_video_frame(&frame) {
-                    Ok(Some(segment)) => {
-                        ring_buffer.lock().unwrap().push(segment);
+                let size = (frame.width, frame.height);
+                if size != encoder.frame_size() && rejected_size != Some(size) {
+                    match encoder.reconfigure(frame.width, frame.height) {
+                        Ok(tail) => {
+                            eprintln!("[encoder] Reopened at {}x{}", frame.width, frame.height);
+                            if let Some(segment) = tail {
+                                push_segment(&ring_buffer, segment, &event_tx);
+                            }
+                            ring_buffer.lock().unwrap().begin_epoch(current_epoch(&encoder));
+                            rejected_size = None;
+                        }
+                        Err(e) => {
+                            eprintln!("[encoder] Cannot reopen at {}x{}: {e}", frame.width, frame.height);
+                            rejected_size = Some(size);
+                        }
                     }
+                }
+                if size != encoder.frame_size() {
+                    continue; // Dropped: see `rejected_size`.
+                }
+                match encoder.push_video_frame(&frame) {
+                    Ok(Some(segment)) => push_segment(&ring_buffer, segment, &event_tx),
                     Ok(None) => {}
                     Err(e) => eprintln!("[encoder] Video error: {e}"),
                 }
@@ -177,9 +258,216 @@ async fn run_encoder(
 
     // Flush any remaining buffered data.
     if let Ok(Some(segment)) = encoder.flush() {
-        ring_buffer.lock().unwrap().push(segment);
+        push_segment(&ring_buffer, segment, &event_tx);
     }
 
     eprintln!("[encoder] Stopped for '{display_name}'");
 }
 
+/// Pushes a finished segment and reports the buffer's new fill level.  The
+/// report is best-effort: if the event queue is full it is dropped rather than
+/// stalling the encoder, and the next segment's report supersedes it anyway.
+fn push_segment(
+    ring_buffer: &Mutex<RingBuffer>,
+    segment: EncodedSegment,
+    event_tx: &mpsc::Sender<DaemonEvent>,
+) {
+    let stats = {
+        let mut rb = ring_buffer.lock().unwrap();
+        rb.push(segment);
+        rb.stats()
+    };
+    let _ = event_tx.try_send(DaemonEvent::BufferUpdated(stats));
+}
+
+/// Codec parameters the encoder is currently producing.
+fn current_epoch(encoder: &SegmentEncoder) -> CodecEpoch {
+    CodecEpoch {
+        video_params: encoder.video_params.clone(),
+        audio_params: encoder.audio_params.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring_buffer::BufferStats;
+    use std::time::Duration;
+
+    /// Upper bound on waiting for the pipeline, so a broken encoder fails the
+    /// test instead of hanging it.
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Collects the `BufferUpdated` reports until one counts `segments`
+    /// segments.
+    async fn wait_for_segments(
+        event_rx: &mut mpsc::Receiver<DaemonEvent>,
+        segments: usize,
+    ) -> Vec<BufferStats> {
+        let mut reports: Vec<BufferStats> = vec![];
+        let collect = async {
+            while reports.last().is_none_or(|stats| stats.segments < segments) {
+                let event = event_rx.recv().await.expect("pipeline dropped the event channel");
+                if let DaemonEvent::BufferUpdated(stats) = event {
+                    reports.push(stats);
+                }
+            }
+        };
+        tokio::time::timeout(EVENT_TIMEOUT, collect).await.expect("timed out waiting for segments");
+        reports
+    }
+
+    fn test_app() -> ApplicationConfig {
+        ApplicationConfig {
+            display_name: "Test Game".to_string(),
+            executable_name: "game.exe".to_string(),
+            buffer_length_secs: None,
+            max_buffer_bytes: None,
+            post_roll_secs: None,
+            hotkey: None,
+            video: Default::default(),
+            audio: Default::default(),
+            retention: Default::default(),
+        }
+    }
+
+    #[test]
+    fn encoder_config_for_applies_app_overrides() {
+        let mut config = Config::default();
+        config.global.video.bitrate_kbps = 20_000;
+        config.global.audio.bitrate_kbps = 320;
+        let mut app = test_app();
+        app.video.fps = Some(30);
+
+        let cfg = encoder_config_for(&app, &config);
+        assert_eq!(cfg.fps, 30);
+        assert_eq!(cfg.gop_length, 30);
+        assert_eq!(cfg.video_bitrate, 20_000_000);
+        assert_eq!(cfg.audio_bitrate, 320_000);
+    }
+
+    #[test]
+    fn session_ids_are_unique() {
+        let id = new_session_id();
+        assert!(id.contains(&format!("-{}-", std::process::id())), "{id}");
+        assert_ne!(id, new_session_id());
+    }
+
+    #[tokio::test]
+    async fn pipeline_runs_headless_with_synthetic_sources() {
+        let cfg = EncoderConfig::default();
+        let ring_buffer = Arc::new(Mutex::new(RingBuffer::new(10)));
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        let pipeline = Pipeline::start(
+            &test_app(),
+            &Config::default(),
+            Arc::clone(&ring_buffer),
+            Box::new(TestPatternSource::new(cfg.width, cfg.height, cfg.fps)),
+            Box::new(SineToneSource::new(cfg.sample_rate, cfg.channels)),
+            event_tx,
+        );
+        let event = tokio::time::timeout(EVENT_TIMEOUT, event_rx.recv()).await;
+        let Ok(Some(DaemonEvent::EncoderSelected(status))) = event else {
+            panic!("expected EncoderSelected");
+        };
+        // The encoder opens its first epoch straight after reporting, before
+        // it can notice the stop.
+        pipeline.stop().await;
+
+        assert!(
+            ring_buffer.lock().unwrap().current_epoch().is_some(),
+            "encoder should publish codec params"
+        );
+        assert_eq!(status.requested, "auto");
+        if cfg!(not(windows)) {
+            assert_eq!(status.backend.as_deref(), Some("software"));
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_reports_buffer_stats_per_segment() {
+        // Three one-frame GOPs at 64×48, then the source ends.
+        let sizes = vec![(64, 48); 3];
+        let mut config = Config::default();
+        config.global.video.gop_length = Some(1);
+        let ring_buffer = Arc::new(Mutex::new(RingBuffer::new(10)));
+        let (event_tx, mut event_rx) = mpsc::channel(16);
+        let pipeline = Pipeline::start(
+            &test_app(),
+            &config,
+            Arc::clone(&ring_buffer),
+            Box::new(ResizingSource { sizes }),
+            Box::new(SineToneSource::new(48_000, 2)),
+            event_tx,
+        );
+        if cfg!(windows) {
+            pipeline.stop().await;
+            return;
+        }
+        let reports = wait_for_segments(&mut event_rx, 3).await;
+        pipeline.stop().await;
+
+        let segments: Vec<usize> = reports.iter().map(|s| s.segments).collect();
+        assert_eq!(segments, vec![1, 2, 3]);
+        assert_eq!(reports.last().unwrap().bytes, ring_buffer.lock().unwrap().stats().bytes);
+    }
+
+    /// Sends a few frames at one size, then switches size mid-stream.
+    struct ResizingSource {
+        sizes: Vec<(u32, u32)>,
+    }
+
+    impl VideoSource for ResizingSource {
+        fn name(&self) -> &'static str {
+            "resizing"
+        }
+
+        fn run(
+            self: Box<Self>,
+            frame_tx: mpsc::Sender<RawFrame>,
+            _stop_rx: watch::Receiver<bool>,
+        ) -> crate::capture::CaptureFuture {
+            Box::pin(async move {
+                for (i, &(w, h)) in self.sizes.iter().enumerate() {
+                    let bgra = crate::synthetic::render_test_pattern(w, h, i as u64);
+                    if frame_tx.send(RawFrame::packed(bgra, w, h)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_starts_new_epoch_on_resolution_change() {
+        let mut sizes = vec![(1920, 1080); 3];
+        sizes.extend(vec![(64, 48); 3]);
+        let ring_buffer = Arc::new(Mutex::new(RingBuffer::new(10)));
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        let pipeline = Pipeline::start(
+            &test_app(),
+            &Config::default(),
+            Arc::clone(&ring_buffer),
+            Box::new(ResizingSource { sizes }),
+            Box::new(SineToneSource::new(48_000, 2)),
+            event_tx,
+        );
+        if cfg!(windows) {
+            pipeline.stop().await;
+            return; // The real backends are hardware dependent; see encoder.rs tests.
+        }
+        // One segment per size: the first ends at the switch, the second when
+        // the source runs out.
+        wait_for_segments(&mut event_rx, 2).await;
+        pipeline.stop().await;
+
+        let parts = ring_buffer.lock().unwrap().snapshot().load().unwrap();
+        let sizes: Vec<(u32, u32)> = parts
+            .iter()
+            .map(|p| (p.epoch.video_params.width, p.epoch.video_params.height))
+            .collect();
+        assert_eq!(sizes, vec![(1920, 1080), (64, 48)]);
+        assert_eq!(parts[0].segments[0].video_packets.len(), 3);
+    }
+}
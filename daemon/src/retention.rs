@@ -0,0 +1,404 @@
+/// Clip retention: deletes the oldest clips once the clip library, or one
+/// application's share of it, exceeds its configured size, count or age.
+///
+/// `[global.retention]` covers every clip under `clip_output_dir`;
+/// `[applications.retention]` covers the clips in that application's
+/// directory (see [`flush::game_clip_dir`]).  Application rules run first, so
+/// the global rule sees what they left.
+///
+/// Only clips the daemon saved count, recognised by the JSON sidecar next to
+/// them, so other videos under `clip_output_dir` are never deleted.  A clip
+/// is a favourite if a `<clip file name>.favorite` marker file sits next to
+/// it.  Favourites count towards the limits but are never deleted.
+/// A deleted clip's JSON sidecar and thumbnail are deleted with it.
+///
+/// A pass runs on a blocking thread at startup and after every saved clip.
+/// Each deletion is appended to `retention.log` as
+/// `<local time>\t<path>\t<bytes>\t<limit>`.
+use anyhow::Result;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{Notify, RwLock};
+
+use crate::config::{Config, RetentionConfig};
+use crate::{flush, sidecar, thumbnail};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+// ── Rules ──────────────────────────────────────────────────────────────────────
+
+/// Limits applied to the clips under one directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// Config table the limits come from, for messages and the log.
+    pub table: String,
+    pub dir: PathBuf,
+    pub limits: RetentionConfig,
+}
+
+/// Returns the rules with at least one limit set, application rules first.
+pub fn rules(config: &Config) -> Vec<Rule> {
+    let global = &config.global;
+    let mut rules = vec![];
+    for app in &config.applications {
+        if app.retention.is_unlimited() {
+            continue;
+        }
+        let table = format!("applications[\"{}\"].retention", app.display_name);
+        let dir = flush::game_clip_dir(
+            &global.clip_output_dir,
+            &global.clip_dir_template,
+            &app.display_name,
+        );
+        match dir {
+            Some(dir) => rules.push(Rule { table, dir, limits: app.retention }),
+            None => eprintln!(
+                "[retention] Ignoring {table}: clip_dir_template does not give each game \
+                 its own directory"
+            ),
+        }
+    }
+    if !global.retention.is_unlimited() {
+        rules.push(Rule {
+            table: "global.retention".into(),
+            dir: flush::clip_root(&global.clip_output_dir),
+            limits: global.retention,
+        });
+    }
+    rules
+}
+
+// ── Selection ──────────────────────────────────────────────────────────────────
+
+struct StoredClip {
+    path: PathBuf,
+    bytes: u64,
+    modified: SystemTime,
+    favorite: bool,
+}
+
+/// Lists the clips with a sidecar anywhere under `dir`, oldest first.
+fn list_clips(dir: &Path) -> Vec<StoredClip> {
+    let mut clips = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else { continue };
+            let path = entry.path();
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if flush::is_clip_file(&entry.file_name().to_string_lossy())
+                && sidecar::sidecar_path(&path).exists()
+            {
+                clips.push(StoredClip {
+                    favorite: favorite_marker(&path).exists(),
+                    path,
+                    bytes: metadata.len(),
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+    }
+    clips.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.path.cmp(&b.path)));
+    clips
+}
+
+/// Returns the path of the marker that makes `clip` a favourite:
+/// `<clip file name>.favorite`.
+pub fn favorite_marker(clip: &Path) -> PathBuf {
+    flush::sibling_path(clip, ".favorite")
+}
+
+/// Picks the clips to delete from `clips` (oldest first) so the rest satisfy
+/// `limits`, returning their indices and the limit each one broke.
+fn select(clips: &[StoredClip], limits: &RetentionConfig, now: SystemTime) -> Vec<(usize, String)> {
+    let max_age =
+        limits.max_age_days.map(|days| Duration::from_secs(u64::from(days) * SECS_PER_DAY));
+    let mut count = clips.len() as u64;
+    let mut total: u64 = clips.iter().map(|clip| clip.bytes).sum();
+    let mut selected = vec![];
+    for (i, clip) in clips.iter().enumerate() {
+        if clip.favorite {
+            continue;
+        }
+        let age = now.duration_since(clip.modified).unwrap_or_default();
+        let limit = match (max_age, limits.max_count, limits.max_total_bytes) {
+            (Some(max), _, _) if age > max => {
+                format!("max_age_days = {}", max.as_secs() / SECS_PER_DAY)
+            }
+            (_, Some(max), _) if count > u64::from(max) => format!("max_count = {max}"),
+            (_, _, Some(max)) if total > max => format!("max_total_bytes = {max}"),
+            _ => continue,
+        };
+        count -= 1;
+        total -= clip.bytes;
+        selected.push((i, limit));
+    }
+    selected
+}
+
+// ── Enforcement ────────────────────────────────────────────────────────────────
+
+/// A clip removed by [`enforce`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deletion {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// The broken limit, e.g. `global.retention.max_count = 50`.
+    pub limit: String,
+}
+
+/// Applies `rules` in order, deleting clips and logging each deletion to
+/// `log_path`.  Clips that cannot be deleted are reported and skipped.
+pub fn enforce(rules: &[Rule], now: SystemTime, log_path: &Path) -> Vec<Deletion> {
+    let mut deleted = vec![];
+    for rule in rules {
+        let clips = list_clips(&rule.dir);
+        for (i, limit) in select(&clips, &rule.limits, now) {
+            let clip = &clips[i];
+            if let Err(e) = std::fs::remove_file(&clip.path) {
+                eprintln!("[retention] Failed to delete {}: {e}", clip.path.display());
+                continue;
+            }
+            let _ = std::fs::remove_file(sidecar::sidecar_path(&clip.path));
+            let _ = std::fs::remove_file(thumbnail::thumbnail_path(&clip.path));
+            let deletion = Deletion {
+                path: clip.path.clone(),
+                bytes: clip.bytes,
+                limit: format!("{}.{limit}", rule.table),
+            };
+            if let Err(e) = log_deletion(log_path, &deletion) {
+                eprintln!("[retention] Failed to write {}: {e}", log_path.display());
+            }
+            deleted.push(deletion);
+        }
+    }
+    deleted
+}
+
+fn log_deletion(log_path: &Path, deletion: &Deletion) -> Result<()> {
+    let mut log = OpenOptions::new().create(true).append(true).open(log_path)?;
+    writeln!(
+        log,
+        "{}\t{}\t{}\t{}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        deletion.path.display(),
+        deletion.bytes,
+        deletion.limit
+    )?;
+    Ok(())
+}
+
+// ── Background task ────────────────────────────────────────────────────────────
+
+/// Handle to the background retention task.
+pub struct RetentionWorker {
+    wake: Arc<Notify>,
+}
+
+impl RetentionWorker {
+    /// Spawns the task, which makes a first pass straight away.
+    pub fn start(config: Arc<RwLock<Config>>, log_path: PathBuf) -> Self {
+        let wake = Arc::new(Notify::new());
+        wake.notify_one();
+        tokio::spawn(run(config, log_path, Arc::clone(&wake)));
+        Self { wake }
+    }
+
+    /// Asks for another pass.  Requests made while a pass is running are
+    /// folded into a single follow-up pass.
+    pub fn request(&self) {
+        self.wake.notify_one();
+    }
+}
+
+async fn run(config: Arc<RwLock<Config>>, log_path: PathBuf, wake: Arc<Notify>) {
+    loop {
+        wake.notified().await;
+        let rules = rules(&*config.read().await);
+        if rules.is_empty() {
+            continue;
+        }
+        let log_path = log_path.clone();
+        let pass = tokio::task::spawn_blocking(move || enforce(&rules, SystemTime::now(), &log_path));
+        match pass.await {
+            Ok(deleted) if !deleted.is_empty() => {
+                let bytes: u64 = deleted.iter().map(|d| d.bytes).sum();
+                println!(
+                    "[retention] Deleted {} clip(s), freeing {} MiB",
+                    deleted.len(),
+                    bytes >> 20
+                );
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("[retention] Pass panicked: {e}"),
+        }
+    }
+}
+
+// ── Tests ──────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApplicationConfig;
+
+    const DAY: Duration = Duration::from_secs(SECS_PER_DAY);
+
+    /// Writes `bytes` bytes to `dir/name`, last modified `age_days` ago.
+    fn file(dir: &Path, name: &str, bytes: usize, age_days: u32) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; bytes]).unwrap();
+        let modified = SystemTime::now() - DAY * age_days;
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        path
+    }
+
+    /// Like [`file`], plus the sidecar the daemon saves with every clip.
+    fn clip(dir: &Path, name: &str, bytes: usize, age_days: u32) -> PathBuf {
+        let path = file(dir, name, bytes, age_days);
+        std::fs::write(sidecar::sidecar_path(&path), b"{}").unwrap();
+        path
+    }
+
+    fn rule(dir: &Path, limits: RetentionConfig) -> Rule {
+        Rule { table: "global.retention".into(), dir: dir.to_path_buf(), limits }
+    }
+
+    fn remaining(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = list_clips(dir)
+            .into_iter()
+            .map(|c| c.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn count_limit_deletes_oldest_first_and_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let game = dir.path().join("Game");
+        for (name, age) in [("a.mp4", 3), ("b.mkv", 2), ("c.mp4", 1)] {
+            let path = clip(&game, name, 10, age);
+            std::fs::write(thumbnail::thumbnail_path(&path), b"").unwrap();
+        }
+        let log = dir.path().join("retention.log");
+        let limits = RetentionConfig { max_count: Some(2), ..Default::default() };
+
+        let deleted = enforce(&[rule(dir.path(), limits)], SystemTime::now(), &log);
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].path, game.join("a.mp4"));
+        assert_eq!(deleted[0].limit, "global.retention.max_count = 2");
+        assert_eq!(remaining(dir.path()), vec!["b.mkv", "c.mp4"]);
+        assert!(!game.join("a.mp4.json").exists(), "sidecar deleted with its clip");
+        assert!(!game.join("a.mp4.png").exists(), "thumbnail deleted with its clip");
+        assert!(game.join("c.mp4.json").exists());
+
+        let logged = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(logged.lines().count(), 1);
+        assert!(logged.contains("a.mp4\t10\tglobal.retention.max_count = 2"), "{logged}");
+    }
+
+    #[test]
+    fn size_and_age_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        clip(dir.path(), "old.mp4", 10, 40);
+        clip(dir.path(), "big.mp4", 100, 5);
+        clip(dir.path(), "new.mp4", 50, 0);
+        let limits = RetentionConfig {
+            max_total_bytes: Some(60),
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+        let clips = list_clips(dir.path());
+        let selected: Vec<_> = select(&clips, &limits, SystemTime::now())
+            .into_iter()
+            .map(|(i, limit)| (clips[i].path.file_name().unwrap().to_owned(), limit))
+            .collect();
+        assert_eq!(
+            selected,
+            [
+                ("old.mp4".into(), "max_age_days = 30".to_string()),
+                ("big.mp4".into(), "max_total_bytes = 60".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn favourites_count_but_are_never_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = clip(dir.path(), "a.mp4", 10, 3);
+        std::fs::write(favorite_marker(&kept), b"").unwrap();
+        clip(dir.path(), "b.mp4", 10, 2);
+        clip(dir.path(), "c.mp4", 10, 1);
+        let limits =
+            RetentionConfig { max_count: Some(2), max_age_days: Some(1), ..Default::default() };
+
+        enforce(&[rule(dir.path(), limits)], SystemTime::now() + DAY, &dir.path().join("log"));
+        assert_eq!(remaining(dir.path()), vec!["a.mp4"]);
+        assert!(favorite_marker(&kept).exists());
+    }
+
+    #[test]
+    fn unfinished_and_unrelated_files_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        clip(dir.path(), ".a.mp4.partial", 10, 9);
+        clip(dir.path(), "notes.txt", 10, 9);
+        let limits = RetentionConfig { max_age_days: Some(1), ..Default::default() };
+        let log = dir.path().join("log");
+        assert!(enforce(&[rule(dir.path(), limits)], SystemTime::now(), &log).is_empty());
+        assert!(!log.exists());
+    }
+
+    #[test]
+    fn videos_the_daemon_did_not_save_survive() {
+        let dir = tempfile::tempdir().unwrap();
+        let holiday = file(&dir.path().join("Holiday"), "beach.mp4", 10, 9);
+        clip(dir.path(), "a.mp4", 10, 2);
+        clip(dir.path(), "b.mp4", 10, 1);
+        let limits = RetentionConfig { max_count: Some(1), ..Default::default() };
+
+        let log = dir.path().join("log");
+        let deleted = enforce(&[rule(dir.path(), limits)], SystemTime::now(), &log);
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].path, dir.path().join("a.mp4"));
+        assert!(holiday.exists());
+    }
+
+    #[test]
+    fn rules_cover_each_game_dir_then_the_library() {
+        let mut config = Config::default();
+        config.global.clip_output_dir = "clips".into();
+        config.global.retention.max_age_days = Some(90);
+        let app = |name: &str, max_count| ApplicationConfig {
+            display_name: name.into(),
+            executable_name: "game.exe".into(),
+            buffer_length_secs: None,
+            max_buffer_bytes: None,
+            post_roll_secs: None,
+            hotkey: None,
+            video: Default::default(),
+            audio: Default::default(),
+            retention: RetentionConfig { max_count, ..Default::default() },
+        };
+        config.applications = vec![app("Unlimited", None), app("Game", Some(5))];
+
+        let rules = rules(&config);
+        let tables: Vec<_> = rules.iter().map(|r| (r.table.as_str(), r.dir.clone())).collect();
+        assert_eq!(
+            tables,
+            [
+                ("applications[\"Game\"].retention", Path::new("clips").join("Game")),
+                ("global.retention", PathBuf::from("clips")),
+            ]
+        );
+
+        config.global.clip_dir_template = "{date}/{game}".into();
+        assert_eq!(super::rules(&config).len(), 1, "per-game rule needs a per-game directory");
+    }
+}
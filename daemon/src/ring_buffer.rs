@@ -1,13 +1,20 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
 use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::config::{MAX_BUFFER_LENGTH_SECS, MIN_BUFFER_LENGTH_SECS};
+use crate::config::{MAX_BUFFER_LENGTH_SECS, MAX_MEMORY_BUFFER_SECS, MIN_BUFFER_LENGTH_SECS};
+use crate::spill::{SpillEntry, SpillFile, SpillReader};
 
 /// A single encoded packet extracted from the encoder output.
 /// Carries enough metadata for the MP4 muxer (Phase 9) to reconstruct timing.
 #[derive(Debug, Clone)]
 pub struct EncodedPacket {
-    /// Raw compressed bytes (H.264 NAL units or AAC ADTS frames).
-    pub data: Vec<u8>,
+    /// Raw compressed bytes (H.264 NAL units or AAC ADTS frames).  Immutable
+    /// and reference-counted, so copying a packet never copies its payload.
+    pub data: Arc<[u8]>,
     /// Presentation timestamp in codec time-base units.
     pub pts: i64,
     /// Decoding timestamp in codec time-base units.
@@ -41,26 +48,242 @@ pub struct AudioCodecParams {
     pub time_base: (i32, i32),
 }
 
-/// One complete 1-second window of encoded video and audio.
+/// One GOP of encoded video plus the audio captured alongside it (one second
+/// with the default GOP length).
 /// Each segment starts with an IDR (keyframe) so it is independently decodable.
 #[derive(Debug, Clone)]
 pub struct EncodedSegment {
     pub video_packets: Vec<EncodedPacket>,
     pub audio_packets: Vec<EncodedPacket>,
+    /// When the ring buffer received the segment, which is about when its
+    /// footage ended.  `None` until it is pushed.
+    pub pushed_at: Option<DateTime<Local>>,
 }
 
-/// Circular buffer of 1-second [`EncodedSegment`]s.
+impl EncodedSegment {
+    /// Total size of the compressed video and audio payloads.
+    pub fn size_bytes(&self) -> u64 {
+        self.video_packets.iter().chain(&self.audio_packets).map(|p| p.data.len() as u64).sum()
+    }
+
+    /// Playback length of the video packets, given the video `time_base`.
+    pub fn duration(&self, time_base: (i32, i32)) -> Duration {
+        let (Some(first), Some(last)) = (self.video_packets.first(), self.video_packets.last())
+        else {
+            return Duration::ZERO;
+        };
+        let ticks = (last.dts - first.dts + last.duration.max(1)).max(0) as u128;
+        let (num, den) = (time_base.0.max(0) as u128, time_base.1.max(1) as u128);
+        Duration::from_nanos((ticks * num * 1_000_000_000 / den) as u64)
+    }
+
+    /// Wall-clock time the footage began: [`EncodedSegment::pushed_at`] less
+    /// the segment's length.
+    pub fn started_at(&self, time_base: (i32, i32)) -> Option<DateTime<Local>> {
+        let duration = chrono::Duration::from_std(self.duration(time_base)).unwrap_or_default();
+        self.pushed_at.map(|pushed| pushed - duration)
+    }
+}
+
+/// Nominal length of a segment pushed before any epoch (and so any time base)
+/// is known.
+const UNTIMED_SEGMENT_DURATION: Duration = Duration::from_secs(1);
+
+/// Codec parameters shared by a contiguous run of segments.  A new epoch
+/// begins every time the encoder is (re)opened, e.g. after the captured
+/// resolution changes.
+#[derive(Debug, Clone)]
+pub struct CodecEpoch {
+    pub video_params: VideoCodecParams,
+    pub audio_params: AudioCodecParams,
+}
+
+/// Consecutive buffered segments that share one [`CodecEpoch`] and can
+/// therefore be muxed into a single file.  Segments are shared with the ring
+/// buffer rather than copied.
+#[derive(Debug, Clone)]
+pub struct EpochSegments {
+    pub epoch: CodecEpoch,
+    pub segments: Vec<Arc<EncodedSegment>>,
+}
+
+/// Point-in-time fill level of a [`RingBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferStats {
+    pub segments: usize,
+    /// Compressed bytes held, in memory and on disk.
+    pub bytes: u64,
+    /// The part of `bytes` held in the spill file.
+    pub disk_bytes: u64,
+    /// Length of video held.
+    pub duration: Duration,
+    /// `bytes` over `duration`, in bits per second (0 while empty).
+    pub avg_bitrate_bps: u64,
+    /// Configured length limit.
+    pub capacity: Duration,
+    /// Configured byte budget, if any.
+    pub max_bytes: Option<u64>,
+}
+
+/// Run-length record of how many consecutive segments belong to an epoch.
+/// `epoch` is `None` for segments pushed before any parameters were known.
+struct EpochRun {
+    epoch: Option<CodecEpoch>,
+    /// Identifies the epoch across snapshots; 0 for the `None` epoch.
+    serial: u64,
+    len: usize,
+}
+
+/// Where a buffered segment currently lives.
+#[derive(Clone)]
+enum SlotData {
+    Memory(Arc<EncodedSegment>),
+    Spilled(SpillEntry),
+}
+
+/// One buffered segment plus the accounting needed to evict it without
+/// reading it back from disk.
+struct Slot {
+    data: SlotData,
+    duration: Duration,
+    bytes: u64,
+    /// Push order, starting at 1.
+    seq: u64,
+}
+
+/// The segments of one epoch within a [`BufferSnapshot`].
+struct SnapshotPart {
+    epoch: CodecEpoch,
+    serial: u64,
+    segments: Vec<SlotData>,
+}
+
+/// Segments copied out of a [`RingBuffer`] for flushing, grouped by epoch.
+///
+/// Taking a snapshot only copies pointers: in-memory segments are shared
+/// with the buffer and spilled ones are referenced by location.
+/// [`BufferSnapshot::load`] reads the latter back after the ring-buffer lock
+/// has been released; a spilled segment that the buffer overwrites in the
+/// meantime is skipped.
+pub struct BufferSnapshot {
+    parts: Vec<SnapshotPart>,
+    reader: Option<SpillReader>,
+    /// Sequence number of the newest segment pushed when this was taken.
+    last_seq: u64,
+    /// Total length of the segments in `parts`.
+    duration: Duration,
+}
+
+impl BufferSnapshot {
+    pub fn segment_count(&self) -> usize {
+        self.parts.iter().map(|part| part.segments.len()).sum()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Pass to [`RingBuffer::snapshot_after`] to get only the segments pushed
+    /// since this snapshot.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Appends the segments of `later`, a snapshot of segments pushed after
+    /// this one's (see [`RingBuffer::snapshot_after`]).
+    pub fn append(&mut self, later: BufferSnapshot) {
+        for part in later.parts {
+            match self.parts.last_mut() {
+                Some(last) if last.serial == part.serial => last.segments.extend(part.segments),
+                _ => self.parts.push(part),
+            }
+        }
+        self.reader = self.reader.take().or(later.reader);
+        self.last_seq = self.last_seq.max(later.last_seq);
+        self.duration += later.duration;
+    }
+
+    /// Materializes every segment, reading spilled ones from disk.  Blocks
+    /// on file I/O, so call it off the async runtime.
+    pub fn load(self) -> Result<Vec<EpochSegments>> {
+        let mut out = vec![];
+        for SnapshotPart { epoch, segments: slots, .. } in self.parts {
+            let mut segments = Vec::with_capacity(slots.len());
+            for slot in slots {
+                match slot {
+                    SlotData::Memory(segment) => segments.push(segment),
+                    SlotData::Spilled(entry) => {
+                        let Some(reader) = &self.reader else { continue };
+                        if let Some(segment) = reader.read(&entry)? {
+                            segments.push(Arc::new(segment));
+                        }
+                    }
+                }
+            }
+            if !segments.is_empty() {
+                out.push(EpochSegments { epoch, segments });
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+impl From<Vec<EpochSegments>> for BufferSnapshot {
+    fn from(parts: Vec<EpochSegments>) -> Self {
+        let parts = parts
+            .into_iter()
+            .zip(1..)
+            .map(|(part, serial)| SnapshotPart {
+                epoch: part.epoch,
+                serial,
+                segments: part.segments.into_iter().map(SlotData::Memory).collect(),
+            })
+            .collect();
+        Self { parts, reader: None, last_seq: 0, duration: Duration::ZERO }
+    }
+}
+
+/// Circular buffer of [`EncodedSegment`]s.
+///
+/// Holds the most recent `capacity` of video (the buffer length in seconds,
+/// clamped to [`MIN_BUFFER_LENGTH_SECS`]–[`MAX_BUFFER_LENGTH_SECS`]).  Whole
+/// segments are evicted oldest-first as long as the rest still cover the
+/// capacity, so with 1-second GOPs the buffer holds exactly `capacity` segments.
+///
+/// At most [`MAX_MEMORY_BUFFER_SECS`] of the newest segments stay in memory.
+/// Older ones move to the disk spill tier when one is configured (see
+/// [`RingBuffer::set_spill`]) and are dropped otherwise.  An optional byte
+/// budget shrinks the in-memory window further when high bitrates would
+/// otherwise hold more RAM than intended; the newest segment is always kept.
 ///
-/// Holds at most `capacity` segments (= buffer length in seconds, clamped to
-/// [`MIN_BUFFER_LENGTH_SECS`]–[`MAX_BUFFER_LENGTH_SECS`]).  When full, the
-/// oldest segment is evicted to make room for the newest.
+/// Segments are grouped into codec-parameter epochs; every segment belongs to
+/// the epoch that was current when it was pushed.
 pub struct RingBuffer {
-    segments: VecDeque<EncodedSegment>,
-    /// Maximum number of segments to retain (= buffer length in seconds).
-    capacity: usize,
-    /// Codec parameters set once when the encoder is first opened.
-    pub video_params: Option<VideoCodecParams>,
-    pub audio_params: Option<AudioCodecParams>,
+    /// Spilled slots first, then in-memory ones, oldest to newest.
+    slots: VecDeque<Slot>,
+    /// Number of spilled slots at the front of `slots`.
+    spilled: usize,
+    /// Epoch runs covering `slots` front to back.  Only the last run may be
+    /// empty (an epoch that has just begun).
+    runs: VecDeque<EpochRun>,
+    /// Length of video to retain.
+    capacity: Duration,
+    /// Sum of the durations of all buffered segments.
+    total: Duration,
+    /// Sum of the durations of the in-memory segments.
+    memory_total: Duration,
+    /// Optional cap on the compressed bytes held in memory.
+    max_bytes: Option<u64>,
+    /// Sum of the sizes of the in-memory segments.
+    memory_bytes: u64,
+    /// Sum of the sizes of the spilled segments.
+    disk_bytes: u64,
+    spill: Option<SpillFile>,
+    /// Segments pushed so far; the sequence number of the newest one.
+    pushed: u64,
+    /// Epochs begun so far; the serial of the newest one.
+    epochs_begun: u64,
 }
 
 impl RingBuffer {
@@ -68,44 +291,278 @@ impl RingBuffer {
     /// The capacity is clamped to the configured min/max.
     pub fn new(capacity_secs: u32) -> Self {
         Self {
-            segments: VecDeque::new(),
+            slots: VecDeque::new(),
+            spilled: 0,
+            runs: VecDeque::new(),
             capacity: Self::clamp_capacity(capacity_secs),
-            video_params: None,
-            audio_params: None,
+            total: Duration::ZERO,
+            memory_total: Duration::ZERO,
+            max_bytes: None,
+            memory_bytes: 0,
+            disk_bytes: 0,
+            spill: None,
+            pushed: 0,
+            epochs_begun: 0,
         }
     }
 
-    /// Pushes a new segment, evicting the oldest if the buffer is at capacity.
-    pub fn push(&mut self, segment: EncodedSegment) {
-        if self.segments.len() == self.capacity {
-            self.segments.pop_front();
+    /// Starts a new codec-parameter epoch; subsequent pushes belong to it.
+    pub fn begin_epoch(&mut self, epoch: CodecEpoch) {
+        // An epoch that never received a segment has nothing to describe.
+        if self.runs.back().is_some_and(|run| run.len == 0) {
+            self.runs.pop_back();
         }
-        self.segments.push_back(segment);
+        self.epochs_begun += 1;
+        self.runs.push_back(EpochRun { epoch: Some(epoch), serial: self.epochs_begun, len: 0 });
     }
 
-    /// Returns a slice view of all segments without removing them.
-    pub fn segments(&self) -> &VecDeque<EncodedSegment> {
-        &self.segments
+    /// Parameters of the epoch new segments are currently assigned to.
+    pub fn current_epoch(&self) -> Option<&CodecEpoch> {
+        self.runs.back().and_then(|run| run.epoch.as_ref())
     }
 
-    /// Clears all segments (e.g. when a new recording session starts).
+    /// Pushes a new segment stamped with the current time, evicting the oldest
+    /// ones that are no longer needed to cover the capacity.
+    pub fn push(&mut self, mut segment: EncodedSegment) {
+        segment.pushed_at = Some(Local::now());
+        if self.runs.is_empty() {
+            self.runs.push_back(EpochRun { epoch: None, serial: 0, len: 0 });
+        }
+        let run = self.runs.back_mut().expect("a run was just ensured");
+        run.len += 1;
+        let duration = Self::segment_duration(run.epoch.as_ref(), &segment);
+        let bytes = segment.size_bytes();
+        self.total += duration;
+        self.memory_total += duration;
+        self.memory_bytes += bytes;
+        self.pushed += 1;
+        let data = SlotData::Memory(Arc::new(segment));
+        self.slots.push_back(Slot { data, duration, bytes, seq: self.pushed });
+        self.evict_excess();
+    }
+
+    /// Returns every buffered segment oldest first, reading spilled ones back.
+    #[cfg(test)]
+    pub fn segments(&self) -> Vec<Arc<EncodedSegment>> {
+        let reader = self.spill.as_ref().map(SpillFile::reader);
+        self.slots
+            .iter()
+            .map(|slot| match &slot.data {
+                SlotData::Memory(segment) => Arc::clone(segment),
+                SlotData::Spilled(entry) => Arc::new(
+                    reader.as_ref().unwrap().read(entry).unwrap().expect("spilled segment is intact"),
+                ),
+            })
+            .collect()
+    }
+
+    /// Shares the buffered segments grouped by epoch, oldest first.  Segments
+    /// pushed before any epoch began are skipped since they cannot be muxed.
+    pub fn snapshot(&self) -> BufferSnapshot {
+        self.snapshot_after(0)
+    }
+
+    /// Like [`RingBuffer::snapshot`], but only includes segments pushed after
+    /// the one numbered `seq` (see [`BufferSnapshot::last_seq`]).
+    pub fn snapshot_after(&self, seq: u64) -> BufferSnapshot {
+        let mut remaining = self.slots.iter();
+        let mut parts = vec![];
+        let mut duration = Duration::ZERO;
+        for run in &self.runs {
+            let slots: Vec<&Slot> =
+                remaining.by_ref().take(run.len).filter(|slot| slot.seq > seq).collect();
+            if let (Some(epoch), false) = (&run.epoch, slots.is_empty()) {
+                duration += slots.iter().map(|slot| slot.duration).sum::<Duration>();
+                parts.push(SnapshotPart {
+                    epoch: epoch.clone(),
+                    serial: run.serial,
+                    segments: slots.into_iter().map(|slot| slot.data.clone()).collect(),
+                });
+            }
+        }
+        BufferSnapshot {
+            parts,
+            reader: self.spill.as_ref().map(SpillFile::reader),
+            last_seq: self.pushed,
+            duration,
+        }
+    }
+
+    /// Clears all segments (e.g. when a new recording session starts) and
+    /// disables the spill tier, deleting its file; snapshots already taken keep
+    /// reading it.  The current epoch stays in effect.
     pub fn clear(&mut self) {
-        self.segments.clear();
+        self.slots.clear();
+        self.spilled = 0;
+        self.spill = None;
+        self.total = Duration::ZERO;
+        self.memory_total = Duration::ZERO;
+        self.memory_bytes = 0;
+        self.disk_bytes = 0;
+        let current = self.runs.pop_back();
+        self.runs.clear();
+        if let Some(mut run) = current {
+            run.len = 0;
+            self.runs.push_back(run);
+        }
     }
 
     /// Resizes the buffer to `capacity_secs` seconds, clamped to min/max.
     /// If the new capacity is smaller than the current fill level, the oldest
     /// segments are dropped.
     pub fn resize(&mut self, capacity_secs: u32) {
-        let new_cap = Self::clamp_capacity(capacity_secs);
-        self.capacity = new_cap;
-        while self.segments.len() > self.capacity {
-            self.segments.pop_front();
+        self.capacity = Self::clamp_capacity(capacity_secs);
+        self.evict_excess();
+    }
+
+    /// Sets the in-memory byte budget (`None` for no limit), evicting or
+    /// spilling the oldest segments immediately if the buffer is over it.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<u64>) {
+        self.max_bytes = max_bytes;
+        self.evict_excess();
+    }
+
+    /// Enables the disk spill tier with a `capacity`-byte file numbered from
+    /// `path` (see [`crate::spill`]), or disables it with `None`.  Changing
+    /// the settings discards everything already spilled; passing the current
+    /// settings again is a no-op.
+    pub fn set_spill(&mut self, spill: Option<(&Path, u64)>) -> Result<()> {
+        let unchanged = match (&self.spill, spill) {
+            (Some(file), Some((path, capacity))) => {
+                file.location() == path && file.capacity() == capacity
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return Ok(());
+        }
+        self.drop_spilled();
+        if let Some((path, capacity)) = spill {
+            self.spill = Some(SpillFile::create(path, capacity)?);
+        }
+        self.evict_excess();
+        Ok(())
+    }
+
+    /// Reports how much the buffer currently holds.
+    pub fn stats(&self) -> BufferStats {
+        let bytes = self.memory_bytes + self.disk_bytes;
+        let secs = self.total.as_secs_f64();
+        let avg_bitrate_bps = if secs > 0.0 { (bytes as f64 * 8.0 / secs).round() as u64 } else { 0 };
+        BufferStats {
+            segments: self.slots.len(),
+            bytes,
+            disk_bytes: self.disk_bytes,
+            duration: self.total,
+            avg_bitrate_bps,
+            capacity: self.capacity,
+            max_bytes: self.max_bytes,
         }
     }
 
-    fn clamp_capacity(secs: u32) -> usize {
-        secs.clamp(MIN_BUFFER_LENGTH_SECS, MAX_BUFFER_LENGTH_SECS) as usize
+    /// Drops the oldest segments while the remaining ones still cover the
+    /// capacity, then moves in-memory segments to the spill tier (or drops
+    /// them) while memory holds more than its window or byte budget.
+    fn evict_excess(&mut self) {
+        while let Some(front) = self.slots.front() {
+            if self.total.saturating_sub(front.duration) < self.capacity {
+                break;
+            }
+            self.pop_oldest();
+        }
+
+        let window = self.capacity.min(Duration::from_secs(u64::from(MAX_MEMORY_BUFFER_SECS)));
+        while let Some(oldest) = self.slots.get(self.spilled) {
+            let over_duration = self.memory_total.saturating_sub(oldest.duration) >= window;
+            let over_bytes = self.max_bytes.is_some_and(|max| self.memory_bytes > max)
+                && self.slots.len() - self.spilled > 1;
+            if !over_duration && !over_bytes {
+                break;
+            }
+            if !self.spill_oldest_in_memory() {
+                // Without a spill tier every slot is in memory, so this is it.
+                self.pop_oldest();
+            }
+        }
+    }
+
+    /// Writes the oldest in-memory segment to the spill file, forgetting any
+    /// spilled segments its record overwrote.  Returns `false` if there is no
+    /// spill tier; a write error disables the tier.
+    fn spill_oldest_in_memory(&mut self) -> bool {
+        let Some(spill) = self.spill.as_mut() else {
+            return false;
+        };
+        let slot = &mut self.slots[self.spilled];
+        let SlotData::Memory(segment) = &slot.data else {
+            unreachable!("slots after the spilled prefix are in memory");
+        };
+        match spill.write(segment) {
+            Ok((entry, overwritten)) => {
+                slot.data = SlotData::Spilled(entry);
+                self.memory_total = self.memory_total.saturating_sub(slot.duration);
+                self.memory_bytes -= slot.bytes;
+                self.disk_bytes += slot.bytes;
+                self.spilled += 1;
+                while let Some(Slot { data: SlotData::Spilled(front), .. }) = self.slots.front() {
+                    if front.seq > overwritten {
+                        break;
+                    }
+                    self.pop_oldest();
+                }
+                true
+            }
+            Err(e) => {
+                eprintln!("[ring_buffer] {e:#}; keeping the buffer in memory only");
+                self.drop_spilled();
+                false
+            }
+        }
+    }
+
+    /// Discards every spilled segment and deletes the spill file.
+    fn drop_spilled(&mut self) {
+        while self.spilled > 0 {
+            self.pop_oldest();
+        }
+        self.spill = None;
+    }
+
+    /// Removes the oldest segment and retires its epoch once it has no
+    /// segments left (unless it is the current epoch).
+    fn pop_oldest(&mut self) {
+        let Some(slot) = self.slots.pop_front() else {
+            return;
+        };
+        self.total = self.total.saturating_sub(slot.duration);
+        match slot.data {
+            SlotData::Memory(_) => {
+                self.memory_total = self.memory_total.saturating_sub(slot.duration);
+                self.memory_bytes -= slot.bytes;
+            }
+            SlotData::Spilled(_) => {
+                self.spilled -= 1;
+                self.disk_bytes -= slot.bytes;
+            }
+        }
+        if let Some(run) = self.runs.front_mut() {
+            run.len -= 1;
+        }
+        while self.runs.len() > 1 && self.runs.front().is_some_and(|run| run.len == 0) {
+            self.runs.pop_front();
+        }
+    }
+
+    fn segment_duration(epoch: Option<&CodecEpoch>, segment: &EncodedSegment) -> Duration {
+        match epoch {
+            Some(epoch) => segment.duration(epoch.video_params.time_base),
+            None => UNTIMED_SEGMENT_DURATION,
+        }
+    }
+
+    fn clamp_capacity(secs: u32) -> Duration {
+        Duration::from_secs(u64::from(secs.clamp(MIN_BUFFER_LENGTH_SECS, MAX_BUFFER_LENGTH_SECS)))
     }
 }
 
@@ -116,17 +573,18 @@ mod tests {
     fn make_segment(id: i64) -> EncodedSegment {
         EncodedSegment {
             video_packets: vec![EncodedPacket {
-                data: vec![],
+                data: Arc::from([]),
                 pts: id,
                 dts: id,
                 duration: 1,
                 is_key: false,
             }],
             audio_packets: vec![],
+            pushed_at: None,
         }
     }
 
-    fn first_pts(seg: &EncodedSegment) -> i64 {
+    fn first_pts(seg: &Arc<EncodedSegment>) -> i64 {
         seg.video_packets[0].pts
     }
 
@@ -139,17 +597,23 @@ mod tests {
             rb.push(make_segment(i as i64));
         }
         assert_eq!(rb.segments().len(), MIN_BUFFER_LENGTH_SECS as usize);
-        assert_eq!(first_pts(rb.segments().front().unwrap()), 1);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 1);
     }
 
     #[test]
     fn new_clamps_above_max() {
-        let mut rb = RingBuffer::new(u32::MAX);
-        for i in 0..MAX_BUFFER_LENGTH_SECS + 1 {
+        let rb = RingBuffer::new(u32::MAX);
+        assert_eq!(rb.stats().capacity, Duration::from_secs(u64::from(MAX_BUFFER_LENGTH_SECS)));
+    }
+
+    #[test]
+    fn memory_only_buffer_holds_at_most_the_memory_window() {
+        let mut rb = RingBuffer::new(MAX_BUFFER_LENGTH_SECS);
+        for i in 0..MAX_MEMORY_BUFFER_SECS + 1 {
             rb.push(make_segment(i as i64));
         }
-        assert_eq!(rb.segments().len(), MAX_BUFFER_LENGTH_SECS as usize);
-        assert_eq!(first_pts(rb.segments().front().unwrap()), 1);
+        assert_eq!(rb.segments().len(), MAX_MEMORY_BUFFER_SECS as usize);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 1);
     }
 
     #[test]
@@ -160,7 +624,7 @@ mod tests {
             rb.push(make_segment(i as i64));
         }
         assert_eq!(rb.segments().len(), cap as usize);
-        assert_eq!(first_pts(rb.segments().front().unwrap()), 1);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 1);
     }
 
     // ── push / eviction ───────────────────────────────────────────────────────
@@ -182,9 +646,9 @@ mod tests {
             rb.push(make_segment(i as i64));
         }
         // The first 3 segments (ids 0, 1, 2) should have been evicted.
-        assert_eq!(first_pts(rb.segments().front().unwrap()), 3);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 3);
         assert_eq!(
-            first_pts(rb.segments().back().unwrap()),
+            first_pts(rb.segments().last().unwrap()),
             (MIN_BUFFER_LENGTH_SECS + 2) as i64
         );
     }
@@ -198,6 +662,15 @@ mod tests {
         assert!(!rb.segments().is_empty());
     }
 
+    #[test]
+    fn push_stamps_segment_with_wall_clock_time() {
+        let mut rb = RingBuffer::new(10);
+        let before = Local::now();
+        rb.push(make_segment(0));
+        let pushed_at = rb.segments()[0].pushed_at.expect("stamped on push");
+        assert!(pushed_at >= before && pushed_at <= Local::now());
+    }
+
     // ── segments view ─────────────────────────────────────────────────────────
 
     #[test]
@@ -229,7 +702,7 @@ mod tests {
         rb.clear();
         rb.push(make_segment(1));
         assert_eq!(rb.segments().len(), 1);
-        assert_eq!(first_pts(rb.segments().front().unwrap()), 1);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 1);
     }
 
     // ── resize ────────────────────────────────────────────────────────────────
@@ -243,7 +716,7 @@ mod tests {
         rb.resize(7);
         assert_eq!(rb.segments().len(), 7);
         // Segments 0-2 should be gone; segment 3 is now the oldest.
-        assert_eq!(first_pts(rb.segments().front().unwrap()), 3);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 3);
     }
 
     #[test]
@@ -288,31 +761,476 @@ mod tests {
         assert_eq!(rb.segments().len(), 10);
     }
 
-    // ── codec params ──────────────────────────────────────────────────────────
+    // ── codec epochs ──────────────────────────────────────────────────────────
+
+    /// Epoch with a one-second video time base, so every `make_segment`
+    /// lasts one second.
+    fn make_epoch(width: u32) -> CodecEpoch {
+        CodecEpoch {
+            video_params: VideoCodecParams {
+                extradata: vec![0x01, 0x02],
+                width,
+                height: 1080,
+                time_base: (1, 1),
+            },
+            audio_params: AudioCodecParams {
+                extradata: vec![0x03],
+                sample_rate: 48_000,
+                channels: 2,
+                time_base: (1, 48_000),
+            },
+        }
+    }
+
+    fn snapshot_shape(rb: &RingBuffer) -> Vec<(u32, Vec<i64>)> {
+        rb.snapshot()
+            .load()
+            .unwrap()
+            .iter()
+            .map(|part| {
+                (part.epoch.video_params.width, part.segments.iter().map(first_pts).collect())
+            })
+            .collect()
+    }
 
     #[test]
-    fn codec_params_start_as_none() {
+    fn current_epoch_starts_as_none() {
         let rb = RingBuffer::new(10);
-        assert!(rb.video_params.is_none());
-        assert!(rb.audio_params.is_none());
+        assert!(rb.current_epoch().is_none());
+        assert_eq!(rb.snapshot().segment_count(), 0);
+    }
+
+    #[test]
+    fn begin_epoch_sets_current_params() {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_epoch(1920));
+        assert_eq!(rb.current_epoch().unwrap().video_params.width, 1920);
+        assert_eq!(rb.current_epoch().unwrap().audio_params.sample_rate, 48_000);
+    }
+
+    #[test]
+    fn snapshot_groups_segments_by_epoch() {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_epoch(1920));
+        rb.push(make_segment(0));
+        rb.push(make_segment(1));
+        rb.begin_epoch(make_epoch(2560));
+        rb.push(make_segment(2));
+        assert_eq!(snapshot_shape(&rb), vec![(1920, vec![0, 1]), (2560, vec![2])]);
     }
 
     #[test]
-    fn codec_params_can_be_set() {
+    fn snapshot_after_returns_only_newer_segments() {
         let mut rb = RingBuffer::new(10);
-        rb.video_params = Some(VideoCodecParams {
-            extradata: vec![0x01, 0x02],
-            width: 1920,
-            height: 1080,
-            time_base: (1, 60),
-        });
-        rb.audio_params = Some(AudioCodecParams {
-            extradata: vec![0x03],
-            sample_rate: 48_000,
-            channels: 2,
-            time_base: (1, 48_000),
-        });
-        assert!(rb.video_params.is_some());
-        assert!(rb.audio_params.is_some());
+        rb.begin_epoch(make_epoch(1920));
+        rb.push(make_segment(0));
+        rb.push(make_segment(1));
+        let mut snapshot = rb.snapshot();
+        assert_eq!(snapshot.last_seq(), 2);
+        assert_eq!(snapshot.duration(), Duration::from_secs(2));
+
+        rb.push(make_segment(2));
+        rb.begin_epoch(make_epoch(2560));
+        rb.push(make_segment(3));
+        let later = rb.snapshot_after(snapshot.last_seq());
+        assert_eq!(later.segment_count(), 2);
+        assert_eq!(later.duration(), Duration::from_secs(2));
+
+        // Segments of the same epoch join the existing part.
+        snapshot.append(later);
+        assert_eq!(snapshot.last_seq(), 4);
+        let shape: Vec<(u32, usize)> = snapshot
+            .load()
+            .unwrap()
+            .iter()
+            .map(|part| (part.epoch.video_params.width, part.segments.len()))
+            .collect();
+        assert_eq!(shape, vec![(1920, 3), (2560, 1)]);
+    }
+
+    #[test]
+    fn appended_snapshot_keeps_segments_evicted_since() {
+        let mut rb = RingBuffer::new(MIN_BUFFER_LENGTH_SECS);
+        rb.begin_epoch(make_epoch(1920));
+        for i in 0..5 {
+            rb.push(make_segment(i));
+        }
+        let mut snapshot = rb.snapshot();
+        for i in 5..15 {
+            rb.push(make_segment(i));
+            snapshot.append(rb.snapshot_after(snapshot.last_seq()));
+        }
+        let parts = snapshot.load().unwrap();
+        let pts: Vec<i64> = parts[0].segments.iter().map(first_pts).collect();
+        assert_eq!(pts, (0..15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn snapshot_shares_segments_instead_of_copying() {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_epoch(1920));
+        rb.push(sized_segment(0, 1000));
+        let parts = rb.snapshot().load().unwrap();
+        let buffered = &rb.segments()[0];
+        assert!(Arc::ptr_eq(&parts[0].segments[0], buffered));
+        assert_eq!(Arc::strong_count(buffered), 3);
+    }
+
+    #[test]
+    fn snapshot_skips_segments_without_epoch() {
+        let mut rb = RingBuffer::new(10);
+        rb.push(make_segment(0));
+        rb.begin_epoch(make_epoch(1920));
+        rb.push(make_segment(1));
+        assert_eq!(snapshot_shape(&rb), vec![(1920, vec![1])]);
+    }
+
+    #[test]
+    fn eviction_retires_old_epochs() {
+        let mut rb = RingBuffer::new(MIN_BUFFER_LENGTH_SECS);
+        rb.begin_epoch(make_epoch(1280));
+        rb.push(make_segment(0));
+        rb.begin_epoch(make_epoch(1920));
+        for i in 1..=MIN_BUFFER_LENGTH_SECS as i64 {
+            rb.push(make_segment(i));
+        }
+        let shape = snapshot_shape(&rb);
+        assert_eq!(shape.len(), 1);
+        assert_eq!(shape[0].0, 1920);
+        assert_eq!(rb.runs.len(), 1);
+    }
+
+    #[test]
+    fn resize_retires_old_epochs() {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_epoch(1280));
+        for i in 0..4 {
+            rb.push(make_segment(i));
+        }
+        rb.begin_epoch(make_epoch(1920));
+        for i in 4..10 {
+            rb.push(make_segment(i));
+        }
+        rb.resize(MIN_BUFFER_LENGTH_SECS);
+        assert_eq!(snapshot_shape(&rb), vec![(1920, vec![5, 6, 7, 8, 9])]);
+    }
+
+    #[test]
+    fn empty_epoch_is_replaced_by_next() {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_epoch(1280));
+        rb.begin_epoch(make_epoch(1920));
+        rb.push(make_segment(0));
+        assert_eq!(rb.runs.len(), 1);
+        assert_eq!(snapshot_shape(&rb), vec![(1920, vec![0])]);
+    }
+
+    #[test]
+    fn clear_keeps_current_epoch() {
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(make_epoch(1280));
+        rb.push(make_segment(0));
+        rb.begin_epoch(make_epoch(1920));
+        rb.push(make_segment(1));
+        rb.clear();
+        assert_eq!(rb.current_epoch().unwrap().video_params.width, 1920);
+        rb.push(make_segment(2));
+        assert_eq!(snapshot_shape(&rb), vec![(1920, vec![2])]);
+    }
+
+    // ── duration-based eviction ───────────────────────────────────────────────
+
+    /// A GOP of `frames` frames at 1/`fps`, starting at frame `start`.
+    fn make_gop(start: i64, frames: i64) -> EncodedSegment {
+        EncodedSegment {
+            video_packets: (start..start + frames)
+                .map(|i| EncodedPacket {
+                    data: Arc::from([]),
+                    pts: i,
+                    dts: i,
+                    duration: 1,
+                    is_key: i == start,
+                })
+                .collect(),
+            audio_packets: vec![],
+            pushed_at: None,
+        }
+    }
+
+    #[test]
+    fn segment_started_at_is_push_time_less_duration() {
+        let pushed_at = "2026-01-01T12:00:01+00:00".parse::<DateTime<Local>>().unwrap();
+        let segment = EncodedSegment { pushed_at: Some(pushed_at), ..make_gop(0, 30) };
+        let started = "2026-01-01T12:00:00.500+00:00".parse::<DateTime<Local>>().unwrap();
+        assert_eq!(segment.started_at((1, 60)), Some(started));
+        assert_eq!(make_gop(0, 30).started_at((1, 60)), None);
+    }
+
+    #[test]
+    fn segment_duration_spans_video_packets() {
+        assert_eq!(make_gop(0, 60).duration((1, 60)), Duration::from_secs(1));
+        assert_eq!(make_gop(120, 30).duration((1, 60)), Duration::from_millis(500));
+        let empty =
+            EncodedSegment { video_packets: vec![], audio_packets: vec![], pushed_at: None };
+        assert_eq!(empty.duration((1, 60)), Duration::ZERO);
+    }
+
+    #[test]
+    fn two_second_gops_fill_capacity_by_duration() {
+        let mut epoch = make_epoch(1920);
+        epoch.video_params.time_base = (1, 30);
+        let mut rb = RingBuffer::new(10);
+        rb.begin_epoch(epoch);
+        for i in 0..12 {
+            rb.push(make_gop(i * 60, 60));
+        }
+        // Ten seconds of two-second GOPs is five segments, not ten.
+        assert_eq!(rb.segments().len(), 5);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 7 * 60);
+    }
+
+    #[test]
+    fn short_gops_keep_enough_to_cover_capacity() {
+        let mut epoch = make_epoch(1920);
+        epoch.video_params.time_base = (1, 60);
+        let mut rb = RingBuffer::new(MIN_BUFFER_LENGTH_SECS);
+        rb.begin_epoch(epoch);
+        for i in 0..40 {
+            rb.push(make_gop(i * 30, 30));
+        }
+        assert_eq!(rb.segments().len(), MIN_BUFFER_LENGTH_SECS as usize * 2);
+    }
+
+    // ── byte budget / stats ───────────────────────────────────────────────────
+
+    /// A one-second segment carrying `bytes` bytes of video.
+    fn sized_segment(id: i64, bytes: usize) -> EncodedSegment {
+        let mut segment = make_segment(id);
+        segment.video_packets[0].data = vec![0; bytes].into();
+        segment
+    }
+
+    #[test]
+    fn byte_budget_evicts_before_duration_capacity() {
+        let mut rb = RingBuffer::new(30);
+        rb.set_max_bytes(Some(1000));
+        rb.begin_epoch(make_epoch(1920));
+        for i in 0..10 {
+            rb.push(sized_segment(i, 300));
+        }
+        let stats = rb.stats();
+        assert_eq!(stats.segments, 3);
+        assert_eq!(stats.bytes, 900);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 7);
+    }
+
+    #[test]
+    fn byte_budget_keeps_newest_segment() {
+        let mut rb = RingBuffer::new(30);
+        rb.set_max_bytes(Some(100));
+        rb.push(sized_segment(0, 500));
+        rb.push(sized_segment(1, 500));
+        assert_eq!(rb.segments().len(), 1);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 1);
+    }
+
+    #[test]
+    fn set_max_bytes_evicts_immediately() {
+        let mut rb = RingBuffer::new(30);
+        for i in 0..5 {
+            rb.push(sized_segment(i, 100));
+        }
+        rb.set_max_bytes(Some(250));
+        assert_eq!(rb.stats().bytes, 200);
+        rb.set_max_bytes(None);
+        assert_eq!(rb.stats().max_bytes, None);
+    }
+
+    #[test]
+    fn stats_report_bytes_duration_and_bitrate() {
+        let mut rb = RingBuffer::new(10);
+        assert_eq!(rb.stats().avg_bitrate_bps, 0);
+        rb.begin_epoch(make_epoch(1920));
+        for i in 0..4 {
+            rb.push(sized_segment(i, 1000));
+        }
+        let stats = rb.stats();
+        assert_eq!(stats.bytes, 4000);
+        assert_eq!(stats.duration, Duration::from_secs(4));
+        assert_eq!(stats.avg_bitrate_bps, 8000);
+        assert_eq!(stats.capacity, Duration::from_secs(10));
+
+        rb.clear();
+        assert_eq!(rb.stats().bytes, 0);
+        assert_eq!(rb.stats().duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn stats_track_eviction() {
+        let mut rb = RingBuffer::new(MIN_BUFFER_LENGTH_SECS);
+        for i in 0..MIN_BUFFER_LENGTH_SECS as i64 + 3 {
+            rb.push(sized_segment(i, 10));
+        }
+        assert_eq!(rb.stats().bytes, u64::from(MIN_BUFFER_LENGTH_SECS) * 10);
+    }
+
+    // ── spill tier ────────────────────────────────────────────────────────────
+
+    /// A buffer longer than the memory window, spilling to a file in `dir`.
+    fn spilling_buffer(dir: &Path, capacity_secs: u32, file_bytes: u64) -> RingBuffer {
+        let mut rb = RingBuffer::new(capacity_secs);
+        rb.set_spill(Some((&dir.join("buffer.spill"), file_bytes))).unwrap();
+        rb.begin_epoch(make_epoch(1920));
+        rb
+    }
+
+    #[test]
+    fn segments_beyond_memory_window_spill_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        for i in 0..150 {
+            rb.push(sized_segment(i, 100));
+        }
+        let stats = rb.stats();
+        assert_eq!(stats.segments, 150);
+        assert_eq!(stats.bytes, 15_000);
+        assert_eq!(stats.disk_bytes, 30 * 100);
+        assert_eq!(rb.spilled, 30);
+
+        // Spilled segments read back transparently, in order.
+        let pts: Vec<i64> = rb.segments().iter().map(first_pts).collect();
+        assert_eq!(pts, (0..150).collect::<Vec<_>>());
+        assert_eq!(*rb.segments()[0].video_packets[0].data, [0; 100]);
+    }
+
+    #[test]
+    fn spilled_segments_still_honour_total_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        for i in 0..250 {
+            rb.push(make_segment(i));
+        }
+        assert_eq!(rb.segments().len(), 200);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 50);
+    }
+
+    #[test]
+    fn snapshot_loads_spilled_segments_after_unlock() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        for i in 0..130 {
+            rb.push(make_segment(i));
+        }
+        let snapshot = rb.snapshot();
+        assert_eq!(snapshot.segment_count(), 130);
+        let parts = snapshot.load().unwrap();
+        assert_eq!(parts[0].segments.len(), 130);
+        assert_eq!(first_pts(&parts[0].segments[0]), 0);
+        assert!(parts[0].segments[0].pushed_at.is_some(), "stamp survives the spill file");
+    }
+
+    #[test]
+    fn snapshot_outlives_the_next_session_spill_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        for i in 0..130 {
+            rb.push(make_segment(i));
+        }
+        let snapshot = rb.snapshot();
+        rb.clear();
+        rb.set_spill(Some((&dir.path().join("buffer.spill"), 1 << 20))).unwrap();
+        for i in 0..130 {
+            rb.push(make_segment(1000 + i));
+        }
+        let parts = snapshot.load().unwrap();
+        assert_eq!(first_pts(&parts[0].segments[0]), 0);
+    }
+
+    #[test]
+    fn small_spill_file_overwrites_oldest_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        // Each 1000-byte segment serializes to a little over 1000 bytes, so
+        // the file holds nine of them.
+        let mut rb = spilling_buffer(dir.path(), 300, 10_000);
+        for i in 0..150 {
+            rb.push(sized_segment(i, 1000));
+        }
+        assert_eq!(rb.spilled, 9);
+        assert_eq!(rb.segments().len(), 120 + 9);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 21);
+    }
+
+    #[test]
+    fn snapshot_skips_segments_overwritten_before_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 300, 10_000);
+        for i in 0..125 {
+            rb.push(sized_segment(i, 1000));
+        }
+        let snapshot = rb.snapshot();
+        for i in 125..127 {
+            rb.push(sized_segment(i, 1000));
+        }
+        let parts = snapshot.load().unwrap();
+        assert_eq!(parts[0].segments.len(), 125);
+        assert_eq!(first_pts(&parts[0].segments[0]), 0);
+
+        for i in 127..140 {
+            rb.push(sized_segment(i, 1000));
+        }
+        let snapshot = rb.snapshot();
+        rb.push(sized_segment(140, 1000));
+        let parts = snapshot.load().unwrap();
+        assert_eq!(first_pts(&parts[0].segments[0]), 12);
+    }
+
+    #[test]
+    fn byte_budget_spills_instead_of_dropping() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 30, 1 << 20);
+        rb.set_max_bytes(Some(1000));
+        for i in 0..10 {
+            rb.push(sized_segment(i, 300));
+        }
+        let stats = rb.stats();
+        assert_eq!(stats.segments, 10);
+        assert_eq!(stats.bytes - stats.disk_bytes, 900);
+    }
+
+    #[test]
+    fn clear_deletes_spill_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        let path = rb.spill.as_ref().unwrap().path().to_path_buf();
+        assert!(path.exists());
+        rb.clear();
+        assert!(!path.exists());
+        assert_eq!(rb.stats().disk_bytes, 0);
+    }
+
+    #[test]
+    fn disabling_spill_drops_spilled_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        let path = rb.spill.as_ref().unwrap().path().to_path_buf();
+        for i in 0..130 {
+            rb.push(make_segment(i));
+        }
+        rb.set_spill(None).unwrap();
+        assert_eq!(rb.segments().len(), 120);
+        assert_eq!(first_pts(rb.segments().first().unwrap()), 10);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn set_spill_with_same_settings_keeps_spilled_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("buffer.spill");
+        let mut rb = spilling_buffer(dir.path(), 200, 1 << 20);
+        for i in 0..130 {
+            rb.push(make_segment(i));
+        }
+        rb.set_spill(Some((&path, 1 << 20))).unwrap();
+        assert_eq!(rb.segments().len(), 130);
     }
 }
@@ -0,0 +1,262 @@
+/// Output-resolution scaling.
+///
+/// Captures arrive at the monitor's native size; `[global.video]` may ask for
+/// a smaller encoded size via `output_width` / `output_height` or a `scale`
+/// factor.  [`OutputScaling::plan`] resolves those settings against the
+/// capture size into a [`ScalePlan`]: which part of the input to sample and
+/// where it lands in the output.  The FFmpeg backends hand the plan to
+/// `sws_scale`; the software backend uses [`scale_bgra`].
+use crate::capture::RawFrame;
+use crate::config::{ScaleFit, VideoConfig};
+
+/// An axis-aligned rectangle in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    fn full(width: u32, height: u32) -> Self {
+        Self { x: 0, y: 0, width, height }
+    }
+}
+
+/// Requested output size, independent of the capture size.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OutputScaling {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub factor: Option<f64>,
+    pub fit: ScaleFit,
+}
+
+/// How one input frame size maps onto the encoded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScalePlan {
+    pub input: (u32, u32),
+    pub output: (u32, u32),
+    /// Region of the input that is scaled; smaller than the input when cropping.
+    pub src: Rect,
+    /// Region of the output the picture is scaled into; smaller than the
+    /// output when letterboxing.  The rest of the output is black.
+    pub dst: Rect,
+}
+
+impl OutputScaling {
+    pub fn from_video_config(video: &VideoConfig) -> Self {
+        Self {
+            width: video.output_width,
+            height: video.output_height,
+            factor: video.scale,
+            fit: video.fit,
+        }
+    }
+
+    /// Resolves the requested size against an `input` frame size.  Computed
+    /// sizes are rounded to even numbers, as 4:2:0 chroma requires.
+    pub fn plan(&self, input: (u32, u32)) -> ScalePlan {
+        let (iw, ih) = input;
+        if iw == 0 || ih == 0 {
+            return ScalePlan::identity(input);
+        }
+        let aspect = f64::from(iw) / f64::from(ih);
+        let output = match (self.width, self.height, self.factor) {
+            (_, _, Some(factor)) => (even(f64::from(iw) * factor), even(f64::from(ih) * factor)),
+            (Some(w), Some(h), None) => (even(f64::from(w)), even(f64::from(h))),
+            (Some(w), None, None) => (even(f64::from(w)), even(f64::from(w) / aspect)),
+            (None, Some(h), None) => (even(f64::from(h) * aspect), even(f64::from(h))),
+            (None, None, None) => return ScalePlan::identity(input),
+        };
+        let (ow, oh) = output;
+        let rx = f64::from(ow) / f64::from(iw);
+        let ry = f64::from(oh) / f64::from(ih);
+
+        let (src, dst) = match self.fit {
+            ScaleFit::Letterbox => {
+                let r = rx.min(ry);
+                let dw = even(f64::from(iw) * r).min(ow);
+                let dh = even(f64::from(ih) * r).min(oh);
+                let dst = Rect { x: ((ow - dw) / 2) & !1, y: ((oh - dh) / 2) & !1, width: dw, height: dh };
+                (Rect::full(iw, ih), dst)
+            }
+            ScaleFit::Crop => {
+                let r = rx.max(ry);
+                let sw = ((f64::from(ow) / r).round() as u32).clamp(1, iw);
+                let sh = ((f64::from(oh) / r).round() as u32).clamp(1, ih);
+                let src = Rect { x: (iw - sw) / 2, y: (ih - sh) / 2, width: sw, height: sh };
+                (src, Rect::full(ow, oh))
+            }
+        };
+        ScalePlan { input, output, src, dst }
+    }
+}
+
+impl ScalePlan {
+    /// A plan that passes frames through unchanged.
+    pub fn identity(input: (u32, u32)) -> Self {
+        let rect = Rect::full(input.0, input.1);
+        Self { input, output: input, src: rect, dst: rect }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::identity(self.input)
+    }
+
+    /// True when part of the output lies outside `dst` and must be painted black.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn has_borders(&self) -> bool {
+        self.dst != Rect::full(self.output.0, self.output.1)
+    }
+}
+
+/// Rounds to the nearest even number, with a minimum of 2.
+fn even(value: f64) -> u32 {
+    (((value / 2.0).round() as u32) * 2).max(2)
+}
+
+/// Applies `plan` to `frame` with nearest-neighbour sampling, returning a
+/// packed BGRA frame at the output size.  Used by the software backend; the
+/// FFmpeg backends scale with `sws_scale` instead.
+pub fn scale_bgra(frame: &RawFrame, plan: &ScalePlan) -> RawFrame {
+    let (ow, oh) = plan.output;
+    let mut bgra = [0u8, 0, 0, 255].repeat(ow as usize * oh as usize);
+    let (src, dst) = (plan.src, plan.dst);
+    for dy in 0..dst.height {
+        // Sample at pixel centres so downscaling by 2 picks every other pixel.
+        let sy = src.y + ((2 * dy + 1) * src.height / (2 * dst.height)).min(src.height - 1);
+        let row = frame.row(sy);
+        let out_row = ((dst.y + dy) * ow) as usize * 4;
+        for dx in 0..dst.width {
+            let sx = src.x + ((2 * dx + 1) * src.width / (2 * dst.width)).min(src.width - 1);
+            let i = sx as usize * 4;
+            let o = out_row + (dst.x + dx) as usize * 4;
+            bgra[o..o + 4].copy_from_slice(&row[i..i + 4]);
+        }
+    }
+    RawFrame::packed(bgra, ow, oh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(width: u32, height: u32) -> OutputScaling {
+        OutputScaling { width: Some(width), height: Some(height), ..OutputScaling::default() }
+    }
+
+    // ── plan ──────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn no_settings_is_identity() {
+        let plan = OutputScaling::default().plan((2560, 1440));
+        assert!(plan.is_identity());
+        assert_eq!(plan.output, (2560, 1440));
+    }
+
+    #[test]
+    fn factor_scales_both_axes() {
+        let scaling = OutputScaling { factor: Some(0.5), ..OutputScaling::default() };
+        let plan = scaling.plan((2560, 1440));
+        assert_eq!(plan.output, (1280, 720));
+        assert!(!plan.has_borders());
+    }
+
+    #[test]
+    fn single_dimension_keeps_capture_aspect() {
+        let scaling = OutputScaling { height: Some(720), ..OutputScaling::default() };
+        assert_eq!(scaling.plan((3440, 1440)).output, (1720, 720));
+        let scaling = OutputScaling { width: Some(1280), ..OutputScaling::default() };
+        assert_eq!(scaling.plan((3840, 2160)).output, (1280, 720));
+    }
+
+    #[test]
+    fn computed_sizes_are_even() {
+        let scaling = OutputScaling { factor: Some(0.3), ..OutputScaling::default() };
+        let (w, h) = scaling.plan((1366, 768)).output;
+        assert_eq!((w % 2, h % 2), (0, 0));
+    }
+
+    #[test]
+    fn matching_aspect_needs_no_borders_or_crop() {
+        let plan = size(1920, 1080).plan((3840, 2160));
+        assert_eq!(plan.src, Rect::full(3840, 2160));
+        assert_eq!(plan.dst, Rect::full(1920, 1080));
+    }
+
+    #[test]
+    fn letterbox_pads_ultrawide_capture() {
+        let plan = size(1920, 1080).plan((3440, 1440));
+        assert_eq!(plan.output, (1920, 1080));
+        assert_eq!(plan.src, Rect::full(3440, 1440));
+        assert_eq!(plan.dst, Rect { x: 0, y: 138, width: 1920, height: 804 });
+        assert!(plan.has_borders());
+    }
+
+    #[test]
+    fn letterbox_pillarboxes_narrow_capture() {
+        let plan = size(1920, 1080).plan((1024, 768));
+        assert_eq!(plan.dst, Rect { x: 240, y: 0, width: 1440, height: 1080 });
+    }
+
+    #[test]
+    fn crop_trims_ultrawide_capture() {
+        let scaling = OutputScaling { fit: ScaleFit::Crop, ..size(1920, 1080) };
+        let plan = scaling.plan((3440, 1440));
+        assert_eq!(plan.src, Rect { x: 440, y: 0, width: 2560, height: 1440 });
+        assert_eq!(plan.dst, Rect::full(1920, 1080));
+        assert!(!plan.has_borders());
+    }
+
+    #[test]
+    fn zero_input_is_identity() {
+        assert!(size(1280, 720).plan((0, 0)).is_identity());
+    }
+
+    // ── scale_bgra ────────────────────────────────────────────────────────────
+
+    /// A frame whose pixel (x, y) is (x, y, 0, 255).
+    fn gradient(width: u32, height: u32) -> RawFrame {
+        let mut bgra = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                bgra.extend_from_slice(&[x as u8, y as u8, 0, 255]);
+            }
+        }
+        RawFrame::packed(bgra, width, height)
+    }
+
+    fn pixel(frame: &RawFrame, x: u32, y: u32) -> [u8; 4] {
+        let i = x as usize * 4;
+        frame.row(y)[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn scale_bgra_halves_by_sampling_pixel_centres() {
+        let plan = OutputScaling { factor: Some(0.5), ..OutputScaling::default() }.plan((8, 4));
+        let out = scale_bgra(&gradient(8, 4), &plan);
+        assert_eq!((out.width, out.height), (4, 2));
+        assert_eq!(pixel(&out, 0, 0), [1, 1, 0, 255]);
+        assert_eq!(pixel(&out, 3, 1), [7, 3, 0, 255]);
+    }
+
+    #[test]
+    fn scale_bgra_paints_letterbox_black() {
+        let plan = size(8, 8).plan((8, 4));
+        let out = scale_bgra(&gradient(8, 4), &plan);
+        assert_eq!(plan.dst, Rect { x: 0, y: 2, width: 8, height: 4 });
+        assert_eq!(pixel(&out, 3, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&out, 3, 2), [3, 0, 0, 255]);
+        assert_eq!(pixel(&out, 3, 7), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn scale_bgra_crops_from_centre() {
+        let plan = OutputScaling { fit: ScaleFit::Crop, ..size(4, 4) }.plan((8, 4));
+        let out = scale_bgra(&gradient(8, 4), &plan);
+        assert_eq!(pixel(&out, 0, 0), [2, 0, 0, 255]);
+        assert_eq!(pixel(&out, 3, 3), [5, 3, 0, 255]);
+    }
+}
@@ -0,0 +1,155 @@
+/// JSON sidecar written next to every saved clip, so the GUI and external
+/// tools can index clips without probing the media files.
+///
+/// The sidecar of `2024-01-01_12-00-00.mp4` is `2024-01-01_12-00-00.mp4.json`.
+/// It records the game and recording session, when the footage was recorded,
+/// its format and encoder settings, any tags, the ring buffer's fill level
+/// when the clip was saved and the name of its thumbnail.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::flush;
+use crate::status::BufferStatus;
+
+/// Schema version, bumped when a field is removed or changes meaning.
+pub const SIDECAR_VERSION: u32 = 1;
+
+/// Contents of a clip's sidecar.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipSidecar {
+    pub version: u32,
+    /// Display name of the recorded application.
+    pub game: String,
+    /// Executable the application was detected by, e.g. `RocketLeague.exe`.
+    pub executable: String,
+    /// Shared by every clip cut from the same run of the game.
+    pub session_id: String,
+    /// RFC 3339 wall-clock time of the clip's first frame.
+    pub started_at: String,
+    /// RFC 3339 wall-clock time of the end of the clip's last frame.
+    pub ended_at: String,
+    /// Seconds of video in this file.
+    pub duration_secs: f64,
+    /// 1-based part number and number of parts, for a clip split at a
+    /// resolution change.
+    pub part: usize,
+    pub parts: usize,
+    pub video: VideoInfo,
+    /// Absent when no audio was captured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioInfo>,
+    /// Encoder backend that produced the footage, e.g. `nvenc`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoder: Option<String>,
+    /// Tags given with the save request.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Ring-buffer fill level when the clip was requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<BufferStatus>,
+    /// File name of the clip's PNG thumbnail, in the same directory; absent
+    /// when thumbnails are disabled or the frame could not be decoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoInfo {
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    /// Configured frame rate.
+    pub fps: u32,
+    /// `cbr`, `vbr` or `cqp`.
+    pub rate_control: String,
+    /// Target bitrate; absent with CQP rate control.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate_kbps: Option<u32>,
+    /// Quantiser; present only with CQP rate control.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qp: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub bitrate_kbps: u32,
+}
+
+/// Returns the sidecar path for `clip`: `<clip file name>.json`.
+pub fn sidecar_path(clip: &Path) -> PathBuf {
+    flush::sibling_path(clip, ".json")
+}
+
+/// Writes `sidecar` as pretty-printed JSON next to `clip`, atomically (see
+/// [`flush::write_atomically`]).
+pub fn write_sidecar(clip: &Path, sidecar: &ClipSidecar) -> Result<PathBuf> {
+    let path = sidecar_path(clip);
+    let json = serde_json::to_string_pretty(sidecar)?;
+    flush::write_atomically(&path, (json + "\n").as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ClipSidecar {
+        ClipSidecar {
+            version: SIDECAR_VERSION,
+            game: "Rocket League".into(),
+            executable: "RocketLeague.exe".into(),
+            session_id: "20240301T120000-42-1".into(),
+            started_at: "2024-03-01T11:59:30+00:00".into(),
+            ended_at: "2024-03-01T12:00:00+00:00".into(),
+            duration_secs: 30.0,
+            part: 1,
+            parts: 1,
+            video: VideoInfo {
+                codec: "h264".into(),
+                width: 1920,
+                height: 1080,
+                fps: 60,
+                rate_control: "vbr".into(),
+                bitrate_kbps: Some(8000),
+                qp: None,
+            },
+            audio: None,
+            encoder: Some("nvenc".into()),
+            tags: vec!["ace".into()],
+            buffer: None,
+            thumbnail: Some("2024-03-01_12-00-00.mp4.png".into()),
+        }
+    }
+
+    #[test]
+    fn sidecar_path_appends_to_clip_name() {
+        let clip = Path::new("clips").join("Game").join("2024-03-01_12-00-00.mp4");
+        assert_eq!(
+            sidecar_path(&clip),
+            Path::new("clips").join("Game").join("2024-03-01_12-00-00.mp4.json")
+        );
+    }
+
+    #[test]
+    fn write_sidecar_round_trips_and_leaves_no_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let clip = dir.path().join("clip.mp4");
+        let path = write_sidecar(&clip, &sample()).unwrap();
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(json.contains(r#""game": "Rocket League""#), "{json}");
+        assert!(!json.contains("audio"), "absent fields are omitted: {json}");
+        let parsed: ClipSidecar = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, sample());
+
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["clip.mp4.json"]);
+    }
+}
@@ -0,0 +1,740 @@
+/// FFmpeg-free "software" encoder backend.
+///
+/// Video is written as H.264 Baseline where every macroblock is `I_PCM`, i.e.
+/// raw 4:2:0 samples wrapped in a standard bitstream.  That needs no motion
+/// search, transforms or entropy coding, so it is exact, deterministic and
+/// decodable by any H.264 decoder — at the cost of a very high bitrate
+/// (12 bits per pixel).  Audio is emitted as silent AAC-LC frames that keep
+/// the audio track's timing intact.
+///
+/// This backend exists so the capture → encode → ring buffer → flush path can
+/// run on machines without FFmpeg (headless Linux CI in particular).  It is not
+/// meant for recording real gameplay.
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+
+use crate::audio_capture::RawAudio;
+use crate::capture::RawFrame;
+use crate::config::EncoderKind;
+use crate::encoder::{EncoderBackend, EncoderConfig};
+use crate::mp4;
+use crate::ring_buffer::{AudioCodecParams, EncodedPacket, VideoCodecParams};
+use crate::scale::{self, ScalePlan};
+
+/// `mb_type` value for `I_PCM` in an I slice.
+const MB_TYPE_I_PCM: u32 = 25;
+/// `frame_num` is coded with this many bits (`log2_max_frame_num`).
+const LOG2_MAX_FRAME_NUM: u32 = 16;
+/// Samples per AAC-LC frame.
+const AAC_FRAME_SAMPLES: usize = 1024;
+
+// ── Bitstream helpers ─────────────────────────────────────────────────────────
+
+/// MSB-first bit writer with Exp-Golomb support.
+struct BitWriter {
+    bytes: Vec<u8>,
+    cur: u8,
+    nbits: u8,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: vec![], cur: 0, nbits: 0 }
+    }
+
+    fn put_bit(&mut self, bit: bool) {
+        self.cur = (self.cur << 1) | u8::from(bit);
+        self.nbits += 1;
+        if self.nbits == 8 {
+            self.bytes.push(self.cur);
+            self.cur = 0;
+            self.nbits = 0;
+        }
+    }
+
+    fn put_bits(&mut self, value: u32, n: u32) {
+        for i in (0..n).rev() {
+            self.put_bit((value >> i) & 1 != 0);
+        }
+    }
+
+    /// Unsigned Exp-Golomb, `ue(v)`.
+    fn put_ue(&mut self, value: u32) {
+        let v = u64::from(value) + 1;
+        let len = 64 - v.leading_zeros();
+        self.put_bits(0, len - 1);
+        for i in (0..len).rev() {
+            self.put_bit((v >> i) & 1 != 0);
+        }
+    }
+
+    /// Signed Exp-Golomb, `se(v)`.
+    fn put_se(&mut self, value: i32) {
+        let mapped = if value <= 0 { (-2 * i64::from(value)) as u32 } else { (2 * value - 1) as u32 };
+        self.put_ue(mapped);
+    }
+
+    fn align_zero(&mut self) {
+        while self.nbits != 0 {
+            self.put_bit(false);
+        }
+    }
+
+    /// Appends whole bytes; the writer must be byte-aligned.
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        debug_assert_eq!(self.nbits, 0);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// `rbsp_trailing_bits()`: a stop bit followed by zero alignment.
+    fn finish_rbsp(mut self) -> Vec<u8> {
+        self.put_bit(true);
+        self.align_zero();
+        self.bytes
+    }
+
+    fn finish_aligned(mut self) -> Vec<u8> {
+        self.align_zero();
+        self.bytes
+    }
+}
+
+/// Wraps an RBSP in an Annex B NAL unit, inserting emulation-prevention bytes.
+fn annex_b_nal(header: u8, rbsp: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rbsp.len() + rbsp.len() / 64 + 5);
+    out.extend_from_slice(&[0, 0, 0, 1, header]);
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b <= 3 {
+            out.push(3);
+            zeros = 0;
+        }
+        out.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+    out
+}
+
+// ── H.264 I_PCM video ─────────────────────────────────────────────────────────
+
+fn mb_dims(width: u32, height: u32) -> (u32, u32) {
+    (width.div_ceil(16), height.div_ceil(16))
+}
+
+/// Sequence parameter set (Baseline, level 5.2, POC type 2).
+fn build_sps(width: u32, height: u32) -> Vec<u8> {
+    let (mbw, mbh) = mb_dims(width, height);
+    let mut w = BitWriter::new();
+    w.put_bits(66, 8); // profile_idc: Baseline
+    w.put_bits(0b1100_0000, 8); // constraint_set0 + constraint_set1
+    w.put_bits(52, 8); // level_idc
+    w.put_ue(0); // seq_parameter_set_id
+    w.put_ue(LOG2_MAX_FRAME_NUM - 4);
+    w.put_ue(2); // pic_order_cnt_type
+    w.put_ue(1); // max_num_ref_frames
+    w.put_bit(false); // gaps_in_frame_num_value_allowed_flag
+    w.put_ue(mbw - 1);
+    w.put_ue(mbh - 1);
+    w.put_bit(true); // frame_mbs_only_flag
+    w.put_bit(true); // direct_8x8_inference_flag
+    // Crop the macroblock padding; offsets are in 2-pixel units for 4:2:0.
+    let crop_right = (mbw * 16 - width) / 2;
+    let crop_bottom = (mbh * 16 - height) / 2;
+    if crop_right > 0 || crop_bottom > 0 {
+        w.put_bit(true);
+        w.put_ue(0);
+        w.put_ue(crop_right);
+        w.put_ue(0);
+        w.put_ue(crop_bottom);
+    } else {
+        w.put_bit(false);
+    }
+    w.put_bit(false); // vui_parameters_present_flag
+    annex_b_nal(0x67, &w.finish_rbsp())
+}
+
+/// Picture parameter set (CAVLC, no deblocking control).
+fn build_pps() -> Vec<u8> {
+    let mut w = BitWriter::new();
+    w.put_ue(0); // pic_parameter_set_id
+    w.put_ue(0); // seq_parameter_set_id
+    w.put_bit(false); // entropy_coding_mode_flag: CAVLC
+    w.put_bit(false); // bottom_field_pic_order_in_frame_present_flag
+    w.put_ue(0); // num_slice_groups_minus1
+    w.put_ue(0); // num_ref_idx_l0_default_active_minus1
+    w.put_ue(0); // num_ref_idx_l1_default_active_minus1
+    w.put_bit(false); // weighted_pred_flag
+    w.put_bits(0, 2); // weighted_bipred_idc
+    w.put_se(0); // pic_init_qp_minus26
+    w.put_se(0); // pic_init_qs_minus26
+    w.put_se(0); // chroma_qp_index_offset
+    w.put_bit(false); // deblocking_filter_control_present_flag
+    w.put_bit(false); // constrained_intra_pred_flag
+    w.put_bit(false); // redundant_pic_cnt_present_flag
+    annex_b_nal(0x68, &w.finish_rbsp())
+}
+
+/// Converts a BGRA frame into BT.601 limited-range 4:2:0 planes padded to
+/// whole macroblocks (edge pixels are replicated into the padding).
+fn bgra_to_yuv420(frame: &RawFrame) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let (mbw, mbh) = mb_dims(frame.width, frame.height);
+    let (pw, ph) = (mbw as usize * 16, mbh as usize * 16);
+    let px = |x: usize, y: usize| {
+        let row = frame.row(y.min(h - 1) as u32);
+        let i = x.min(w - 1) * 4;
+        (i32::from(row[i + 2]), i32::from(row[i + 1]), i32::from(row[i]))
+    };
+
+    let mut luma = Vec::with_capacity(pw * ph);
+    for y in 0..ph {
+        for x in 0..pw {
+            let (r, g, b) = px(x, y);
+            luma.push((((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8);
+        }
+    }
+
+    let mut cb = Vec::with_capacity(pw * ph / 4);
+    let mut cr = Vec::with_capacity(pw * ph / 4);
+    for y in (0..ph).step_by(2) {
+        for x in (0..pw).step_by(2) {
+            let quad = [px(x, y), px(x + 1, y), px(x, y + 1), px(x + 1, y + 1)];
+            let (r, g, b) = quad
+                .iter()
+                .fold((0, 0, 0), |acc, p| (acc.0 + p.0, acc.1 + p.1, acc.2 + p.2));
+            let (r, g, b) = ((r + 2) / 4, (g + 2) / 4, (b + 2) / 4);
+            cb.push((((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8);
+            cr.push((((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8);
+        }
+    }
+    (luma, cb, cr)
+}
+
+/// Encodes one picture as a single I slice of `I_PCM` macroblocks.
+fn build_pcm_slice(frame: &RawFrame, is_idr: bool, frame_num: u32, idr_pic_id: u32) -> Vec<u8> {
+    let (mbw, mbh) = mb_dims(frame.width, frame.height);
+    let (luma, cb, cr) = bgra_to_yuv420(frame);
+    let luma_stride = mbw as usize * 16;
+    let chroma_stride = mbw as usize * 8;
+
+    let mut w = BitWriter::new();
+    w.bytes.reserve(luma.len() + cb.len() + cr.len() + (mbw * mbh) as usize * 2);
+    w.put_ue(0); // first_mb_in_slice
+    w.put_ue(7); // slice_type: I (all slices in the picture)
+    w.put_ue(0); // pic_parameter_set_id
+    w.put_bits(frame_num, LOG2_MAX_FRAME_NUM);
+    if is_idr {
+        w.put_ue(idr_pic_id);
+        w.put_bit(false); // no_output_of_prior_pics_flag
+        w.put_bit(false); // long_term_reference_flag
+    } else {
+        w.put_bit(false); // adaptive_ref_pic_marking_mode_flag
+    }
+    w.put_se(0); // slice_qp_delta
+
+    for mby in 0..mbh as usize {
+        for mbx in 0..mbw as usize {
+            w.put_ue(MB_TYPE_I_PCM);
+            w.align_zero(); // pcm_alignment_zero_bit
+            for row in 0..16 {
+                let start = (mby * 16 + row) * luma_stride + mbx * 16;
+                w.put_bytes(&luma[start..start + 16]);
+            }
+            for plane in [&cb, &cr] {
+                for row in 0..8 {
+                    let start = (mby * 8 + row) * chroma_stride + mbx * 8;
+                    w.put_bytes(&plane[start..start + 8]);
+                }
+            }
+        }
+    }
+
+    let header = if is_idr { 0x65 } else { 0x61 };
+    annex_b_nal(header, &w.finish_rbsp())
+}
+
+// ── I_PCM decoding ────────────────────────────────────────────────────────────
+
+/// MSB-first bit reader over an RBSP, the counterpart of [`BitWriter`].
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Result<bool> {
+        let byte = *self.data.get(self.pos / 8).context("Slice data is truncated")?;
+        self.pos += 1;
+        Ok((byte >> (7 - (self.pos - 1) % 8)) & 1 != 0)
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32> {
+        (0..n).try_fold(0, |acc, _| Ok((acc << 1) | u32::from(self.bit()?)))
+    }
+
+    fn ue(&mut self) -> Result<u32> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+            if zeros > 31 {
+                bail!("Invalid Exp-Golomb code");
+            }
+        }
+        Ok(((1u64 << zeros) - 1 + u64::from(self.bits(zeros)?)) as u32)
+    }
+
+    fn se(&mut self) -> Result<i32> {
+        let k = self.ue()?;
+        Ok(if k % 2 == 1 { (k / 2 + 1) as i32 } else { -((k / 2) as i32) })
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let start = self.pos / 8;
+        let bytes = self.data.get(start..start + n).context("Slice data is truncated")?;
+        self.pos += n * 8;
+        Ok(bytes)
+    }
+}
+
+/// Strips emulation-prevention bytes from a NAL unit payload, undoing
+/// [`annex_b_nal`].
+fn nal_to_rbsp(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len());
+    let mut zeros = 0;
+    for &b in payload {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        out.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+    out
+}
+
+/// Converts BT.601 limited-range 4:2:0 planes with the given luma stride
+/// back to packed BGRA, dropping any macroblock padding.
+fn yuv420_to_bgra(
+    (luma, cb, cr): (&[u8], &[u8], &[u8]),
+    stride: usize,
+    width: u32,
+    height: u32,
+) -> RawFrame {
+    let clamp = |v: i32| (v >> 8).clamp(0, 255) as u8;
+    let mut bgra = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let c = 298 * (i32::from(luma[y * stride + x]) - 16);
+            let chroma = (y / 2) * (stride / 2) + x / 2;
+            let (d, e) = (i32::from(cb[chroma]) - 128, i32::from(cr[chroma]) - 128);
+            bgra.extend_from_slice(&[
+                clamp(c + 516 * d + 128),
+                clamp(c - 100 * d - 208 * e + 128),
+                clamp(c + 409 * e + 128),
+                255,
+            ]);
+        }
+    }
+    RawFrame::packed(bgra, width, height)
+}
+
+/// Decodes a keyframe produced by this backend into a BGRA frame of the
+/// size in `params`.  Only `I_PCM` IDR pictures are understood, which lets
+/// clips recorded with this backend get thumbnails without FFmpeg.
+#[cfg_attr(windows, allow(dead_code))]
+pub fn decode_pcm_picture(params: &VideoCodecParams, data: &[u8]) -> Result<RawFrame> {
+    let slice = mp4::annex_b_nal_units(data)
+        .into_iter()
+        .find(|nal| nal[0] & 0x1F == 5)
+        .context("Keyframe has no IDR slice")?;
+    let rbsp = nal_to_rbsp(&slice[1..]);
+    let mut r = BitReader::new(&rbsp);
+    r.ue()?; // first_mb_in_slice
+    r.ue()?; // slice_type
+    r.ue()?; // pic_parameter_set_id
+    r.bits(LOG2_MAX_FRAME_NUM)?; // frame_num
+    r.ue()?; // idr_pic_id
+    r.bits(2)?; // no_output_of_prior_pics_flag, long_term_reference_flag
+    r.se()?; // slice_qp_delta
+
+    let (mbw, mbh) = mb_dims(params.width, params.height);
+    let luma_stride = mbw as usize * 16;
+    let chroma_stride = mbw as usize * 8;
+    let mut luma = vec![0; luma_stride * mbh as usize * 16];
+    let mut cb = vec![0; chroma_stride * mbh as usize * 8];
+    let mut cr = vec![0; cb.len()];
+    for mby in 0..mbh as usize {
+        for mbx in 0..mbw as usize {
+            if r.ue()? != MB_TYPE_I_PCM {
+                bail!("Only I_PCM pictures can be decoded without FFmpeg");
+            }
+            r.align(); // pcm_alignment_zero_bit
+            for row in 0..16 {
+                let start = (mby * 16 + row) * luma_stride + mbx * 16;
+                luma[start..start + 16].copy_from_slice(r.bytes(16)?);
+            }
+            for plane in [&mut cb, &mut cr] {
+                for row in 0..8 {
+                    let start = (mby * 8 + row) * chroma_stride + mbx * 8;
+                    plane[start..start + 8].copy_from_slice(r.bytes(8)?);
+                }
+            }
+        }
+    }
+    Ok(yuv420_to_bgra((&luma, &cb, &cr), luma_stride, params.width, params.height))
+}
+
+// ── AAC silence ───────────────────────────────────────────────────────────────
+
+/// `ics_info()` for a long window with no scale-factor bands.
+fn put_ics_info(w: &mut BitWriter) {
+    w.put_bit(false); // ics_reserved_bit
+    w.put_bits(0, 2); // window_sequence: ONLY_LONG_SEQUENCE
+    w.put_bit(true); // window_shape: KBD
+    w.put_bits(0, 6); // max_sfb
+    w.put_bit(false); // predictor_data_present
+}
+
+/// `individual_channel_stream()` with no spectral data.
+fn put_silent_ics(w: &mut BitWriter, with_ics_info: bool) {
+    w.put_bits(140, 8); // global_gain
+    if with_ics_info {
+        put_ics_info(w);
+    }
+    w.put_bit(false); // pulse_data_present
+    w.put_bit(false); // tns_data_present
+    w.put_bit(false); // gain_control_data_present
+}
+
+/// Returns one raw AAC-LC frame of digital silence for 1 or 2 channels.
+fn silent_aac_frame(channels: u16) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    if channels == 1 {
+        w.put_bits(0, 3); // ID_SCE
+        w.put_bits(0, 4); // element_instance_tag
+        put_silent_ics(&mut w, true);
+    } else {
+        w.put_bits(1, 3); // ID_CPE
+        w.put_bits(0, 4); // element_instance_tag
+        w.put_bit(true); // common_window
+        put_ics_info(&mut w);
+        w.put_bits(0, 2); // ms_mask_present
+        put_silent_ics(&mut w, false);
+        put_silent_ics(&mut w, false);
+    }
+    w.put_bits(7, 3); // ID_END
+    w.finish_aligned()
+}
+
+// ── Backend ───────────────────────────────────────────────────────────────────
+
+/// Pure-Rust [`EncoderBackend`]: `I_PCM` H.264 video and silent AAC audio.
+pub struct SoftwareBackend {
+    config: EncoderConfig,
+    scale_plan: ScalePlan,
+    video_params: VideoCodecParams,
+    audio_params: AudioCodecParams,
+    frame_count: u64,
+    idr_count: u32,
+    /// Shared by every emitted audio packet.
+    silent_frame: Arc<[u8]>,
+    /// Interleaved samples received but not yet covered by an emitted frame.
+    pending_samples: usize,
+    audio_pts: i64,
+}
+
+impl SoftwareBackend {
+    pub fn open(config: &EncoderConfig) -> Result<Self> {
+        if config.width == 0 || config.height == 0 || config.fps == 0 {
+            bail!("Invalid encoder dimensions {}x{} @ {} fps", config.width, config.height, config.fps);
+        }
+        if config.gop_length == 0 {
+            bail!("GOP length must be at least one frame");
+        }
+        if !(1..=2).contains(&config.channels) {
+            bail!("Software backend supports mono or stereo audio, not {} channels", config.channels);
+        }
+        let scale_plan = config.scale_plan();
+        let (width, height) = scale_plan.output;
+        let mut extradata = build_sps(width, height);
+        extradata.extend_from_slice(&build_pps());
+        Ok(Self {
+            config: config.clone(),
+            scale_plan,
+            video_params: VideoCodecParams {
+                extradata,
+                width,
+                height,
+                time_base: (1, config.fps as i32),
+            },
+            audio_params: AudioCodecParams {
+                extradata: mp4::build_audio_specific_config(
+                    config.sample_rate,
+                    u32::from(config.channels),
+                )?,
+                sample_rate: config.sample_rate,
+                channels: u32::from(config.channels),
+                time_base: (1, config.sample_rate as i32),
+            },
+            frame_count: 0,
+            idr_count: 0,
+            silent_frame: silent_aac_frame(config.channels).into(),
+            pending_samples: 0,
+            audio_pts: 0,
+        })
+    }
+}
+
+impl EncoderBackend for SoftwareBackend {
+    fn kind(&self) -> EncoderKind {
+        EncoderKind::Software
+    }
+
+    fn video_params(&self) -> &VideoCodecParams {
+        &self.video_params
+    }
+
+    fn audio_params(&self) -> &AudioCodecParams {
+        &self.audio_params
+    }
+
+    fn encode_video(&mut self, frame: &RawFrame, out: &mut Vec<EncodedPacket>) -> Result<()> {
+        if (frame.width, frame.height) != (self.config.width, self.config.height) {
+            bail!(
+                "Frame is {}x{} but the encoder was opened for {}x{}",
+                frame.width,
+                frame.height,
+                self.config.width,
+                self.config.height
+            );
+        }
+        frame.validate()?;
+
+        let gop_pos = self.frame_count % u64::from(self.config.gop_length);
+        let is_idr = gop_pos == 0;
+        let frame_num = (gop_pos % (1 << LOG2_MAX_FRAME_NUM)) as u32;
+        let data = if self.scale_plan.is_identity() {
+            build_pcm_slice(frame, is_idr, frame_num, self.idr_count)
+        } else {
+            let scaled = scale::scale_bgra(frame, &self.scale_plan);
+            build_pcm_slice(&scaled, is_idr, frame_num, self.idr_count)
+        };
+        if is_idr {
+            self.idr_count = (self.idr_count + 1) % 65_536;
+        }
+
+        let ts = self.frame_count as i64;
+        out.push(EncodedPacket { data: data.into(), pts: ts, dts: ts, duration: 1, is_key: is_idr });
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    fn encode_audio(&mut self, audio: &RawAudio, out: &mut Vec<EncodedPacket>) -> Result<()> {
+        self.pending_samples += audio.samples_f32.len();
+        let per_frame = AAC_FRAME_SAMPLES * self.config.channels as usize;
+        while self.pending_samples >= per_frame {
+            self.pending_samples -= per_frame;
+            out.push(EncodedPacket {
+                data: Arc::clone(&self.silent_frame),
+                pts: self.audio_pts,
+                dts: self.audio_pts,
+                duration: AAC_FRAME_SAMPLES as i64,
+                is_key: true,
+            });
+            self.audio_pts += AAC_FRAME_SAMPLES as i64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, _video: &mut Vec<EncodedPacket>, _audio: &mut Vec<EncodedPacket>) -> Result<()> {
+        // Nothing is buffered inside the encoder; a partial AAC frame is dropped.
+        self.pending_samples = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_cfg() -> EncoderConfig {
+        EncoderConfig { width: 40, height: 24, fps: 10, gop_length: 10, ..EncoderConfig::default() }
+    }
+
+    fn frame(cfg: &EncoderConfig) -> RawFrame {
+        RawFrame::packed(vec![0x80; (cfg.width * cfg.height * 4) as usize], cfg.width, cfg.height)
+    }
+
+    #[test]
+    fn exp_golomb_codes() {
+        let mut w = BitWriter::new();
+        w.put_ue(0); // 1
+        w.put_ue(1); // 010
+        w.put_ue(4); // 00101
+        w.put_se(-1); // ue(2) = 011
+        assert_eq!(w.finish_aligned(), vec![0b1010_0010, 0b1011_0000]);
+    }
+
+    #[test]
+    fn emulation_prevention_is_inserted() {
+        let nal = annex_b_nal(0x65, &[0, 0, 1, 0, 0, 0]);
+        assert_eq!(nal, vec![0, 0, 0, 1, 0x65, 0, 0, 3, 1, 0, 0, 3, 0]);
+    }
+
+    #[test]
+    fn sps_crops_non_macroblock_sizes() {
+        // 1080 is not a multiple of 16, so frame_cropping_flag must be set.
+        let cropped = build_sps(1920, 1080);
+        let exact = build_sps(1920, 1088);
+        assert_ne!(cropped, exact);
+        assert_eq!(&cropped[..8], &[0, 0, 0, 1, 0x67, 66, 0xC0, 52]);
+    }
+
+    #[test]
+    fn stereo_silence_matches_reference_frame() {
+        assert_eq!(silent_aac_frame(2), vec![0x21, 0x10, 0x04, 0x60, 0x8C, 0x1C]);
+    }
+
+    #[test]
+    fn yuv_conversion_of_white_and_black() {
+        let white = bgra_to_yuv420(&RawFrame::packed(vec![255, 255, 255, 255], 1, 1));
+        assert_eq!((white.0[0], white.1[0], white.2[0]), (235, 128, 128));
+        let black = bgra_to_yuv420(&RawFrame::packed(vec![0, 0, 0, 255], 1, 1));
+        assert_eq!((black.0[0], black.1[0], black.2[0]), (16, 128, 128));
+    }
+
+    #[test]
+    fn yuv_conversion_ignores_stride_padding() {
+        // 1×2 frame whose rows are padded with red pixels that must not be read.
+        let mut data = vec![255, 255, 255, 255, 0, 0, 255, 255];
+        data.extend_from_slice(&[255, 255, 255, 255, 0, 0, 255, 255]);
+        let padded = RawFrame { bgra_data: data, width: 1, height: 2, stride: 8 };
+        let (luma, _, cr) = bgra_to_yuv420(&padded);
+        assert!(luma.iter().all(|&y| y == 235));
+        assert_eq!(cr[0], 128);
+    }
+
+    #[test]
+    fn keyframe_once_per_second() {
+        let cfg = small_cfg();
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut packets = vec![];
+        for _ in 0..25 {
+            backend.encode_video(&frame(&cfg), &mut packets).unwrap();
+        }
+        let keys: Vec<i64> = packets.iter().filter(|p| p.is_key).map(|p| p.pts).collect();
+        assert_eq!(keys, vec![0, 10, 20]);
+        assert!(packets[0].data.starts_with(&[0, 0, 0, 1, 0x65]));
+        assert!(packets[1].data.starts_with(&[0, 0, 0, 1, 0x61]));
+    }
+
+    #[test]
+    fn keyframe_interval_follows_gop_length() {
+        let cfg = EncoderConfig { gop_length: 4, ..small_cfg() };
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut packets = vec![];
+        for _ in 0..9 {
+            backend.encode_video(&frame(&cfg), &mut packets).unwrap();
+        }
+        let keys: Vec<i64> = packets.iter().filter(|p| p.is_key).map(|p| p.pts).collect();
+        assert_eq!(keys, vec![0, 4, 8]);
+    }
+
+    #[test]
+    fn scaled_output_reports_output_size() {
+        let output = crate::scale::OutputScaling { factor: Some(0.5), ..Default::default() };
+        let cfg = EncoderConfig { output, ..small_cfg() };
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        assert_eq!((backend.video_params().width, backend.video_params().height), (20, 12));
+
+        let mut packets = vec![];
+        backend.encode_video(&frame(&cfg), &mut packets).unwrap();
+        let full = {
+            let mut b = SoftwareBackend::open(&small_cfg()).unwrap();
+            let mut p = vec![];
+            b.encode_video(&frame(&small_cfg()), &mut p).unwrap();
+            p.remove(0).data.len()
+        };
+        assert!(packets[0].data.len() < full / 2, "slice should cover the scaled picture");
+    }
+
+    #[test]
+    fn wrong_frame_size_is_rejected() {
+        let mut backend = SoftwareBackend::open(&small_cfg()).unwrap();
+        let bad = RawFrame::packed(vec![0; 16], 2, 2);
+        assert!(backend.encode_video(&bad, &mut vec![]).is_err());
+        let short = RawFrame::packed(vec![0; 16], 40, 24);
+        assert!(backend.encode_video(&short, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn audio_emits_one_packet_per_1024_samples() {
+        let cfg = small_cfg();
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut packets = vec![];
+        let chunk = RawAudio { samples_f32: vec![0.0; 1500 * 2] };
+        backend.encode_audio(&chunk, &mut packets).unwrap();
+        backend.encode_audio(&chunk, &mut packets).unwrap();
+        let pts: Vec<i64> = packets.iter().map(|p| p.pts).collect();
+        assert_eq!(pts, vec![0, 1024]);
+    }
+
+    #[test]
+    fn output_muxes_to_mp4() {
+        let cfg = small_cfg();
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut video = vec![];
+        let mut audio = vec![];
+        for _ in 0..cfg.fps {
+            backend.encode_video(&frame(&cfg), &mut video).unwrap();
+        }
+        backend.encode_audio(&RawAudio { samples_f32: vec![0.0; 4800 * 2] }, &mut audio).unwrap();
+        let segment = crate::ring_buffer::EncodedSegment {
+            video_packets: video,
+            audio_packets: audio,
+            pushed_at: None,
+        };
+        let mut out = vec![];
+        let (video, audio) = (backend.video_params(), backend.audio_params());
+        let metadata = crate::metadata::ClipMetadata::default();
+        mp4::write_mp4(&mut out, &[Arc::new(segment)], video, audio, &metadata).unwrap();
+        assert_eq!(&out[4..8], b"ftyp");
+    }
+
+    #[test]
+    fn keyframe_decodes_back_to_source_colours() {
+        // 40×24 is not a multiple of 16, so the padding must be cropped away.
+        let cfg = small_cfg();
+        let mut bgra = vec![];
+        for y in 0..cfg.height {
+            for _ in 0..cfg.width {
+                let pixel = if y < 8 { [0, 0, 255, 255] } else { [200, 100, 50, 255] };
+                bgra.extend_from_slice(&pixel);
+            }
+        }
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut packets = vec![];
+        let frame = RawFrame::packed(bgra.clone(), cfg.width, cfg.height);
+        backend.encode_video(&frame, &mut packets).unwrap();
+
+        let decoded = decode_pcm_picture(backend.video_params(), &packets[0].data).unwrap();
+        assert_eq!((decoded.width, decoded.height), (cfg.width, cfg.height));
+        for (got, want) in decoded.bgra_data.iter().zip(&bgra) {
+            assert!(got.abs_diff(*want) <= 3, "{got} vs {want}");
+        }
+    }
+
+    #[test]
+    fn emulation_prevention_is_removed() {
+        let nal = annex_b_nal(0x65, &[0, 0, 1, 0, 0, 0]);
+        assert_eq!(nal_to_rbsp(&nal[5..]), vec![0, 0, 1, 0, 0, 0]);
+    }
+}
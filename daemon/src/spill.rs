@@ -0,0 +1,487 @@
+/// Disk spill tier for the ring buffer.
+///
+/// Buffers longer than [`crate::config::MAX_MEMORY_BUFFER_SECS`] keep only
+/// their newest segments in RAM; older ones are serialized into a single
+/// preallocated file that is written as a circular log.  When the write
+/// position reaches the end of the file it wraps to the start, overwriting the
+/// oldest records.
+///
+/// Every record carries a sequence number.  Before a region is overwritten the
+/// file's shared "overwritten through" watermark is raised past every record in
+/// it, so a [`SpillReader`] holding a snapshot can read records without the
+/// ring-buffer lock and detect (and skip) ones that were recycled mid-read.
+///
+/// Each spill file gets a fresh numbered name next to the configured location
+/// (`buffer.spill` becomes `buffer.1.spill`, `buffer.2.spill`, …).  A flush
+/// job may still be reading the previous file when the buffer is cleared or
+/// resized, and Windows can keep a deleted name reserved until the job's
+/// handle is closed, so the replacement must not reuse it.
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::ring_buffer::{EncodedPacket, EncodedSegment};
+
+/// Location of one serialized segment in the spill file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpillEntry {
+    /// Monotonic record number, starting at 1.
+    pub seq: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Numbers the spill files created by this process.
+static NEXT_FILE_NUMBER: AtomicU64 = AtomicU64::new(1);
+
+/// The writer side of the spill file.  Owned by the ring buffer; the file is
+/// deleted when this is dropped.
+pub struct SpillFile {
+    /// The configured location the file was numbered from.
+    location: PathBuf,
+    path: PathBuf,
+    file: Arc<File>,
+    capacity: u64,
+    write_pos: u64,
+    next_seq: u64,
+    /// Every record still physically intact on disk, oldest first.
+    records: VecDeque<SpillEntry>,
+    /// Highest sequence number whose bytes may have been overwritten.
+    overwritten: Arc<AtomicU64>,
+}
+
+/// A read handle that stays valid after the ring-buffer lock is released.
+#[derive(Clone)]
+pub struct SpillReader {
+    file: Arc<File>,
+    overwritten: Arc<AtomicU64>,
+}
+
+impl SpillFile {
+    /// Creates a new spill file numbered from `location` and preallocates
+    /// `capacity` bytes.
+    pub fn create(location: &Path, capacity: u64) -> Result<Self> {
+        let path = numbered_path(location, NEXT_FILE_NUMBER.fetch_add(1, Ordering::Relaxed));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create spill file {}", path.display()))?;
+        file.set_len(capacity)
+            .with_context(|| format!("Failed to preallocate {capacity} bytes for {}", path.display()))?;
+        Ok(Self {
+            location: location.to_path_buf(),
+            path,
+            file: Arc::new(file),
+            capacity,
+            write_pos: 0,
+            next_seq: 1,
+            records: VecDeque::new(),
+            overwritten: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+
+    #[cfg(test)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn reader(&self) -> SpillReader {
+        SpillReader { file: Arc::clone(&self.file), overwritten: Arc::clone(&self.overwritten) }
+    }
+
+    /// Appends `segment`, wrapping to the start of the file if it does not fit
+    /// at the end.  Returns the new record plus the highest sequence number
+    /// that was overwritten to make room (0 if none); the caller must forget
+    /// every record up to and including it.
+    pub fn write(&mut self, segment: &EncodedSegment) -> Result<(SpillEntry, u64)> {
+        let bytes = encode_segment(segment);
+        let len = bytes.len() as u64;
+        if len > self.capacity {
+            bail!("Segment of {len} bytes does not fit in a {}-byte spill file", self.capacity);
+        }
+
+        // The consumed region is [start, start + len), plus the unused tail
+        // [write_pos, capacity) when wrapping.
+        let wraps = self.write_pos + len > self.capacity;
+        let start = if wraps { 0 } else { self.write_pos };
+        let overlaps = |entry: &SpillEntry| {
+            let end = entry.offset + entry.len;
+            let in_new = entry.offset < start + len && end > start;
+            let in_tail = wraps && end > self.write_pos;
+            in_new || in_tail
+        };
+        let mut overwritten = 0;
+        while let Some(oldest) = self.records.front() {
+            if !overlaps(oldest) {
+                break;
+            }
+            overwritten = oldest.seq;
+            self.records.pop_front();
+        }
+        if overwritten > 0 {
+            // Publish before touching the bytes so concurrent readers notice.
+            self.overwritten.fetch_max(overwritten, Ordering::SeqCst);
+        }
+
+        write_all_at(&self.file, &bytes, start)
+            .with_context(|| format!("Failed to write spill file {}", self.path.display()))?;
+        let entry = SpillEntry { seq: self.next_seq, offset: start, len };
+        self.next_seq += 1;
+        self.write_pos = start + len;
+        self.records.push_back(entry);
+        Ok((entry, overwritten))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            eprintln!("[spill] Failed to delete {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Deletes the spill files numbered from `location` (and a file at
+/// `location` itself) that an earlier run left behind, returning their paths.
+/// Only safe while no [`SpillFile`] for `location` exists.
+pub fn remove_stale(location: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (location.parent(), location.file_name()) else {
+        return vec![];
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut removed = vec![];
+    for entry in entries.flatten() {
+        let entry_name = entry.file_name();
+        if entry_name != name && !is_numbered_from(location, &entry_name.to_string_lossy()) {
+            continue;
+        }
+        let path = entry.path();
+        match std::fs::remove_file(&path) {
+            Ok(()) => removed.push(path),
+            Err(e) => eprintln!("[spill] Failed to delete {}: {e}", path.display()),
+        }
+    }
+    removed
+}
+
+/// `<stem>.<number>.<extension>` next to `location`.
+fn numbered_path(location: &Path, number: u64) -> PathBuf {
+    let stem = location.file_stem().unwrap_or_default().to_string_lossy();
+    match location.extension() {
+        Some(ext) => location.with_file_name(format!("{stem}.{number}.{}", ext.to_string_lossy())),
+        None => location.with_file_name(format!("{stem}.{number}")),
+    }
+}
+
+fn is_numbered_from(location: &Path, name: &str) -> bool {
+    let stem = location.file_stem().unwrap_or_default().to_string_lossy();
+    let Some(rest) = name.strip_prefix(&format!("{stem}.")) else {
+        return false;
+    };
+    let number = match location.extension() {
+        Some(ext) => rest.strip_suffix(&format!(".{}", ext.to_string_lossy())),
+        None => Some(rest),
+    };
+    number.is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+impl SpillReader {
+    /// Reads back the segment stored at `entry`, or `None` if it has been
+    /// overwritten since the entry was recorded.
+    pub fn read(&self, entry: &SpillEntry) -> Result<Option<EncodedSegment>> {
+        if self.is_overwritten(entry) {
+            return Ok(None);
+        }
+        let mut bytes = vec![0u8; entry.len as usize];
+        read_exact_at(&self.file, &mut bytes, entry.offset).context("Failed to read spill file")?;
+        // The writer may have started recycling the region during the read.
+        if self.is_overwritten(entry) {
+            return Ok(None);
+        }
+        decode_segment(&bytes).map(Some)
+    }
+
+    fn is_overwritten(&self, entry: &SpillEntry) -> bool {
+        self.overwritten.load(Ordering::SeqCst) >= entry.seq
+    }
+}
+
+// ── Record format ─────────────────────────────────────────────────────────────
+//
+// i64 pushed_at (microseconds since the Unix epoch, or i64::MIN if unset),
+// u32 video count, u32 audio count, then for each packet (video first):
+// i64 pts, i64 dts, i64 duration, u8 is_key, u32 length, payload.
+// All integers are little-endian.
+
+const RECORD_HEADER_LEN: usize = 8 + 4 + 4;
+const PACKET_HEADER_LEN: usize = 8 * 3 + 1 + 4;
+/// `pushed_at` of a segment that was never pushed.
+const NOT_PUSHED: i64 = i64::MIN;
+
+fn encode_segment(segment: &EncodedSegment) -> Vec<u8> {
+    let payload = segment.size_bytes() as usize;
+    let packets = segment.video_packets.len() + segment.audio_packets.len();
+    let mut out = Vec::with_capacity(RECORD_HEADER_LEN + packets * PACKET_HEADER_LEN + payload);
+    let pushed_at = segment.pushed_at.map_or(NOT_PUSHED, |time| time.timestamp_micros());
+    out.extend_from_slice(&pushed_at.to_le_bytes());
+    out.extend_from_slice(&(segment.video_packets.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(segment.audio_packets.len() as u32).to_le_bytes());
+    for packet in segment.video_packets.iter().chain(&segment.audio_packets) {
+        out.extend_from_slice(&packet.pts.to_le_bytes());
+        out.extend_from_slice(&packet.dts.to_le_bytes());
+        out.extend_from_slice(&packet.duration.to_le_bytes());
+        out.push(packet.is_key as u8);
+        out.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&packet.data);
+    }
+    out
+}
+
+fn decode_segment(bytes: &[u8]) -> Result<EncodedSegment> {
+    let mut cursor = bytes;
+    let mut take = |n: usize| -> Result<&[u8]> {
+        if cursor.len() < n {
+            bail!("Truncated spill record");
+        }
+        let (head, rest) = cursor.split_at(n);
+        cursor = rest;
+        Ok(head)
+    };
+    let u32_at = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+    let i64_at = |b: &[u8]| i64::from_le_bytes(b.try_into().unwrap());
+
+    let pushed_at = match i64_at(take(8)?) {
+        NOT_PUSHED => None,
+        micros => DateTime::from_timestamp_micros(micros).map(|time| time.with_timezone(&Local)),
+    };
+    let video_count = u32_at(take(4)?) as usize;
+    let audio_count = u32_at(take(4)?) as usize;
+    let mut packets = Vec::with_capacity(video_count + audio_count);
+    for _ in 0..video_count + audio_count {
+        let header = take(PACKET_HEADER_LEN)?;
+        let len = u32_at(&header[25..29]) as usize;
+        packets.push(EncodedPacket {
+            pts: i64_at(&header[0..8]),
+            dts: i64_at(&header[8..16]),
+            duration: i64_at(&header[16..24]),
+            is_key: header[24] != 0,
+            data: take(len)?.into(),
+        });
+    }
+    let audio_packets = packets.split_off(video_count);
+    Ok(EncodedSegment { video_packets: packets, audio_packets, pushed_at })
+}
+
+// ── Positional I/O ────────────────────────────────────────────────────────────
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_write(buf, offset)?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_read(buf, offset)?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf = &mut buf[n..];
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A segment whose single video packet has `bytes` bytes of `fill`.
+    fn segment(pts: i64, bytes: usize, fill: u8) -> EncodedSegment {
+        EncodedSegment {
+            video_packets: vec![EncodedPacket {
+                data: vec![fill; bytes].into(),
+                pts,
+                dts: pts,
+                duration: 1,
+                is_key: true,
+            }],
+            audio_packets: vec![EncodedPacket {
+                data: vec![0xAA; 3].into(),
+                pts: pts * 2,
+                dts: pts * 2,
+                duration: 1024,
+                is_key: true,
+            }],
+            pushed_at: None,
+        }
+    }
+
+    /// Serialized size of `segment(_, bytes, _)`.
+    fn record_len(bytes: usize) -> u64 {
+        (RECORD_HEADER_LEN + 2 * PACKET_HEADER_LEN + bytes + 3) as u64
+    }
+
+    #[test]
+    fn record_round_trips() {
+        let pushed_at = "2026-03-04T05:06:07.123456+01:00".parse::<DateTime<Local>>().unwrap();
+        let original = EncodedSegment { pushed_at: Some(pushed_at), ..segment(7, 100, 0x42) };
+        let decoded = decode_segment(&encode_segment(&original)).unwrap();
+        assert_eq!(decoded.pushed_at, Some(pushed_at));
+        assert_eq!(decode_segment(&encode_segment(&segment(7, 1, 0))).unwrap().pushed_at, None);
+        assert_eq!(decoded.video_packets.len(), 1);
+        assert_eq!(decoded.video_packets[0].data, original.video_packets[0].data);
+        assert_eq!(decoded.video_packets[0].pts, 7);
+        assert!(decoded.video_packets[0].is_key);
+        assert_eq!(decoded.audio_packets[0].pts, 14);
+        assert_eq!(decoded.audio_packets[0].duration, 1024);
+    }
+
+    #[test]
+    fn truncated_record_is_an_error() {
+        let bytes = encode_segment(&segment(0, 10, 1));
+        assert!(decode_segment(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn create_preallocates_and_drop_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill = SpillFile::create(&dir.path().join("buffer.spill"), 4096).unwrap();
+        let path = spill.path().to_path_buf();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 4096);
+        drop(spill);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn each_spill_file_gets_a_new_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("buffer.spill");
+        let mut first = SpillFile::create(&location, 4096).unwrap();
+        let (entry, _) = first.write(&segment(0, 10, 7)).unwrap();
+        let reader = first.reader();
+        drop(first);
+        // A flush job can still read the dropped file while the buffer
+        // creates its replacement.
+        let second = SpillFile::create(&location, 4096).unwrap();
+        assert_ne!(second.path(), location);
+        assert_eq!(second.location(), location);
+        assert!(is_numbered_from(&location, &second.path().file_name().unwrap().to_string_lossy()));
+        assert_eq!(*reader.read(&entry).unwrap().unwrap().video_packets[0].data, [7; 10]);
+    }
+
+    #[test]
+    fn remove_stale_deletes_only_spill_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("buffer.spill");
+        for name in ["buffer.spill", "buffer.3.spill", "buffer.x.spill", "config.toml"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let mut removed = remove_stale(&location);
+        removed.sort();
+        assert_eq!(removed, vec![dir.path().join("buffer.3.spill"), location]);
+        assert!(dir.path().join("buffer.x.spill").exists());
+        assert!(dir.path().join("config.toml").exists());
+    }
+
+    #[test]
+    fn write_then_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spill = SpillFile::create(&dir.path().join("s"), 4096).unwrap();
+        let (a, _) = spill.write(&segment(0, 50, 1)).unwrap();
+        let (b, _) = spill.write(&segment(1, 60, 2)).unwrap();
+        assert_eq!((a.seq, b.seq), (1, 2));
+        assert_eq!(b.offset, a.len);
+
+        let reader = spill.reader();
+        assert_eq!(*reader.read(&a).unwrap().unwrap().video_packets[0].data, [1; 50]);
+        assert_eq!(*reader.read(&b).unwrap().unwrap().video_packets[0].data, [2; 60]);
+    }
+
+    #[test]
+    fn wrapping_overwrites_oldest_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let len = record_len(100);
+        // Room for three records plus a little slack.
+        let mut spill = SpillFile::create(&dir.path().join("s"), len * 3 + 10).unwrap();
+        let entries: Vec<SpillEntry> =
+            (0..3).map(|i| spill.write(&segment(i, 100, i as u8)).unwrap().0).collect();
+        let reader = spill.reader();
+
+        // The fourth record wraps to offset 0 and recycles the first.
+        let (fourth, overwritten) = spill.write(&segment(3, 100, 3)).unwrap();
+        assert_eq!(fourth.offset, 0);
+        assert_eq!(overwritten, 1);
+        assert!(reader.read(&entries[0]).unwrap().is_none());
+        assert!(reader.read(&entries[1]).unwrap().is_some());
+        assert_eq!(*reader.read(&fourth).unwrap().unwrap().video_packets[0].data, [3; 100]);
+    }
+
+    #[test]
+    fn wrapping_discards_records_in_the_unused_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        // Payload sizes chosen so records are 400, 400, 150 and 300 bytes.
+        let payload = |record: u64| (record - record_len(0)) as usize;
+        let mut spill = SpillFile::create(&dir.path().join("s"), 1000).unwrap();
+        spill.write(&segment(0, payload(400), 0)).unwrap(); // [0, 400)
+        spill.write(&segment(1, payload(400), 1)).unwrap(); // [400, 800)
+        let (c, _) = spill.write(&segment(2, payload(150), 2)).unwrap(); // [800, 950)
+        assert_eq!(spill.write(&segment(3, payload(400), 3)).unwrap().1, 1); // wraps to 0
+        assert_eq!(spill.write(&segment(4, payload(400), 4)).unwrap().1, 2); // [400, 800)
+        let reader = spill.reader();
+
+        // 300 bytes do not fit after 800, so the write wraps again: record 3
+        // in the skipped tail and record 4 at the start are both recycled.
+        let (_, overwritten) = spill.write(&segment(5, payload(300), 5)).unwrap();
+        assert_eq!(overwritten, 4);
+        assert!(reader.read(&c).unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_segment_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spill = SpillFile::create(&dir.path().join("s"), 64).unwrap();
+        assert!(spill.write(&segment(0, 100, 0)).is_err());
+    }
+}
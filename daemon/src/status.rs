@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::ring_buffer::BufferStats;
+
 /// Current operational state of the daemon.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "lowercase")]
@@ -9,16 +12,66 @@ pub enum DaemonState {
     Idle,
     /// A watched process is running and frames are being captured into the ring buffer.
     Recording,
-    /// The ring buffer is currently being flushed and muxed to an MP4 file on disk.
+    /// The clip hotkey was pressed and the daemon is recording post-roll
+    /// before saving; further presses extend the clip.
+    Pending,
+    /// One or more clips are being muxed to disk in the background.
     Flushing,
+    /// Recording was paused over IPC; watched games are not recorded until
+    /// it is resumed.
+    Paused,
+}
+
+impl DaemonState {
+    /// The state to report given what the daemon is doing.  A clip waiting
+    /// for post-roll wins over background saves, which win over being paused
+    /// or recording.
+    pub fn from_activity(
+        recording: bool,
+        paused: bool,
+        pending_clip: bool,
+        flushing: bool,
+    ) -> Self {
+        if pending_clip {
+            DaemonState::Pending
+        } else if flushing {
+            DaemonState::Flushing
+        } else if paused {
+            DaemonState::Paused
+        } else if recording {
+            DaemonState::Recording
+        } else {
+            DaemonState::Idle
+        }
+    }
 }
 
 /// Runtime status written by the daemon to %APPDATA%\Peaking\status.toml.
-/// The GUI reads this file (read-only) to display daemon state.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// The GUI reads this file (read-only), or subscribes to changes over the
+/// control socket, to display daemon state.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DaemonStatus {
     /// Daemon binary version (set from Cargo.toml at compile time).
     pub version: String,
+    /// Incremented on every write and carried over from the previous status
+    /// file when the daemon starts, so readers can tell a stale or
+    /// out-of-order copy even across restarts.
+    #[serde(default)]
+    pub sequence: u64,
+    /// RFC 3339 time of the write.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    /// Process ID of the daemon that wrote the file.
+    #[serde(default)]
+    pub pid: u32,
+    /// RFC 3339 time the daemon started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    /// RFC 3339 time of the daemon's latest heartbeat, refreshed every few
+    /// seconds while it runs.  Cleared on a clean exit, so a present but old
+    /// value means the daemon died.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_at: Option<String>,
     /// Current operational state.
     pub state: DaemonState,
     /// Display name of the application currently being recorded, if any.
@@ -30,9 +83,72 @@ pub struct DaemonStatus {
     /// RFC 3339 timestamp of the most recently saved clip, if any.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_clip_timestamp: Option<String>,
+    /// Absolute path of the most recently saved clip's PNG thumbnail, if one
+    /// was written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_clip_thumbnail: Option<String>,
     /// Human-readable error message if the daemon encountered a non-fatal error.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Human-readable warning that needs attention but does not stop the
+    /// daemon, e.g. low disk space on the clip drive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// Bytes free on the drive holding the clip directory, as of the last check.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_free_bytes: Option<u64>,
+    /// Outcome of the most recent encoder probe. Absent until a recording starts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoder: Option<EncoderStatus>,
+    /// Ring-buffer fill level while recording.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<BufferStatus>,
+}
+
+/// Which encoder backend the daemon picked, and why the others were skipped.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct EncoderStatus {
+    /// The `[global] encoder` value from config.toml (e.g. "auto").
+    pub requested: String,
+    /// Name of the backend in use, or absent if every candidate failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Backend name → reason it was rejected, for every candidate tried before
+    /// (or instead of) the selected one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rejected: BTreeMap<String, String>,
+}
+
+/// Memory and disk held by the ring buffer, so the GUI can show real usage.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BufferStatus {
+    /// Compressed bytes currently buffered.
+    pub bytes: u64,
+    /// The part of `bytes` held in the disk spill file.
+    #[serde(default)]
+    pub disk_bytes: u64,
+    /// Seconds of video currently buffered (one decimal place).
+    pub seconds: f64,
+    /// Average bitrate of the buffered footage in kbit/s.
+    pub average_bitrate_kbps: u64,
+    /// Configured buffer length in seconds.
+    pub capacity_secs: u64,
+    /// Configured byte budget, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+}
+
+impl From<BufferStats> for BufferStatus {
+    fn from(stats: BufferStats) -> Self {
+        Self {
+            bytes: stats.bytes,
+            disk_bytes: stats.disk_bytes,
+            seconds: (stats.duration.as_secs_f64() * 10.0).round() / 10.0,
+            average_bitrate_kbps: stats.avg_bitrate_bps / 1000,
+            capacity_secs: stats.capacity.as_secs(),
+            max_bytes: stats.max_bytes,
+        }
+    }
 }
 
 impl DaemonStatus {
@@ -40,17 +156,50 @@ impl DaemonStatus {
     pub fn new() -> Self {
         Self {
             version: env!("CARGO_PKG_VERSION").to_string(),
+            sequence: 0,
+            updated_at: None,
+            pid: std::process::id(),
+            started_at: Some(chrono::Local::now().to_rfc3339()),
+            heartbeat_at: None,
             state: DaemonState::Idle,
             active_application: None,
             last_clip_path: None,
             last_clip_timestamp: None,
+            last_clip_thumbnail: None,
             error: None,
+            warning: None,
+            disk_free_bytes: None,
+            encoder: None,
+            buffer: None,
         }
     }
+
+    /// Marks the status as a new revision about to be written: the next
+    /// `sequence`, updated now.
+    pub fn stamp(&mut self) {
+        self.sequence += 1;
+        self.updated_at = Some(chrono::Local::now().to_rfc3339());
+    }
+
+    /// Whether `self` and `other` differ at most in their revision stamps and
+    /// heartbeat.
+    pub fn same_as(&self, other: &DaemonStatus) -> bool {
+        let restamped = Self {
+            sequence: other.sequence,
+            updated_at: other.updated_at.clone(),
+            heartbeat_at: other.heartbeat_at.clone(),
+            ..self.clone()
+        };
+        restamped == *other
+    }
 }
 
+const TEMP_SUFFIX: &str = ".tmp";
+
 /// Serializes `status` to TOML and writes it to `path`.
-/// Creates the parent directory if it does not exist.
+/// Creates the parent directory if it does not exist.  The file is written
+/// as a `.tmp` sibling and renamed over `path`, so readers never see it
+/// half-written.
 /// Logs errors to stderr rather than panicking — a status write failure should
 /// never crash the daemon.
 pub fn write_status(path: &Path, status: &DaemonStatus) {
@@ -62,7 +211,7 @@ pub fn write_status(path: &Path, status: &DaemonStatus) {
     }
     match toml::to_string_pretty(status) {
         Ok(content) => {
-            if let Err(e) = std::fs::write(path, content) {
+            if let Err(e) = replace_file(path, &content) {
                 eprintln!("[status] Failed to write status file: {e}");
             }
         }
@@ -70,6 +219,25 @@ pub fn write_status(path: &Path, status: &DaemonStatus) {
     }
 }
 
+/// The `sequence` of the status file at `path`, so a restarted daemon keeps
+/// counting up from it.  0 if the file is missing or unreadable.
+pub fn last_sequence(path: &Path) -> u64 {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| toml::from_str::<DaemonStatus>(&content).ok())
+        .map_or(0, |status| status.sequence)
+}
+
+/// Replaces `path` with `content` via a temporary sibling file.
+fn replace_file(path: &Path, content: &str) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!("{name}{TEMP_SUFFIX}"));
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,6 +257,8 @@ mod tests {
         assert!(s.last_clip_path.is_none());
         assert!(s.last_clip_timestamp.is_none());
         assert!(s.error.is_none());
+        assert!(s.encoder.is_none());
+        assert!(s.buffer.is_none());
     }
 
     #[test]
@@ -110,6 +280,10 @@ mod tests {
         let recording = toml::to_string_pretty(&s).unwrap();
         assert!(recording.contains("state = \"recording\""));
 
+        s.state = DaemonState::Pending;
+        let pending = toml::to_string_pretty(&s).unwrap();
+        assert!(pending.contains("state = \"pending\""));
+
         s.state = DaemonState::Flushing;
         let flushing = toml::to_string_pretty(&s).unwrap();
         assert!(flushing.contains("state = \"flushing\""));
@@ -117,7 +291,13 @@ mod tests {
 
     #[test]
     fn state_round_trips_through_toml() {
-        for state in [DaemonState::Idle, DaemonState::Recording, DaemonState::Flushing] {
+        for state in [
+            DaemonState::Idle,
+            DaemonState::Recording,
+            DaemonState::Pending,
+            DaemonState::Flushing,
+            DaemonState::Paused,
+        ] {
             let mut status = DaemonStatus::new();
             status.state = state.clone();
             let serialized = toml::to_string_pretty(&status).unwrap();
@@ -126,6 +306,28 @@ mod tests {
         }
     }
 
+    // ── DaemonState::from_activity ────────────────────────────────────────────
+
+    #[test]
+    fn from_activity_precedence() {
+        assert_eq!(DaemonState::from_activity(false, false, false, false), DaemonState::Idle);
+        assert_eq!(DaemonState::from_activity(true, false, false, false), DaemonState::Recording);
+        assert_eq!(DaemonState::from_activity(true, false, false, true), DaemonState::Flushing);
+        assert_eq!(DaemonState::from_activity(true, false, true, true), DaemonState::Pending);
+    }
+
+    #[test]
+    fn from_activity_paused_yields_to_saves() {
+        assert_eq!(DaemonState::from_activity(false, true, false, false), DaemonState::Paused);
+        assert_eq!(DaemonState::from_activity(false, true, false, true), DaemonState::Flushing);
+    }
+
+    #[test]
+    fn from_activity_flushing_outlives_recording() {
+        // Clips still being saved after the game exits keep the state busy.
+        assert_eq!(DaemonState::from_activity(false, false, false, true), DaemonState::Flushing);
+    }
+
     // ── write_status ──────────────────────────────────────────────────────────
 
     #[test]
@@ -164,6 +366,66 @@ mod tests {
         assert_eq!(parsed.active_application.as_deref(), Some("Rocket League"));
     }
 
+    #[test]
+    fn write_status_replaces_file_without_leaving_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.toml");
+        let mut status = DaemonStatus::new();
+        write_status(&path, &status);
+        status.state = DaemonState::Recording;
+        write_status(&path, &status);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let parsed: DaemonStatus = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.state, DaemonState::Recording);
+        let names: Vec<_> =
+            std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["status.toml"]);
+    }
+
+    // ── Revision stamps ───────────────────────────────────────────────────────
+
+    #[test]
+    fn stamp_counts_up_and_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.toml");
+        let mut status = DaemonStatus::new();
+        status.stamp();
+        status.stamp();
+        write_status(&path, &status);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let parsed: DaemonStatus = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.sequence, 2);
+        let updated_at = parsed.updated_at.unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&updated_at).is_ok(), "{updated_at}");
+    }
+
+    #[test]
+    fn restarted_daemon_continues_the_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.toml");
+        assert_eq!(last_sequence(&path), 0);
+        let mut status = DaemonStatus::new();
+        status.sequence = 41;
+        status.stamp();
+        write_status(&path, &status);
+        assert_eq!(last_sequence(&path), 42);
+        std::fs::write(&path, "not toml").unwrap();
+        assert_eq!(last_sequence(&path), 0);
+    }
+
+    #[test]
+    fn same_as_ignores_stamps() {
+        let mut status = DaemonStatus::new();
+        let before = status.clone();
+        status.stamp();
+        status.heartbeat_at = status.updated_at.clone();
+        assert!(status.same_as(&before));
+        status.state = DaemonState::Recording;
+        assert!(!status.same_as(&before));
+    }
+
     #[test]
     fn write_status_omits_none_optional_fields() {
         let dir = tempfile::tempdir().unwrap();
@@ -175,7 +437,10 @@ mod tests {
         assert!(!content.contains("active_application"));
         assert!(!content.contains("last_clip_path"));
         assert!(!content.contains("last_clip_timestamp"));
+        assert!(!content.contains("last_clip_thumbnail"));
         assert!(!content.contains("error"));
+        assert!(!content.contains("warning"));
+        assert!(!content.contains("disk_free_bytes"));
     }
 
     #[test]
@@ -195,4 +460,73 @@ mod tests {
         assert!(content.contains("last_clip_path"));
         assert!(content.contains("error"));
     }
+
+    // ── EncoderStatus ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn encoder_status_serializes_as_table() {
+        let mut status = DaemonStatus::new();
+        let mut rejected = BTreeMap::new();
+        rejected.insert("nvenc".to_string(), "encoder not found".to_string());
+        status.encoder = Some(EncoderStatus {
+            requested: "auto".to_string(),
+            backend: Some("x264".to_string()),
+            rejected,
+        });
+
+        let content = toml::to_string_pretty(&status).unwrap();
+        assert!(content.contains("[encoder]"), "{content}");
+        assert!(content.contains("backend = \"x264\""), "{content}");
+
+        let parsed: DaemonStatus = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.encoder, status.encoder);
+    }
+
+    #[test]
+    fn encoder_status_without_backend_round_trips() {
+        let mut status = DaemonStatus::new();
+        status.encoder = Some(EncoderStatus { requested: "qsv".to_string(), ..Default::default() });
+        let content = toml::to_string_pretty(&status).unwrap();
+        let parsed: DaemonStatus = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.encoder.unwrap().backend, None);
+    }
+
+    // ── BufferStatus ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn buffer_status_converts_units() {
+        let stats = BufferStats {
+            segments: 12,
+            bytes: 12_500_000,
+            disk_bytes: 2_500_000,
+            duration: std::time::Duration::from_millis(12_345),
+            avg_bitrate_bps: 8_100_000,
+            capacity: std::time::Duration::from_secs(15),
+            max_bytes: Some(64 << 20),
+        };
+        let buffer = BufferStatus::from(stats);
+        assert_eq!(buffer.disk_bytes, 2_500_000);
+        assert_eq!(buffer.seconds, 12.3);
+        assert_eq!(buffer.average_bitrate_kbps, 8_100);
+        assert_eq!(buffer.capacity_secs, 15);
+        assert_eq!(buffer.max_bytes, Some(64 << 20));
+    }
+
+    #[test]
+    fn buffer_status_serializes_as_table() {
+        let mut status = DaemonStatus::new();
+        status.buffer = Some(BufferStatus {
+            bytes: 1024,
+            disk_bytes: 0,
+            seconds: 1.5,
+            average_bitrate_kbps: 5,
+            capacity_secs: 15,
+            max_bytes: None,
+        });
+        let content = toml::to_string_pretty(&status).unwrap();
+        assert!(content.contains("[buffer]"), "{content}");
+        assert!(!content.contains("max_bytes"), "{content}");
+        let parsed: DaemonStatus = toml::from_str(&content).unwrap();
+        assert_eq!(parsed.buffer, status.buffer);
+    }
 }
@@ -0,0 +1,329 @@
+/// Synthetic capture sources for headless runs and tests.
+///
+/// [`TestPatternSource`] renders moving colour bars with a frame counter, and
+/// [`SineToneSource`] generates a continuous sine tone.  Output depends only on
+/// the frame / sample index, never on wall-clock time, so two runs produce
+/// identical data.  Both are paced in real time so the encoder sees the same
+/// cadence it would from WGC/WASAPI.
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+use tokio::time::MissedTickBehavior;
+
+use crate::audio_capture::{AudioSource, RawAudio};
+use crate::capture::{CaptureFuture, RawFrame, VideoSource};
+
+/// Horizontal distance the bars move per frame, in pixels.
+const BAR_SPEED_PX: usize = 4;
+
+/// SMPTE-style bar colours as (B, G, R).
+const BAR_COLORS: [[u8; 3]; 8] = [
+    [192, 192, 192], // grey
+    [0, 192, 192],   // yellow
+    [192, 192, 0],   // cyan
+    [0, 192, 0],     // green
+    [192, 0, 192],   // magenta
+    [0, 0, 192],     // red
+    [192, 0, 0],     // blue
+    [16, 16, 16],    // black
+];
+
+/// 3×5 bitmap font for the digits 0–9; bit 14 is the top-left pixel.
+const DIGIT_FONT: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_001_001_001,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+
+/// Length of each generated audio chunk in milliseconds.
+const AUDIO_CHUNK_MS: u32 = 10;
+
+// ── Test pattern ──────────────────────────────────────────────────────────────
+
+/// Renders frame `frame_index` of the test pattern as tightly-packed BGRA.
+pub fn render_test_pattern(width: u32, height: u32, frame_index: u64) -> Vec<u8> {
+    let (w, h) = (width as usize, height as usize);
+    if w == 0 || h == 0 {
+        return vec![];
+    }
+
+    // Every row of the bars is identical, so render one and replicate it.
+    let bar_width = (w / BAR_COLORS.len()).max(1);
+    let shift = (frame_index as usize).wrapping_mul(BAR_SPEED_PX) % w;
+    let mut row = Vec::with_capacity(w * 4);
+    for x in 0..w {
+        let [b, g, r] = BAR_COLORS[((x + shift) / bar_width) % BAR_COLORS.len()];
+        row.extend_from_slice(&[b, g, r, 255]);
+    }
+    let mut bgra = Vec::with_capacity(w * h * 4);
+    for _ in 0..h {
+        bgra.extend_from_slice(&row);
+    }
+
+    draw_counter(&mut bgra, w, h, frame_index);
+    bgra
+}
+
+/// Draws `value` in white on a black box in the top-left corner.
+fn draw_counter(bgra: &mut [u8], w: usize, h: usize, value: u64) {
+    let digits: Vec<usize> =
+        value.to_string().bytes().map(|b| (b - b'0') as usize).collect();
+    // One font pixel is `scale` screen pixels; 1080p gives 20 px per font pixel.
+    let scale = (h / 54).max(1);
+    let margin = scale;
+    let box_w = (digits.len() * 4 + 1) * scale;
+    let box_h = 7 * scale;
+
+    let mut fill = |x0: usize, y0: usize, bw: usize, bh: usize, v: u8| {
+        for y in y0..(y0 + bh).min(h) {
+            for x in x0..(x0 + bw).min(w) {
+                let i = (y * w + x) * 4;
+                bgra[i..i + 4].copy_from_slice(&[v, v, v, 255]);
+            }
+        }
+    };
+
+    fill(margin, margin, box_w, box_h, 0);
+    for (n, &digit) in digits.iter().enumerate() {
+        let glyph = DIGIT_FONT[digit];
+        for gy in 0..5 {
+            for gx in 0..3 {
+                if glyph & (1 << (14 - (gy * 3 + gx))) != 0 {
+                    let x = margin + (1 + n * 4 + gx) * scale;
+                    let y = margin + (1 + gy) * scale;
+                    fill(x, y, scale, scale, 255);
+                }
+            }
+        }
+    }
+}
+
+/// Deterministic moving colour bars with a frame counter.
+pub struct TestPatternSource {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+impl TestPatternSource {
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        Self { width, height, fps }
+    }
+}
+
+impl VideoSource for TestPatternSource {
+    fn name(&self) -> &'static str {
+        "test-pattern"
+    }
+
+    fn run(
+        self: Box<Self>,
+        frame_tx: mpsc::Sender<RawFrame>,
+        mut stop_rx: watch::Receiver<bool>,
+    ) -> CaptureFuture {
+        Box::pin(async move {
+            let mut ticker =
+                tokio::time::interval(Duration::from_secs(1) / self.fps.max(1));
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut frame_index = 0u64;
+            eprintln!("[capture] Test pattern started ({}×{} @ {} fps)", self.width, self.height, self.fps);
+
+            while !*stop_rx.borrow_and_update() {
+                ticker.tick().await;
+                let bgra_data = render_test_pattern(self.width, self.height, frame_index);
+                let frame = RawFrame::packed(bgra_data, self.width, self.height);
+                if frame_tx.send(frame).await.is_err() {
+                    break; // Encoder task dropped.
+                }
+                frame_index += 1;
+            }
+
+            eprintln!("[capture] Test pattern stopped after {frame_index} frames");
+            Ok(())
+        })
+    }
+}
+
+// ── Sine tone ─────────────────────────────────────────────────────────────────
+
+/// Generates `frames` interleaved samples of a sine tone starting at absolute
+/// sample index `start`.  Every channel carries the same signal.
+pub fn sine_samples(
+    start: u64,
+    frames: usize,
+    sample_rate: u32,
+    channels: u16,
+    frequency_hz: f32,
+    amplitude: f32,
+) -> Vec<f32> {
+    let mut samples = Vec::with_capacity(frames * channels as usize);
+    for n in 0..frames as u64 {
+        // Reduce the index modulo the sample rate so the phase stays exact
+        // for long runs; any whole number of seconds is a whole number of
+        // cycles for integer frequencies.
+        let t = ((start + n) % u64::from(sample_rate)) as f64 / f64::from(sample_rate);
+        let v = (std::f64::consts::TAU * f64::from(frequency_hz) * t).sin() as f32 * amplitude;
+        samples.extend(std::iter::repeat_n(v, channels as usize));
+    }
+    samples
+}
+
+/// A continuous sine tone (440 Hz by default) at the encoder's sample format.
+pub struct SineToneSource {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub frequency_hz: f32,
+    pub amplitude: f32,
+}
+
+impl SineToneSource {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self { sample_rate, channels, frequency_hz: 440.0, amplitude: 0.25 }
+    }
+}
+
+impl AudioSource for SineToneSource {
+    fn name(&self) -> &'static str {
+        "sine-tone"
+    }
+
+    fn run(
+        self: Box<Self>,
+        audio_tx: mpsc::Sender<RawAudio>,
+        mut stop_rx: watch::Receiver<bool>,
+    ) -> CaptureFuture {
+        Box::pin(async move {
+            let frames_per_chunk = (self.sample_rate * AUDIO_CHUNK_MS / 1000) as usize;
+            let mut ticker =
+                tokio::time::interval(Duration::from_millis(u64::from(AUDIO_CHUNK_MS)));
+            // Burst to catch up after a stall so the sample count tracks real time.
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+            let mut position = 0u64;
+            eprintln!(
+                "[audio] Sine tone started ({}ch @ {}Hz, {} Hz tone)",
+                self.channels, self.sample_rate, self.frequency_hz
+            );
+
+            while !*stop_rx.borrow_and_update() {
+                ticker.tick().await;
+                let samples_f32 = sine_samples(
+                    position,
+                    frames_per_chunk,
+                    self.sample_rate,
+                    self.channels,
+                    self.frequency_hz,
+                    self.amplitude,
+                );
+                if audio_tx.send(RawAudio { samples_f32 }).await.is_err() {
+                    break;
+                }
+                position += frames_per_chunk as u64;
+            }
+
+            eprintln!("[audio] Sine tone stopped");
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ── render_test_pattern ───────────────────────────────────────────────────
+
+    #[test]
+    fn test_pattern_has_bgra_size() {
+        let frame = render_test_pattern(64, 36, 0);
+        assert_eq!(frame.len(), 64 * 36 * 4);
+        assert!(frame.chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[test]
+    fn test_pattern_is_deterministic() {
+        assert_eq!(render_test_pattern(64, 36, 7), render_test_pattern(64, 36, 7));
+    }
+
+    #[test]
+    fn test_pattern_changes_between_frames() {
+        assert_ne!(render_test_pattern(64, 36, 0), render_test_pattern(64, 36, 1));
+    }
+
+    #[test]
+    fn test_pattern_draws_counter_digits() {
+        // With scale 1 the first "1" glyph column sits at x = 1 + 1*1 + 1.
+        let frame = render_test_pattern(64, 36, 1);
+        let px = |x: usize, y: usize| &frame[(y * 64 + x) * 4..(y * 64 + x) * 4 + 3];
+        assert_eq!(px(3, 2), &[255, 255, 255], "digit pixel");
+        assert_eq!(px(1, 1), &[0, 0, 0], "counter background");
+    }
+
+    #[test]
+    fn test_pattern_zero_size_is_empty() {
+        assert!(render_test_pattern(0, 0, 5).is_empty());
+    }
+
+    // ── sine_samples ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn sine_samples_interleaves_channels() {
+        let s = sine_samples(0, 100, 48_000, 2, 440.0, 0.5);
+        assert_eq!(s.len(), 200);
+        assert!(s.chunks_exact(2).all(|lr| lr[0] == lr[1]));
+    }
+
+    #[test]
+    fn sine_samples_respects_amplitude() {
+        let s = sine_samples(0, 48_000, 48_000, 1, 440.0, 0.25);
+        let peak = s.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+        assert!(peak <= 0.25 && peak > 0.24, "peak {peak}");
+    }
+
+    #[test]
+    fn sine_samples_are_phase_continuous_across_chunks() {
+        let whole = sine_samples(0, 960, 48_000, 2, 440.0, 0.25);
+        let mut split = sine_samples(0, 480, 48_000, 2, 440.0, 0.25);
+        split.extend(sine_samples(480, 480, 48_000, 2, 440.0, 0.25));
+        assert_eq!(whole, split);
+    }
+
+    // ── Sources ───────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_pattern_source_sends_sequential_frames() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let handle = tokio::spawn(Box::new(TestPatternSource::new(32, 18, 240)).run(tx, stop_rx));
+
+        let first = rx.recv().await.unwrap();
+        let second = rx.recv().await.unwrap();
+        assert_eq!(first.bgra_data, render_test_pattern(32, 18, 0));
+        assert_eq!(second.bgra_data, render_test_pattern(32, 18, 1));
+        assert_eq!((second.width, second.height, second.stride), (32, 18, 32 * 4));
+
+        stop_tx.send(true).unwrap();
+        drop(rx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn sine_tone_source_sends_10ms_chunks() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let handle = tokio::spawn(Box::new(SineToneSource::new(48_000, 2)).run(tx, stop_rx));
+
+        let chunk = rx.recv().await.unwrap();
+        assert_eq!(chunk.samples_f32.len(), 480 * 2);
+
+        stop_tx.send(true).unwrap();
+        drop(rx);
+        handle.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+/// Fixtures shared by the unit tests: hand-made H.264 and AAC packets plus
+/// codec parameters that the muxers accept without a real encoder, and the
+/// clips built from them.
+use chrono::Local;
+use std::sync::Arc;
+
+use crate::flush::ClipInfo;
+use crate::metadata::ClipMetadata;
+use crate::ring_buffer::{
+    AudioCodecParams, CodecEpoch, EncodedPacket, EncodedSegment, EpochSegments, VideoCodecParams,
+};
+
+pub const SPS: &[u8] = &[0x67, 0x64, 0x00, 0x1F, 0xAC, 0xD9];
+pub const PPS: &[u8] = &[0x68, 0xEB, 0xE3, 0xCB];
+
+pub fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+    nals.iter().flat_map(|n| [&[0u8, 0, 0, 1][..], n].concat()).collect()
+}
+
+/// 320x240 at 30 fps, with the SPS/PPS as Annex B extradata.
+pub fn video_params() -> VideoCodecParams {
+    VideoCodecParams {
+        extradata: annex_b(&[SPS, PPS]),
+        width: 320,
+        height: 240,
+        time_base: (1, 30),
+    }
+}
+
+/// 48 kHz stereo AAC-LC.
+pub fn audio_params() -> AudioCodecParams {
+    AudioCodecParams {
+        extradata: vec![0x11, 0x90],
+        sample_rate: 48_000,
+        channels: 2,
+        time_base: (1, 48_000),
+    }
+}
+
+pub fn packet(data: Vec<u8>, ts: i64, duration: i64, is_key: bool) -> EncodedPacket {
+    EncodedPacket { data: data.into(), pts: ts, dts: ts, duration, is_key }
+}
+
+/// Two 1-second segments: 30 video frames (keyframe first) and 47 AAC
+/// frames of 1024 samples each.
+pub fn make_segments() -> Vec<EncodedSegment> {
+    make_segments_from(0, 0)
+}
+
+/// Like [`make_segments`], but video starts at frame `first_frame` and audio
+/// at AAC frame `first_aac_frame`.
+pub fn make_segments_from(first_frame: i64, first_aac_frame: i64) -> Vec<EncodedSegment> {
+    (0..2i64)
+        .map(|s| EncodedSegment {
+            video_packets: (0..30i64)
+                .map(|f| {
+                    let nal: &[u8] = if f == 0 { &[0x65, 0x88, 0x84] } else { &[0x41, 0x9A] };
+                    packet(annex_b(&[nal]), first_frame + s * 30 + f, 1, f == 0)
+                })
+                .collect(),
+            audio_packets: (0..47i64)
+                .map(|f| {
+                    let ts = (first_aac_frame + s * 47 + f) * 1024;
+                    packet(vec![0x21; 10], ts, 1024, true)
+                })
+                .collect(),
+            pushed_at: None,
+        })
+        .collect()
+}
+
+pub fn shared(segments: &[EncodedSegment]) -> Vec<Arc<EncodedSegment>> {
+    segments.iter().cloned().map(Arc::new).collect()
+}
+
+pub fn metadata() -> ClipMetadata {
+    ClipMetadata {
+        title: "Rocket League".into(),
+        created: "2024-03-01T12:00:00Z".parse().unwrap(),
+        comment: "Buffer length: 30 s, encoder: nvenc".into(),
+        tag: None,
+    }
+}
+
+// ── Clips ──────────────────────────────────────────────────────────────────────
+
+/// One segment of a single IDR frame and AAC frame, captured at `width`x240.
+pub fn make_part(width: u32) -> EpochSegments {
+    let segment = EncodedSegment {
+        video_packets: vec![packet(annex_b(&[&[0x65, 0x88, 0x84]]), 0, 1, true)],
+        audio_packets: vec![packet(vec![0x21; 8], 0, 1024, true)],
+        pushed_at: None,
+    };
+    EpochSegments {
+        epoch: CodecEpoch {
+            video_params: VideoCodecParams { width, ..video_params() },
+            audio_params: audio_params(),
+        },
+        segments: vec![Arc::new(segment)],
+    }
+}
+
+/// A clip of `game` requested now.
+pub fn clip_info(game: &str) -> ClipInfo {
+    ClipInfo { display_name: game.into(), requested_at: Local::now(), ..ClipInfo::default() }
+}
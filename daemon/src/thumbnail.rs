@@ -0,0 +1,119 @@
+/// Clip thumbnails: a small PNG saved next to every clip so the GUI can show
+/// a preview without decoding any video.
+///
+/// The thumbnail of `2024-01-01_12-00-00.mp4` is `2024-01-01_12-00-00.mp4.png`.
+/// It shows the first keyframe of the clip's middle segment, decoded with
+/// [`encoder::decode_keyframe`] and scaled down to `thumbnail_width` pixels
+/// wide with the video's aspect ratio.
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::capture::RawFrame;
+use crate::encoder;
+use crate::flush;
+use crate::ring_buffer::EpochSegments;
+use crate::scale::{self, OutputScaling};
+
+/// Returns the thumbnail path for `clip`: `<clip file name>.png`.
+pub fn thumbnail_path(clip: &Path) -> PathBuf {
+    flush::sibling_path(clip, ".png")
+}
+
+/// Decodes the first keyframe of the middle segment of `part`.
+fn middle_keyframe(part: &EpochSegments) -> Result<RawFrame> {
+    let segment = part.segments.get(part.segments.len() / 2).context("Clip has no segments")?;
+    let packet = segment
+        .video_packets
+        .iter()
+        .find(|p| p.is_key)
+        .context("Middle segment has no keyframe")?;
+    encoder::decode_keyframe(&part.epoch.video_params, &packet.data)
+}
+
+/// Scales `frame` down to `width` pixels wide, keeping its aspect ratio.
+/// Frames that are already narrower keep their size.
+fn shrink(frame: &RawFrame, width: u32) -> RawFrame {
+    let scaling = OutputScaling { width: Some(width.min(frame.width)), ..OutputScaling::default() };
+    scale::scale_bgra(frame, &scaling.plan((frame.width, frame.height)))
+}
+
+/// Encodes `frame` as an 8-bit RGB PNG.
+fn encode_png(frame: &RawFrame) -> Result<Vec<u8>> {
+    let mut rgb = Vec::with_capacity(frame.width as usize * frame.height as usize * 3);
+    for y in 0..frame.height {
+        for px in frame.row(y).chunks_exact(4) {
+            rgb.extend_from_slice(&[px[2], px[1], px[0]]);
+        }
+    }
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&rgb)?;
+    Ok(out)
+}
+
+/// Saves a thumbnail of `part`, `width` pixels wide, next to `clip`,
+/// atomically (see [`flush::write_atomically`]).
+pub fn write_thumbnail(clip: &Path, part: &EpochSegments, width: u32) -> Result<PathBuf> {
+    let path = thumbnail_path(clip);
+    let frame = middle_keyframe(part)
+        .with_context(|| format!("Failed to decode a frame for {}", path.display()))?;
+    let png = encode_png(&shrink(&frame, width))?;
+    flush::write_atomically(&path, &png)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{EncoderBackend, EncoderConfig};
+    use crate::ring_buffer::{CodecEpoch, EncodedSegment};
+    use crate::software_encoder::SoftwareBackend;
+    use std::sync::Arc;
+
+    /// Three one-frame segments; only the middle one is red.
+    fn part() -> EpochSegments {
+        let cfg =
+            EncoderConfig { width: 64, height: 36, gop_length: 1, ..EncoderConfig::default() };
+        let mut backend = SoftwareBackend::open(&cfg).unwrap();
+        let mut segments = vec![];
+        for colour in [[255, 0, 0, 255], [0, 0, 255, 255], [255, 0, 0, 255]] {
+            let frame = RawFrame::packed(colour.repeat(64 * 36), 64, 36);
+            let mut packets = vec![];
+            backend.encode_video(&frame, &mut packets).unwrap();
+            let segment =
+                EncodedSegment { video_packets: packets, audio_packets: vec![], pushed_at: None };
+            segments.push(Arc::new(segment));
+        }
+        let epoch = CodecEpoch {
+            video_params: backend.video_params().clone(),
+            audio_params: backend.audio_params().clone(),
+        };
+        EpochSegments { epoch, segments }
+    }
+
+    #[test]
+    fn thumbnail_path_appends_to_clip_name() {
+        let clip = Path::new("clips").join("2024-03-01_12-00-00.mkv");
+        assert_eq!(thumbnail_path(&clip), Path::new("clips").join("2024-03-01_12-00-00.mkv.png"));
+    }
+
+    #[test]
+    fn thumbnail_shows_middle_segment_at_configured_width() {
+        let dir = tempfile::tempdir().unwrap();
+        let clip = dir.path().join("clip.mp4");
+        let path = write_thumbnail(&clip, &part(), 32).unwrap();
+        assert_eq!(path, dir.path().join("clip.mp4.png"));
+        assert!(!flush::partial_path(&path).exists());
+
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut rgb = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgb).unwrap();
+        assert_eq!((info.width, info.height), (32, 18));
+        // Pure red survives the YUV round trip to within a few levels.
+        let (r, g, b) = (rgb[0], rgb[1], rgb[2]);
+        assert!(r > 250 && g < 5 && b < 5, "{r} {g} {b}");
+    }
+}
@@ -1,12 +1,14 @@
 import { shell } from 'electron'
-import { readdir, unlink } from 'fs/promises'
-import { join } from 'path'
+import { readdir, stat, unlink, writeFile } from 'fs/promises'
+import { basename, join } from 'path'
 import { readConfig } from './config'
 
 interface Clip {
   name: string
   path: string
   timestamp: string
+  favorite: boolean
+  thumbnail?: string
 }
 
 interface ClipGroup {
@@ -14,16 +16,71 @@ interface ClipGroup {
   clips: Clip[]
 }
 
+const CLIP_EXTENSION = /\.(mp4|mkv)$/i
+
+// An empty `<clip>.favorite` file next to a clip protects it from the
+// daemon's retention rules.
+const FAVORITE_SUFFIX = '.favorite'
+
+// The daemon writes a `<clip>.json` sidecar describing each clip.
+const SIDECAR_SUFFIX = '.json'
+
+// ...and a `<clip>.png` thumbnail unless thumbnails are disabled.
+const THUMBNAIL_SUFFIX = '.png'
+
 function resolveEnvVars(p: string): string {
   return p.replace(/%([^%]+)%/g, (_, key) => process.env[key] ?? `%${key}%`)
 }
 
-function parseTimestamp(filename: string): Date {
+function parseTimestamp(filename: string): Date | null {
   const match = filename.match(/^(\d{4}-\d{2}-\d{2})_(\d{2}-\d{2}-\d{2})/)
-  if (!match) return new Date(0)
+  if (!match) return null
   return new Date(`${match[1]}T${match[2].replace(/-/g, ':')}`)
 }
 
+// Clip names come from clip_filename_template, so fall back to the file's
+// modification time when the name does not start with a timestamp.
+async function clipTimestamp(path: string, filename: string): Promise<Date> {
+  const parsed = parseTimestamp(filename)
+  if (parsed) return parsed
+  try {
+    return (await stat(path)).mtime
+  } catch {
+    return new Date(0)
+  }
+}
+
+// clip_dir_template may nest directories below the per-game one.
+interface ClipFile {
+  path: string
+  favorite: boolean
+  thumbnail?: string
+}
+
+async function findClipFiles(dir: string): Promise<ClipFile[]> {
+  let entries: Awaited<ReturnType<typeof readdir>>
+  try {
+    entries = await readdir(dir, { withFileTypes: true })
+  } catch {
+    return []
+  }
+  const names = new Set(entries.map((entry) => entry.name))
+  const files: ClipFile[] = []
+  for (const entry of entries) {
+    const path = join(dir, entry.name)
+    if (entry.isDirectory()) files.push(...(await findClipFiles(path)))
+    else if (CLIP_EXTENSION.test(entry.name)) {
+      const thumbnail = entry.name + THUMBNAIL_SUFFIX
+      files.push({
+        path,
+        favorite: names.has(entry.name + FAVORITE_SUFFIX),
+        thumbnail: names.has(thumbnail) ? join(dir, thumbnail) : undefined
+      })
+    }
+  }
+  return files
+}
+
 export async function discoverClips(): Promise<ClipGroup[]> {
   const config = await readConfig()
   const clipDir = resolveEnvVars(config.global.clip_output_dir)
@@ -41,21 +98,21 @@ export async function discoverClips(): Promise<ClipGroup[]> {
     if (!entry.isDirectory()) continue
     const gameDir = join(clipDir, entry.name)
 
-    let files: string[]
-    try {
-      files = await readdir(gameDir)
-    } catch {
-      continue
-    }
-
-    const clips: Clip[] = files
-      .filter((f) => f.toLowerCase().endsWith('.mp4'))
-      .map((f) => ({
-        name: f.replace(/\.mp4$/i, ''),
-        path: join(gameDir, f),
-        timestamp: parseTimestamp(f).toISOString()
-      }))
-      .sort((a, b) => new Date(b.timestamp).getTime() - new Date(a.timestamp).getTime())
+    const files = await findClipFiles(gameDir)
+    const clips: Clip[] = (
+      await Promise.all(
+        files.map(async ({ path, favorite, thumbnail }) => {
+          const file = basename(path)
+          return {
+            name: file.replace(CLIP_EXTENSION, ''),
+            path,
+            timestamp: (await clipTimestamp(path, file)).toISOString(),
+            favorite,
+            thumbnail
+          }
+        })
+      )
+    ).sort((a, b) => new Date(b.timestamp).getTime() - new Date(a.timestamp).getTime())
 
     if (clips.length > 0) {
       groups.push({ game: entry.name, clips })
@@ -70,6 +127,14 @@ export async function discoverClips(): Promise<ClipGroup[]> {
 
 export async function deleteClip(filePath: string): Promise<void> {
   await unlink(filePath)
+  await unlink(filePath + FAVORITE_SUFFIX).catch(() => undefined)
+  await unlink(filePath + SIDECAR_SUFFIX).catch(() => undefined)
+  await unlink(filePath + THUMBNAIL_SUFFIX).catch(() => undefined)
+}
+
+export async function setClipFavorite(filePath: string, favorite: boolean): Promise<void> {
+  if (favorite) await writeFile(filePath + FAVORITE_SUFFIX, '')
+  else await unlink(filePath + FAVORITE_SUFFIX).catch(() => undefined)
 }
 
 export function showClipInFolder(filePath: string): void {
@@ -3,18 +3,64 @@ import { readFile, writeFile, mkdir } from 'fs/promises'
 import { join, dirname, basename } from 'path'
 import { parse, stringify } from 'smol-toml'
 
+type RateControl = 'cbr' | 'vbr' | 'cqp'
+
+type EncoderPreset = 'speed' | 'balanced' | 'quality'
+
+type ScaleFit = 'letterbox' | 'crop'
+
+interface VideoSettings {
+  fps?: number
+  bitrate_kbps?: number
+  rate_control?: RateControl
+  qp?: number
+  preset?: EncoderPreset
+  gop_length?: number
+  output_width?: number
+  output_height?: number
+  scale?: number
+  fit?: ScaleFit
+}
+
+interface AudioSettings {
+  bitrate_kbps?: number
+}
+
+interface RetentionSettings {
+  max_total_bytes?: number
+  max_count?: number
+  max_age_days?: number
+}
+
 interface AppConfig {
   display_name: string
   executable_name: string
   executable_path: string
   buffer_length_secs?: number
+  max_buffer_bytes?: number
+  post_roll_secs?: number
   hotkey?: string
+  video?: VideoSettings
+  audio?: AudioSettings
+  retention?: RetentionSettings
 }
 
 interface GlobalConfig {
   buffer_length_secs: number
+  max_buffer_bytes?: number
+  spill_file_bytes?: number
+  post_roll_secs?: number
   hotkey: string
   clip_output_dir: string
+  clip_filename_template?: string
+  clip_dir_template?: string
+  container?: 'mp4' | 'fmp4' | 'mkv'
+  thumbnail_width?: number
+  disk_space_warning_bytes?: number
+  encoder?: 'auto' | 'nvenc' | 'qsv' | 'amf' | 'x264' | 'software'
+  video?: VideoSettings
+  audio?: AudioSettings
+  retention?: RetentionSettings
 }
 
 interface Config {
@@ -59,7 +105,12 @@ export async function writeConfig(config: Config): Promise<void> {
         executable_path: app.executable_path
       }
       if (app.buffer_length_secs !== undefined) entry.buffer_length_secs = app.buffer_length_secs
+      if (app.max_buffer_bytes !== undefined) entry.max_buffer_bytes = app.max_buffer_bytes
+      if (app.post_roll_secs !== undefined) entry.post_roll_secs = app.post_roll_secs
       if (app.hotkey !== undefined) entry.hotkey = app.hotkey
+      if (app.video !== undefined) entry.video = app.video
+      if (app.audio !== undefined) entry.audio = app.audio
+      if (app.retention !== undefined) entry.retention = app.retention
       return entry
     })
   }
@@ -2,9 +2,9 @@ import { app, BrowserWindow, ipcMain, dialog, protocol, net } from 'electron'
 import { join, sep } from 'path'
 import { pathToFileURL } from 'url'
 import { readConfig, writeConfig, executableToAppConfig } from './config'
-import { readStatus } from './status'
+import { readStatus, subscribeStatus } from './status'
 import { spawnDaemon, stopDaemon, restartDaemon, isDaemonRunning } from './daemon'
-import { discoverClips, deleteClip, showClipInFolder } from './clips'
+import { discoverClips, deleteClip, setClipFavorite, showClipInFolder } from './clips'
 
 // Must be called before app.ready
 protocol.registerSchemesAsPrivileged([
@@ -31,15 +31,27 @@ function createWindow(): void {
 }
 
 function startStatusPolling(win: BrowserWindow): void {
+  // Changes are pushed over the control pipe; status.toml is polled only
+  // while the pipe is not connected.
+  const subscription = subscribeStatus(() => push())
+
   async function push(): Promise<void> {
     if (win.isDestroyed()) return
-    const [status, running] = await Promise.all([readStatus(), isDaemonRunning()])
+    const live = subscription.latest()
+    const [status, running] = live
+      ? [live, true]
+      : await Promise.all([readStatus(), isDaemonRunning()])
     win.webContents.send('status:update', { status, running })
   }
 
   win.webContents.once('did-finish-load', () => push())
-  const interval = setInterval(push, 2000)
-  win.on('closed', () => clearInterval(interval))
+  const interval = setInterval(() => {
+    if (!subscription.latest()) push()
+  }, 2000)
+  win.on('closed', () => {
+    clearInterval(interval)
+    subscription.close()
+  })
 }
 
 // Config IPC
@@ -70,6 +82,9 @@ ipcMain.handle('daemon:restart', () => restartDaemon())
 // Clips IPC
 ipcMain.handle('clips:discover', () => discoverClips())
 ipcMain.handle('clips:delete', (_event, filePath: string) => deleteClip(filePath))
+ipcMain.handle('clips:set-favorite', (_event, filePath: string, favorite: boolean) =>
+  setClipFavorite(filePath, favorite)
+)
 ipcMain.handle('clips:show-in-explorer', (_event, filePath: string) => showClipInFolder(filePath))
 
 app.whenReady().then(() => {
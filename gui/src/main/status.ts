@@ -1,27 +1,139 @@
 import { app } from 'electron'
 import { readFile } from 'fs/promises'
+import { connect } from 'net'
 import { join } from 'path'
 import { parse } from 'smol-toml'
 
+interface EncoderStatus {
+  requested: string
+  backend?: string
+  rejected?: Record<string, string>
+}
+
+interface BufferStatus {
+  bytes: number
+  disk_bytes: number
+  seconds: number
+  average_bitrate_kbps: number
+  capacity_secs: number
+  max_bytes?: number
+}
+
 interface DaemonStatus {
   version: string
-  state: 'idle' | 'recording' | 'flushing'
+  sequence?: number
+  updated_at?: string
+  pid?: number
+  started_at?: string
+  heartbeat_at?: string
+  state: 'idle' | 'recording' | 'pending' | 'flushing' | 'paused'
   active_application?: string
   last_clip_path?: string
   last_clip_timestamp?: string
+  last_clip_thumbnail?: string
   error?: string
+  warning?: string
+  disk_free_bytes?: number
+  encoder?: EncoderStatus
+  buffer?: BufferStatus
 }
 
-function getStatusPath(): string {
+const PIPE_PATH = '\\\\.\\pipe\\peaking-daemon'
+const RECONNECT_MS = 2000
+
+function getAppDataDir(): string {
   const appData = process.env.APPDATA ?? join(app.getPath('home'), '.config')
-  return join(appData, 'Peaking', 'status.toml')
+  return join(appData, 'Peaking')
+}
+
+function getStatusPath(): string {
+  return join(getAppDataDir(), 'status.toml')
+}
+
+function getControlPath(): string {
+  return process.platform === 'win32' ? PIPE_PATH : join(getAppDataDir(), 'daemon.sock')
+}
+
+let lastRead: DaemonStatus | null = null
+
+/** Whether `status` was written before `than`, going by its revision stamps. */
+function isOlder(status: DaemonStatus, than: DaemonStatus): boolean {
+  const written = Date.parse(status.updated_at ?? '')
+  const thanWritten = Date.parse(than.updated_at ?? '')
+  if (isNaN(written) || isNaN(thanWritten)) return false
+  if (written !== thanWritten) return written < thanWritten
+  return (status.sequence ?? 0) < (than.sequence ?? 0)
 }
 
 export async function readStatus(): Promise<DaemonStatus | null> {
   try {
     const content = await readFile(getStatusPath(), 'utf-8')
-    return parse(content) as unknown as DaemonStatus
+    const status = parse(content) as unknown as DaemonStatus
+    // Keep the newer copy if reads complete out of order.
+    if (lastRead && isOlder(status, lastRead)) return lastRead
+    lastRead = status
+    return status
   } catch {
     return null
   }
 }
+
+export interface StatusSubscription {
+  /** The newest status pushed by the daemon, or null while disconnected. */
+  latest(): DaemonStatus | null
+  close(): void
+}
+
+/**
+ * Subscribes to status changes over the daemon's control pipe, reconnecting
+ * whenever the daemon restarts. `onStatus` runs on every change.
+ */
+export function subscribeStatus(onStatus: (status: DaemonStatus) => void): StatusSubscription {
+  let latest: DaemonStatus | null = null
+  let closed = false
+  let timer: NodeJS.Timeout | null = null
+  let socket: ReturnType<typeof connect> | null = null
+
+  function open(): void {
+    let buffered = ''
+    socket = connect(getControlPath())
+    socket.setEncoding('utf-8')
+    socket.on('connect', () => {
+      socket?.write(JSON.stringify({ jsonrpc: '2.0', id: 1, method: 'subscribe' }) + '\n')
+    })
+    socket.on('data', (chunk: string) => {
+      buffered += chunk
+      const lines = buffered.split('\n')
+      buffered = lines.pop() ?? ''
+      for (const line of lines) {
+        let message
+        try {
+          message = JSON.parse(line)
+        } catch {
+          continue
+        }
+        const status = message.id === 1 ? message.result?.status : message.params?.status
+        if (status) {
+          latest = status as DaemonStatus
+          onStatus(latest)
+        }
+      }
+    })
+    socket.on('error', () => {})
+    socket.on('close', () => {
+      latest = null
+      socket = null
+      if (!closed) timer = setTimeout(open, RECONNECT_MS)
+    })
+  }
+
+  open()
+  return {
+    latest: () => latest,
+    close: () => {
+      closed = true
+      if (timer) clearTimeout(timer)
+      socket?.destroy()
+    }
+  }
+}
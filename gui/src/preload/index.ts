@@ -26,5 +26,7 @@ contextBridge.exposeInMainWorld('electronAPI', {
   // Clips
   discoverClips: () => ipcRenderer.invoke('clips:discover'),
   deleteClip: (filePath: string) => ipcRenderer.invoke('clips:delete', filePath),
+  setClipFavorite: (filePath: string, favorite: boolean) =>
+    ipcRenderer.invoke('clips:set-favorite', filePath, favorite),
   showInExplorer: (filePath: string) => ipcRenderer.invoke('clips:show-in-explorer', filePath)
 })
@@ -11,12 +11,14 @@ const sampleGroups: ClipGroup[] = [
       {
         name: '2024-06-15_14-30-00',
         path: 'C:\\Clips\\Rocket League\\2024-06-15_14-30-00.mp4',
-        timestamp: '2024-06-15T14:30:00.000Z'
+        timestamp: '2024-06-15T14:30:00.000Z',
+        favorite: false
       },
       {
         name: '2024-06-14_10-00-00',
         path: 'C:\\Clips\\Rocket League\\2024-06-14_10-00-00.mp4',
-        timestamp: '2024-06-14T10:00:00.000Z'
+        timestamp: '2024-06-14T10:00:00.000Z',
+        favorite: true
       }
     ]
   },
@@ -26,7 +28,8 @@ const sampleGroups: ClipGroup[] = [
       {
         name: '2024-06-13_20-00-00',
         path: 'C:\\Clips\\Apex Legends\\2024-06-13_20-00-00.mp4',
-        timestamp: '2024-06-13T20:00:00.000Z'
+        timestamp: '2024-06-13T20:00:00.000Z',
+        favorite: false
       }
     ]
   }
@@ -35,6 +38,7 @@ const sampleGroups: ClipGroup[] = [
 const mockElectronAPI = {
   discoverClips: vi.fn<[], Promise<ClipGroup[]>>(),
   deleteClip: vi.fn<[string], Promise<void>>(),
+  setClipFavorite: vi.fn<[string, boolean], Promise<void>>(),
   showInExplorer: vi.fn<[string], Promise<void>>()
 }
 
@@ -43,6 +47,7 @@ beforeEach(() => {
   vi.stubGlobal('electronAPI', mockElectronAPI)
   mockElectronAPI.discoverClips.mockResolvedValue(sampleGroups)
   mockElectronAPI.deleteClip.mockResolvedValue(undefined)
+  mockElectronAPI.setClipFavorite.mockResolvedValue(undefined)
   mockElectronAPI.showInExplorer.mockResolvedValue(undefined)
 })
 
@@ -181,4 +186,17 @@ describe('Clips', () => {
     expect(video.src).toMatch(/^local-file:\/\/\//)
     expect(video.src).toContain('2024-06-15_14-30-00.mp4')
   })
+
+  it('toggles a clip favorite when the star is clicked', async () => {
+    const user = userEvent.setup()
+    render(<Clips />)
+    await waitFor(() => expect(screen.getAllByLabelText('Add to favorites')[0]).toBeInTheDocument())
+    expect(screen.getByLabelText('Remove from favorites')).toBeInTheDocument()
+    await user.click(screen.getAllByLabelText('Add to favorites')[0])
+    expect(mockElectronAPI.setClipFavorite).toHaveBeenCalledWith(
+      'C:\\Clips\\Rocket League\\2024-06-15_14-30-00.mp4',
+      true
+    )
+    expect(screen.getAllByLabelText('Remove from favorites')).toHaveLength(2)
+  })
 })
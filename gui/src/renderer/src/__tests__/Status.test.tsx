@@ -98,6 +98,12 @@ describe('Status', () => {
     expect(screen.getByText('flushing')).toBeInTheDocument()
   })
 
+  it('displays pending state badge', () => {
+    render(<Status />)
+    pushUpdate({ ...idleUpdate, status: { ...idleUpdate.status!, state: 'pending' } })
+    expect(screen.getByText('pending')).toBeInTheDocument()
+  })
+
   it('shows active application name when present', () => {
     render(<Status />)
     pushUpdate({ ...idleUpdate, status: { ...idleUpdate.status!, state: 'recording', active_application: 'Rocket League' } })
@@ -141,6 +147,13 @@ describe('Status', () => {
     expect(screen.queryByText('encoder failed')).not.toBeInTheDocument()
   })
 
+  it('shows warning message when warning field is present', () => {
+    render(<Status />)
+    const warning = 'Low disk space: 1024 MiB free on the clip drive (warning below 5120 MiB)'
+    pushUpdate({ ...idleUpdate, status: { ...idleUpdate.status!, warning } })
+    expect(screen.getByText(warning)).toBeInTheDocument()
+  })
+
   it('calls daemonStart when Start button is clicked', async () => {
     const user = userEvent.setup()
     render(<Status />)
@@ -1,5 +1,5 @@
 import React, { useState } from 'react'
-import { Play, Square, FolderOpen, Trash2 } from 'lucide-react'
+import { Play, Square, FolderOpen, Star, Trash2 } from 'lucide-react'
 import type { Clip } from '../types/clips'
 
 interface ClipCardProps {
@@ -22,6 +22,17 @@ export default function ClipCard({ clip, onDeleted }: ClipCardProps): React.JSX.
   const [confirming, setConfirming] = useState(false)
   const [deleting, setDeleting] = useState(false)
   const [deleteError, setDeleteError] = useState<string | null>(null)
+  const [favorite, setFavorite] = useState(clip.favorite)
+
+  async function toggleFavorite(): Promise<void> {
+    const next = !favorite
+    setFavorite(next)
+    try {
+      await window.electronAPI.setClipFavorite(clip.path, next)
+    } catch {
+      setFavorite(!next)
+    }
+  }
 
   async function handleDelete(): Promise<void> {
     setDeleting(true)
@@ -46,6 +57,15 @@ export default function ClipCard({ clip, onDeleted }: ClipCardProps): React.JSX.
         </div>
 
         <div className="flex items-center gap-1.5 shrink-0">
+          <button
+            onClick={toggleFavorite}
+            className={`p-1.5 hover:bg-gray-600 rounded transition-colors ${favorite ? 'text-yellow-400 hover:text-yellow-300' : 'text-gray-400 hover:text-white'}`}
+            aria-label={favorite ? 'Remove from favorites' : 'Add to favorites'}
+            aria-pressed={favorite}
+          >
+            <Star className={`w-3.5 h-3.5 ${favorite ? 'fill-current' : ''}`} />
+          </button>
+
           <button
             onClick={() => setPlaying((p) => !p)}
             className="p-1.5 text-gray-400 hover:text-white hover:bg-gray-600 rounded transition-colors"
@@ -97,14 +117,23 @@ export default function ClipCard({ clip, onDeleted }: ClipCardProps): React.JSX.
         <p className="text-xs text-red-400">{deleteError}</p>
       )}
 
-      {playing && (
+      {playing ? (
         // eslint-disable-next-line jsx-a11y/media-has-caption
         <video
           src={pathToLocalUrl(clip.path)}
+          poster={clip.thumbnail && pathToLocalUrl(clip.thumbnail)}
           controls
           className="w-full rounded"
           aria-label={`Video player for ${clip.name}`}
         />
+      ) : (
+        clip.thumbnail && (
+          <img
+            src={pathToLocalUrl(clip.thumbnail)}
+            alt={`Thumbnail of ${clip.name}`}
+            className="w-full rounded"
+          />
+        )
       )}
     </div>
   )
@@ -6,7 +6,9 @@ function StateBadge({ state }: { state: DaemonState }): React.JSX.Element {
   const styles: Record<DaemonState, string> = {
     idle: 'bg-gray-700 text-gray-300',
     recording: 'bg-red-900 text-red-300',
-    flushing: 'bg-amber-900 text-amber-300'
+    pending: 'bg-orange-900 text-orange-300',
+    flushing: 'bg-amber-900 text-amber-300',
+    paused: 'bg-blue-900 text-blue-300'
   }
   return (
     <span className={`inline-block px-2 py-0.5 rounded text-xs font-medium capitalize ${styles[state]}`}>
@@ -145,6 +147,12 @@ export default function Status(): React.JSX.Element {
                 {status.error}
               </div>
             )}
+
+            {status.warning && (
+              <div className="mt-2 p-3 bg-yellow-950 border border-yellow-800 rounded text-sm text-yellow-300">
+                {status.warning}
+              </div>
+            )}
           </div>
         )}
       </div>
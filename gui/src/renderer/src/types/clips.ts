@@ -1,14 +1,18 @@
 export interface Clip {
   /** Filename without extension, e.g. "2024-01-01_12-00-00" */
   name: string
-  /** Full Windows path to the .mp4 file */
+  /** Full Windows path to the .mp4 or .mkv file */
   path: string
-  /** ISO 8601 timestamp parsed from the filename */
+  /** ISO 8601 timestamp parsed from the filename, or the file's modification time */
   timestamp: string
+  /** Favourites are never deleted by the daemon's retention rules */
+  favorite: boolean
+  /** Full path to the PNG thumbnail saved next to the clip, if there is one */
+  thumbnail?: string
 }
 
 export interface ClipGroup {
-  /** Game display name (subdirectory name under the clip output dir) */
+  /** Top-level subdirectory under the clip output dir (the game by default) */
   game: string
   /** Clips sorted newest-first */
   clips: Clip[]
@@ -1,15 +1,61 @@
+export type RateControl = 'cbr' | 'vbr' | 'cqp'
+
+export type EncoderPreset = 'speed' | 'balanced' | 'quality'
+
+export type ScaleFit = 'letterbox' | 'crop'
+
+export interface VideoSettings {
+  fps?: number
+  bitrate_kbps?: number
+  rate_control?: RateControl
+  qp?: number
+  preset?: EncoderPreset
+  gop_length?: number
+  output_width?: number
+  output_height?: number
+  scale?: number
+  fit?: ScaleFit
+}
+
+export interface AudioSettings {
+  bitrate_kbps?: number
+}
+
+export interface RetentionSettings {
+  max_total_bytes?: number
+  max_count?: number
+  max_age_days?: number
+}
+
 export interface AppConfig {
   display_name: string
   executable_name: string
   executable_path: string
   buffer_length_secs?: number
+  max_buffer_bytes?: number
+  post_roll_secs?: number
   hotkey?: string
+  video?: VideoSettings
+  audio?: AudioSettings
+  retention?: RetentionSettings
 }
 
 export interface GlobalConfig {
   buffer_length_secs: number
+  max_buffer_bytes?: number
+  spill_file_bytes?: number
+  post_roll_secs?: number
   hotkey: string
   clip_output_dir: string
+  clip_filename_template?: string
+  clip_dir_template?: string
+  container?: 'mp4' | 'fmp4' | 'mkv'
+  thumbnail_width?: number
+  disk_space_warning_bytes?: number
+  encoder?: 'auto' | 'nvenc' | 'qsv' | 'amf' | 'x264' | 'software'
+  video?: VideoSettings
+  audio?: AudioSettings
+  retention?: RetentionSettings
 }
 
 export interface Config {
@@ -35,4 +81,4 @@ export const HOTKEY_OPTIONS = [
 ]
 
 export const BUFFER_MIN = 5
-export const BUFFER_MAX = 120
+export const BUFFER_MAX = 1800
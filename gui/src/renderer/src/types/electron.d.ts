@@ -15,6 +15,7 @@ declare global {
       daemonRestart(): Promise<void>
       discoverClips(): Promise<ClipGroup[]>
       deleteClip(filePath: string): Promise<void>
+      setClipFavorite(filePath: string, favorite: boolean): Promise<void>
       showInExplorer(filePath: string): Promise<void>
     }
   }
@@ -1,12 +1,37 @@
-export type DaemonState = 'idle' | 'recording' | 'flushing'
+export type DaemonState = 'idle' | 'recording' | 'pending' | 'flushing' | 'paused'
+
+export interface EncoderStatus {
+  requested: string
+  backend?: string
+  rejected?: Record<string, string>
+}
+
+export interface BufferStatus {
+  bytes: number
+  disk_bytes: number
+  seconds: number
+  average_bitrate_kbps: number
+  capacity_secs: number
+  max_bytes?: number
+}
 
 export interface DaemonStatus {
   version: string
+  sequence?: number
+  updated_at?: string
+  pid?: number
+  started_at?: string
+  heartbeat_at?: string
   state: DaemonState
   active_application?: string
   last_clip_path?: string
   last_clip_timestamp?: string
+  last_clip_thumbnail?: string
   error?: string
+  warning?: string
+  disk_free_bytes?: number
+  encoder?: EncoderStatus
+  buffer?: BufferStatus
 }
 
 export interface StatusUpdate {